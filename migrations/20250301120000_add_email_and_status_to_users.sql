@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
+ALTER TABLE users ADD COLUMN is_active BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE users ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,10 @@
+-- Create User Invitations Table
+CREATE TABLE user_invitations(
+    invitation_id uuid PRIMARY KEY,
+    email TEXT NOT NULL,
+    invited_by uuid NOT NULL
+        REFERENCES users (user_id),
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    accepted_at timestamptz NULL
+);
@@ -0,0 +1,13 @@
+-- Create User Events Table
+-- `user_id` is the account the event is about. It is NULL for invitations,
+-- since the invited account does not exist yet.
+CREATE TABLE user_events(
+    id uuid PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    actor_id uuid NOT NULL
+        REFERENCES users (user_id),
+    user_id uuid NULL
+        REFERENCES users (user_id),
+    details TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
@@ -0,0 +1,15 @@
+-- Every existing account could do anything until now: keep it that way by
+-- making them owners. New accounts always get an explicit role.
+BEGIN;
+    ALTER TABLE users ADD COLUMN role TEXT NULL;
+    UPDATE users
+        SET role = 'owner'
+        WHERE role IS NULL;
+    ALTER TABLE users ALTER COLUMN role SET NOT NULL;
+
+    ALTER TABLE user_invitations ADD COLUMN role TEXT NULL;
+    UPDATE user_invitations
+        SET role = 'editor'
+        WHERE role IS NULL;
+    ALTER TABLE user_invitations ALTER COLUMN role SET NOT NULL;
+COMMIT;
@@ -0,0 +1,22 @@
+-- TOTP secrets are stored base32-encoded, as shown to authenticator apps.
+-- `totp_last_step` remembers the last accepted time step so that a code
+-- cannot be replayed.
+BEGIN;
+    ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+    ALTER TABLE users ADD COLUMN totp_last_step BIGINT NULL;
+
+    CREATE TABLE user_recovery_codes(
+        user_id uuid NOT NULL
+            REFERENCES users (user_id),
+        code_hash TEXT NOT NULL,
+        used_at timestamptz NULL,
+        PRIMARY KEY (user_id, code_hash)
+    );
+
+    -- Single-row table holding settings that apply to every account.
+    CREATE TABLE security_settings(
+        id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+        require_two_factor BOOLEAN NOT NULL
+    );
+    INSERT INTO security_settings (id, require_two_factor) VALUES (TRUE, FALSE);
+COMMIT;
@@ -0,0 +1,10 @@
+-- Single-use links to set a new password. As for API tokens, only a
+-- SHA-256 hash of the token is stored.
+CREATE TABLE password_reset_tokens(
+    token_hash TEXT PRIMARY KEY,
+    user_id uuid NOT NULL
+        REFERENCES users (user_id),
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    used_at timestamptz NULL
+);
@@ -0,0 +1,8 @@
+-- The sessions a user is logged in with, so that they can be ended from the
+-- server side. The session data itself lives in the session store.
+CREATE TABLE user_sessions(
+    session_id TEXT PRIMARY KEY,
+    user_id uuid NOT NULL
+        REFERENCES users (user_id),
+    created_at timestamptz NOT NULL
+);
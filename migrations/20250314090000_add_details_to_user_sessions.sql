@@ -0,0 +1,7 @@
+-- Details shown on /admin/sessions, so that users can recognise their
+-- sessions. `id` identifies a session in forms and links without revealing
+-- the value of its cookie.
+ALTER TABLE user_sessions ADD COLUMN id uuid NOT NULL UNIQUE DEFAULT gen_random_uuid();
+ALTER TABLE user_sessions ADD COLUMN last_seen_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE user_sessions ADD COLUMN ip TEXT NULL;
+ALTER TABLE user_sessions ADD COLUMN user_agent TEXT NULL;
@@ -0,0 +1,28 @@
+-- Create Audit Log Table
+-- `actor_id` is NULL when nobody is logged in, e.g. for failed logins.
+-- `target` names what the action was about, e.g. `subscriber:<id>`.
+CREATE TABLE audit_log(
+    id BIGSERIAL PRIMARY KEY,
+    occurred_at timestamptz NOT NULL DEFAULT now(),
+    actor_id uuid NULL
+        REFERENCES users (user_id),
+    action TEXT NOT NULL,
+    target TEXT NULL,
+    ip TEXT NULL,
+    details TEXT NULL
+);
+CREATE INDEX audit_log_occurred_at_idx ON audit_log (occurred_at);
+
+-- Entries can be added, never changed or removed.
+CREATE FUNCTION reject_audit_log_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'The audit log is append-only.';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_is_append_only
+    BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_log_changes();
+CREATE TRIGGER audit_log_cannot_be_truncated
+    BEFORE TRUNCATE ON audit_log
+    FOR EACH STATEMENT EXECUTE FUNCTION reject_audit_log_changes();
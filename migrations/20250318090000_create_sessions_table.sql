@@ -0,0 +1,8 @@
+-- Create Sessions Table
+-- Only used when the session store is configured as `postgres`.
+CREATE TABLE sessions(
+    id TEXT PRIMARY KEY,
+    data TEXT NOT NULL,
+    expires_at timestamptz NOT NULL
+);
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
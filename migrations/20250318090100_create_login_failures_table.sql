@@ -0,0 +1,7 @@
+-- Create Login Failures Table
+-- Counters of failed login attempts when sessions are not kept in Redis.
+CREATE TABLE login_failures(
+    key TEXT PRIMARY KEY,
+    failures INTEGER NOT NULL,
+    expires_at timestamptz NOT NULL
+);
@@ -0,0 +1,8 @@
+-- Databases migrated before the first-run setup existed have an `admin`
+-- account whose password is public. Disable it if it was never changed;
+-- the setup page then asks for a new owner.
+UPDATE users
+    SET is_active = FALSE
+    WHERE user_id = 'ddf8994f-d522-4659-8d02-c1d479057be6'
+        AND password_hash = '$argon2id$v=19$m=15000,t=2,p=1$OEx/rcq+3ts//'
+            'WUDzGNl2g$Am8UFBA4w5NJEmAtquGvBmAlu92q/VQcaoL5AyJPfc8';
@@ -0,0 +1,49 @@
+-- Outbound webhooks. The signing secret is kept in clear text, since every
+-- delivery has to be signed with it; it is shown to admins once, when the
+-- endpoint is registered.
+CREATE TABLE webhook_endpoints(
+    endpoint_id uuid PRIMARY KEY,
+    url TEXT NOT NULL,
+    description TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    events TEXT[] NOT NULL,
+    created_by uuid NOT NULL
+        REFERENCES users (user_id),
+    created_at timestamptz NOT NULL,
+    disabled_at timestamptz NULL
+);
+
+-- The queue of deliveries: one per endpoint and event, retried until it
+-- succeeds or runs out of attempts. `payload` is the exact body that is
+-- signed and sent. A replay is a new delivery of the same payload.
+CREATE TABLE webhook_deliveries(
+    delivery_id uuid PRIMARY KEY,
+    endpoint_id uuid NOT NULL
+        REFERENCES webhook_endpoints (endpoint_id),
+    event TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    status TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at timestamptz NOT NULL,
+    next_attempt_at timestamptz NULL,
+    replay_of uuid NULL
+        REFERENCES webhook_deliveries (delivery_id)
+);
+CREATE INDEX webhook_deliveries_due_idx
+    ON webhook_deliveries (next_attempt_at)
+    WHERE status = 'pending';
+CREATE INDEX webhook_deliveries_endpoint_idx
+    ON webhook_deliveries (endpoint_id, created_at);
+
+-- Every attempt, with the response of the endpoint or why there was none.
+CREATE TABLE webhook_delivery_attempts(
+    id BIGSERIAL PRIMARY KEY,
+    delivery_id uuid NOT NULL
+        REFERENCES webhook_deliveries (delivery_id),
+    attempted_at timestamptz NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    response_status SMALLINT NULL,
+    error TEXT NULL
+);
+CREATE INDEX webhook_delivery_attempts_delivery_idx
+    ON webhook_delivery_attempts (delivery_id);
@@ -0,0 +1,15 @@
+-- Published newsletter issues, kept to be listed and read back through the
+-- API. `published_by` is the owner of the API token that published it.
+CREATE TABLE newsletter_issues(
+    issue_id uuid PRIMARY KEY,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    published_at timestamptz NOT NULL,
+    published_by uuid NULL
+        REFERENCES users (user_id)
+);
+CREATE INDEX newsletter_issues_published_at_idx
+    ON newsletter_issues (published_at, issue_id);
+CREATE INDEX subscriptions_subscribed_at_idx
+    ON subscriptions (subscribed_at, id);
@@ -0,0 +1,20 @@
+-- One row per recipient of a newsletter issue. The recipients are the
+-- confirmed subscribers at publishing time; the address is copied, so the
+-- report survives subscribers being edited or deleted.
+-- `status` is one of 'pending', 'sent', 'failed' or 'suppressed', the last
+-- for stored addresses that are no longer valid and are never sent to.
+CREATE TABLE issue_deliveries(
+    delivery_id uuid PRIMARY KEY,
+    issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (issue_id),
+    recipient TEXT NOT NULL,
+    status TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    provider_message_id TEXT NULL,
+    error TEXT NULL,
+    created_at timestamptz NOT NULL,
+    last_attempt_at timestamptz NULL,
+    sent_at timestamptz NULL,
+    UNIQUE (issue_id, recipient)
+);
+CREATE INDEX issue_deliveries_status_idx ON issue_deliveries (status);
@@ -0,0 +1,4 @@
+-- A sender claims a recipient, with status 'sending', before emailing them,
+-- so that two senders never pick the same recipient. A claim left behind by
+-- a sender that stopped halfway is given back after a while.
+ALTER TABLE issue_deliveries ADD COLUMN claimed_at timestamptz NULL;
@@ -0,0 +1,5 @@
+-- A worker claims a delivery, with status 'sending', and commits before
+-- calling the endpoint, so that no transaction is held open during the
+-- request. A claim left behind by a worker that stopped halfway is taken
+-- over by another one after a while.
+ALTER TABLE webhook_deliveries ADD COLUMN claimed_at timestamptz NULL;
@@ -0,0 +1,220 @@
+use anyhow::Context;
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::domain::ApiScope;
+
+/// Makes tokens easy to recognise, e.g. for secret scanners.
+const TOKEN_PREFIX: &str = "nlt_";
+const TOKEN_LENGTH: usize = 40;
+
+/// A token that is valid, has not expired and has not been revoked.
+#[derive(Debug)]
+pub struct AuthenticatedToken {
+    pub token_id: Uuid,
+    pub user_id: Uuid,
+    pub scopes: Vec<ApiScope>,
+}
+
+impl AuthenticatedToken {
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+pub struct ApiTokenRecord {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+fn generate_token() -> SecretString {
+    let token: String = rand::rng()
+        .sample_iter(rand::distr::Alphanumeric)
+        .map(char::from)
+        .take(TOKEN_LENGTH)
+        .collect();
+    SecretString::new(format!("{}{}", TOKEN_PREFIX, token).into())
+}
+
+/// Tokens are long and random, so a fast hash is enough: unlike passwords
+/// they cannot be brute-forced from a dictionary.
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Extract the token from an `Authorization: Bearer <token>` header.
+pub fn bearer_token(headers: &HeaderMap) -> Result<SecretString, anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let token = header_value
+        .strip_prefix("Bearer ")
+        .context("The authorization scheme was not 'Bearer'.")?
+        .trim();
+    Ok(SecretString::new(token.into()))
+}
+
+/// Store a new token for `user_id` and return it. This is the only time the
+/// token is available in clear text.
+#[tracing::instrument(name = "Create API token", skip(executor))]
+pub async fn create_api_token(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<(Uuid, SecretString), anyhow::Error> {
+    let token_id = Uuid::new_v4();
+    let token = generate_token();
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_string()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (token_id, user_id, name, token_hash, scopes, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        token_id,
+        user_id,
+        name,
+        hash_token(token.expose_secret()),
+        &scopes,
+        Utc::now(),
+        expires_at,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store the API token.")?;
+    Ok((token_id, token))
+}
+
+/// Look up a token and record that it was used. Returns `None` if the token
+/// is unknown, expired, revoked, or belongs to a deactivated user.
+#[tracing::instrument(name = "Authenticate API token", skip(token, pool))]
+pub async fn authenticate_api_token(
+    token: &SecretString,
+    pool: &PgPool,
+) -> Result<Option<AuthenticatedToken>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET last_used_at = now()
+        FROM users
+        WHERE api_tokens.user_id = users.user_id
+            AND api_tokens.token_hash = $1
+            AND api_tokens.revoked_at IS NULL
+            AND (api_tokens.expires_at IS NULL OR api_tokens.expires_at > now())
+            AND users.is_active
+        RETURNING api_tokens.token_id, api_tokens.user_id, api_tokens.scopes
+        "#,
+        hash_token(token.expose_secret()),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the API token.")?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let scopes = row
+        .scopes
+        .into_iter()
+        .map(ApiScope::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!(e))?;
+    Ok(Some(AuthenticatedToken {
+        token_id: row.token_id,
+        user_id: row.user_id,
+        scopes,
+    }))
+}
+
+#[tracing::instrument(name = "List API tokens", skip(pool))]
+pub async fn list_api_tokens(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<Vec<ApiTokenRecord>, anyhow::Error> {
+    let tokens = sqlx::query_as!(
+        ApiTokenRecord,
+        r#"
+        SELECT token_id, name, scopes, created_at, expires_at, last_used_at, revoked_at
+        FROM api_tokens
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve API tokens.")?;
+    Ok(tokens)
+}
+
+/// Returns `false` if `user_id` has no active token with the given id.
+#[tracing::instrument(name = "Revoke API token", skip(executor))]
+pub async fn revoke_api_token(
+    executor: impl PgExecutor<'_>,
+    token_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = now()
+        WHERE token_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        token_id,
+        user_id,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to revoke the API token.")?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue};
+    use claims::assert_err;
+    use secrecy::ExposeSecret;
+
+    use super::{bearer_token, generate_token, hash_token, TOKEN_PREFIX};
+
+    #[test]
+    fn generated_tokens_are_unique_and_prefixed() {
+        let first = generate_token();
+        let second = generate_token();
+        assert!(first.expose_secret().starts_with(TOKEN_PREFIX));
+        assert_ne!(first.expose_secret(), second.expose_secret());
+        assert_ne!(
+            hash_token(first.expose_secret()),
+            hash_token(second.expose_secret())
+        );
+    }
+
+    #[test]
+    fn bearer_token_is_extracted_from_the_authorization_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Authorization", HeaderValue::from_static("Bearer nlt_abc"));
+        assert_eq!(bearer_token(&headers).unwrap().expose_secret(), "nlt_abc");
+    }
+
+    #[test]
+    fn basic_credentials_are_not_a_bearer_token() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "Authorization",
+            HeaderValue::from_static("Basic dXNlcjpwYXNz"),
+        );
+        assert_err!(bearer_token(&headers));
+    }
+}
@@ -0,0 +1,245 @@
+use std::net::IpAddr;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// Security-relevant and publishing actions recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    LoginSucceeded,
+    LoginFailed,
+    LoggedOut,
+    PasswordChanged,
+    PasswordReset,
+    IssuePublished,
+    SubscriberCreated,
+    SubscriberEdited,
+    SubscriberDeleted,
+    UserCreated,
+    UserInvited,
+    UserDeactivated,
+    RoleChanged,
+    TwoFactorPolicyChanged,
+    WebhookCreated,
+    WebhookDisabled,
+    WebhookReplayed,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+    SessionRevoked,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 20] = [
+        AuditAction::LoginSucceeded,
+        AuditAction::LoginFailed,
+        AuditAction::LoggedOut,
+        AuditAction::PasswordChanged,
+        AuditAction::PasswordReset,
+        AuditAction::IssuePublished,
+        AuditAction::SubscriberCreated,
+        AuditAction::SubscriberEdited,
+        AuditAction::SubscriberDeleted,
+        AuditAction::UserCreated,
+        AuditAction::UserInvited,
+        AuditAction::UserDeactivated,
+        AuditAction::RoleChanged,
+        AuditAction::TwoFactorPolicyChanged,
+        AuditAction::WebhookCreated,
+        AuditAction::WebhookDisabled,
+        AuditAction::WebhookReplayed,
+        AuditAction::ApiTokenCreated,
+        AuditAction::ApiTokenRevoked,
+        AuditAction::SessionRevoked,
+    ];
+
+    pub fn parse(s: String) -> Result<AuditAction, String> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("{} is not a known action.", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::LoginSucceeded => "login_succeeded",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::LoggedOut => "logged_out",
+            AuditAction::PasswordChanged => "password_changed",
+            AuditAction::PasswordReset => "password_reset",
+            AuditAction::IssuePublished => "issue_published",
+            AuditAction::SubscriberCreated => "subscriber_created",
+            AuditAction::SubscriberEdited => "subscriber_edited",
+            AuditAction::SubscriberDeleted => "subscriber_deleted",
+            AuditAction::UserCreated => "user_created",
+            AuditAction::UserInvited => "user_invited",
+            AuditAction::UserDeactivated => "user_deactivated",
+            AuditAction::RoleChanged => "role_changed",
+            AuditAction::TwoFactorPolicyChanged => "two_factor_policy_changed",
+            AuditAction::WebhookCreated => "webhook_created",
+            AuditAction::WebhookDisabled => "webhook_disabled",
+            AuditAction::WebhookReplayed => "webhook_replayed",
+            AuditAction::ApiTokenCreated => "api_token_created",
+            AuditAction::ApiTokenRevoked => "api_token_revoked",
+            AuditAction::SessionRevoked => "session_revoked",
+        }
+    }
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Append an entry to the audit log. `actor_id` is the logged-in user, if
+/// any, and `target` what the action was about, e.g. `subscriber:<id>`.
+#[tracing::instrument(name = "Record audit entry", skip(executor))]
+pub async fn record_audit_entry(
+    executor: impl PgExecutor<'_>,
+    action: AuditAction,
+    actor_id: Option<Uuid>,
+    target: Option<&str>,
+    ip: Option<IpAddr>,
+    details: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (occurred_at, actor_id, action, target, ip, details)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Utc::now(),
+        actor_id,
+        action.as_str(),
+        target,
+        ip.map(|ip| ip.to_string()),
+        details,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to record an audit entry.")?;
+    Ok(())
+}
+
+/// An entry of the audit log, with the current username of its actor.
+#[derive(Debug, serde::Serialize)]
+pub struct AuditRecord {
+    pub id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub actor_id: Option<Uuid>,
+    pub actor: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub details: Option<String>,
+}
+
+/// Which entries to look for. `None` means "no filter".
+#[derive(Debug, Default)]
+pub struct AuditQuery {
+    /// Matched against the actor, target, IP address and details.
+    pub search: Option<String>,
+    pub action: Option<AuditAction>,
+    pub from: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Returns the matching entries, most recent first, skipping `offset` of
+/// them and returning at most `limit`, along with the number of matches.
+#[tracing::instrument(name = "Search audit log", skip(pool))]
+pub async fn search_audit_log(
+    query: &AuditQuery,
+    limit: i64,
+    offset: i64,
+    pool: &PgPool,
+) -> Result<(Vec<AuditRecord>, i64), anyhow::Error> {
+    let pattern = query.search.as_deref().map(|s| {
+        let escaped = s
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        format!("%{}%", escaped)
+    });
+    let action = query.action.map(|a| a.as_str());
+
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM audit_log
+        LEFT JOIN users ON users.user_id = audit_log.actor_id
+        WHERE ($1::TEXT IS NULL
+                OR users.username ILIKE $1
+                OR audit_log.target ILIKE $1
+                OR audit_log.ip ILIKE $1
+                OR audit_log.details ILIKE $1)
+            AND ($2::TEXT IS NULL OR audit_log.action = $2)
+            AND ($3::TIMESTAMPTZ IS NULL OR audit_log.occurred_at >= $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR audit_log.occurred_at < $4)
+        "#,
+        pattern,
+        action,
+        query.from,
+        query.until,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count audit entries.")?;
+
+    let records = sqlx::query_as!(
+        AuditRecord,
+        r#"
+        SELECT
+            audit_log.id,
+            audit_log.occurred_at,
+            audit_log.actor_id,
+            users.username AS "actor?",
+            audit_log.action,
+            audit_log.target,
+            audit_log.ip,
+            audit_log.details
+        FROM audit_log
+        LEFT JOIN users ON users.user_id = audit_log.actor_id
+        WHERE ($1::TEXT IS NULL
+                OR users.username ILIKE $1
+                OR audit_log.target ILIKE $1
+                OR audit_log.ip ILIKE $1
+                OR audit_log.details ILIKE $1)
+            AND ($2::TEXT IS NULL OR audit_log.action = $2)
+            AND ($3::TIMESTAMPTZ IS NULL OR audit_log.occurred_at >= $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR audit_log.occurred_at < $4)
+        ORDER BY audit_log.id DESC
+        LIMIT $5 OFFSET $6
+        "#,
+        pattern,
+        action,
+        query.from,
+        query.until,
+        limit,
+        offset,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve audit entries.")?;
+
+    Ok((records, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use super::AuditAction;
+
+    #[test]
+    fn every_action_round_trips_through_its_string_form() {
+        for action in AuditAction::ALL {
+            assert_ok_eq!(AuditAction::parse(action.as_str().to_string()), action);
+        }
+    }
+
+    #[test]
+    fn unknown_actions_are_rejected() {
+        assert_err!(AuditAction::parse("deleted_everything".to_string()));
+    }
+}
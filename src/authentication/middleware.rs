@@ -0,0 +1,66 @@
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::{request::Parts, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::{session_state::TypedSession, utils::e500};
+
+/// The id of the logged-in user. It can only be extracted behind
+/// [`reject_anonymous_users`].
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(pub Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<S> FromRequestParts<S> for UserId
+where
+    S: Sync + Send,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<UserId>().copied().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Can't extract the user id. Is the route behind `reject_anonymous_users`?",
+        ))
+    }
+}
+
+/// Send anonymous users to the login form, and hand the id of logged-in
+/// users to the handlers as a [`UserId`]. Sessions past their absolute
+/// lifetime are logged out first.
+pub async fn reject_anonymous_users(
+    session: TypedSession,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    match logged_in_user(&session).await {
+        Ok(Some(user_id)) => {
+            request.extensions_mut().insert(UserId(user_id));
+            next.run(request).await
+        }
+        Ok(None) => Redirect::to("/login").into_response(),
+        Err(e) => e500(e),
+    }
+}
+
+async fn logged_in_user(session: &TypedSession) -> Result<Option<Uuid>, anyhow::Error> {
+    let Some(user_id) = session.get_user_id().await? else {
+        return Ok(None);
+    };
+    if let Some(expires_at) = session.get_expires_at().await? {
+        if expires_at <= Utc::now() {
+            session.clone().log_out().await?;
+            return Ok(None);
+        }
+    }
+    Ok(Some(user_id))
+}
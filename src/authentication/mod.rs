@@ -0,0 +1,263 @@
+use anyhow::Context;
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+
+use crate::{
+    domain::{NewPassword, UserRole, Username},
+    telemetry::spawn_blocking_with_tracing,
+};
+
+mod middleware;
+pub use middleware::{reject_anonymous_users, UserId};
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuthError {
+    #[error("Invalid credentials")]
+    InvalidCredentials(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+pub struct Credentials {
+    pub username: String,
+    pub password: SecretString,
+}
+
+/// How password hashes are computed.
+#[derive(Clone)]
+pub struct PasswordHashing {
+    params: Params,
+    /// Checked against when the username is unknown, so that the answer
+    /// takes as long as for a known username.
+    dummy_hash: SecretString,
+}
+
+impl PasswordHashing {
+    pub fn new(params: Params) -> Result<Self, anyhow::Error> {
+        let dummy_password = SecretString::new(uuid::Uuid::new_v4().to_string().into());
+        let dummy_hash = compute_password_hash(dummy_password, params.clone())?;
+        Ok(Self { params, dummy_hash })
+    }
+
+    /// Whether `password_hash` was computed with other parameters than the
+    /// current ones.
+    fn is_outdated(&self, password_hash: &SecretString) -> bool {
+        let Ok(password_hash) = PasswordHash::new(password_hash.expose_secret()) else {
+            return true;
+        };
+        let Ok(params) = Params::try_from(&password_hash) else {
+            return true;
+        };
+        password_hash.algorithm != Algorithm::Argon2id.ident()
+            || password_hash.version != Some(Version::V0x13.into())
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+    }
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(credentials, hashing, pool))]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    hashing: &PasswordHashing,
+    pool: &PgPool,
+) -> Result<uuid::Uuid, AuthError> {
+    let mut user_id = None;
+    let mut expected_password_hash = hashing.dummy_hash.clone();
+
+    if let Some((stored_user_id, stored_password_hash)) =
+        get_stored_credentials(&credentials.username, pool)
+            .await
+            .map_err(AuthError::UnexpectedError)?
+    {
+        user_id = Some(stored_user_id);
+        expected_password_hash = stored_password_hash;
+    }
+
+    let password = credentials.password.clone();
+    let stored_password_hash = expected_password_hash.clone();
+    spawn_blocking_with_tracing(move || {
+        verify_password_hash(expected_password_hash, credentials.password)
+    })
+    .await
+    .context("Failed to spawn blocking task.")
+    .map_err(AuthError::UnexpectedError)??;
+
+    let user_id = user_id
+        .ok_or_else(|| AuthError::InvalidCredentials(anyhow::anyhow!("Unknown username.")))?;
+    if hashing.is_outdated(&stored_password_hash) {
+        // The user is logged in either way: a failed upgrade is retried at
+        // the next login.
+        if let Err(e) =
+            upgrade_password_hash(user_id, password, &stored_password_hash, hashing, pool).await
+        {
+            tracing::warn!(error.cause_chain = ?e, "Failed to upgrade a password hash.");
+        }
+    }
+    Ok(user_id)
+}
+
+/// Store a hash of `password` computed with the current parameters, unless
+/// the password was changed in the meantime.
+#[tracing::instrument(
+    name = "Upgrade password hash",
+    skip(password, old_password_hash, hashing, pool)
+)]
+async fn upgrade_password_hash(
+    user_id: uuid::Uuid,
+    password: SecretString,
+    old_password_hash: &SecretString,
+    hashing: &PasswordHashing,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let params = hashing.params.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+            .await?
+            .context("Failed to hash password")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2 AND password_hash = $3
+        "#,
+        password_hash.expose_secret(),
+        user_id,
+        old_password_hash.expose_secret(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the upgraded password hash.")?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Verify password hash",
+    skip(expected_password_hash, password_candidate)
+)]
+pub fn verify_password_hash(
+    expected_password_hash: SecretString,
+    password_candidate: SecretString,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .context("Failed to parse hash in PHC string format.")
+        .map_err(AuthError::UnexpectedError)?;
+
+    Argon2::default()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .context("Invalid password.")
+        .map_err(AuthError::InvalidCredentials)
+}
+
+#[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
+pub async fn get_stored_credentials(
+    username: &str,
+    pool: &PgPool,
+) -> Result<Option<(uuid::Uuid, SecretString)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, password_hash
+        FROM users
+        WHERE username = $1 AND is_active
+        "#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve stored credentials.")?
+    .map(|row| (row.user_id, SecretString::new(row.password_hash.into())));
+    Ok(row)
+}
+
+#[tracing::instrument(name = "Change password", skip(password, hashing, executor))]
+pub async fn change_password(
+    user_id: uuid::Uuid,
+    password: NewPassword,
+    hashing: &PasswordHashing,
+    executor: impl PgExecutor<'_>,
+) -> Result<(), anyhow::Error> {
+    let params = hashing.params.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password.into_secret(), params))
+            .await?
+            .context("Failed to hash password")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2
+        "#,
+        password_hash.expose_secret(),
+        user_id
+    )
+    .execute(executor)
+    .await
+    .context("Failed to change user's password in the database.")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get user role", skip(pool))]
+pub async fn get_user_role(user_id: uuid::Uuid, pool: &PgPool) -> Result<UserRole, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT role
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve a user's role.")?;
+    UserRole::parse(row.role).map_err(|e| anyhow::anyhow!(e))
+}
+
+#[tracing::instrument(name = "Create user", skip(password, hashing, transaction))]
+pub async fn create_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &Username,
+    email: &str,
+    role: UserRole,
+    password: NewPassword,
+    hashing: &PasswordHashing,
+) -> Result<uuid::Uuid, anyhow::Error> {
+    let params = hashing.params.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password.into_secret(), params))
+            .await?
+            .context("Failed to hash password")?;
+    let user_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, role, password_hash)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        user_id,
+        username.as_ref(),
+        email,
+        role.as_str(),
+        password_hash.expose_secret(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to store a new user in the database.")?;
+    Ok(user_id)
+}
+
+fn compute_password_hash(
+    password: SecretString,
+    params: Params,
+) -> Result<SecretString, anyhow::Error> {
+    let salt = SaltString::generate(&mut rand_08::thread_rng());
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
+    Ok(SecretString::new(password_hash.into()))
+}
@@ -0,0 +1,28 @@
+use newsletter::{
+    cli::{run, Command, USAGE},
+    configuration::get_configuration,
+    telemetry::{get_subscriber, init_subscriber},
+};
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    // Standard output is kept for the data that commands print.
+    let subscriber = get_subscriber("newsletter-admin", "warn", std::io::stderr, None);
+    init_subscriber(subscriber);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if matches!(args.as_slice(), [flag] if flag == "--help" || flag == "-h") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let command = match Command::parse(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let configuration = get_configuration()?;
+    run(command, configuration).await
+}
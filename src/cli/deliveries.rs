@@ -0,0 +1,48 @@
+use sqlx::PgPool;
+use std::fmt::Write;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{
+    email_client::EmailClient,
+    issue_deliveries::{list_failed, retry_failed, send_pending, SendReport},
+};
+
+/// The failed deliveries, one per line, tab separated.
+pub async fn list(issue_id: Option<Uuid>, pool: &PgPool) -> Result<String, anyhow::Error> {
+    let mut report = String::new();
+    for delivery in list_failed(issue_id, pool).await? {
+        writeln!(
+            report,
+            "{}\t{}\t{}\t{}\t{}",
+            delivery.issue_id,
+            delivery.recipient,
+            delivery.attempts,
+            delivery
+                .last_attempt_at
+                .map(|d| d.to_rfc3339())
+                .unwrap_or_default(),
+            delivery.error.unwrap_or_default(),
+        )
+        .unwrap();
+    }
+    Ok(report)
+}
+
+/// Send the failed and interrupted deliveries again, adding up how this
+/// retry went for every issue.
+pub async fn retry(
+    issue_id: Option<Uuid>,
+    email_client: &EmailClient,
+    pool: &PgPool,
+) -> Result<SendReport, anyhow::Error> {
+    let mut total = SendReport::default();
+    let shutdown = CancellationToken::new();
+    for issue_id in retry_failed(issue_id, pool).await? {
+        let report = send_pending(email_client, pool, issue_id, &shutdown).await?;
+        total.sent += report.sent;
+        total.failed += report.failed;
+        total.suppressed += report.suppressed;
+    }
+    Ok(total)
+}
@@ -0,0 +1,327 @@
+//! Operational tasks run from the command line, against the same
+//! configuration as the server.
+
+use anyhow::Context;
+use secrecy::SecretString;
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::{SubscriberEmail, UserRole},
+    email_client::EmailClient,
+    startup::get_connection_pool,
+};
+
+mod deliveries;
+mod subscribers;
+mod users;
+
+pub const USAGE: &str = "\
+Usage: newsletter-admin <command>
+
+Commands:
+    migrate
+        Apply the pending database migrations.
+    users create <username> <email> [--role owner|editor|viewer]
+        Create a user, an owner unless told otherwise.
+    users reset-password <username>
+        Set a new password for a user, and log them out everywhere.
+    users disable <username>
+        Prevent a user from logging in, and log them out everywhere.
+    subscribers import <file>
+        Add the subscribers of a CSV file with `email,name` columns as
+        confirmed subscribers. Known email addresses are skipped.
+    subscribers export [<file>]
+        Write every subscriber as CSV, to standard output by default.
+    deliveries list [<issue_id>]
+        List the recipients an issue could not be sent to, of every issue
+        by default.
+    deliveries retry [<issue_id>]
+        Send the issue again to the recipients it could not be sent to, and
+        to those an interrupted publication did not get to.
+    send-test-email <address>
+        Check the email delivery settings by sending an email.
+
+Passwords are read from the ADMIN_PASSWORD environment variable if it is set,
+or else prompted for on the terminal, without being echoed.
+The configuration is read as for the server, based on APP_ENVIRONMENT.";
+
+/// A task to run, as given on the command line.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Migrate,
+    CreateUser {
+        username: String,
+        email: String,
+        role: UserRole,
+    },
+    ResetPassword {
+        username: String,
+    },
+    DisableUser {
+        username: String,
+    },
+    ImportSubscribers {
+        path: String,
+    },
+    ExportSubscribers {
+        path: Option<String>,
+    },
+    ListFailedDeliveries {
+        issue_id: Option<Uuid>,
+    },
+    RetryFailedDeliveries {
+        issue_id: Option<Uuid>,
+    },
+    SendTestEmail {
+        recipient: String,
+    },
+}
+
+impl Command {
+    /// Parse the arguments that follow the name of the program.
+    pub fn parse(args: &[String]) -> Result<Command, String> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match args.as_slice() {
+            ["migrate"] => Ok(Command::Migrate),
+            ["users", "create", username, email, options @ ..] => {
+                let role = match options {
+                    [] => UserRole::Owner,
+                    ["--role", role] => UserRole::parse(role.to_string())?,
+                    _ => return Err(format!("Unexpected options: {}", options.join(" "))),
+                };
+                Ok(Command::CreateUser {
+                    username: username.to_string(),
+                    email: email.to_string(),
+                    role,
+                })
+            }
+            ["users", "reset-password", username] => Ok(Command::ResetPassword {
+                username: username.to_string(),
+            }),
+            ["users", "disable", username] => Ok(Command::DisableUser {
+                username: username.to_string(),
+            }),
+            ["subscribers", "import", path] => Ok(Command::ImportSubscribers {
+                path: path.to_string(),
+            }),
+            ["subscribers", "export"] => Ok(Command::ExportSubscribers { path: None }),
+            ["subscribers", "export", path] => Ok(Command::ExportSubscribers {
+                path: Some(path.to_string()),
+            }),
+            ["deliveries", "list", rest @ ..] => Ok(Command::ListFailedDeliveries {
+                issue_id: parse_issue_id(rest)?,
+            }),
+            ["deliveries", "retry", rest @ ..] => Ok(Command::RetryFailedDeliveries {
+                issue_id: parse_issue_id(rest)?,
+            }),
+            ["send-test-email", recipient] => Ok(Command::SendTestEmail {
+                recipient: recipient.to_string(),
+            }),
+            [] => Err("No command given.".into()),
+            _ => Err(format!("Unknown command: {}", args.join(" "))),
+        }
+    }
+}
+
+/// The optional issue id ending a `deliveries` command.
+fn parse_issue_id(args: &[&str]) -> Result<Option<Uuid>, String> {
+    match args {
+        [] => Ok(None),
+        [issue_id] => Uuid::parse_str(issue_id)
+            .map(Some)
+            .map_err(|_| format!("{} is not a valid issue id.", issue_id)),
+        _ => Err(format!("Unexpected arguments: {}", args.join(" "))),
+    }
+}
+
+/// Run `command`, reporting what was done on standard error.
+pub async fn run(command: Command, configuration: Settings) -> Result<(), anyhow::Error> {
+    let pool = get_connection_pool(&configuration.database);
+    match command {
+        Command::Migrate => {
+            let mut migrator = sqlx::migrate!("./migrations");
+            // The migration seeding the `admin` account was removed:
+            // tolerate it in databases created before that.
+            migrator.set_ignore_missing(true);
+            migrator
+                .run(&pool)
+                .await
+                .context("Failed to migrate the database.")?;
+            eprintln!("The database is up to date.");
+        }
+        Command::CreateUser {
+            username,
+            email,
+            role,
+        } => {
+            let password = read_password()?;
+            users::create(&username, &email, role, password, &configuration, &pool).await?;
+            eprintln!("{} can now log in as {}.", username, role);
+        }
+        Command::ResetPassword { username } => {
+            let password = read_password()?;
+            users::reset_password(&username, password, &configuration, &pool).await?;
+            eprintln!("The password of {} has been changed.", username);
+        }
+        Command::DisableUser { username } => {
+            users::disable(&username, &configuration, &pool).await?;
+            eprintln!("{} can no longer log in.", username);
+        }
+        Command::ImportSubscribers { path } => {
+            let csv = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}.", path))?;
+            let report = subscribers::import(&csv, &pool).await?;
+            eprintln!(
+                "Imported {} subscriber(s), skipped {} already known.",
+                report.imported, report.skipped
+            );
+        }
+        Command::ExportSubscribers { path } => {
+            let csv = subscribers::export(&pool).await?;
+            match path {
+                Some(path) => std::fs::write(&path, csv)
+                    .with_context(|| format!("Failed to write {}.", path))?,
+                None => print!("{}", csv),
+            }
+        }
+        Command::ListFailedDeliveries { issue_id } => {
+            print!("{}", deliveries::list(issue_id, &pool).await?);
+        }
+        Command::RetryFailedDeliveries { issue_id } => {
+            let email_client = email_client(configuration)?;
+            let report = deliveries::retry(issue_id, &email_client, &pool).await?;
+            eprintln!(
+                "Sent {} email(s), {} failed again, {} suppressed.",
+                report.sent, report.failed, report.suppressed
+            );
+        }
+        Command::SendTestEmail { recipient } => {
+            send_test_email(&recipient, configuration).await?;
+            eprintln!("A test email has been sent to {}.", recipient);
+        }
+    }
+    Ok(())
+}
+
+/// Read a password from `ADMIN_PASSWORD`, or else prompt for it on the
+/// terminal without echoing it.
+fn read_password() -> Result<SecretString, anyhow::Error> {
+    if let Ok(password) = std::env::var("ADMIN_PASSWORD") {
+        return Ok(SecretString::new(password.into()));
+    }
+    let password =
+        rpassword::prompt_password("Password: ").context("Failed to read the password.")?;
+    Ok(SecretString::new(password.into()))
+}
+
+fn email_client(configuration: Settings) -> Result<EmailClient, anyhow::Error> {
+    let sender = configuration
+        .email_client
+        .sender()
+        .map_err(anyhow::Error::msg)?;
+    let timeout = configuration.email_client.timeout();
+    Ok(EmailClient::new(
+        configuration.email_client.base_url,
+        sender,
+        configuration.email_client.authorization_token,
+        timeout,
+    ))
+}
+
+async fn send_test_email(recipient: &str, configuration: Settings) -> Result<(), anyhow::Error> {
+    let recipient = SubscriberEmail::parse(recipient.to_string()).map_err(anyhow::Error::msg)?;
+    email_client(configuration)?
+        .send_email(
+            &recipient,
+            "Test email",
+            "<p>The newsletter can send emails.</p>",
+            "The newsletter can send emails.",
+        )
+        .await
+        .context("Failed to send the test email.")
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use super::Command;
+    use crate::domain::UserRole;
+
+    fn parse(args: &str) -> Result<Command, String> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        Command::parse(&args)
+    }
+
+    #[test]
+    fn users_are_created_as_owners_by_default() {
+        assert_ok_eq!(
+            parse("users create ursula ursula@example.com"),
+            Command::CreateUser {
+                username: "ursula".into(),
+                email: "ursula@example.com".into(),
+                role: UserRole::Owner,
+            }
+        );
+    }
+
+    #[test]
+    fn the_role_of_a_new_user_can_be_chosen() {
+        assert_ok_eq!(
+            parse("users create ursula ursula@example.com --role viewer"),
+            Command::CreateUser {
+                username: "ursula".into(),
+                email: "ursula@example.com".into(),
+                role: UserRole::Viewer,
+            }
+        );
+    }
+
+    #[test]
+    fn an_unknown_role_is_rejected() {
+        assert_err!(parse("users create ursula ursula@example.com --role admin"));
+    }
+
+    #[test]
+    fn the_export_file_is_optional() {
+        assert_ok_eq!(
+            parse("subscribers export"),
+            Command::ExportSubscribers { path: None }
+        );
+        assert_ok_eq!(
+            parse("subscribers export out.csv"),
+            Command::ExportSubscribers {
+                path: Some("out.csv".into())
+            }
+        );
+    }
+
+    #[test]
+    fn deliveries_are_of_every_issue_unless_one_is_given() {
+        let issue_id = uuid::Uuid::new_v4();
+        assert_ok_eq!(
+            parse("deliveries list"),
+            Command::ListFailedDeliveries { issue_id: None }
+        );
+        assert_ok_eq!(
+            parse(&format!("deliveries retry {}", issue_id)),
+            Command::RetryFailedDeliveries {
+                issue_id: Some(issue_id)
+            }
+        );
+    }
+
+    #[test]
+    fn an_invalid_issue_id_is_rejected() {
+        assert_err!(parse("deliveries retry latest"));
+        assert_err!(parse("deliveries list a b"));
+    }
+
+    #[test]
+    fn unknown_commands_are_rejected() {
+        assert_err!(parse(""));
+        assert_err!(parse("users"));
+        assert_err!(parse("migrate now"));
+    }
+}
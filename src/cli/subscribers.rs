@@ -0,0 +1,191 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::{SubscriberEmail, SubscriberName},
+    utils::csv_field,
+};
+
+pub struct ImportReport {
+    pub imported: usize,
+    /// Rows whose email address is already subscribed.
+    pub skipped: usize,
+}
+
+/// Add the subscribers of `csv`, with a header row, as confirmed subscribers.
+/// Nothing is imported if any row is invalid.
+pub async fn import(csv: &str, pool: &PgPool) -> Result<ImportReport, anyhow::Error> {
+    let mut subscribers = Vec::new();
+    for (index, line) in csv.lines().enumerate().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row = index + 1;
+        let fields = parse_csv_line(line).with_context(|| format!("Invalid row {}.", row))?;
+        let [email, name] = fields.as_slice() else {
+            anyhow::bail!("Row {} should have an email and a name.", row);
+        };
+        let email = SubscriberEmail::parse(email.clone())
+            .map_err(|e| anyhow::anyhow!("Row {}: {}", row, e))?;
+        let name = SubscriberName::parse(name.clone())
+            .map_err(|e| anyhow::anyhow!("Row {}: {}", row, e))?;
+        subscribers.push((email, name));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let mut imported = 0;
+    for (email, name) in &subscribers {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, $2, $3, $4, 'confirmed')
+            ON CONFLICT (email) DO NOTHING
+            "#,
+            Uuid::new_v4(),
+            email.as_ref(),
+            name.as_ref(),
+            Utc::now(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to store an imported subscriber.")?;
+        imported += result.rows_affected() as usize;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import subscribers.")?;
+    Ok(ImportReport {
+        imported,
+        skipped: subscribers.len() - imported,
+    })
+}
+
+/// Every subscriber, oldest first, as CSV with a header row.
+pub async fn export(pool: &PgPool) -> Result<String, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT email, name, status, subscribed_at
+        FROM subscriptions
+        ORDER BY subscribed_at, id
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscribers.")?;
+    let mut csv = String::from("email,name,status,subscribed_at\n");
+    for row in rows {
+        writeln!(
+            csv,
+            "{},{},{},{}",
+            csv_field(&row.email),
+            csv_field(&row.name),
+            csv_field(&row.status),
+            row.subscribed_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    Ok(csv)
+}
+
+/// Split a line of CSV into its fields. Quoted fields may contain commas and
+/// doubled quotes, but not line breaks.
+fn parse_csv_line(line: &str) -> Result<Vec<String>, anyhow::Error> {
+    let mut fields = Vec::new();
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => anyhow::bail!("A quoted field is not closed."),
+                }
+            }
+            match chars.next() {
+                Some(',') => {}
+                None => {
+                    fields.push(field);
+                    return Ok(fields);
+                }
+                Some(c) => anyhow::bail!("Unexpected {:?} after a quoted field.", c),
+            }
+        } else {
+            loop {
+                match chars.next() {
+                    Some(',') => break,
+                    Some(c) => field.push(c),
+                    None => {
+                        fields.push(field);
+                        return Ok(fields);
+                    }
+                }
+            }
+        }
+        fields.push(field);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use super::parse_csv_line;
+    use crate::utils::csv_field;
+
+    #[test]
+    fn plain_fields_are_split_on_commas() {
+        assert_ok_eq!(
+            parse_csv_line("ursula@example.com,Ursula Le Guin"),
+            vec!["ursula@example.com".to_string(), "Ursula Le Guin".into()]
+        );
+    }
+
+    #[test]
+    fn quoted_fields_may_contain_commas_and_quotes() {
+        assert_ok_eq!(
+            parse_csv_line(r#"a@example.com,"Le Guin, ""Ursula""""#),
+            vec!["a@example.com".to_string(), r#"Le Guin, "Ursula""#.into()]
+        );
+    }
+
+    #[test]
+    fn unterminated_quotes_are_rejected() {
+        assert_err!(parse_csv_line(r#"a@example.com,"Ursula"#));
+    }
+
+    #[test]
+    fn exported_fields_can_be_read_back() {
+        let name = r#"Le Guin, "Ursula""#;
+        let line = format!("a@example.com,{}", csv_field(name));
+        assert_ok_eq!(
+            parse_csv_line(&line),
+            vec!["a@example.com".to_string(), name.into()]
+        );
+    }
+
+    #[test]
+    fn exported_formulas_are_not_run_by_spreadsheets() {
+        assert_eq!(
+            csv_field("=HYPERLINK(\"http://x\")"),
+            r#""'=HYPERLINK(""http://x"")""#
+        );
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-1"), "'-1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("Ursula"), "Ursula");
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::Context;
+use secrecy::SecretString;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_entry, AuditAction},
+    authentication::{change_password, create_user, PasswordHashing},
+    configuration::Settings,
+    domain::{NewPassword, SubscriberEmail, UserRole, Username},
+    session_store::SessionBackend,
+    user_sessions::end_user_sessions,
+};
+
+/// Entries of the audit log made from the command line have no actor.
+const AUDIT_DETAILS: &str = "command line";
+
+pub async fn create(
+    username: &str,
+    email: &str,
+    role: UserRole,
+    password: SecretString,
+    configuration: &Settings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let username = Username::parse(username.to_string()).map_err(anyhow::Error::msg)?;
+    let email = SubscriberEmail::parse(email.to_string()).map_err(anyhow::Error::msg)?;
+    let password = NewPassword::parse(password, &configuration.password.policy())
+        .map_err(anyhow::Error::msg)?;
+    let hashing = password_hashing(configuration)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let user_id = create_user(
+        &mut transaction,
+        &username,
+        email.as_ref(),
+        role,
+        password,
+        &hashing,
+    )
+    .await?;
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::UserCreated,
+        None,
+        Some(&format!("user:{}", user_id)),
+        None,
+        Some(AUDIT_DETAILS),
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create a user.")?;
+    Ok(())
+}
+
+/// Whoever knew the old password is logged out.
+pub async fn reset_password(
+    username: &str,
+    password: SecretString,
+    configuration: &Settings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let user_id = find_user(username, pool).await?;
+    let password = NewPassword::parse(password, &configuration.password.policy())
+        .map_err(anyhow::Error::msg)?;
+    let hashing = password_hashing(configuration)?;
+    change_password(user_id, password, &hashing, pool).await?;
+    record_audit_entry(
+        pool,
+        AuditAction::PasswordReset,
+        None,
+        Some(&format!("user:{}", user_id)),
+        None,
+        Some(AUDIT_DETAILS),
+    )
+    .await?;
+    log_out_everywhere(user_id, configuration, pool).await
+}
+
+/// The user is logged out everywhere, as when an owner deactivates them from
+/// the admin pages.
+pub async fn disable(
+    username: &str,
+    configuration: &Settings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let user_id = find_user(username, pool).await?;
+    sqlx::query!(
+        r#"UPDATE users SET is_active = FALSE WHERE user_id = $1"#,
+        user_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to deactivate the user in the database.")?;
+    record_audit_entry(
+        pool,
+        AuditAction::UserDeactivated,
+        None,
+        Some(&format!("user:{}", user_id)),
+        None,
+        Some(AUDIT_DETAILS),
+    )
+    .await?;
+    log_out_everywhere(user_id, configuration, pool).await
+}
+
+/// End the sessions of the user in the store of the server.
+async fn log_out_everywhere(
+    user_id: Uuid,
+    configuration: &Settings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let store =
+        SessionBackend::connect(configuration.session.store, &configuration.redis_uri, pool)
+            .await?;
+    end_user_sessions(user_id, None, &store, pool).await
+}
+
+async fn find_user(username: &str, pool: &PgPool) -> Result<Uuid, anyhow::Error> {
+    sqlx::query_scalar!(r#"SELECT user_id FROM users WHERE username = $1"#, username,)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to look up the user.")?
+        .with_context(|| format!("There is no user named {}.", username))
+}
+
+fn password_hashing(configuration: &Settings) -> Result<PasswordHashing, anyhow::Error> {
+    let params = configuration
+        .password
+        .argon2
+        .params()
+        .context("Invalid Argon2 parameters.")?;
+    PasswordHashing::new(params)
+}
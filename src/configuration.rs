@@ -1,24 +1,138 @@
+use chrono::TimeDelta;
 use secrecy::{ExposeSecret, SecretString};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::{
     postgres::{PgConnectOptions, PgSslMode},
     ConnectOptions,
 };
+use tower_sessions::cookie::SameSite;
+use tower_sessions_redis_store::fred::prelude::Config as RedisConfig;
 
-use crate::domain::SubscriberEmail;
+use crate::{
+    domain::{NewPassword, PasswordPolicy, SubscriberEmail, MIN_PASSWORD_LENGTH},
+    routes::error_chain_fmt,
+    user_sessions::SessionLifetime,
+};
 
-#[derive(Clone, serde::Deserialize)]
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    #[serde(serialize_with = "redact")]
     pub redis_uri: SecretString,
+    pub login_throttle: LoginThrottleSettings,
+    pub password: PasswordSettings,
+    pub session: SessionSettings,
+    pub metrics: MetricsSettings,
+    pub telemetry: TelemetrySettings,
+    pub health: HealthSettings,
+    pub webhooks: WebhookSettings,
+    pub issue_deliveries: IssueDeliverySettings,
+    /// The first owner, created at startup if no user can log in yet.
+    /// Without it, the owner is created through the `/setup` page.
+    pub initial_admin: Option<InitialAdminSettings>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct InitialAdminSettings {
+    pub username: String,
+    pub email: String,
+    #[serde(serialize_with = "redact")]
+    pub password: SecretString,
+}
+
+/// The delivery of outbound webhooks.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct WebhookSettings {
+    /// Background tasks sending the queued deliveries. With `0`, deliveries
+    /// stay queued.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub workers: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    /// A delivery is given up on after this many failed attempts.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: u32,
+    /// Doubled after every further failed attempt.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub first_retry_delay_seconds: u64,
+    /// How often workers look for new deliveries while the queue is empty.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_milliseconds: u64,
+    /// Whether endpoints may be on this host or on a private network. Only
+    /// for local development: otherwise admins could probe internal hosts.
+    pub allow_private_addresses: bool,
+}
+
+impl WebhookSettings {
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_milliseconds)
+    }
+
+    pub fn first_retry_delay(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.first_retry_delay_seconds)
+    }
+
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval_milliseconds)
+    }
+}
+
+/// The sending of published issues to their recipients.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct IssueDeliverySettings {
+    /// Background tasks sending the published issues. With `0`, recipients
+    /// stay pending.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub workers: u32,
+    /// How often workers look for pending recipients while there are none.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_milliseconds: u64,
+}
+
+impl IssueDeliverySettings {
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval_milliseconds)
+    }
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct HealthSettings {
+    /// Whether readiness also checks the settings of the email API.
+    pub check_email: bool,
+}
+
+/// The export of spans to an OpenTelemetry collector, next to the logs.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct TelemetrySettings {
+    pub otlp_enabled: bool,
+    /// The OTLP/HTTP traces endpoint of the collector.
+    pub otlp_endpoint: String,
+    pub service_name: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+}
+
+impl TelemetrySettings {
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
+/// Where Prometheus scrapes `/metrics`, away from the public port.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct MetricsSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
 }
 
-#[derive(Clone, serde::Deserialize)]
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub struct EmailClientSettings {
     pub base_url: String,
     pub sender_email: String,
+    #[serde(serialize_with = "redact")]
     pub authorization_token: SecretString,
     pub timeout_milliseconds: u64,
 }
@@ -33,9 +147,136 @@ impl EmailClientSettings {
     }
 }
 
-#[derive(Clone, serde::Deserialize)]
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct LoginThrottleSettings {
+    /// Prefix of the Redis keys holding the counters of failed attempts.
+    pub key_prefix: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_username: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_ip: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_seconds: u64,
+    /// Added to the delay of every attempt for each recent failure.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub delay_step_milliseconds: u64,
+}
+
+impl LoginThrottleSettings {
+    pub fn lockout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.lockout_seconds)
+    }
+
+    pub fn delay_step(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.delay_step_milliseconds)
+    }
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct PasswordSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_length: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_length: usize,
+    pub argon2: Argon2Settings,
+}
+
+impl PasswordSettings {
+    pub fn policy(&self) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: self.min_length,
+            max_length: self.max_length,
+        }
+    }
+}
+
+/// Parameters of the Argon2id hashes of new passwords. Existing hashes are
+/// upgraded when their owner logs in.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct Argon2Settings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+impl Argon2Settings {
+    pub fn params(&self) -> Result<argon2::Params, argon2::Error> {
+        argon2::Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+    }
+}
+
+/// How long users stay logged in, and the attributes of the session cookie.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct SessionSettings {
+    pub store: SessionStoreKind,
+    pub cookie_name: String,
+    /// Leave unset to only send the cookie to the host that set it.
+    pub domain: Option<String>,
+    /// Only send the cookie over HTTPS.
+    pub secure: bool,
+    pub same_site: SameSiteSetting,
+    /// Users are logged out after this long without sending a request.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idle_timeout_minutes: u32,
+    /// Users are logged out this long after logging in, however active.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub absolute_timeout_hours: u32,
+    /// How long "Remember me" keeps users logged in, across browser restarts
+    /// and idle periods. `0` removes the option from the login form.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub remember_me_days: u32,
+}
+
+impl SessionSettings {
+    pub fn idle_timeout(&self) -> time::Duration {
+        time::Duration::minutes(self.idle_timeout_minutes.into())
+    }
+
+    pub fn lifetime(&self) -> SessionLifetime {
+        SessionLifetime {
+            absolute: TimeDelta::hours(self.absolute_timeout_hours.into()),
+            remember_me: (self.remember_me_days > 0)
+                .then(|| TimeDelta::days(self.remember_me_days.into())),
+        }
+    }
+}
+
+/// Where sessions, and the counters of failed logins, are kept.
+#[derive(Clone, Copy, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    Redis,
+    /// In the application database, for deployments without Redis.
+    Postgres,
+    /// In the memory of the process, for tests and development.
+    Memory,
+}
+
+#[derive(Clone, Copy, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSiteSetting {
+    Strict,
+    Lax,
+    None,
+}
+
+impl From<SameSiteSetting> for SameSite {
+    fn from(setting: SameSiteSetting) -> Self {
+        match setting {
+            SameSiteSetting::Strict => SameSite::Strict,
+            SameSiteSetting::Lax => SameSite::Lax,
+            SameSiteSetting::None => SameSite::None,
+        }
+    }
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub struct DatabaseSettings {
     pub username: String,
+    #[serde(serialize_with = "redact")]
     pub password: SecretString,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
@@ -44,27 +285,52 @@ pub struct DatabaseSettings {
     pub require_ssl: bool,
 }
 
-#[derive(Clone, serde::Deserialize)]
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    #[serde(serialize_with = "redact")]
     pub hmac_secret: SecretString,
+    /// How long requests in flight and background tasks are given to finish
+    /// once a shutdown has been asked for.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_timeout_seconds: u64,
+}
+
+impl ApplicationSettings {
+    pub fn shutdown_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_timeout_seconds)
+    }
 }
 
-pub fn get_configuration() -> Result<Settings, config::ConfigError> {
+/// Load the settings of the environment named by `APP_ENVIRONMENT`, and
+/// check them. See [`load_configuration`].
+pub fn get_configuration() -> Result<Settings, ConfigurationError> {
+    let settings = load_configuration()?;
+    settings.validate()?;
+    Ok(settings)
+}
+
+/// Load the settings of the environment named by `APP_ENVIRONMENT` (`local`
+/// if unset), without checking them.
+///
+/// `configuration/base.yaml` is overlaid with `configuration/<environment>.yaml`,
+/// then with `APP_`-prefixed environment variables, `__` separating the
+/// levels (e.g. `APP_DATABASE__PASSWORD`). A variable suffixed with `_FILE`
+/// names a file holding the value instead, as Docker and Kubernetes mount
+/// secrets (e.g. `APP_DATABASE__PASSWORD_FILE=/run/secrets/db_password`).
+pub fn load_configuration() -> Result<Settings, ConfigurationError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
     let configuration_directory = base_path.join("configuration");
 
-    // Detect the running environment
-    // Default to `local` if unspecified
     let environment: Environment = std::env::var("APP_ENVIRONMENT")
         .unwrap_or_else(|_| "local".into())
         .try_into()
-        .expect("Failed to parse APP_ENVIRONMENT");
+        .map_err(ConfigurationError::Environment)?;
     let environment_filename = format!("{}.yaml", environment.as_str());
-    let settings = config::Config::builder()
+    let mut builder = config::Config::builder()
         .add_source(config::File::from(
             configuration_directory.join("base.yaml"),
         ))
@@ -75,24 +341,48 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
             config::Environment::with_prefix("APP")
                 .prefix_separator("_")
                 .separator("__"),
-        )
-        .build()?;
+        );
+    for (key, value) in read_secret_files(std::env::vars().collect())? {
+        builder = builder.set_override(key, value)?;
+    }
 
-    settings.try_deserialize::<Settings>()
+    Ok(builder.build()?.try_deserialize::<Settings>()?)
 }
 
-/// The possible runtime environment for our application
-pub enum Environment {
-    Local,
-    Production,
+/// The settings given by `APP_*_FILE` variables, read from their files.
+fn read_secret_files(
+    variables: Vec<(String, String)>,
+) -> Result<Vec<(String, String)>, ConfigurationError> {
+    let mut secrets = Vec::new();
+    for (variable, path) in &variables {
+        let Some(name) = variable
+            .strip_prefix("APP_")
+            .and_then(|name| name.strip_suffix("_FILE"))
+        else {
+            continue;
+        };
+        let plain = format!("APP_{}", name);
+        if variables.iter().any(|(other, _)| *other == plain) {
+            return Err(ConfigurationError::SecretConflict(plain));
+        }
+        let value =
+            std::fs::read_to_string(path).map_err(|source| ConfigurationError::SecretFile {
+                variable: variable.clone(),
+                source,
+            })?;
+        let key = name.to_lowercase().replace("__", ".");
+        secrets.push((key, value.trim_end_matches(['\r', '\n']).to_string()));
+    }
+    Ok(secrets)
 }
 
+/// The runtime environment, named after its file in `configuration/`, such
+/// as `local`, `staging` or `production`.
+pub struct Environment(String);
+
 impl Environment {
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Environment::Local => "local",
-            Environment::Production => "production",
-        }
+    pub fn as_str(&self) -> &str {
+        &self.0
     }
 }
 
@@ -100,16 +390,229 @@ impl TryFrom<String> for Environment {
     type Error = String;
 
     fn try_from(s: String) -> Result<Self, Self::Error> {
-        match s.to_lowercase().as_str() {
-            "local" => Ok(Self::Local),
-            "production" => Ok(Self::Production),
-            other => Err(format!(
-                "{} is not a supported environment. Use either `local` or `production`.",
-                other
-            )),
+        let name = s.to_lowercase();
+        let valid = !name.is_empty()
+            && name != "base"
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if valid {
+            Ok(Self(name))
+        } else {
+            Err(format!(
+                "{} is not a valid environment. Name a file of the configuration directory, such as `local` or `production`.",
+                s
+            ))
         }
     }
 }
+
+#[derive(thiserror::Error)]
+pub enum ConfigurationError {
+    #[error("{0}")]
+    Environment(String),
+    #[error("Failed to read the secret file named by {variable}.")]
+    SecretFile {
+        variable: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Both {0} and {0}_FILE are set. Use only one of them.")]
+    SecretConflict(String),
+    #[error("Failed to load the configuration.")]
+    Load(#[from] config::ConfigError),
+    #[error("The configuration is invalid:{}", bullet_list(.0))]
+    Invalid(Vec<String>),
+}
+
+fn bullet_list(problems: &[String]) -> String {
+    problems
+        .iter()
+        .map(|problem| format!("\n  - {}", problem))
+        .collect()
+}
+
+impl std::fmt::Debug for ConfigurationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// The shortest HMAC secret accepted.
+const MIN_SECRET_LENGTH: usize = 32;
+
+impl Settings {
+    /// Check every setting, reporting all the problems found at once rather
+    /// than failing on the first one used.
+    pub fn validate(&self) -> Result<(), ConfigurationError> {
+        let mut problems = Vec::new();
+        let mut require = |valid: bool, problem: String| {
+            if !valid {
+                problems.push(problem);
+            }
+        };
+
+        let application = &self.application;
+        require(
+            is_http_url(&application.base_url),
+            format!(
+                "application.base_url: {} is not an HTTP(S) URL.",
+                application.base_url
+            ),
+        );
+        require(
+            application.hmac_secret.expose_secret().len() >= MIN_SECRET_LENGTH,
+            format!(
+                "application.hmac_secret: use at least {} characters.",
+                MIN_SECRET_LENGTH
+            ),
+        );
+
+        let email_client = &self.email_client;
+        if let Err(e) = email_client.sender() {
+            require(false, format!("email_client.sender_email: {}", e));
+        }
+        require(
+            is_http_url(&email_client.base_url),
+            format!(
+                "email_client.base_url: {} is not an HTTP(S) URL.",
+                email_client.base_url
+            ),
+        );
+        require(
+            email_client.timeout_milliseconds > 0,
+            "email_client.timeout_milliseconds: must be above 0.".into(),
+        );
+
+        require(
+            !self.database.host.is_empty() && !self.database.database_name.is_empty(),
+            "database: host and database_name cannot be empty.".into(),
+        );
+        if let SessionStoreKind::Redis = self.session.store {
+            require(
+                RedisConfig::from_url(self.redis_uri.expose_secret()).is_ok(),
+                "redis_uri: not a valid Redis URL.".into(),
+            );
+        }
+
+        let throttle = &self.login_throttle;
+        require(
+            throttle.max_failures_per_username > 0 && throttle.max_failures_per_ip > 0,
+            "login_throttle: the maximum numbers of failures must be above 0.".into(),
+        );
+
+        let password = &self.password;
+        require(
+            password.min_length >= MIN_PASSWORD_LENGTH
+                && password.min_length <= password.max_length,
+            format!(
+                "password: min_length ({}) must be at least {} and at most max_length ({}).",
+                password.min_length, MIN_PASSWORD_LENGTH, password.max_length
+            ),
+        );
+        if let Err(e) = password.argon2.params() {
+            require(false, format!("password.argon2: {}", e));
+        }
+
+        let session = &self.session;
+        require(
+            !session.cookie_name.is_empty(),
+            "session.cookie_name: cannot be empty.".into(),
+        );
+        require(
+            session.idle_timeout_minutes > 0 && session.absolute_timeout_hours > 0,
+            "session: the idle and absolute timeouts must be above 0.".into(),
+        );
+        if session.cookie_name.starts_with("__Host-") {
+            require(
+                session.secure && session.domain.is_none(),
+                "session: a `__Host-` cookie must be secure and have no domain.".into(),
+            );
+        }
+        if session.cookie_name.starts_with("__Secure-") {
+            require(
+                session.secure,
+                "session: a `__Secure-` cookie must be secure.".into(),
+            );
+        }
+        if let SameSiteSetting::None = session.same_site {
+            require(
+                session.secure,
+                "session: same_site `none` requires a secure cookie.".into(),
+            );
+        }
+
+        require(
+            self.metrics.port == 0 || self.metrics.port != application.port,
+            format!(
+                "metrics.port: {} is the port of the application already.",
+                self.metrics.port
+            ),
+        );
+
+        let telemetry = &self.telemetry;
+        if telemetry.otlp_enabled {
+            require(
+                is_http_url(&telemetry.otlp_endpoint),
+                format!(
+                    "telemetry.otlp_endpoint: {} is not an HTTP(S) URL.",
+                    telemetry.otlp_endpoint
+                ),
+            );
+            require(
+                !telemetry.service_name.is_empty(),
+                "telemetry.service_name: cannot be empty.".into(),
+            );
+        }
+
+        let webhooks = &self.webhooks;
+        require(
+            webhooks.timeout_milliseconds > 0 && webhooks.poll_interval_milliseconds > 0,
+            "webhooks: the timeout and the poll interval must be above 0.".into(),
+        );
+        require(
+            webhooks.max_attempts > 0,
+            "webhooks.max_attempts: must be above 0.".into(),
+        );
+        require(
+            self.issue_deliveries.poll_interval_milliseconds > 0,
+            "issue_deliveries.poll_interval_milliseconds: must be above 0.".into(),
+        );
+
+        if let Some(admin) = &self.initial_admin {
+            require(
+                !admin.username.trim().is_empty(),
+                "initial_admin.username: cannot be empty.".into(),
+            );
+            if let Err(e) = SubscriberEmail::parse(admin.email.clone()) {
+                require(false, format!("initial_admin.email: {}", e));
+            }
+            if let Err(e) = NewPassword::parse(admin.password.clone(), &password.policy()) {
+                require(false, format!("initial_admin.password: {}", e));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigurationError::Invalid(problems))
+        }
+    }
+
+    /// The settings as pretty-printed JSON, with every secret redacted.
+    pub fn to_redacted_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+fn redact<S: serde::Serializer>(_secret: &SecretString, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("[REDACTED]")
+}
+
 impl DatabaseSettings {
     pub fn with_db(&self) -> PgConnectOptions {
         self.without_db()
@@ -131,3 +634,170 @@ impl DatabaseSettings {
             .ssl_mode(ssl_mode)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::*;
+
+    fn settings(overrides: &[(&str, &str)]) -> Settings {
+        let mut builder = config::Config::builder()
+            .add_source(config::File::from_str(
+                include_str!("../configuration/base.yaml"),
+                config::FileFormat::Yaml,
+            ))
+            .add_source(config::File::from_str(
+                include_str!("../configuration/local.yaml"),
+                config::FileFormat::Yaml,
+            ));
+        for (key, value) in overrides {
+            builder = builder.set_override(*key, *value).unwrap();
+        }
+        builder.build().unwrap().try_deserialize().unwrap()
+    }
+
+    fn problems(settings: &Settings) -> Vec<String> {
+        match settings.validate() {
+            Err(ConfigurationError::Invalid(problems)) => problems,
+            other => panic!("Expected problems, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn any_environment_with_a_plain_name_is_accepted() {
+        for name in ["local", "production", "Staging", "test", "eu-west_2"] {
+            let environment: Environment = assert_ok!(name.to_string().try_into());
+            assert_eq!(environment.as_str(), name.to_lowercase());
+        }
+    }
+
+    #[test]
+    fn environments_that_are_not_plain_names_are_rejected() {
+        for name in ["", "base", "../secrets", "prod.yaml"] {
+            assert_err!(Environment::try_from(name.to_string()));
+        }
+    }
+
+    #[test]
+    fn the_shipped_configuration_is_valid() {
+        assert_ok!(settings(&[]).validate());
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let settings = settings(&[
+            ("application.hmac_secret", "short"),
+            ("email_client.sender_email", "not-an-email"),
+            ("email_client.base_url", "localhost"),
+            ("password.min_length", "200"),
+            ("metrics.port", "8000"),
+        ]);
+
+        let problems = problems(&settings);
+
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        for setting in [
+            "application.hmac_secret",
+            "email_client.sender_email",
+            "email_client.base_url",
+            "password:",
+            "metrics.port",
+        ] {
+            assert!(
+                problems.iter().any(|p| p.starts_with(setting)),
+                "{} not reported in {:?}",
+                setting,
+                problems
+            );
+        }
+    }
+
+    #[test]
+    fn host_prefixed_cookies_must_be_secure() {
+        let settings = settings(&[("session.cookie_name", "__Host-session_id")]);
+
+        let problems = problems(&settings);
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("__Host-"));
+    }
+
+    #[test]
+    fn an_invalid_initial_admin_is_reported() {
+        let settings = settings(&[
+            ("initial_admin.username", "admin"),
+            ("initial_admin.email", "admin"),
+            ("initial_admin.password", "short"),
+        ]);
+
+        let problems = problems(&settings);
+
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+    }
+
+    #[test]
+    fn secrets_are_redacted_when_printed() {
+        let json = settings(&[]).to_redacted_json().unwrap();
+
+        assert!(json.contains("[REDACTED]"));
+        for secret in [
+            "password",
+            "my-secret-token",
+            "long-and-very-secret",
+            "redis://",
+        ] {
+            assert!(
+                !json.contains(&format!(": \"{}", secret)),
+                "{} leaked",
+                secret
+            );
+        }
+    }
+
+    #[test]
+    fn secret_files_set_the_setting_they_are_named_after() {
+        let file = std::env::temp_dir().join(format!("secret-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&file, "hunter2\n").unwrap();
+        let variables = vec![
+            (
+                "APP_DATABASE__PASSWORD_FILE".to_string(),
+                file.display().to_string(),
+            ),
+            ("APP_APPLICATION__PORT".to_string(), "8000".to_string()),
+        ];
+
+        let secrets = read_secret_files(variables);
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(
+            assert_ok!(secrets),
+            vec![("database.password".to_string(), "hunter2".to_string())]
+        );
+    }
+
+    #[test]
+    fn a_setting_cannot_be_given_both_directly_and_by_file() {
+        let variables = vec![
+            (
+                "APP_DATABASE__PASSWORD_FILE".to_string(),
+                "/run/secrets/db".to_string(),
+            ),
+            ("APP_DATABASE__PASSWORD".to_string(), "password".to_string()),
+        ];
+
+        let error = assert_err!(read_secret_files(variables));
+
+        assert!(matches!(error, ConfigurationError::SecretConflict(_)));
+    }
+
+    #[test]
+    fn a_missing_secret_file_is_reported() {
+        let variables = vec![(
+            "APP_DATABASE__PASSWORD_FILE".to_string(),
+            "/nonexistent/secret".to_string(),
+        )];
+
+        assert_err!(read_secret_files(variables));
+    }
+}
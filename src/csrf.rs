@@ -0,0 +1,162 @@
+use axum::{
+    body::Body,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{request::Parts, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Form,
+};
+use rand::Rng;
+
+use crate::{session_state::TypedSession, utils::e500};
+
+/// Name of the hidden form field carrying the token.
+pub const CSRF_FIELD: &str = "csrf_token";
+/// Header carrying the token for requests that are not sent by a form.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+const TOKEN_LENGTH: usize = 40;
+/// Forms are small: there is no reason to buffer more than this to look for
+/// the token.
+const MAX_FORM_SIZE: usize = 1024 * 1024;
+
+/// The CSRF token of the current session, created on first use. Every HTML
+/// form posting back to the application must embed it with
+/// [`CsrfToken::form_field`].
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn form_field(&self) -> String {
+        format!(
+            r#"<input type="hidden" name="{}" value="{}">"#,
+            CSRF_FIELD, self.0
+        )
+    }
+}
+
+impl<S> FromRequestParts<S> for CsrfToken
+where
+    S: Sync + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let session = TypedSession::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        if let Some(token) = session.get_csrf_token().await.map_err(e500)? {
+            return Ok(Self(token));
+        }
+        let token = generate_csrf_token();
+        session.insert_csrf_token(&token).await.map_err(e500)?;
+        Ok(Self(token))
+    }
+}
+
+/// Reject state-changing requests that do not carry the CSRF token of their
+/// session, either in the `csrf_token` form field or in the `X-CSRF-Token`
+/// header.
+pub async fn verify_csrf_token(session: TypedSession, request: Request, next: Next) -> Response {
+    if request.method().is_safe() {
+        return next.run(request).await;
+    }
+    let expected = match session.get_csrf_token().await {
+        Ok(Some(token)) => token,
+        Ok(None) => return reject(),
+        Err(e) => return e500(e),
+    };
+
+    let from_header = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    let (submitted, request) = match from_header {
+        Some(token) => (Some(token), request),
+        None => match read_form_token(request).await {
+            Ok(result) => result,
+            Err(response) => return response,
+        },
+    };
+
+    match submitted {
+        Some(token) if tokens_match(&token, &expected) => next.run(request).await,
+        _ => {
+            tracing::warn!("Rejected a request without a valid CSRF token.");
+            reject()
+        }
+    }
+}
+
+/// Look for the token in a form body. The body is buffered and handed back in
+/// a new request, so that the handler can still read it.
+async fn read_form_token(request: Request) -> Result<(Option<String>, Request), Response> {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, MAX_FORM_SIZE)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE.into_response())?;
+    let form_request = Request::from_parts(parts.clone(), Body::from(bytes.clone()));
+    let token = Form::<Vec<(String, String)>>::from_request(form_request, &())
+        .await
+        .ok()
+        .and_then(|Form(fields)| {
+            fields
+                .into_iter()
+                .find(|(key, _)| key == CSRF_FIELD)
+                .map(|(_, value)| value)
+        });
+    Ok((token, Request::from_parts(parts, Body::from(bytes))))
+}
+
+fn reject() -> Response {
+    (
+        StatusCode::FORBIDDEN,
+        "The form has expired or did not come from this site. Reload the page and try again.",
+    )
+        .into_response()
+}
+
+fn generate_csrf_token() -> String {
+    rand::rng()
+        .sample_iter(rand::distr::Alphanumeric)
+        .map(char::from)
+        .take(TOKEN_LENGTH)
+        .collect()
+}
+
+/// Compare in constant time, so that the token cannot be guessed byte by
+/// byte from response times.
+pub(crate) fn tokens_match(submitted: &str, expected: &str) -> bool {
+    submitted.len() == expected.len()
+        && submitted
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_csrf_token, tokens_match};
+
+    #[test]
+    fn identical_tokens_match() {
+        let token = generate_csrf_token();
+        assert!(tokens_match(&token, &token.clone()));
+    }
+
+    #[test]
+    fn different_tokens_do_not_match() {
+        assert!(!tokens_match(
+            &generate_csrf_token(),
+            &generate_csrf_token()
+        ));
+    }
+
+    #[test]
+    fn a_prefix_of_the_token_does_not_match() {
+        let token = generate_csrf_token();
+        assert!(!tokens_match(&token[..10], &token));
+        assert!(!tokens_match("", &token));
+    }
+}
@@ -0,0 +1,85 @@
+use crate::domain::Permission;
+
+/// What an API token may be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    /// Publish newsletter issues.
+    Publish,
+    /// Read the subscriber list.
+    SubscribersRead,
+    /// Add, edit and remove subscribers.
+    SubscribersWrite,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 3] = [
+        ApiScope::Publish,
+        ApiScope::SubscribersRead,
+        ApiScope::SubscribersWrite,
+    ];
+
+    pub fn parse(s: String) -> Result<ApiScope, String> {
+        match s.as_str() {
+            "publish" => Ok(Self::Publish),
+            "subscribers:read" => Ok(Self::SubscribersRead),
+            "subscribers:write" => Ok(Self::SubscribersWrite),
+            other => Err(format!("{} is not a valid scope.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::Publish => "publish",
+            ApiScope::SubscribersRead => "subscribers:read",
+            ApiScope::SubscribersWrite => "subscribers:write",
+        }
+    }
+
+    /// The permission the token owner needs for the scope to be granted:
+    /// a token can never do more than its owner.
+    pub fn permission(&self) -> Permission {
+        match self {
+            ApiScope::Publish => Permission::PublishNewsletter,
+            ApiScope::SubscribersRead => Permission::ViewSubscribers,
+            ApiScope::SubscribersWrite => Permission::EditSubscribers,
+        }
+    }
+}
+
+impl AsRef<str> for ApiScope {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl std::fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{ApiScope, Permission};
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn every_scope_round_trips_through_its_string_form() {
+        for scope in ApiScope::ALL {
+            assert_ok_eq!(ApiScope::parse(scope.as_str().to_string()), scope);
+        }
+    }
+
+    #[test]
+    fn unknown_scope_is_rejected() {
+        assert_err!(ApiScope::parse("subscribers:delete".to_string()));
+    }
+
+    #[test]
+    fn publishing_requires_the_publish_permission() {
+        assert_eq!(
+            ApiScope::Publish.permission(),
+            Permission::PublishNewsletter
+        );
+    }
+}
@@ -1,7 +1,17 @@
+mod api_scope;
+mod new_password;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_status;
+mod user_role;
+mod username;
 
+pub use api_scope::ApiScope;
+pub use new_password::{NewPassword, PasswordPolicy, MIN_PASSWORD_LENGTH};
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_status::SubscriberStatus;
+pub use user_role::{Permission, UserRole};
+pub use username::Username;
@@ -0,0 +1,122 @@
+use std::{collections::HashSet, sync::LazyLock};
+
+use secrecy::{ExposeSecret, SecretString};
+
+/// The shortest minimum length a policy can have: the list of common
+/// passwords only holds passwords of at least this length.
+pub const MIN_PASSWORD_LENGTH: usize = 12;
+
+static COMMON_PASSWORDS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    include_str!("common_passwords.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+});
+
+/// The rules a password has to follow when it is set.
+#[derive(Clone, Debug)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+}
+
+/// A password that follows the password policy and can be stored.
+#[derive(Debug)]
+pub struct NewPassword(SecretString);
+
+impl NewPassword {
+    pub fn parse(password: SecretString, policy: &PasswordPolicy) -> Result<NewPassword, String> {
+        let candidate = password.expose_secret();
+        let length = candidate.chars().count();
+        if length < policy.min_length {
+            return Err(format!(
+                "The password must be at least {} characters long.",
+                policy.min_length
+            ));
+        }
+        if length > policy.max_length {
+            return Err(format!(
+                "The password must be at most {} characters long.",
+                policy.max_length
+            ));
+        }
+        if COMMON_PASSWORDS.contains(candidate.to_lowercase().as_str()) {
+            return Err("This password is too common. Choose one that is harder to guess.".into());
+        }
+        Ok(Self(password))
+    }
+
+    pub fn into_secret(self) -> SecretString {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use secrecy::SecretString;
+
+    use super::{NewPassword, PasswordPolicy};
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 12,
+            max_length: 128,
+        }
+    }
+
+    fn parse(password: &str) -> Result<NewPassword, String> {
+        NewPassword::parse(SecretString::new(password.into()), &policy())
+    }
+
+    #[test]
+    fn an_empty_password_is_rejected() {
+        assert_err!(parse(""));
+    }
+
+    #[test]
+    fn a_password_shorter_than_the_minimum_is_rejected() {
+        assert_err!(parse("short-pass1"));
+    }
+
+    #[test]
+    fn the_length_is_counted_in_characters() {
+        assert_ok!(parse(&"ё".repeat(12)));
+        assert_err!(parse(&"ё".repeat(129)));
+    }
+
+    #[test]
+    fn a_password_longer_than_the_maximum_is_rejected() {
+        assert_err!(parse(&"a-long-password".repeat(10)));
+    }
+
+    #[test]
+    fn common_passwords_are_rejected_whatever_their_case() {
+        assert_err!(parse("password1234"));
+        assert_err!(parse("PassWord1234"));
+    }
+
+    #[test]
+    fn breached_passwords_are_rejected() {
+        assert_err!(parse("qwertyuiop123"));
+        assert_err!(parse("1qaz2wsx3edc4rfv"));
+        assert_err!(parse("christopher1"));
+    }
+
+    #[test]
+    fn every_common_password_is_long_enough_to_be_allowed() {
+        for password in super::COMMON_PASSWORDS.iter() {
+            assert!(
+                password.chars().count() >= super::MIN_PASSWORD_LENGTH,
+                "{} is shorter than any allowed password.",
+                password
+            );
+        }
+    }
+
+    #[test]
+    fn a_valid_password_is_parsed_successfully() {
+        assert_ok!(parse("everythinghastostartsomewhere"));
+    }
+}
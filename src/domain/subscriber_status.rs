@@ -0,0 +1,71 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriberStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl SubscriberStatus {
+    pub const ALL: [SubscriberStatus; 3] = [
+        SubscriberStatus::PendingConfirmation,
+        SubscriberStatus::Confirmed,
+        SubscriberStatus::Unsubscribed,
+    ];
+
+    pub fn parse(s: String) -> Result<SubscriberStatus, String> {
+        match s.as_str() {
+            "pending_confirmation" => Ok(Self::PendingConfirmation),
+            "confirmed" => Ok(Self::Confirmed),
+            "unsubscribed" => Ok(Self::Unsubscribed),
+            other => Err(format!("{} is not a valid subscriber status.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriberStatus::PendingConfirmation => "pending_confirmation",
+            SubscriberStatus::Confirmed => "confirmed",
+            SubscriberStatus::Unsubscribed => "unsubscribed",
+        }
+    }
+}
+
+impl AsRef<str> for SubscriberStatus {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl std::fmt::Display for SubscriberStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberStatus;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn every_status_round_trips_through_its_string_form() {
+        for status in SubscriberStatus::ALL {
+            assert_ok_eq!(SubscriberStatus::parse(status.as_str().to_string()), status);
+        }
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(SubscriberStatus::parse("".to_string()));
+    }
+
+    #[test]
+    fn unknown_status_is_rejected() {
+        assert_err!(SubscriberStatus::parse("deleted".to_string()));
+    }
+
+    #[test]
+    fn status_parsing_is_case_sensitive() {
+        assert_err!(SubscriberStatus::parse("Confirmed".to_string()));
+    }
+}
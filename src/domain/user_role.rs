@@ -0,0 +1,118 @@
+/// Actions on the admin area and the publishing API that are restricted
+/// by role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    ViewSubscribers,
+    EditSubscribers,
+    PublishNewsletter,
+    ManageUsers,
+    ViewAuditLog,
+    ManageWebhooks,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserRole {
+    /// Full access, including managing other users and reading the audit
+    /// log.
+    Owner,
+    /// Can manage subscribers and publish issues.
+    Editor,
+    /// Read-only access to the admin area.
+    Viewer,
+}
+
+impl UserRole {
+    pub const ALL: [UserRole; 3] = [UserRole::Owner, UserRole::Editor, UserRole::Viewer];
+
+    pub fn parse(s: String) -> Result<UserRole, String> {
+        match s.as_str() {
+            "owner" => Ok(Self::Owner),
+            "editor" => Ok(Self::Editor),
+            "viewer" => Ok(Self::Viewer),
+            other => Err(format!("{} is not a valid role.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::Owner => "owner",
+            UserRole::Editor => "editor",
+            UserRole::Viewer => "viewer",
+        }
+    }
+
+    /// Returns `true` if users with this role are allowed to perform `permission`.
+    pub fn can(&self, permission: Permission) -> bool {
+        match self {
+            UserRole::Owner => true,
+            UserRole::Editor => !matches!(
+                permission,
+                Permission::ManageUsers | Permission::ViewAuditLog | Permission::ManageWebhooks
+            ),
+            UserRole::Viewer => permission == Permission::ViewSubscribers,
+        }
+    }
+}
+
+impl AsRef<str> for UserRole {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl std::fmt::Display for UserRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{Permission, UserRole};
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn every_role_round_trips_through_its_string_form() {
+        for role in UserRole::ALL {
+            assert_ok_eq!(UserRole::parse(role.as_str().to_string()), role);
+        }
+    }
+
+    #[test]
+    fn unknown_role_is_rejected() {
+        assert_err!(UserRole::parse("admin".to_string()));
+    }
+
+    #[test]
+    fn owners_can_do_everything() {
+        for permission in [
+            Permission::ViewSubscribers,
+            Permission::EditSubscribers,
+            Permission::PublishNewsletter,
+            Permission::ManageUsers,
+            Permission::ViewAuditLog,
+            Permission::ManageWebhooks,
+        ] {
+            assert!(UserRole::Owner.can(permission));
+        }
+    }
+
+    #[test]
+    fn editors_cannot_manage_users() {
+        assert!(UserRole::Editor.can(Permission::PublishNewsletter));
+        assert!(UserRole::Editor.can(Permission::EditSubscribers));
+        assert!(!UserRole::Editor.can(Permission::ManageUsers));
+        assert!(!UserRole::Editor.can(Permission::ViewAuditLog));
+        assert!(!UserRole::Editor.can(Permission::ManageWebhooks));
+    }
+
+    #[test]
+    fn viewers_are_read_only() {
+        assert!(UserRole::Viewer.can(Permission::ViewSubscribers));
+        assert!(!UserRole::Viewer.can(Permission::EditSubscribers));
+        assert!(!UserRole::Viewer.can(Permission::PublishNewsletter));
+        assert!(!UserRole::Viewer.can(Permission::ManageUsers));
+        assert!(!UserRole::Viewer.can(Permission::ViewAuditLog));
+        assert!(!UserRole::Viewer.can(Permission::ManageWebhooks));
+    }
+}
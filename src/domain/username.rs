@@ -0,0 +1,67 @@
+/// The name a user logs in with. It is shown across the admin area, so it is
+/// kept to characters that need no escaping anywhere.
+#[derive(Debug)]
+pub struct Username(String);
+
+const MAX_USERNAME_LENGTH: usize = 64;
+
+impl Username {
+    pub fn parse(s: String) -> Result<Username, String> {
+        let s = s.trim();
+        let is_allowed = |c: char| c.is_ascii_alphanumeric() || ['.', '_', '-'].contains(&c);
+        if s.is_empty() || s.len() > MAX_USERNAME_LENGTH || !s.chars().all(is_allowed) {
+            Err(format!(
+                "Usernames are 1 to {} letters, digits, dots, dashes or underscores.",
+                MAX_USERNAME_LENGTH
+            ))
+        } else {
+            Ok(Self(s.to_string()))
+        }
+    }
+}
+
+impl AsRef<str> for Username {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::Username;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn a_64_character_long_username_is_valid() {
+        assert_ok!(Username::parse("a".repeat(64)));
+    }
+
+    #[test]
+    fn a_username_longer_than_64_characters_is_rejected() {
+        assert_err!(Username::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn empty_usernames_are_rejected() {
+        assert_err!(Username::parse("".to_string()));
+        assert_err!(Username::parse("   ".to_string()));
+    }
+
+    #[test]
+    fn surrounding_whitespace_is_trimmed() {
+        let username = Username::parse(" le_guin ".to_string()).unwrap();
+        assert_eq!(username.as_ref(), "le_guin");
+    }
+
+    #[test]
+    fn usernames_containing_markup_are_rejected() {
+        for username in ["<script>", "le guin", "a&b", "\"quoted\"", "ёж"] {
+            assert_err!(Username::parse(username.to_string()));
+        }
+    }
+
+    #[test]
+    fn a_valid_username_is_parsed_successfully() {
+        assert_ok!(Username::parse("ursula.le-guin_1".to_string()));
+    }
+}
@@ -1,13 +1,23 @@
+use anyhow::Context;
 use reqwest::Client;
 use secrecy::{ExposeSecret, SecretString};
 
-use crate::domain::SubscriberEmail;
+use crate::{
+    domain::SubscriberEmail,
+    metrics::Metrics,
+    request_id::{current_request_id, X_REQUEST_ID},
+    telemetry::trace_context_headers,
+};
+
+/// The label of the emails sent through the HTTP API in the metrics.
+const TRANSPORT: &str = "api";
 
 pub struct EmailClient {
     http_client: Client,
     base_url: String,
     sender: SubscriberEmail,
     authorization_token: SecretString,
+    metrics: Option<Metrics>,
 }
 
 impl EmailClient {
@@ -23,7 +33,28 @@ impl EmailClient {
             base_url,
             sender,
             authorization_token,
+            metrics: None,
+        }
+    }
+
+    /// Count the emails sent and failed in `metrics`.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Check that the settings of the email API can be used to send emails,
+    /// without sending any.
+    pub fn check_settings(&self) -> Result<(), anyhow::Error> {
+        let url = reqwest::Url::parse(&self.base_url)
+            .context("The base URL of the email API is invalid.")?;
+        if !matches!(url.scheme(), "http" | "https") {
+            anyhow::bail!("The email API must be reached over HTTP or HTTPS.");
         }
+        if self.authorization_token.expose_secret().is_empty() {
+            anyhow::bail!("The authorization token of the email API is empty.");
+        }
+        Ok(())
     }
 
     pub async fn send_email(
@@ -33,6 +64,20 @@ impl EmailClient {
         html_content: &str,
         text_content: &str,
     ) -> Result<(), reqwest::Error> {
+        self.send_tracked_email(recipient, subject, html_content, text_content)
+            .await
+            .map(|_| ())
+    }
+
+    /// Like [`EmailClient::send_email`], returning the id the email API gave
+    /// to the message, if its response carries one.
+    pub async fn send_tracked_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<Option<String>, reqwest::Error> {
         let url = format!("{}/email", self.base_url);
         let request_body = SendEmailRequest {
             from: self.sender.as_ref(),
@@ -41,14 +86,30 @@ impl EmailClient {
             html_body: html_content,
             text_body: text_content,
         };
-        self.http_client
+        let mut request = self
+            .http_client
             .post(url)
             .bearer_auth(self.authorization_token.expose_secret())
+            .headers(trace_context_headers());
+        if let Some(request_id) = current_request_id() {
+            request = request.header(X_REQUEST_ID, request_id.header_value().clone());
+        }
+        let outcome = request
             .json(&request_body)
             .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Some(metrics) = &self.metrics {
+            metrics.record_email(TRANSPORT, outcome.is_ok());
+        }
+        let response = outcome?;
+        // The email is sent even if the response cannot be read.
+        let message_id = response
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .map(|response| response.message_id);
+        Ok(message_id)
     }
 }
 
@@ -62,9 +123,15 @@ struct SendEmailRequest<'a> {
     text_body: &'a str,
 }
 
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+}
+
 #[cfg(test)]
 mod tests {
-    use claims::{assert_err, assert_ok};
+    use claims::{assert_err, assert_ok, assert_ok_eq};
     use fake::{
         faker::{
             internet::en::SafeEmail,
@@ -72,13 +139,16 @@ mod tests {
         },
         Fake, Faker,
     };
+    use opentelemetry_sdk::trace::SdkTracerProvider;
     use secrecy::SecretBox;
+    use tracing::Instrument;
     use wiremock::{
         matchers::{any, header, header_exists, method, path},
         Mock, MockServer, ResponseTemplate,
     };
 
     use super::*;
+    use crate::telemetry::get_subscriber;
 
     /// Generate a random email subject
     fn subject() -> String {
@@ -125,6 +195,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn settings_with_an_http_base_url_are_usable() {
+        assert_ok!(email_client("https://api.example.com".into()).check_settings());
+    }
+
+    #[test]
+    fn settings_without_a_valid_base_url_are_not_usable() {
+        assert_err!(email_client("localhost".into()).check_settings());
+        assert_err!(email_client("ftp://api.example.com".into()).check_settings());
+    }
+
     #[tokio::test]
     async fn send_email_fires_a_request_to_base_url() {
         // Arrange
@@ -201,6 +282,35 @@ mod tests {
         assert_err!(outcome);
     }
 
+    #[tokio::test]
+    async fn the_message_id_given_by_the_server_is_returned() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "To": "ursula_le_guin@gmail.com",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+                "ErrorCode": 0,
+                "Message": "OK",
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_tracked_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok_eq!(
+            outcome,
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817".to_string())
+        );
+    }
+
     #[tokio::test]
     async fn send_email_times_out_if_the_server_takes_too_long() {
         // Arrange
@@ -252,4 +362,30 @@ mod tests {
 
         // Assert
     }
+
+    #[tokio::test]
+    async fn send_email_passes_the_trace_context_on() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let tracer_provider = SdkTracerProvider::builder().build();
+        let subscriber = get_subscriber("test", "info", std::io::sink, Some(&tracer_provider));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        Mock::given(header_exists("traceparent"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .instrument(tracing::info_span!("Send an email"))
+            .await;
+
+        // Assert
+    }
 }
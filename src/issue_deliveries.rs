@@ -0,0 +1,584 @@
+//! The recipients of each newsletter issue, and how sending it to them went.
+//! Publishing records every confirmed subscriber as a pending recipient;
+//! background workers then try each of them once, picking up where they
+//! left off after a restart. Failed recipients stay in the report and can
+//! be retried from the command line.
+//!
+//! Each recipient is claimed before being sent to, so that concurrent
+//! senders, e.g. a retry running while the issue is still being sent, never
+//! email the same recipient twice.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::{PgExecutor, PgPool};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{domain::SubscriberEmail, email_client::EmailClient};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecipientStatus {
+    Pending,
+    /// Claimed by a sender, which is emailing the recipient right now.
+    Sending,
+    Sent,
+    Failed,
+    /// The stored address is not valid: nothing is sent to it.
+    Suppressed,
+}
+
+impl RecipientStatus {
+    pub const ALL: [RecipientStatus; 5] = [
+        RecipientStatus::Pending,
+        RecipientStatus::Sending,
+        RecipientStatus::Sent,
+        RecipientStatus::Failed,
+        RecipientStatus::Suppressed,
+    ];
+
+    pub fn parse(s: String) -> Result<RecipientStatus, String> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid delivery status.", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecipientStatus::Pending => "pending",
+            RecipientStatus::Sending => "sending",
+            RecipientStatus::Sent => "sent",
+            RecipientStatus::Failed => "failed",
+            RecipientStatus::Suppressed => "suppressed",
+        }
+    }
+}
+
+impl std::fmt::Display for RecipientStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// How long a claimed recipient is left to its sender. Past that, the sender
+/// is assumed to have stopped halfway, e.g. because the process was killed.
+const CLAIM_TIMEOUT: TimeDelta = TimeDelta::minutes(10);
+
+/// How many recipients of an issue are in each status. Recipients being
+/// sent to right now are counted as pending.
+#[derive(Debug, Default, PartialEq, serde::Serialize, utoipa::ToSchema)]
+pub struct DeliveryCounts {
+    pub pending: i64,
+    pub sent: i64,
+    pub failed: i64,
+    pub suppressed: i64,
+}
+
+/// How the recipients tried by one run of `send_pending` fared, leaving out
+/// those sent to by earlier runs.
+#[derive(Debug, Default, PartialEq)]
+pub struct SendReport {
+    pub sent: i64,
+    pub failed: i64,
+    pub suppressed: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueDelivery {
+    pub delivery_id: Uuid,
+    pub recipient: String,
+    pub status: String,
+    pub attempts: i32,
+    pub provider_message_id: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+pub struct IssueSummary {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub counts: DeliveryCounts,
+}
+
+pub struct FailedDelivery {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub recipient: String,
+    pub attempts: i32,
+    pub error: Option<String>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+}
+
+/// Filters for the recipients of an issue. `None` means "any".
+#[derive(Debug, Default)]
+pub struct DeliveryQuery {
+    pub status: Option<RecipientStatus>,
+    /// Matched against the address of the recipient, as an `ILIKE` pattern.
+    pub search: Option<String>,
+}
+
+/// Record every confirmed subscriber as a pending recipient of the issue.
+#[tracing::instrument(name = "Record the recipients of an issue", skip(executor))]
+pub async fn record_recipients(
+    executor: impl PgExecutor<'_>,
+    issue_id: Uuid,
+) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (delivery_id, issue_id, recipient, status, created_at)
+        SELECT gen_random_uuid(), $1, email, 'pending', now()
+        FROM subscriptions
+        WHERE status = 'confirmed'
+        "#,
+        issue_id,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to record the recipients of the issue.")?;
+    Ok(result.rows_affected())
+}
+
+/// Make an attempt at every pending recipient of the issue, recording the
+/// outcome of each. A recipient that cannot be sent the issue does not stop
+/// the others from getting it. Once `shutdown` is cancelled, the recipient
+/// being sent to is finished and the others are left pending. Returns how
+/// the recipients tried by this call fared.
+#[tracing::instrument(
+    name = "Send an issue to its pending recipients",
+    skip(email_client, pool, shutdown)
+)]
+pub async fn send_pending(
+    email_client: &EmailClient,
+    pool: &PgPool,
+    issue_id: Uuid,
+    shutdown: &CancellationToken,
+) -> Result<SendReport, anyhow::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT title, html_content, text_content
+        FROM newsletter_issues
+        WHERE issue_id = $1
+        "#,
+        issue_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the newsletter issue.")?;
+    let mut report = SendReport::default();
+    while !shutdown.is_cancelled() {
+        let Some(delivery) = claim_pending(issue_id, pool).await? else {
+            break;
+        };
+        let email = match SubscriberEmail::parse(delivery.recipient) {
+            Ok(email) => email,
+            Err(error) => {
+                tracing::warn!(%error, "Skipping a confirmed subscriber. Their stored contact details are invalid.");
+                record_outcome(
+                    pool,
+                    delivery.delivery_id,
+                    RecipientStatus::Suppressed,
+                    None,
+                    Some(&error),
+                )
+                .await?;
+                report.suppressed += 1;
+                continue;
+            }
+        };
+        let outcome = email_client
+            .send_tracked_email(
+                &email,
+                &issue.title,
+                &issue.html_content,
+                &issue.text_content,
+            )
+            .await;
+        match outcome {
+            Ok(message_id) => {
+                record_outcome(
+                    pool,
+                    delivery.delivery_id,
+                    RecipientStatus::Sent,
+                    message_id.as_deref(),
+                    None,
+                )
+                .await?;
+                report.sent += 1;
+            }
+            Err(error) => {
+                tracing::warn!(
+                    error.cause_chain = ?error,
+                    "Failed to send newsletter issue to {}", email
+                );
+                record_outcome(
+                    pool,
+                    delivery.delivery_id,
+                    RecipientStatus::Failed,
+                    None,
+                    Some(&error.to_string()),
+                )
+                .await?;
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Claim the next pending recipient of the issue, skipping those that other
+/// senders are claiming at the same time.
+async fn claim_pending(
+    issue_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<ClaimedDelivery>, anyhow::Error> {
+    sqlx::query_as!(
+        ClaimedDelivery,
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'sending', claimed_at = now()
+        WHERE delivery_id = (
+            SELECT delivery_id
+            FROM issue_deliveries
+            WHERE issue_id = $1 AND status = 'pending'
+            ORDER BY recipient
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING delivery_id, recipient
+        "#,
+        issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to claim a pending recipient of the issue.")
+}
+
+struct ClaimedDelivery {
+    delivery_id: Uuid,
+    recipient: String,
+}
+
+/// Suppressed recipients were never tried, so their attempt is not counted.
+async fn record_outcome(
+    pool: &PgPool,
+    delivery_id: Uuid,
+    status: RecipientStatus,
+    provider_message_id: Option<&str>,
+    error: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let attempted = status != RecipientStatus::Suppressed;
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = $2,
+            attempts = attempts + $3::integer,
+            provider_message_id = $4,
+            error = $5,
+            last_attempt_at = CASE WHEN $3::integer > 0 THEN now() ELSE last_attempt_at END,
+            sent_at = CASE WHEN $2 = 'sent' THEN now() ELSE NULL END,
+            claimed_at = NULL
+        WHERE delivery_id = $1
+        "#,
+        delivery_id,
+        status.as_str(),
+        attempted as i32,
+        provider_message_id,
+        error,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the outcome of a delivery.")?;
+    Ok(())
+}
+
+/// Put the failed recipients back in the queue, of one issue or of every
+/// issue, along with those whose sender stopped halfway. Returns the issues
+/// that have recipients to send to.
+#[tracing::instrument(name = "Queue failed deliveries again", skip(pool))]
+pub async fn retry_failed(
+    issue_id: Option<Uuid>,
+    pool: &PgPool,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'pending'
+        WHERE status = 'failed' AND ($1::uuid IS NULL OR issue_id = $1)
+        "#,
+        issue_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to queue the failed deliveries again.")?;
+    resume_pending(issue_id, pool).await
+}
+
+/// Put the recipients whose sender stopped halfway back in the queue, of one
+/// issue or of every issue. Returns the issues that have recipients to send
+/// to, including those left pending by an interrupted publication.
+///
+/// A sender may have stopped after emailing a recipient but before recording
+/// it: that recipient gets the issue twice rather than not at all.
+#[tracing::instrument(name = "Resume pending deliveries", skip(pool))]
+pub async fn resume_pending(
+    issue_id: Option<Uuid>,
+    pool: &PgPool,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'pending', claimed_at = NULL
+        WHERE status = 'sending'
+            AND claimed_at < $2
+            AND ($1::uuid IS NULL OR issue_id = $1)
+        "#,
+        issue_id,
+        Utc::now() - CLAIM_TIMEOUT,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to queue the interrupted deliveries again.")?;
+    let issue_ids = sqlx::query_scalar!(
+        r#"
+        SELECT DISTINCT issue_id
+        FROM issue_deliveries
+        WHERE status = 'pending' AND ($1::uuid IS NULL OR issue_id = $1)
+        ORDER BY issue_id
+        "#,
+        issue_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to retrieve the issues with pending recipients.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to resume pending deliveries.")?;
+    Ok(issue_ids)
+}
+
+/// Send the published issues to their pending recipients until the
+/// application shuts down, checking for new ones every `poll_interval`
+/// while there are none. At shutdown, the email being sent is finished and
+/// recorded before the worker stops.
+pub async fn send_issues(
+    email_client: Arc<EmailClient>,
+    pool: PgPool,
+    poll_interval: Duration,
+    shutdown: CancellationToken,
+) {
+    while !shutdown.is_cancelled() {
+        let sending = async {
+            for issue_id in resume_pending(None, &pool).await? {
+                let report = send_pending(&email_client, &pool, issue_id, &shutdown).await?;
+                if report.failed > 0 {
+                    tracing::warn!(
+                        %issue_id,
+                        failed = report.failed,
+                        sent = report.sent,
+                        "Some recipients could not be sent the issue."
+                    );
+                }
+                if shutdown.is_cancelled() {
+                    break;
+                }
+            }
+            Ok::<(), anyhow::Error>(())
+        };
+        if let Err(e) = sending.await {
+            tracing::warn!(error.cause_chain = ?e, "Failed to send the published issues.");
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(poll_interval) => {}
+            _ = shutdown.cancelled() => {}
+        }
+    }
+}
+
+#[tracing::instrument(name = "Count the deliveries of an issue", skip(executor))]
+pub async fn count_deliveries(
+    issue_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<DeliveryCounts, anyhow::Error> {
+    sqlx::query_as!(
+        DeliveryCounts,
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE status IN ('pending', 'sending')) AS "pending!",
+            COUNT(*) FILTER (WHERE status = 'sent') AS "sent!",
+            COUNT(*) FILTER (WHERE status = 'failed') AS "failed!",
+            COUNT(*) FILTER (WHERE status = 'suppressed') AS "suppressed!"
+        FROM issue_deliveries
+        WHERE issue_id = $1
+        "#,
+        issue_id,
+    )
+    .fetch_one(executor)
+    .await
+    .context("Failed to count the deliveries of the issue.")
+}
+
+/// The latest issues, with how their deliveries went.
+pub async fn list_issues(limit: i64, pool: &PgPool) -> Result<Vec<IssueSummary>, anyhow::Error> {
+    summarize_issues(None, limit, pool).await
+}
+
+pub async fn get_issue_summary(
+    issue_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<IssueSummary>, anyhow::Error> {
+    Ok(summarize_issues(Some(issue_id), 1, pool).await?.pop())
+}
+
+#[tracing::instrument(name = "Summarize issues with their deliveries", skip(pool))]
+async fn summarize_issues(
+    issue_id: Option<Uuid>,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<IssueSummary>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            i.issue_id,
+            i.title,
+            i.published_at,
+            COUNT(d.delivery_id) FILTER (WHERE d.status IN ('pending', 'sending')) AS "pending!",
+            COUNT(d.delivery_id) FILTER (WHERE d.status = 'sent') AS "sent!",
+            COUNT(d.delivery_id) FILTER (WHERE d.status = 'failed') AS "failed!",
+            COUNT(d.delivery_id) FILTER (WHERE d.status = 'suppressed') AS "suppressed!"
+        FROM newsletter_issues i
+        LEFT JOIN issue_deliveries d ON d.issue_id = i.issue_id
+        WHERE $1::uuid IS NULL OR i.issue_id = $1
+        GROUP BY i.issue_id
+        ORDER BY i.published_at DESC, i.issue_id DESC
+        LIMIT $2
+        "#,
+        issue_id,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the newsletter issues.")?;
+    Ok(rows
+        .into_iter()
+        .map(|row| IssueSummary {
+            issue_id: row.issue_id,
+            title: row.title,
+            published_at: row.published_at,
+            counts: DeliveryCounts {
+                pending: row.pending,
+                sent: row.sent,
+                failed: row.failed,
+                suppressed: row.suppressed,
+            },
+        })
+        .collect())
+}
+
+/// The recipients of an issue matching `query`, by address, along with the
+/// total number of matches.
+#[tracing::instrument(name = "Search the deliveries of an issue", skip(pool))]
+pub async fn search_deliveries(
+    issue_id: Uuid,
+    query: &DeliveryQuery,
+    limit: i64,
+    offset: i64,
+    pool: &PgPool,
+) -> Result<(Vec<IssueDelivery>, i64), anyhow::Error> {
+    let status = query.status.map(|status| status.as_str());
+    let pattern = query.search.as_ref().map(|s| {
+        let escaped = s
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        format!("%{}%", escaped)
+    });
+    let deliveries = sqlx::query_as!(
+        IssueDelivery,
+        r#"
+        SELECT delivery_id, recipient, status, attempts, provider_message_id, error,
+            created_at, last_attempt_at, sent_at
+        FROM issue_deliveries
+        WHERE issue_id = $1
+            AND ($2::text IS NULL OR status = $2)
+            AND ($3::text IS NULL OR recipient ILIKE $3)
+        ORDER BY recipient
+        LIMIT $4 OFFSET $5
+        "#,
+        issue_id,
+        status,
+        pattern,
+        limit,
+        offset,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the deliveries of the issue.")?;
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM issue_deliveries
+        WHERE issue_id = $1
+            AND ($2::text IS NULL OR status = $2)
+            AND ($3::text IS NULL OR recipient ILIKE $3)
+        "#,
+        issue_id,
+        status,
+        pattern,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the deliveries of the issue.")?;
+    Ok((deliveries, total))
+}
+
+/// The failed recipients, of one issue or of every issue, latest issue
+/// first.
+#[tracing::instrument(name = "List failed deliveries", skip(pool))]
+pub async fn list_failed(
+    issue_id: Option<Uuid>,
+    pool: &PgPool,
+) -> Result<Vec<FailedDelivery>, anyhow::Error> {
+    sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT d.issue_id, i.title, d.recipient, d.attempts, d.error, d.last_attempt_at
+        FROM issue_deliveries d
+        JOIN newsletter_issues i ON i.issue_id = d.issue_id
+        WHERE d.status = 'failed' AND ($1::uuid IS NULL OR d.issue_id = $1)
+        ORDER BY i.published_at DESC, d.recipient
+        "#,
+        issue_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the failed deliveries.")
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use super::RecipientStatus;
+
+    #[test]
+    fn every_status_round_trips_through_its_string_form() {
+        for status in RecipientStatus::ALL {
+            assert_ok_eq!(RecipientStatus::parse(status.as_str().to_string()), status);
+        }
+    }
+
+    #[test]
+    fn unknown_status_is_rejected() {
+        assert_err!(RecipientStatus::parse("delivered".to_string()));
+    }
+}
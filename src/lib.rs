@@ -1,19 +1,41 @@
 use std::sync::Arc;
 
+use authentication::PasswordHashing;
 use axum::extract::FromRef;
+use configuration::{HealthSettings, WebhookSettings};
+use domain::PasswordPolicy;
 use email_client::EmailClient;
+use login_throttle::LoginThrottle;
+use metrics::Metrics;
+use session_store::SessionBackend;
+use setup::Setup;
 use sqlx::PgPool;
 use startup::HmacSecret;
+use user_sessions::SessionLifetime;
 
+pub mod api_tokens;
+pub mod audit;
 pub mod authentication;
+pub mod cli;
 pub mod configuration;
+pub mod csrf;
 pub mod domain;
 pub mod email_client;
+pub mod issue_deliveries;
+pub mod login_throttle;
+pub mod metrics;
+pub mod request_id;
 pub mod routes;
 pub mod session_state;
+pub mod session_store;
+pub mod setup;
+pub mod shutdown;
 pub mod startup;
 pub mod telemetry;
+pub mod two_factor;
+pub mod user_sessions;
 pub mod utils;
+pub mod webhooks;
 
 #[derive(Clone)]
 pub struct AppState {
@@ -22,6 +44,15 @@ pub struct AppState {
     base_url: String,
     hmac_secret: HmacSecret,
     flash_config: axum_flash::Config,
+    session_store: SessionBackend,
+    login_throttle: LoginThrottle,
+    password_policy: PasswordPolicy,
+    password_hashing: PasswordHashing,
+    session_lifetime: SessionLifetime,
+    setup: Setup,
+    metrics: Metrics,
+    health: HealthSettings,
+    webhooks: WebhookSettings,
 }
 
 impl FromRef<AppState> for axum_flash::Config {
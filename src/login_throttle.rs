@@ -0,0 +1,447 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use tower_sessions_redis_store::fred::prelude::*;
+use uuid::Uuid;
+
+use crate::configuration::LoginThrottleSettings;
+
+/// No attempt is held back for longer than this, however many failures
+/// preceded it.
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// Counts failed login attempts, per username and per IP address. Failed
+/// second factors are counted per user, apart from the failed passwords, and
+/// share the counter of the IP address.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    counters: FailureCounters,
+    settings: LoginThrottleSettings,
+}
+
+/// Where failed attempts are counted. They are kept next to the sessions, so
+/// that running without Redis does not need it for throttling either.
+#[derive(Clone)]
+pub enum FailureCounters {
+    Redis(Pool),
+    /// In the `login_failures` table.
+    Postgres(PgPool),
+    /// Counters are lost on restart and not shared between instances.
+    Memory(Arc<Mutex<HashMap<String, MemoryCounter>>>),
+}
+
+pub struct MemoryCounter {
+    failures: u32,
+    expires_at: Instant,
+}
+
+/// What to do with a login attempt before checking the password.
+#[derive(Debug)]
+pub enum Verdict {
+    /// Check the password after waiting for `delay`.
+    Proceed { delay: Duration },
+    /// Too many recent failures: reject the attempt without checking the
+    /// password.
+    LockedOut { retry_after: Duration },
+}
+
+impl LoginThrottle {
+    pub fn new(counters: FailureCounters, settings: LoginThrottleSettings) -> Self {
+        Self { counters, settings }
+    }
+
+    fn username_key(&self, username: &str) -> String {
+        format!("{}:username:{}", self.settings.key_prefix, username)
+    }
+
+    fn ip_key(&self, ip: IpAddr) -> String {
+        format!("{}:ip:{}", self.settings.key_prefix, ip)
+    }
+
+    fn second_factor_key(&self, user_id: Uuid) -> String {
+        format!("{}:second_factor:{}", self.settings.key_prefix, user_id)
+    }
+
+    #[tracing::instrument(name = "Check failed login attempts", skip(self))]
+    pub async fn check(&self, username: &str, ip: IpAddr) -> Result<Verdict, anyhow::Error> {
+        self.check_keys(self.username_key(username), ip).await
+    }
+
+    /// Like `check`, for the second login step of `user_id`.
+    #[tracing::instrument(name = "Check failed second factor attempts", skip(self))]
+    pub async fn check_second_factor(
+        &self,
+        user_id: Uuid,
+        ip: IpAddr,
+    ) -> Result<Verdict, anyhow::Error> {
+        self.check_keys(self.second_factor_key(user_id), ip).await
+    }
+
+    async fn check_keys(&self, user_key: String, ip: IpAddr) -> Result<Verdict, anyhow::Error> {
+        let ip_key = self.ip_key(ip);
+        let username_failures = self.failures(&user_key).await?;
+        let ip_failures = self.failures(&ip_key).await?;
+
+        let locked_key = if username_failures >= self.settings.max_failures_per_username {
+            Some(user_key)
+        } else if ip_failures >= self.settings.max_failures_per_ip {
+            Some(ip_key)
+        } else {
+            None
+        };
+        if let Some(key) = locked_key {
+            let retry_after = self.counters.remaining(&key).await?;
+            return Ok(Verdict::LockedOut { retry_after });
+        }
+
+        let failures = username_failures.max(ip_failures);
+        Ok(Verdict::Proceed {
+            delay: delay_for(failures, self.settings.delay_step()),
+        })
+    }
+
+    /// Count a failed attempt against the username and the IP address, and
+    /// return the number of recent failures for the username.
+    #[tracing::instrument(name = "Record failed login attempt", skip(self))]
+    pub async fn record_failure(&self, username: &str, ip: IpAddr) -> Result<u32, anyhow::Error> {
+        self.record_failure_of(self.username_key(username), ip)
+            .await
+    }
+
+    /// Count a wrong second factor of `user_id`, and return the number of
+    /// recent ones.
+    #[tracing::instrument(name = "Record failed second factor attempt", skip(self))]
+    pub async fn record_second_factor_failure(
+        &self,
+        user_id: Uuid,
+        ip: IpAddr,
+    ) -> Result<u32, anyhow::Error> {
+        self.record_failure_of(self.second_factor_key(user_id), ip)
+            .await
+    }
+
+    async fn record_failure_of(&self, user_key: String, ip: IpAddr) -> Result<u32, anyhow::Error> {
+        let lockout = self.settings.lockout();
+        let username_failures = self
+            .counters
+            .increment(&user_key, self.settings.max_failures_per_username, lockout)
+            .await?;
+        self.counters
+            .increment(&self.ip_key(ip), self.settings.max_failures_per_ip, lockout)
+            .await?;
+        Ok(username_failures)
+    }
+
+    /// Forget the failures of a username once its password has been entered
+    /// correctly. The failures of the IP address are kept, so that logging
+    /// into one's own account does not allow guessing more passwords for
+    /// other accounts.
+    #[tracing::instrument(name = "Reset failed login attempts", skip(self))]
+    pub async fn record_success(&self, username: &str) -> Result<(), anyhow::Error> {
+        self.counters.reset(&self.username_key(username)).await
+    }
+
+    /// Forget the wrong second factors of a user once logged in.
+    #[tracing::instrument(name = "Reset failed second factor attempts", skip(self))]
+    pub async fn record_second_factor_success(&self, user_id: Uuid) -> Result<(), anyhow::Error> {
+        self.counters.reset(&self.second_factor_key(user_id)).await
+    }
+
+    async fn failures(&self, key: &str) -> Result<u32, anyhow::Error> {
+        self.counters.get(key).await
+    }
+}
+
+impl FailureCounters {
+    pub fn memory() -> Self {
+        FailureCounters::Memory(Arc::new(Mutex::new(HashMap::new())))
+    }
+
+    async fn get(&self, key: &str) -> Result<u32, anyhow::Error> {
+        match self {
+            FailureCounters::Redis(redis) => {
+                let failures: Option<u32> = redis
+                    .get(key)
+                    .await
+                    .context("Failed to read the number of failed login attempts.")?;
+                Ok(failures.unwrap_or(0))
+            }
+            FailureCounters::Postgres(pool) => {
+                let failures = sqlx::query_scalar!(
+                    r#"
+                    SELECT failures FROM login_failures
+                    WHERE key = $1 AND expires_at > now()
+                    "#,
+                    key,
+                )
+                .fetch_optional(pool)
+                .await
+                .context("Failed to read the number of failed login attempts.")?;
+                Ok(failures.unwrap_or(0) as u32)
+            }
+            FailureCounters::Memory(counters) => {
+                let counters = counters.lock().unwrap();
+                Ok(counters
+                    .get(key)
+                    .filter(|counter| counter.expires_at > Instant::now())
+                    .map_or(0, |counter| counter.failures))
+            }
+        }
+    }
+
+    /// How long until the counter expires. At least a second, since the
+    /// counter may have expired in between.
+    async fn remaining(&self, key: &str) -> Result<Duration, anyhow::Error> {
+        let seconds = match self {
+            FailureCounters::Redis(redis) => {
+                let ttl: i64 = redis
+                    .ttl(key)
+                    .await
+                    .context("Failed to read the remaining lockout time.")?;
+                ttl
+            }
+            FailureCounters::Postgres(pool) => {
+                let expires_at = sqlx::query_scalar!(
+                    r#"SELECT expires_at FROM login_failures WHERE key = $1"#,
+                    key,
+                )
+                .fetch_optional(pool)
+                .await
+                .context("Failed to read the remaining lockout time.")?;
+                expires_at.map_or(0, |expires_at| (expires_at - Utc::now()).num_seconds())
+            }
+            FailureCounters::Memory(counters) => {
+                let counters = counters.lock().unwrap();
+                counters.get(key).map_or(0, |counter| {
+                    counter
+                        .expires_at
+                        .saturating_duration_since(Instant::now())
+                        .as_secs() as i64
+                })
+            }
+        };
+        Ok(Duration::from_secs(seconds.max(1) as u64))
+    }
+
+    /// The counter starts its countdown at the first failure, and again when
+    /// it reaches `max_failures`, so that a lockout always lasts the full
+    /// `lockout`.
+    async fn increment(
+        &self,
+        key: &str,
+        max_failures: u32,
+        lockout: Duration,
+    ) -> Result<u32, anyhow::Error> {
+        match self {
+            FailureCounters::Redis(redis) => {
+                // The counter is created with its expiry in the same
+                // transaction as it is incremented, so that it cannot be
+                // left without one.
+                let lockout_seconds = lockout.as_secs() as i64;
+                let transaction = redis.multi();
+                transaction
+                    .set::<(), _, _>(
+                        key,
+                        0,
+                        Some(Expiration::EX(lockout_seconds)),
+                        Some(SetOptions::NX),
+                        false,
+                    )
+                    .await
+                    .context("Failed to count a failed login attempt.")?;
+                transaction
+                    .incr::<(), _>(key)
+                    .await
+                    .context("Failed to count a failed login attempt.")?;
+                let (_, failures): (Value, u32) = transaction
+                    .exec(true)
+                    .await
+                    .context("Failed to count a failed login attempt.")?;
+                if failures == max_failures {
+                    redis
+                        .expire::<(), _>(key, lockout_seconds, None)
+                        .await
+                        .context("Failed to set the expiry of failed login attempts.")?;
+                }
+                Ok(failures)
+            }
+            FailureCounters::Postgres(pool) => {
+                let expires_at = Utc::now()
+                    + chrono::Duration::from_std(lockout).context("Invalid lockout duration.")?;
+                let failures = sqlx::query_scalar!(
+                    r#"
+                    INSERT INTO login_failures (key, failures, expires_at)
+                    VALUES ($1, 1, $2)
+                    ON CONFLICT (key) DO UPDATE
+                    SET
+                        failures = CASE
+                            WHEN login_failures.expires_at <= now() THEN 1
+                            ELSE login_failures.failures + 1
+                        END,
+                        expires_at = CASE
+                            WHEN login_failures.expires_at <= now()
+                                OR login_failures.failures + 1 = $3
+                            THEN $2
+                            ELSE login_failures.expires_at
+                        END
+                    RETURNING failures
+                    "#,
+                    key,
+                    expires_at,
+                    max_failures as i32,
+                )
+                .fetch_one(pool)
+                .await
+                .context("Failed to count a failed login attempt.")?;
+                Ok(failures as u32)
+            }
+            FailureCounters::Memory(counters) => {
+                let mut counters = counters.lock().unwrap();
+                let now = Instant::now();
+                let counter = counters
+                    .entry(key.to_string())
+                    .and_modify(|counter| {
+                        if counter.expires_at <= now {
+                            counter.failures = 0;
+                        }
+                    })
+                    .or_insert(MemoryCounter {
+                        failures: 0,
+                        expires_at: now,
+                    });
+                counter.failures += 1;
+                if counter.failures == 1 || counter.failures == max_failures {
+                    counter.expires_at = now + lockout;
+                }
+                Ok(counter.failures)
+            }
+        }
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), anyhow::Error> {
+        match self {
+            FailureCounters::Redis(redis) => redis
+                .del::<(), _>(key)
+                .await
+                .context("Failed to reset the failed login attempts of a username."),
+            FailureCounters::Postgres(pool) => {
+                sqlx::query!(r#"DELETE FROM login_failures WHERE key = $1"#, key)
+                    .execute(pool)
+                    .await
+                    .context("Failed to reset the failed login attempts of a username.")?;
+                Ok(())
+            }
+            FailureCounters::Memory(counters) => {
+                counters.lock().unwrap().remove(key);
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove the counters that expired. Reading ignores them anyway, this
+    /// only keeps them from piling up. Redis expires its keys by itself.
+    #[tracing::instrument(name = "Delete expired login failures", skip(self))]
+    pub async fn delete_expired(&self) -> Result<u64, anyhow::Error> {
+        match self {
+            FailureCounters::Redis(_) => Ok(0),
+            FailureCounters::Postgres(pool) => {
+                let result =
+                    sqlx::query!(r#"DELETE FROM login_failures WHERE expires_at <= now()"#)
+                        .execute(pool)
+                        .await
+                        .context("Failed to delete expired login failures.")?;
+                Ok(result.rows_affected())
+            }
+            FailureCounters::Memory(counters) => {
+                let mut counters = counters.lock().unwrap();
+                let before = counters.len();
+                let now = Instant::now();
+                counters.retain(|_, counter| counter.expires_at > now);
+                Ok((before - counters.len()) as u64)
+            }
+        }
+    }
+}
+
+/// Every recent failure adds `step` to the time an attempt is held back.
+fn delay_for(failures: u32, step: Duration) -> Duration {
+    step.saturating_mul(failures).min(MAX_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{delay_for, FailureCounters, MAX_DELAY};
+
+    #[test]
+    fn there_is_no_delay_without_failures() {
+        assert_eq!(delay_for(0, Duration::from_millis(250)), Duration::ZERO);
+    }
+
+    #[test]
+    fn the_delay_grows_with_each_failure() {
+        let step = Duration::from_millis(250);
+        assert!(delay_for(1, step) < delay_for(2, step));
+        assert_eq!(delay_for(3, step), Duration::from_millis(750));
+    }
+
+    #[test]
+    fn the_delay_is_capped() {
+        assert_eq!(delay_for(u32::MAX, Duration::from_secs(1)), MAX_DELAY);
+    }
+
+    #[tokio::test]
+    async fn memory_counters_count_until_reset() {
+        let counters = FailureCounters::memory();
+        let lockout = Duration::from_secs(60);
+        assert_eq!(counters.get("key").await.unwrap(), 0);
+        assert_eq!(counters.increment("key", 3, lockout).await.unwrap(), 1);
+        assert_eq!(counters.increment("key", 3, lockout).await.unwrap(), 2);
+        assert_eq!(counters.get("key").await.unwrap(), 2);
+        assert!(counters.remaining("key").await.unwrap() <= lockout);
+
+        counters.reset("key").await.unwrap();
+        assert_eq!(counters.get("key").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn expired_memory_counters_are_deleted() {
+        let counters = FailureCounters::memory();
+        counters
+            .increment("expired", 3, Duration::ZERO)
+            .await
+            .unwrap();
+        counters
+            .increment("recent", 3, Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        assert_eq!(counters.delete_expired().await.unwrap(), 1);
+
+        let FailureCounters::Memory(map) = &counters else {
+            unreachable!()
+        };
+        let map = map.lock().unwrap();
+        assert!(!map.contains_key("expired"));
+        assert!(map.contains_key("recent"));
+    }
+
+    #[tokio::test]
+    async fn memory_counters_expire() {
+        let counters = FailureCounters::memory();
+        counters.increment("key", 3, Duration::ZERO).await.unwrap();
+        assert_eq!(counters.get("key").await.unwrap(), 0);
+        assert_eq!(
+            counters.increment("key", 3, Duration::ZERO).await.unwrap(),
+            1
+        );
+    }
+}
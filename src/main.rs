@@ -1,16 +1,39 @@
 use newsletter::{
-    configuration::get_configuration,
+    configuration::load_configuration,
     startup::Application,
-    telemetry::{get_subscriber, init_subscriber},
+    telemetry::{get_subscriber, get_tracer_provider, init_subscriber},
 };
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    let subscriber = get_subscriber("newsletter", "info", std::io::stdout);
+    let configuration = load_configuration()?;
+    if std::env::args().skip(1).any(|arg| arg == "--print-config") {
+        // Printed before validation, to see what an invalid configuration
+        // was loaded from.
+        println!("{}", configuration.to_redacted_json()?);
+        configuration.validate()?;
+        return Ok(());
+    }
+    configuration.validate()?;
+    let tracer_provider = get_tracer_provider(&configuration.telemetry)?;
+    let subscriber = get_subscriber(
+        "newsletter",
+        "info",
+        std::io::stdout,
+        tracer_provider.as_ref(),
+    );
     init_subscriber(subscriber);
 
-    let configuration = get_configuration().expect("Failed to read configuration.");
-    let application = Application::build(configuration).await?;
-    application.run_until_stopped().await?;
-    Ok(())
+    let outcome = async {
+        let application = Application::build(configuration).await?;
+        application.run_until_stopped().await?;
+        Ok::<(), anyhow::Error>(())
+    }
+    .await;
+    // Send the buffered spans even when the server failed: they are the
+    // ones telling why.
+    if let Some(provider) = tracer_provider {
+        provider.shutdown()?;
+    }
+    outcome
 }
@@ -0,0 +1,191 @@
+//! Prometheus metrics. They are served on a port of their own, so that they
+//! can be scraped without being exposed to the public.
+
+use std::time::Instant;
+
+use anyhow::Context;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use sqlx::PgPool;
+
+use crate::utils::e500;
+
+/// Every metric of the application, in a registry of its own.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    emails: IntCounterVec,
+    signups: IntCounter,
+    confirmations: IntCounter,
+    login_failures: IntCounterVec,
+    db_connections: IntGaugeVec,
+    pending_deliveries: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled."),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to handle HTTP requests.",
+            ),
+            &["method", "route", "status"],
+        )?;
+        let emails = IntCounterVec::new(
+            Opts::new("emails_total", "Emails handed to a transport."),
+            &["transport", "outcome"],
+        )?;
+        let signups = IntCounter::new("subscriber_signups_total", "New subscriptions.")?;
+        let confirmations = IntCounter::new(
+            "subscriber_confirmations_total",
+            "Subscriptions confirmed by their subscriber.",
+        )?;
+        let login_failures = IntCounterVec::new(
+            Opts::new("login_failures_total", "Rejected login attempts."),
+            &["reason"],
+        )?;
+        let db_connections = IntGaugeVec::new(
+            Opts::new(
+                "db_pool_connections",
+                "Connections of the Postgres pool, by state.",
+            ),
+            &["state"],
+        )?;
+        let pending_deliveries = IntGaugeVec::new(
+            Opts::new(
+                "pending_deliveries",
+                "Deliveries waiting to be sent, by queue.",
+            ),
+            &["queue"],
+        )?;
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(emails.clone()))?;
+        registry.register(Box::new(signups.clone()))?;
+        registry.register(Box::new(confirmations.clone()))?;
+        registry.register(Box::new(login_failures.clone()))?;
+        registry.register(Box::new(db_connections.clone()))?;
+        registry.register(Box::new(pending_deliveries.clone()))?;
+        Ok(Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            emails,
+            signups,
+            confirmations,
+            login_failures,
+            db_connections,
+            pending_deliveries,
+        })
+    }
+
+    pub fn record_email(&self, transport: &str, sent: bool) {
+        let outcome = if sent { "sent" } else { "failed" };
+        self.emails.with_label_values(&[transport, outcome]).inc();
+    }
+
+    pub fn record_signup(&self) {
+        self.signups.inc();
+    }
+
+    pub fn record_confirmation(&self) {
+        self.confirmations.inc();
+    }
+
+    pub fn record_login_failure(&self, reason: &str) {
+        self.login_failures.with_label_values(&[reason]).inc();
+    }
+
+    /// Render every metric in the Prometheus text format, with the usage of
+    /// `db_pool` and the depth of the delivery queues as of now.
+    pub async fn render(&self, db_pool: &PgPool) -> Result<String, anyhow::Error> {
+        let idle = db_pool.num_idle() as i64;
+        let open = db_pool.size() as i64;
+        self.db_connections.with_label_values(&["idle"]).set(idle);
+        self.db_connections
+            .with_label_values(&["in_use"])
+            .set(open - idle);
+        let queues = sqlx::query!(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM issue_deliveries
+                    WHERE status IN ('pending', 'sending')) AS "issues!",
+                (SELECT COUNT(*) FROM webhook_deliveries
+                    WHERE status IN ('pending', 'sending')) AS "webhooks!"
+            "#
+        )
+        .fetch_one(db_pool)
+        .await
+        .context("Failed to count the pending deliveries.")?;
+        self.pending_deliveries
+            .with_label_values(&["issue_deliveries"])
+            .set(queues.issues);
+        self.pending_deliveries
+            .with_label_values(&["webhook_deliveries"])
+            .set(queues.webhooks);
+        Ok(TextEncoder::new().encode_to_string(&self.registry.gather())?)
+    }
+}
+
+/// Count and time every request, by the route it matched rather than its
+/// path, to keep the number of series bounded.
+pub async fn track_http_requests(
+    State(metrics): State<Metrics>,
+    matched_path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let route = matched_path
+        .as_ref()
+        .map_or("unmatched", |path| path.as_str())
+        .to_string();
+    let response = next.run(request).await;
+    let status = response.status().as_u16().to_string();
+    let labels = [method.as_str(), route.as_str(), status.as_str()];
+    metrics.http_requests.with_label_values(&labels).inc();
+    metrics
+        .http_request_duration
+        .with_label_values(&labels)
+        .observe(started.elapsed().as_secs_f64());
+    response
+}
+
+#[derive(Clone)]
+struct MetricsEndpoint {
+    metrics: Metrics,
+    db_pool: PgPool,
+}
+
+/// The router of the metrics port.
+pub fn metrics_router(metrics: Metrics, db_pool: PgPool) -> Router {
+    Router::new()
+        .route("/metrics", get(render_metrics))
+        .with_state(MetricsEndpoint { metrics, db_pool })
+}
+
+async fn render_metrics(State(endpoint): State<MetricsEndpoint>) -> Response {
+    match endpoint.metrics.render(&endpoint.db_pool).await {
+        Ok(body) => ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response(),
+        Err(e) => e500(e),
+    }
+}
@@ -0,0 +1,124 @@
+//! Every request gets an `X-Request-Id`, taken from the caller when it sends
+//! a usable one. The id is recorded on the root span, so every log line of
+//! the request carries it. It is also returned in the response and passed
+//! on to the email API.
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use uuid::Uuid;
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longer ids sent by callers are replaced rather than logged.
+const MAX_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: RequestId;
+}
+
+#[derive(Clone, Debug)]
+pub struct RequestId(HeaderValue);
+
+impl RequestId {
+    /// Keep the id sent by the caller if it is short and made of letters,
+    /// digits and `-_.:`, or else generate a new one.
+    fn from_header(value: Option<&HeaderValue>) -> Self {
+        match value {
+            Some(value) if is_valid(value.as_bytes()) => RequestId(value.clone()),
+            _ => RequestId::generate(),
+        }
+    }
+
+    fn generate() -> Self {
+        let id = Uuid::new_v4().to_string();
+        RequestId(HeaderValue::from_str(&id).expect("A UUID is a valid header value"))
+    }
+
+    pub fn header_value(&self) -> &HeaderValue {
+        &self.0
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0
+            .to_str()
+            .expect("Request ids are checked to be ASCII")
+    }
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+fn is_valid(id: &[u8]) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LENGTH
+        && id
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+/// The id of the request being handled by the current task, if any.
+pub fn current_request_id() -> Option<RequestId> {
+    REQUEST_ID.try_with(RequestId::clone).ok()
+}
+
+/// Must run outside of the tracing layer, for the root span to find the id.
+pub async fn propagate_request_id(mut request: Request, next: Next) -> Response {
+    let request_id = RequestId::from_header(request.headers().get(&X_REQUEST_ID));
+    request
+        .headers_mut()
+        .insert(X_REQUEST_ID, request_id.header_value().clone());
+    request.extensions_mut().insert(request_id.clone());
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), next.run(request))
+        .await;
+    response
+        .headers_mut()
+        .insert(X_REQUEST_ID, request_id.header_value().clone());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_id(value: &str) -> RequestId {
+        RequestId::from_header(Some(&HeaderValue::from_str(value).unwrap()))
+    }
+
+    #[test]
+    fn a_usable_id_from_the_caller_is_kept() {
+        assert_eq!(
+            request_id("signup-2025.03:42_a").as_str(),
+            "signup-2025.03:42_a"
+        );
+    }
+
+    #[test]
+    fn an_id_is_generated_if_the_caller_sent_none() {
+        let id = RequestId::from_header(None);
+        assert!(Uuid::parse_str(id.as_str()).is_ok());
+    }
+
+    #[test]
+    fn unusable_ids_from_the_caller_are_replaced() {
+        for value in ["", "with space", "quote\"", &"a".repeat(MAX_LENGTH + 1)] {
+            let id = request_id(value);
+            assert!(Uuid::parse_str(id.as_str()).is_ok(), "{:?} was kept", value);
+        }
+    }
+
+    #[tokio::test]
+    async fn the_id_is_only_known_while_handling_the_request() {
+        assert!(current_request_id().is_none());
+        let id = request_id("abc");
+        let seen = REQUEST_ID.scope(id, async { current_request_id() }).await;
+        assert_eq!(seen.unwrap().as_str(), "abc");
+    }
+}
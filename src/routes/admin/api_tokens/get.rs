@@ -0,0 +1,145 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse, Response},
+};
+use axum_flash::IncomingFlashes;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+
+use crate::{
+    api_tokens,
+    authentication::{get_user_role, UserId},
+    csrf::CsrfToken,
+    domain::ApiScope,
+    utils::e500,
+    AppState,
+};
+
+#[tracing::instrument(name = "List API tokens", skip(state, csrf_token, flash_messages))]
+pub async fn list_api_tokens(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+) -> Result<Response, Response> {
+    let tokens = api_tokens::list_api_tokens(user_id, &state.db_pool)
+        .await
+        .map_err(e500)?;
+    let role = get_user_role(user_id, &state.db_pool).await.map_err(e500)?;
+
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+
+    let now = Utc::now();
+    let mut tokens_html = String::new();
+    for token in &tokens {
+        let status = if token.revoked_at.is_some() {
+            "revoked"
+        } else if token.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            "expired"
+        } else {
+            "active"
+        };
+        let revoke_html = if status == "active" {
+            format!(
+                r#"<form action="/admin/api-tokens/{}/revoke" method="post">
+                {csrf_field}
+                <button type="submit">Revoke</button>
+            </form>"#,
+                token.token_id
+            )
+        } else {
+            String::new()
+        };
+        writeln!(
+            tokens_html,
+            r#"        <tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            encode_minimal(&token.name),
+            encode_minimal(&token.scopes.join(", ")),
+            token.created_at.format("%Y-%m-%d"),
+            format_optional_date(token.expires_at, "never"),
+            format_optional_date(token.last_used_at, "never"),
+            status,
+            revoke_html,
+        )
+        .unwrap();
+    }
+
+    // A token can never do more than its owner.
+    let mut scopes_html = String::new();
+    for scope in ApiScope::ALL {
+        if role.can(scope.permission()) {
+            writeln!(
+                scopes_html,
+                r#"        <label><input type="checkbox" name="scope" value="{scope}"> {scope}</label>"#
+            )
+            .unwrap();
+        }
+    }
+
+    Ok((
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API tokens</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr>
+            <th>Name</th>
+            <th>Scopes</th>
+            <th>Created</th>
+            <th>Expires</th>
+            <th>Last used</th>
+            <th>Status</th>
+            <th></th>
+        </tr>
+{tokens_html}    </table>
+    <form action="/admin/api-tokens" method="post">
+        {csrf_field}
+        <label>Name
+            <input
+                type="text"
+                placeholder="What the token is used for"
+                name="name"
+            >
+        </label>
+{scopes_html}        <label>Expires in (days)
+            <input
+                type="number"
+                min="1"
+                placeholder="Leave empty for no expiry"
+                name="expires_in_days"
+            >
+        </label>
+        <button type="submit">Create token</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )),
+    )
+        .into_response())
+}
+
+fn format_optional_date(date: Option<DateTime<Utc>>, default: &str) -> String {
+    date.map(|d| d.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| default.to_string())
+}
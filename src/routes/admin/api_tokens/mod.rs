@@ -0,0 +1,4 @@
+mod get;
+pub use get::list_api_tokens;
+mod post;
+pub use post::{create_api_token, revoke_api_token};
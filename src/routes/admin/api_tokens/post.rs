@@ -0,0 +1,222 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use chrono::{DateTime, TimeDelta, Utc};
+use secrecy::ExposeSecret;
+use uuid::Uuid;
+
+use crate::{
+    api_tokens,
+    audit::{record_audit_entry, AuditAction},
+    authentication::{get_user_role, UserId},
+    domain::ApiScope,
+    routes::admin::{record_user_event, UserEvent},
+    user_sessions::ClientInfo,
+    utils::e500,
+    AppState,
+};
+
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_VALIDITY_DAYS: i64 = 3650;
+
+struct NewApiToken {
+    name: String,
+    scopes: Vec<ApiScope>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// The form repeats the `scope` field once per checked box, so it is read
+/// as a list of pairs rather than into a struct.
+impl TryFrom<Vec<(String, String)>> for NewApiToken {
+    type Error = String;
+
+    fn try_from(fields: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let mut name = String::new();
+        let mut scopes = Vec::new();
+        let mut expires_at = None;
+        for (key, value) in fields {
+            match key.as_str() {
+                "name" => name = value.trim().to_string(),
+                "scope" => {
+                    let scope = ApiScope::parse(value)?;
+                    if !scopes.contains(&scope) {
+                        scopes.push(scope);
+                    }
+                }
+                "expires_in_days" if !value.trim().is_empty() => {
+                    let days = value
+                        .trim()
+                        .parse::<i64>()
+                        .ok()
+                        .filter(|days| (1..=MAX_VALIDITY_DAYS).contains(days))
+                        .ok_or_else(|| {
+                            format!(
+                                "The expiry must be between 1 and {} days.",
+                                MAX_VALIDITY_DAYS
+                            )
+                        })?;
+                    expires_at = Some(Utc::now() + TimeDelta::days(days));
+                }
+                _ => {}
+            }
+        }
+        if name.is_empty() {
+            return Err("The token needs a name.".into());
+        }
+        if name.chars().count() > MAX_NAME_LENGTH {
+            return Err(format!(
+                "The token name cannot be longer than {} characters.",
+                MAX_NAME_LENGTH
+            ));
+        }
+        if scopes.is_empty() {
+            return Err("Select at least one scope.".into());
+        }
+        Ok(Self {
+            name,
+            scopes,
+            expires_at,
+        })
+    }
+}
+
+#[tracing::instrument(name = "Create an API token", skip(state, client, flash, form))]
+pub async fn create_api_token(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+    Form(form): Form<Vec<(String, String)>>,
+) -> Result<Response, Response> {
+    let new_token: NewApiToken = match form.try_into() {
+        Ok(new_token) => new_token,
+        Err(e) => return Ok((flash.error(e), Redirect::to("/admin/api-tokens")).into_response()),
+    };
+    let role = get_user_role(user_id, &state.db_pool).await.map_err(e500)?;
+    if let Some(scope) = new_token
+        .scopes
+        .iter()
+        .find(|scope| !role.can(scope.permission()))
+    {
+        return Ok((
+            flash.error(format!("Your role does not allow the {} scope.", scope)),
+            Redirect::to("/admin/api-tokens"),
+        )
+            .into_response());
+    }
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let (token_id, token) = api_tokens::create_api_token(
+        &mut *transaction,
+        user_id,
+        &new_token.name,
+        &new_token.scopes,
+        new_token.expires_at,
+    )
+    .await
+    .map_err(e500)?;
+    record_user_event(
+        &mut *transaction,
+        UserEvent::ApiTokenCreated,
+        user_id,
+        Some(user_id),
+        Some(&format!("{} ({})", new_token.name, token_id)),
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::ApiTokenCreated,
+        Some(user_id),
+        Some(&format!("api_token:{}", token_id)),
+        Some(client.ip),
+        Some(&new_token.name),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create an API token.")
+        .map_err(e500)?;
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>New API token</title>
+</head>
+<body>
+    <p>Copy your new API token now. It will not be shown again.</p>
+    <p><code>{}</code></p>
+    <p>Send it in the <code>Authorization: Bearer</code> header of your API requests.</p>
+    <p><a href="/admin/api-tokens">Continue</a></p>
+</body>
+</html>"#,
+        token.expose_secret()
+    ))
+    .into_response())
+}
+
+#[tracing::instrument(name = "Revoke an API token", skip(state, client, flash))]
+pub async fn revoke_api_token(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+    Path(token_id): Path<Uuid>,
+) -> Result<Response, Response> {
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let revoked = api_tokens::revoke_api_token(&mut *transaction, token_id, user_id)
+        .await
+        .map_err(e500)?;
+    if !revoked {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+    record_user_event(
+        &mut *transaction,
+        UserEvent::ApiTokenRevoked,
+        user_id,
+        Some(user_id),
+        Some(&token_id.to_string()),
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::ApiTokenRevoked,
+        Some(user_id),
+        Some(&format!("api_token:{}", token_id)),
+        Some(client.ip),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to revoke an API token.")
+        .map_err(e500)?;
+
+    Ok((
+        flash.info("The token has been revoked."),
+        Redirect::to("/admin/api-tokens"),
+    )
+        .into_response())
+}
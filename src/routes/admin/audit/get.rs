@@ -0,0 +1,255 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use chrono::NaiveDate;
+use htmlescape::{encode_attribute, encode_minimal};
+
+use crate::{
+    audit::{search_audit_log, AuditAction, AuditQuery},
+    authentication::UserId,
+    domain::Permission,
+    routes::admin::require_permission,
+    utils::e500,
+    AppState,
+};
+
+/// Number of entries rendered on a single page of the audit log.
+const PAGE_SIZE: i64 = 50;
+/// Upper bound on the number of entries returned by a single export.
+const EXPORT_LIMIT: i64 = 10_000;
+
+#[derive(serde::Deserialize)]
+pub struct AuditParameters {
+    search: Option<String>,
+    action: Option<String>,
+    from: Option<String>,
+    until: Option<String>,
+    page: Option<i64>,
+}
+
+/// Validated filters for the audit log.
+///
+/// As for the subscriber listing, empty values mean "no filter".
+struct AuditFilter {
+    search: Option<String>,
+    action: Option<AuditAction>,
+    from: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    page: i64,
+}
+
+impl TryFrom<AuditParameters> for AuditFilter {
+    type Error = String;
+
+    fn try_from(parameters: AuditParameters) -> Result<Self, Self::Error> {
+        let non_empty =
+            |s: Option<String>| s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        let parse_date = |s: Option<String>| {
+            non_empty(s)
+                .map(|s| {
+                    NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+                        .map_err(|_| format!("{} is not a valid date.", s))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            search: non_empty(parameters.search),
+            action: non_empty(parameters.action)
+                .map(AuditAction::parse)
+                .transpose()?,
+            from: parse_date(parameters.from)?,
+            until: parse_date(parameters.until)?,
+            page: parameters.page.unwrap_or(1).max(1),
+        })
+    }
+}
+
+impl AuditFilter {
+    /// Render the filters as a query string, so that links keep them.
+    fn query_string(&self, page: i64) -> String {
+        let mut query = format!("page={}", page);
+        if let Some(search) = &self.search {
+            write!(query, "&search={}", urlencoding::encode(search)).unwrap();
+        }
+        if let Some(action) = &self.action {
+            write!(query, "&action={}", action).unwrap();
+        }
+        if let Some(date) = &self.from {
+            write!(query, "&from={}", date).unwrap();
+        }
+        if let Some(date) = &self.until {
+            write!(query, "&until={}", date).unwrap();
+        }
+        query
+    }
+
+    /// The end date is inclusive: we filter on the start of the following day.
+    fn to_query(&self) -> AuditQuery {
+        AuditQuery {
+            search: self.search.clone(),
+            action: self.action,
+            from: self
+                .from
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|d| d.and_utc()),
+            until: self
+                .until
+                .and_then(|d| d.succ_opt())
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|d| d.and_utc()),
+        }
+    }
+}
+
+#[tracing::instrument(name = "List audit log", skip(state, parameters))]
+pub async fn list_audit_log(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    Query(parameters): Query<AuditParameters>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ViewAuditLog, &state.db_pool).await?;
+    let filter = match AuditFilter::try_from(parameters) {
+        Ok(filter) => filter,
+        Err(e) => return Err((StatusCode::BAD_REQUEST, e).into_response()),
+    };
+
+    let (records, total) = search_audit_log(
+        &filter.to_query(),
+        PAGE_SIZE,
+        (filter.page - 1) * PAGE_SIZE,
+        &state.db_pool,
+    )
+    .await
+    .map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for record in &records {
+        writeln!(
+            rows_html,
+            r#"        <tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            record.occurred_at.format("%Y-%m-%d %H:%M:%S"),
+            encode_minimal(record.actor.as_deref().unwrap_or("-")),
+            encode_minimal(&record.action),
+            encode_minimal(record.target.as_deref().unwrap_or_default()),
+            encode_minimal(record.ip.as_deref().unwrap_or_default()),
+            encode_minimal(record.details.as_deref().unwrap_or_default()),
+        )
+        .unwrap();
+    }
+
+    let mut action_options = String::from(r#"<option value="">Any</option>"#);
+    for action in AuditAction::ALL {
+        let selected = if filter.action == Some(action) {
+            " selected"
+        } else {
+            ""
+        };
+        write!(
+            action_options,
+            r#"<option value="{action}"{selected}>{action}</option>"#
+        )
+        .unwrap();
+    }
+
+    let last_page = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    let mut pagination_html = String::new();
+    if filter.page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/audit?{}">&lt; Previous</a> "#,
+            encode_attribute(&filter.query_string(filter.page - 1))
+        )
+        .unwrap();
+    }
+    write!(pagination_html, "Page {} of {}", filter.page, last_page).unwrap();
+    if filter.page < last_page {
+        write!(
+            pagination_html,
+            r#" <a href="/admin/audit?{}">Next &gt;</a>"#,
+            encode_attribute(&filter.query_string(filter.page + 1))
+        )
+        .unwrap();
+    }
+
+    let search = encode_attribute(filter.search.as_deref().unwrap_or_default());
+    let from = filter.from.map(|d| d.to_string()).unwrap_or_default();
+    let until = filter.until.map(|d| d.to_string()).unwrap_or_default();
+    let export_query = encode_attribute(&filter.query_string(1));
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Audit log</title>
+</head>
+<body>
+    <form action="/admin/audit" method="get">
+        <label>Search
+            <input
+                type="text"
+                placeholder="User, target, IP or details"
+                name="search"
+                value="{search}"
+            >
+        </label>
+        <label>Action
+            <select name="action">{action_options}</select>
+        </label>
+        <label>From
+            <input type="date" name="from" value="{from}">
+        </label>
+        <label>Until
+            <input type="date" name="until" value="{until}">
+        </label>
+        <button type="submit">Filter</button>
+    </form>
+    <p>Entries found: {total}. <a href="/admin/audit/export?{export_query}">Export as JSON</a></p>
+    <table>
+        <tr>
+            <th>Time (UTC)</th>
+            <th>User</th>
+            <th>Action</th>
+            <th>Target</th>
+            <th>IP address</th>
+            <th>Details</th>
+        </tr>
+{rows_html}    </table>
+    <p>{pagination_html}</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+    ))
+    .into_response())
+}
+
+/// Every entry matching the filters, most recent first, as JSON.
+/// The page number is ignored.
+#[tracing::instrument(name = "Export audit log", skip(state, parameters))]
+pub async fn export_audit_log(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    Query(parameters): Query<AuditParameters>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ViewAuditLog, &state.db_pool).await?;
+    let filter = match AuditFilter::try_from(parameters) {
+        Ok(filter) => filter,
+        Err(e) => return Err((StatusCode::BAD_REQUEST, e).into_response()),
+    };
+    let (records, _total) = search_audit_log(&filter.to_query(), EXPORT_LIMIT, 0, &state.db_pool)
+        .await
+        .map_err(e500)?;
+    Ok(Json(records).into_response())
+}
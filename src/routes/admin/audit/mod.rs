@@ -0,0 +1,2 @@
+mod get;
+pub use get::{export_audit_log, list_audit_log};
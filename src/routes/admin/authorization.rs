@@ -0,0 +1,25 @@
+use axum::response::Response;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::get_user_role,
+    domain::{Permission, UserRole},
+    utils::{e403, e500},
+};
+
+/// Look up the role of `user_id` and render a 403 page unless it grants
+/// `permission`.
+#[tracing::instrument(name = "Check permission", skip(pool))]
+pub async fn require_permission(
+    user_id: Uuid,
+    permission: Permission,
+    pool: &PgPool,
+) -> Result<UserRole, Response> {
+    let role = get_user_role(user_id, pool).await.map_err(e500)?;
+    if !role.can(permission) {
+        tracing::warn!(%role, ?permission, "Permission denied");
+        return Err(e403());
+    }
+    Ok(role)
+}
@@ -1,30 +1,62 @@
 use anyhow::Context;
 use axum::{
     extract::State,
-    response::{Html, IntoResponse, Redirect},
+    response::{Html, IntoResponse},
 };
+use htmlescape::encode_minimal;
 use reqwest::StatusCode;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{session_state::TypedSession, AppState};
+use crate::{
+    authentication::{get_user_role, UserId},
+    csrf::CsrfToken,
+    domain::Permission,
+    AppState,
+};
 
-#[tracing::instrument(name = "Admin Dashboard", skip(state, session))]
+#[tracing::instrument(name = "Admin Dashboard", skip(state, csrf_token))]
 pub async fn admin_dashboard(
     State(state): State<AppState>,
-    session: TypedSession,
+    UserId(user_id): UserId,
+    csrf_token: CsrfToken,
 ) -> Result<Html<String>, impl IntoResponse> {
-    let username = match session
-        .get_user_id()
+    let username = get_username(user_id, &state.db_pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+    let role = get_user_role(user_id, &state.db_pool)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?
-    { Some(user_id) => {
-        get_username(user_id, &state.db_pool)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?
-    } _ => {
-        return Err(Redirect::to("/login").into_response());
-    }};
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    let mut actions_html = String::new();
+    if role.can(Permission::ViewSubscribers) {
+        actions_html.push_str(
+            r#"<li><a href="/admin/subscribers">Manage subscribers</a></li>
+        <li><a href="/admin/newsletters">Newsletter issues</a></li>
+        "#,
+        );
+    }
+    if role.can(Permission::ManageUsers) {
+        actions_html.push_str(
+            r#"<li><a href="/admin/users">Manage users</a></li>
+        "#,
+        );
+    }
+    if role.can(Permission::ViewAuditLog) {
+        actions_html.push_str(
+            r#"<li><a href="/admin/audit">Audit log</a></li>
+        "#,
+        );
+    }
+    if role.can(Permission::ManageWebhooks) {
+        actions_html.push_str(
+            r#"<li><a href="/admin/webhooks">Webhooks</a></li>
+        "#,
+        );
+    }
+
+    let csrf_field = csrf_token.form_field();
+    let username = encode_minimal(&username);
 
     Ok(Html(format!(
         r#"<!DOCTYPE html>
@@ -35,11 +67,16 @@ pub async fn admin_dashboard(
 </head>
 <body>
     <p>Welcome {username}!</p>
+    <p>You are signed in as {role}.</p>
     <p>Available actions:</p>
     <ol>
-        <li><a href="/admin/password">Change password</a></li>
+        {actions_html}<li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/two-factor">Two-factor authentication</a></li>
+        <li><a href="/admin/api-tokens">API tokens</a></li>
+        <li><a href="/admin/sessions">Active sessions</a></li>
         <li>
             <form name="logoutForm" action="/admin/logout" method="post">
+                {csrf_field}
                 <input type="submit" value="Logout">
             </form>
         </li>
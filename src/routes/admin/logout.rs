@@ -1,20 +1,40 @@
-use axum::response::{IntoResponse, Redirect};
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+};
 use axum_flash::Flash;
 use reqwest::StatusCode;
 
-use crate::{session_state::TypedSession, utils::e500};
+use crate::{
+    audit::{record_audit_entry, AuditAction},
+    authentication::UserId,
+    session_state::TypedSession,
+    user_sessions::{forget_session, ClientInfo},
+    utils::e500,
+    AppState,
+};
 
 pub async fn log_out(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    client: ClientInfo,
     flash: Flash,
     session: TypedSession,
 ) -> Result<impl IntoResponse, impl IntoResponse> {
-    match session.get_user_id().await {
-        Ok(user_id) => {
-            if user_id.is_none() {
-                return Ok(Redirect::to("/login").into_response());
-            }
-        }
-        Err(e) => return Err(e500(e).into_response()),
+    record_audit_entry(
+        &state.db_pool,
+        AuditAction::LoggedOut,
+        Some(user_id),
+        None,
+        Some(client.ip),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    if let Some(session_id) = session.id() {
+        forget_session(session_id, &state.db_pool)
+            .await
+            .map_err(e500)?;
     }
     session
         .log_out()
@@ -1,7 +1,25 @@
+mod api_tokens;
+mod audit;
+mod authorization;
 mod dashboard;
 mod logout;
+mod newsletters;
 mod password;
+mod sessions;
+mod subscribers;
+mod two_factor;
+mod users;
+mod webhooks;
 
-pub use dashboard::admin_dashboard;
+pub use api_tokens::*;
+pub use audit::*;
+pub use authorization::require_permission;
+pub use dashboard::{admin_dashboard, get_username};
 pub use logout::log_out;
+pub use newsletters::*;
 pub use password::*;
+pub use sessions::*;
+pub use subscribers::*;
+pub use two_factor::*;
+pub use users::*;
+pub use webhooks::*;
@@ -0,0 +1,358 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use htmlescape::{encode_attribute, encode_minimal};
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    domain::Permission,
+    issue_deliveries::{
+        get_issue_summary, list_issues, search_deliveries, DeliveryQuery, RecipientStatus,
+    },
+    routes::admin::require_permission,
+    utils::{csv_field, e500},
+    AppState,
+};
+
+/// How many of the latest issues the listing shows.
+const ISSUES_SHOWN: i64 = 50;
+/// Number of recipients rendered on a single page of a report.
+const PAGE_SIZE: i64 = 50;
+/// Upper bound on the number of recipients in a single export.
+const EXPORT_LIMIT: i64 = 100_000;
+
+#[derive(serde::Deserialize)]
+pub struct ReportParameters {
+    search: Option<String>,
+    status: Option<String>,
+    page: Option<i64>,
+}
+
+/// Validated filters for the recipients of an issue.
+///
+/// As for the subscriber listing, empty values mean "no filter".
+struct ReportFilter {
+    search: Option<String>,
+    status: Option<RecipientStatus>,
+    page: i64,
+}
+
+impl TryFrom<ReportParameters> for ReportFilter {
+    type Error = String;
+
+    fn try_from(parameters: ReportParameters) -> Result<Self, Self::Error> {
+        let non_empty =
+            |s: Option<String>| s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        Ok(Self {
+            search: non_empty(parameters.search),
+            status: non_empty(parameters.status)
+                .map(RecipientStatus::parse)
+                .transpose()?,
+            page: parameters.page.unwrap_or(1).max(1),
+        })
+    }
+}
+
+impl ReportFilter {
+    /// Render the filters as a query string, so that links keep them.
+    fn query_string(&self, page: i64) -> String {
+        let mut query = format!("page={}", page);
+        if let Some(search) = &self.search {
+            write!(query, "&search={}", urlencoding::encode(search)).unwrap();
+        }
+        if let Some(status) = &self.status {
+            write!(query, "&status={}", status).unwrap();
+        }
+        query
+    }
+
+    fn to_query(&self) -> DeliveryQuery {
+        DeliveryQuery {
+            status: self.status,
+            search: self.search.clone(),
+        }
+    }
+}
+
+#[tracing::instrument(name = "List newsletter issues", skip(state))]
+pub async fn list_newsletter_issues(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ViewSubscribers, &state.db_pool).await?;
+    let issues = list_issues(ISSUES_SHOWN, &state.db_pool)
+        .await
+        .map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for issue in &issues {
+        writeln!(
+            rows_html,
+            r#"        <tr>
+            <td><a href="/admin/newsletters/{}">{}</a></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            issue.issue_id,
+            encode_minimal(&issue.title),
+            issue.published_at.format("%Y-%m-%d %H:%M UTC"),
+            issue.counts.sent,
+            issue.counts.failed,
+            issue.counts.pending,
+            issue.counts.suppressed,
+        )
+        .unwrap();
+    }
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter issues</title>
+</head>
+<body>
+    <table>
+        <tr>
+            <th>Title</th>
+            <th>Published</th>
+            <th>Sent</th>
+            <th>Failed</th>
+            <th>Pending</th>
+            <th>Suppressed</th>
+        </tr>
+{rows_html}    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+    ))
+    .into_response())
+}
+
+#[tracing::instrument(name = "Newsletter delivery report", skip(state, parameters))]
+pub async fn newsletter_report(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    Path(issue_id): Path<Uuid>,
+    Query(parameters): Query<ReportParameters>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ViewSubscribers, &state.db_pool).await?;
+    let filter = match ReportFilter::try_from(parameters) {
+        Ok(filter) => filter,
+        Err(e) => return Err((StatusCode::BAD_REQUEST, e).into_response()),
+    };
+    let Some(issue) = get_issue_summary(issue_id, &state.db_pool)
+        .await
+        .map_err(e500)?
+    else {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    };
+    let (deliveries, total) = search_deliveries(
+        issue_id,
+        &filter.to_query(),
+        PAGE_SIZE,
+        (filter.page - 1) * PAGE_SIZE,
+        &state.db_pool,
+    )
+    .await
+    .map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for delivery in &deliveries {
+        writeln!(
+            rows_html,
+            r#"        <tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            encode_minimal(&delivery.recipient),
+            encode_minimal(&delivery.status),
+            delivery.attempts,
+            delivery
+                .last_attempt_at
+                .map(|d| d.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            delivery
+                .sent_at
+                .map(|d| d.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            encode_minimal(delivery.provider_message_id.as_deref().unwrap_or_default()),
+            encode_minimal(delivery.error.as_deref().unwrap_or_default()),
+        )
+        .unwrap();
+    }
+
+    let mut status_options = String::from(r#"<option value="">Any</option>"#);
+    for status in RecipientStatus::ALL {
+        let selected = if filter.status == Some(status) {
+            " selected"
+        } else {
+            ""
+        };
+        write!(
+            status_options,
+            r#"<option value="{status}"{selected}>{status}</option>"#
+        )
+        .unwrap();
+    }
+
+    let report_url = format!("/admin/newsletters/{}", issue_id);
+    let last_page = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    let mut pagination_html = String::new();
+    if filter.page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="{}?{}">&lt; Previous</a> "#,
+            report_url,
+            encode_attribute(&filter.query_string(filter.page - 1))
+        )
+        .unwrap();
+    }
+    write!(pagination_html, "Page {} of {}", filter.page, last_page).unwrap();
+    if filter.page < last_page {
+        write!(
+            pagination_html,
+            r#" <a href="{}?{}">Next &gt;</a>"#,
+            report_url,
+            encode_attribute(&filter.query_string(filter.page + 1))
+        )
+        .unwrap();
+    }
+
+    let title = encode_minimal(&issue.title);
+    let published_at = issue.published_at.format("%Y-%m-%d %H:%M UTC");
+    let counts = issue.counts;
+    let search = encode_attribute(filter.search.as_deref().unwrap_or_default());
+    let export_query = encode_attribute(&filter.query_string(1));
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Delivery report</title>
+</head>
+<body>
+    <p>Issue: {title}</p>
+    <p>Published at: {published_at}</p>
+    <ul>
+        <li>Sent: {sent}</li>
+        <li>Failed: {failed}</li>
+        <li>Pending: {pending}</li>
+        <li>Suppressed: {suppressed}</li>
+    </ul>
+    <form action="{report_url}" method="get">
+        <label>Search
+            <input
+                type="text"
+                placeholder="Email"
+                name="search"
+                value="{search}"
+            >
+        </label>
+        <label>Status
+            <select name="status">{status_options}</select>
+        </label>
+        <button type="submit">Filter</button>
+    </form>
+    <p>Recipients found: {total}. <a href="{report_url}/export?{export_query}">Export as CSV</a></p>
+    <table>
+        <tr>
+            <th>Recipient</th>
+            <th>Status</th>
+            <th>Attempts</th>
+            <th>Last attempt</th>
+            <th>Sent at</th>
+            <th>Message ID</th>
+            <th>Error</th>
+        </tr>
+{rows_html}    </table>
+    <p>{pagination_html}</p>
+    <p><a href="/admin/newsletters">&lt;- Back</a></p>
+</body>
+</html>"#,
+        sent = counts.sent,
+        failed = counts.failed,
+        pending = counts.pending,
+        suppressed = counts.suppressed,
+    ))
+    .into_response())
+}
+
+/// Every recipient matching the filters, as CSV with a header row. The page
+/// number is ignored.
+#[tracing::instrument(name = "Export newsletter delivery report", skip(state, parameters))]
+pub async fn export_newsletter_report(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    Path(issue_id): Path<Uuid>,
+    Query(parameters): Query<ReportParameters>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ViewSubscribers, &state.db_pool).await?;
+    let filter = match ReportFilter::try_from(parameters) {
+        Ok(filter) => filter,
+        Err(e) => return Err((StatusCode::BAD_REQUEST, e).into_response()),
+    };
+    if get_issue_summary(issue_id, &state.db_pool)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+    let (deliveries, _total) = search_deliveries(
+        issue_id,
+        &filter.to_query(),
+        EXPORT_LIMIT,
+        0,
+        &state.db_pool,
+    )
+    .await
+    .map_err(e500)?;
+
+    let mut csv = String::from(
+        "recipient,status,attempts,provider_message_id,error,created_at,last_attempt_at,sent_at\n",
+    );
+    let format_date = |date: Option<chrono::DateTime<chrono::Utc>>| {
+        date.map(|d| d.to_rfc3339()).unwrap_or_default()
+    };
+    for delivery in deliveries {
+        writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{}",
+            csv_field(&delivery.recipient),
+            csv_field(&delivery.status),
+            delivery.attempts,
+            csv_field(delivery.provider_message_id.as_deref().unwrap_or_default()),
+            csv_field(delivery.error.as_deref().unwrap_or_default()),
+            delivery.created_at.to_rfc3339(),
+            format_date(delivery.last_attempt_at),
+            format_date(delivery.sent_at),
+        )
+        .unwrap();
+    }
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(r#"attachment; filename="issue-{}.csv""#, issue_id),
+            ),
+        ],
+        csv,
+    )
+        .into_response())
+}
@@ -0,0 +1,2 @@
+mod get;
+pub use get::{export_newsletter_report, list_newsletter_issues, newsletter_report};
@@ -1,24 +1,22 @@
 use std::fmt::Write;
 
-use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::response::Html;
 use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
 
-use crate::{session_state::TypedSession, utils::e500};
+use crate::csrf::CsrfToken;
 
 pub async fn change_password_form(
-    session: TypedSession,
+    csrf_token: CsrfToken,
     flash_messages: IncomingFlashes,
-) -> Result<impl IntoResponse, Response> {
-    if session.get_user_id().await.map_err(e500)?.is_none() {
-        return Ok(Redirect::to("/login").into_response());
-    }
-
+) -> Html<String> {
+    let csrf_field = csrf_token.form_field();
     let mut msg_html = String::new();
     for (_level, content) in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", content).unwrap()
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap()
     }
 
-    Ok(Html(format!(
+    Html(format!(
         r#"<!DOCTYPE html>
 <html lang="en">
 <head>
@@ -28,6 +26,7 @@ pub async fn change_password_form(
 <body>
     {msg_html}
     <form action="/admin/password" method="post">
+        {csrf_field}
         <label>Current password
             <input
                 type="password"
@@ -58,5 +57,4 @@ pub async fn change_password_form(
 </body>
 </html>"#,
     ))
-    .into_response())
 }
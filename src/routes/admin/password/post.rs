@@ -7,9 +7,12 @@ use axum_flash::Flash;
 use secrecy::{ExposeSecret, SecretString};
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials},
+    audit::{record_audit_entry, AuditAction},
+    authentication::{validate_credentials, AuthError, Credentials, UserId},
+    domain::NewPassword,
     routes::admin::dashboard::get_username,
     session_state::TypedSession,
+    user_sessions::{end_user_sessions, ClientInfo},
     utils::e500,
     AppState,
 };
@@ -24,18 +27,11 @@ pub struct FormData {
 pub async fn change_password(
     State(state): State<AppState>,
     session: TypedSession,
+    UserId(user_id): UserId,
+    client: ClientInfo,
     flash: Flash,
     Form(form): Form<FormData>,
 ) -> Result<impl IntoResponse, impl IntoResponse> {
-    let user_id = match session.get_user_id().await {
-        Ok(user_id) => user_id,
-        Err(e) => return Err(e500(e)),
-    };
-    if user_id.is_none() {
-        return Ok(Redirect::to("/login").into_response());
-    }
-    let user_id = user_id.unwrap();
-
     if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
         return Err((
             flash.error("You entered two different new passwords - the field values must match."),
@@ -43,13 +39,18 @@ pub async fn change_password(
         )
             .into_response());
     }
+    let new_password = match NewPassword::parse(form.new_password, &state.password_policy) {
+        Ok(new_password) => new_password,
+        Err(e) => return Ok((flash.error(e), Redirect::to("/admin/password")).into_response()),
+    };
     let username = get_username(user_id, &state.db_pool).await.map_err(e500)?;
 
     let credentials = Credentials {
         username,
         password: form.current_password,
     };
-    if let Err(e) = validate_credentials(credentials, &state.db_pool).await {
+    if let Err(e) = validate_credentials(credentials, &state.password_hashing, &state.db_pool).await
+    {
         return match e {
             AuthError::InvalidCredentials(_) => Ok((
                 flash.error("The current password is incorrect."),
@@ -59,7 +60,26 @@ pub async fn change_password(
             AuthError::UnexpectedError(_) => Err(e500(e).into_response()),
         };
     }
-    crate::authentication::change_password(user_id, form.new_password, &state.db_pool)
+    crate::authentication::change_password(
+        user_id,
+        new_password,
+        &state.password_hashing,
+        &state.db_pool,
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_entry(
+        &state.db_pool,
+        AuditAction::PasswordChanged,
+        Some(user_id),
+        Some(&format!("user:{}", user_id)),
+        Some(client.ip),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    // Whoever else knew the old password must not stay logged in.
+    end_user_sessions(user_id, session.id(), &state.session_store, &state.db_pool)
         .await
         .map_err(e500)?;
     Ok((
@@ -0,0 +1,97 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse, Response},
+};
+use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
+
+use crate::{
+    authentication::UserId, csrf::CsrfToken, session_state::TypedSession,
+    user_sessions::list_user_sessions, utils::e500, AppState,
+};
+
+#[tracing::instrument(
+    name = "List active sessions",
+    skip(state, session, csrf_token, flash_messages)
+)]
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    session: TypedSession,
+    UserId(user_id): UserId,
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+) -> Result<Response, Response> {
+    let sessions = list_user_sessions(user_id, session.id(), &state.session_store, &state.db_pool)
+        .await
+        .map_err(e500)?;
+
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+
+    let mut sessions_html = String::new();
+    for active_session in &sessions {
+        let action_html = if active_session.is_current {
+            "This session".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/{}/revoke" method="post">
+                {csrf_field}
+                <button type="submit">Revoke</button>
+            </form>"#,
+                active_session.id
+            )
+        };
+        writeln!(
+            sessions_html,
+            r#"        <tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            active_session.created_at.format("%Y-%m-%d %H:%M UTC"),
+            active_session.last_seen_at.format("%Y-%m-%d %H:%M UTC"),
+            encode_minimal(active_session.ip.as_deref().unwrap_or("unknown")),
+            encode_minimal(active_session.user_agent.as_deref().unwrap_or("unknown")),
+            action_html,
+        )
+        .unwrap();
+    }
+
+    Ok((
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Active sessions</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr>
+            <th>Signed in</th>
+            <th>Last seen</th>
+            <th>IP address</th>
+            <th>Browser</th>
+            <th></th>
+        </tr>
+{sessions_html}    </table>
+    <form action="/admin/sessions/revoke-others" method="post">
+        {csrf_field}
+        <button type="submit">Sign out all other sessions</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )),
+    )
+        .into_response())
+}
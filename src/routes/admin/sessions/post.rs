@@ -0,0 +1,114 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_flash::Flash;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_entry, AuditAction},
+    authentication::UserId,
+    routes::admin::{record_user_event, UserEvent},
+    session_state::TypedSession,
+    user_sessions::{end_user_sessions, get_public_session_id, revoke_user_session, ClientInfo},
+    utils::e500,
+    AppState,
+};
+
+#[tracing::instrument(name = "Revoke a session", skip(state, session, client, flash))]
+pub async fn revoke_session(
+    State(state): State<AppState>,
+    session: TypedSession,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+    Path(id): Path<Uuid>,
+) -> Result<Response, Response> {
+    // The current session is ended by logging out, not from here.
+    if let Some(session_id) = session.id() {
+        let current = get_public_session_id(session_id, &state.db_pool)
+            .await
+            .map_err(e500)?;
+        if current == Some(id) {
+            return Ok((
+                flash.error("Log out to end the current session."),
+                Redirect::to("/admin/sessions"),
+            )
+                .into_response());
+        }
+    }
+
+    let revoked = revoke_user_session(user_id, id, &state.session_store, &state.db_pool)
+        .await
+        .map_err(e500)?;
+    if !revoked {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+    record_user_event(
+        &state.db_pool,
+        UserEvent::SessionsRevoked,
+        user_id,
+        Some(user_id),
+        Some(&id.to_string()),
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_entry(
+        &state.db_pool,
+        AuditAction::SessionRevoked,
+        Some(user_id),
+        Some(&format!("session:{}", id)),
+        Some(client.ip),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+
+    Ok((
+        flash.info("The session has been revoked."),
+        Redirect::to("/admin/sessions"),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(
+    name = "Revoke all other sessions",
+    skip(state, session, client, flash)
+)]
+pub async fn revoke_other_sessions(
+    State(state): State<AppState>,
+    session: TypedSession,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+) -> Result<Response, Response> {
+    end_user_sessions(user_id, session.id(), &state.session_store, &state.db_pool)
+        .await
+        .map_err(e500)?;
+    record_user_event(
+        &state.db_pool,
+        UserEvent::SessionsRevoked,
+        user_id,
+        Some(user_id),
+        Some("all other sessions"),
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_entry(
+        &state.db_pool,
+        AuditAction::SessionRevoked,
+        Some(user_id),
+        Some(&format!("user:{}", user_id)),
+        Some(client.ip),
+        Some("all other sessions"),
+    )
+    .await
+    .map_err(e500)?;
+
+    Ok((
+        flash.info("All your other sessions have been signed out."),
+        Redirect::to("/admin/sessions"),
+    )
+        .into_response())
+}
@@ -0,0 +1,428 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use axum_flash::IncomingFlashes;
+use chrono::{DateTime, NaiveDate, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    csrf::CsrfToken,
+    domain::{Permission, SubscriberStatus},
+    routes::admin::require_permission,
+    utils::e500,
+    AppState,
+};
+
+/// Number of subscribers rendered on a single page of the listing.
+const PAGE_SIZE: i64 = 25;
+
+#[derive(serde::Deserialize)]
+pub struct ListParameters {
+    search: Option<String>,
+    status: Option<String>,
+    subscribed_from: Option<String>,
+    subscribed_until: Option<String>,
+    page: Option<i64>,
+}
+
+/// Validated filters for the subscriber listing.
+///
+/// HTML forms submit empty strings for untouched fields, so every empty
+/// value is treated as "no filter".
+struct SubscriberFilter {
+    search: Option<String>,
+    status: Option<SubscriberStatus>,
+    subscribed_from: Option<NaiveDate>,
+    subscribed_until: Option<NaiveDate>,
+    page: i64,
+}
+
+impl TryFrom<ListParameters> for SubscriberFilter {
+    type Error = String;
+
+    fn try_from(parameters: ListParameters) -> Result<Self, Self::Error> {
+        let non_empty =
+            |s: Option<String>| s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        let parse_date = |s: Option<String>| {
+            non_empty(s)
+                .map(|s| {
+                    NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+                        .map_err(|_| format!("{} is not a valid date.", s))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            search: non_empty(parameters.search),
+            status: non_empty(parameters.status)
+                .map(SubscriberStatus::parse)
+                .transpose()?,
+            subscribed_from: parse_date(parameters.subscribed_from)?,
+            subscribed_until: parse_date(parameters.subscribed_until)?,
+            page: parameters.page.unwrap_or(1).max(1),
+        })
+    }
+}
+
+impl SubscriberFilter {
+    /// Render the filters as a query string, so that pagination links keep them.
+    fn query_string(&self, page: i64) -> String {
+        let mut query = format!("page={}", page);
+        if let Some(search) = &self.search {
+            write!(query, "&search={}", urlencoding::encode(search)).unwrap();
+        }
+        if let Some(status) = &self.status {
+            write!(query, "&status={}", status).unwrap();
+        }
+        if let Some(date) = &self.subscribed_from {
+            write!(query, "&subscribed_from={}", date).unwrap();
+        }
+        if let Some(date) = &self.subscribed_until {
+            write!(query, "&subscribed_until={}", date).unwrap();
+        }
+        query
+    }
+
+    /// Turn the search term into a case-insensitive `ILIKE` pattern.
+    fn search_pattern(&self) -> Option<String> {
+        self.search.as_ref().map(|s| {
+            let escaped = s
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{}%", escaped)
+        })
+    }
+
+    fn start_timestamp(&self) -> Option<DateTime<Utc>> {
+        self.subscribed_from
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|d| d.and_utc())
+    }
+
+    /// The end date is inclusive: we filter on the start of the following day.
+    fn end_timestamp(&self) -> Option<DateTime<Utc>> {
+        self.subscribed_until
+            .and_then(|d| d.succ_opt())
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|d| d.and_utc())
+    }
+}
+
+pub struct SubscriberRow {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List subscribers", skip(state, flash_messages, parameters))]
+pub async fn list_subscribers(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    flash_messages: IncomingFlashes,
+    Query(parameters): Query<ListParameters>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ViewSubscribers, &state.db_pool).await?;
+    let filter = match SubscriberFilter::try_from(parameters) {
+        Ok(filter) => filter,
+        Err(e) => return Err((StatusCode::BAD_REQUEST, e).into_response()),
+    };
+
+    let (subscribers, total) = search_subscribers(&filter, &state.db_pool)
+        .await
+        .map_err(e500)?;
+
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for subscriber in &subscribers {
+        writeln!(
+            rows_html,
+            r#"        <tr>
+            <td><a href="/admin/subscribers/{}">{}</a></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            subscriber.id,
+            encode_minimal(&subscriber.email),
+            encode_minimal(&subscriber.name),
+            encode_minimal(&subscriber.status),
+            subscriber.subscribed_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+
+    let mut status_options = String::from(r#"<option value="">Any</option>"#);
+    for status in SubscriberStatus::ALL {
+        let selected = if filter.status == Some(status) {
+            " selected"
+        } else {
+            ""
+        };
+        write!(
+            status_options,
+            r#"<option value="{status}"{selected}>{status}</option>"#
+        )
+        .unwrap();
+    }
+
+    let last_page = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    let mut pagination_html = String::new();
+    if filter.page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/subscribers?{}">&lt; Previous</a> "#,
+            encode_attribute(&filter.query_string(filter.page - 1))
+        )
+        .unwrap();
+    }
+    write!(pagination_html, "Page {} of {}", filter.page, last_page).unwrap();
+    if filter.page < last_page {
+        write!(
+            pagination_html,
+            r#" <a href="/admin/subscribers?{}">Next &gt;</a>"#,
+            encode_attribute(&filter.query_string(filter.page + 1))
+        )
+        .unwrap();
+    }
+
+    let search = encode_attribute(filter.search.as_deref().unwrap_or_default());
+    let subscribed_from = filter
+        .subscribed_from
+        .map(|d| d.to_string())
+        .unwrap_or_default();
+    let subscribed_until = filter
+        .subscribed_until
+        .map(|d| d.to_string())
+        .unwrap_or_default();
+
+    Ok((
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/subscribers" method="get">
+        <label>Search
+            <input
+                type="text"
+                placeholder="Email or name"
+                name="search"
+                value="{search}"
+            >
+        </label>
+        <label>Status
+            <select name="status">{status_options}</select>
+        </label>
+        <label>Subscribed from
+            <input type="date" name="subscribed_from" value="{subscribed_from}">
+        </label>
+        <label>Subscribed until
+            <input type="date" name="subscribed_until" value="{subscribed_until}">
+        </label>
+        <button type="submit">Filter</button>
+    </form>
+    <p>{total} subscriber(s) found.</p>
+    <table>
+        <tr>
+            <th>Email</th>
+            <th>Name</th>
+            <th>Status</th>
+            <th>Subscribed at</th>
+        </tr>
+{rows_html}    </table>
+    <p>{pagination_html}</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(name = "Search subscribers", skip(filter, pool))]
+async fn search_subscribers(
+    filter: &SubscriberFilter,
+    pool: &PgPool,
+) -> Result<(Vec<SubscriberRow>, i64), anyhow::Error> {
+    let pattern = filter.search_pattern();
+    let status = filter.status.map(|s| s.as_str());
+    let from = filter.start_timestamp();
+    let until = filter.end_timestamp();
+
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscriptions
+        WHERE ($1::TEXT IS NULL OR email ILIKE $1 OR name ILIKE $1)
+            AND ($2::TEXT IS NULL OR status = $2)
+            AND ($3::TIMESTAMPTZ IS NULL OR subscribed_at >= $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR subscribed_at < $4)
+        "#,
+        pattern,
+        status,
+        from,
+        until,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count subscribers.")?;
+
+    let subscribers = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE ($1::TEXT IS NULL OR email ILIKE $1 OR name ILIKE $1)
+            AND ($2::TEXT IS NULL OR status = $2)
+            AND ($3::TIMESTAMPTZ IS NULL OR subscribed_at >= $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR subscribed_at < $4)
+        ORDER BY subscribed_at DESC, id
+        LIMIT $5 OFFSET $6
+        "#,
+        pattern,
+        status,
+        from,
+        until,
+        PAGE_SIZE,
+        (filter.page - 1) * PAGE_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve subscribers.")?;
+
+    Ok((subscribers, total))
+}
+
+#[tracing::instrument(name = "Subscriber details", skip(state, csrf_token, flash_messages))]
+pub async fn subscriber_details(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Response, Response> {
+    let role = require_permission(user_id, Permission::ViewSubscribers, &state.db_pool).await?;
+    let subscriber = match get_subscriber(subscriber_id, &state.db_pool)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => subscriber,
+        None => return Err(StatusCode::NOT_FOUND.into_response()),
+    };
+
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+
+    let mut status_options = String::new();
+    for status in SubscriberStatus::ALL {
+        let selected = if subscriber.status == status.as_str() {
+            " selected"
+        } else {
+            ""
+        };
+        write!(
+            status_options,
+            r#"<option value="{status}"{selected}>{status}</option>"#
+        )
+        .unwrap();
+    }
+
+    let id = subscriber.id;
+    let email = encode_minimal(&subscriber.email);
+    let subscribed_at = subscriber.subscribed_at.format("%Y-%m-%d %H:%M:%S UTC");
+    let details_html = if role.can(Permission::EditSubscribers) {
+        let name = encode_attribute(&subscriber.name);
+        format!(
+            r#"<form action="/admin/subscribers/{id}" method="post">
+        {csrf_field}
+        <label>Name
+            <input
+                type="text"
+                placeholder="Enter the subscriber name"
+                name="name"
+                value="{name}"
+            >
+        </label>
+        <br>
+        <label>Status
+            <select name="status">{status_options}</select>
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>
+    <form action="/admin/subscribers/{id}/delete" method="post">
+        {csrf_field}
+        <button type="submit">Delete subscriber</button>
+    </form>"#
+        )
+    } else {
+        format!(
+            "<p>Name: {}</p>\n    <p>Status: {}</p>",
+            encode_minimal(&subscriber.name),
+            encode_minimal(&subscriber.status),
+        )
+    };
+
+    Ok((
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber</title>
+</head>
+<body>
+    {msg_html}
+    <p>Email: {email}</p>
+    <p>Subscribed at: {subscribed_at}</p>
+    {details_html}
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+pub async fn get_subscriber(
+    subscriber_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<SubscriberRow>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a subscriber.")?;
+    Ok(subscriber)
+}
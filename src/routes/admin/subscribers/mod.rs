@@ -0,0 +1,6 @@
+mod get;
+pub use get::{list_subscribers, subscriber_details};
+mod post;
+pub use post::{
+    delete_subscriber, edit_subscriber, remove_subscriber, update_subscriber, SubscriberUpdate,
+};
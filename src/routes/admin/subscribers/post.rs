@@ -0,0 +1,245 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_entry, AuditAction},
+    authentication::UserId,
+    domain::{Permission, SubscriberName, SubscriberStatus},
+    routes::admin::require_permission,
+    user_sessions::ClientInfo,
+    utils::e500,
+    webhooks::{enqueue_event, WebhookEvent},
+    AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    status: String,
+}
+
+/// The details to change. Fields left to `None` are kept as they are.
+pub struct SubscriberUpdate {
+    pub name: Option<SubscriberName>,
+    pub status: Option<SubscriberStatus>,
+}
+
+impl TryFrom<FormData> for SubscriberUpdate {
+    type Error = String;
+
+    fn try_from(form: FormData) -> Result<Self, Self::Error> {
+        let name = SubscriberName::parse(form.name)?;
+        let status = SubscriberStatus::parse(form.status)?;
+        Ok(Self {
+            name: Some(name),
+            status: Some(status),
+        })
+    }
+}
+
+#[tracing::instrument(name = "Edit a subscriber", skip(state, client, flash, form))]
+pub async fn edit_subscriber(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+    Path(subscriber_id): Path<Uuid>,
+    Form(form): Form<FormData>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::EditSubscribers, &state.db_pool).await?;
+    let details_url = format!("/admin/subscribers/{}", subscriber_id);
+    let update: SubscriberUpdate = match form.try_into() {
+        Ok(update) => update,
+        Err(e) => return Ok((flash.error(e), Redirect::to(&details_url)).into_response()),
+    };
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let updated = update_subscriber(&mut transaction, subscriber_id, &update)
+        .await
+        .map_err(e500)?;
+    if !updated {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::SubscriberEdited,
+        Some(user_id),
+        Some(&format!("subscriber:{}", subscriber_id)),
+        Some(client.ip),
+        update.audit_details().as_deref(),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a subscriber.")
+        .map_err(e500)?;
+    Ok((
+        flash.info("The subscriber has been updated."),
+        Redirect::to(&details_url),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(name = "Delete a subscriber", skip(state, client, flash))]
+pub async fn delete_subscriber(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::EditSubscribers, &state.db_pool).await?;
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let deleted = remove_subscriber(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?;
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::SubscriberDeleted,
+        Some(user_id),
+        Some(&format!("subscriber:{}", subscriber_id)),
+        Some(client.ip),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber.")
+        .map_err(e500)?;
+    Ok((
+        flash.info("The subscriber has been deleted."),
+        Redirect::to("/admin/subscribers"),
+    )
+        .into_response())
+}
+
+impl SubscriberUpdate {
+    /// What the audit log records about the change.
+    pub fn audit_details(&self) -> Option<String> {
+        self.status.map(|status| format!("status {}", status))
+    }
+}
+
+struct UpdatedSubscriber {
+    email: String,
+    name: String,
+    status: String,
+    previous_status: String,
+}
+
+/// Apply `update`, and queue the `subscriber.unsubscribed` event if it
+/// unsubscribes them. Returns `false` if there is no subscriber with the
+/// given id.
+#[tracing::instrument(name = "Update subscriber details", skip(transaction, update))]
+pub async fn update_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    update: &SubscriberUpdate,
+) -> Result<bool, anyhow::Error> {
+    let updated = sqlx::query_as!(
+        UpdatedSubscriber,
+        r#"
+        WITH previous AS (
+            SELECT id, status FROM subscriptions WHERE id = $3 FOR UPDATE
+        )
+        UPDATE subscriptions
+        SET name = COALESCE($1, subscriptions.name),
+            status = COALESCE($2, subscriptions.status)
+        FROM previous
+        WHERE subscriptions.id = previous.id
+        RETURNING subscriptions.email, subscriptions.name, subscriptions.status,
+            previous.status AS previous_status
+        "#,
+        update.name.as_ref().map(|name| name.as_ref()),
+        update.status.map(|status| status.as_str()),
+        subscriber_id,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to update the subscriber in the database.")?;
+    let Some(updated) = updated else {
+        return Ok(false);
+    };
+    let unsubscribed = SubscriberStatus::Unsubscribed.as_str();
+    if updated.status == unsubscribed && updated.previous_status != unsubscribed {
+        enqueue_event(
+            &mut **transaction,
+            WebhookEvent::SubscriberUnsubscribed,
+            serde_json::json!({
+                "id": subscriber_id,
+                "email": updated.email,
+                "name": updated.name,
+                "status": updated.status,
+            }),
+        )
+        .await?;
+    }
+    Ok(true)
+}
+
+/// Delete the subscriber, and queue the `subscriber.unsubscribed` event
+/// unless they had already unsubscribed. Returns `false` if there is no
+/// subscriber with the given id.
+#[tracing::instrument(name = "Remove subscriber from the database", skip(transaction))]
+pub async fn remove_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete the subscriber's confirmation tokens.")?;
+    let deleted = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = $1 RETURNING email, name, status"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to delete the subscriber.")?;
+    let Some(deleted) = deleted else {
+        return Ok(false);
+    };
+    let unsubscribed = SubscriberStatus::Unsubscribed.as_str();
+    if deleted.status != unsubscribed {
+        enqueue_event(
+            &mut **transaction,
+            WebhookEvent::SubscriberUnsubscribed,
+            serde_json::json!({
+                "id": subscriber_id,
+                "email": deleted.email,
+                "name": deleted.name,
+                "status": unsubscribed,
+                "deleted": true,
+            }),
+        )
+        .await?;
+    }
+    Ok(true)
+}
@@ -0,0 +1,157 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse, Response},
+};
+use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
+
+use crate::{
+    authentication::{get_user_role, UserId},
+    csrf::CsrfToken,
+    domain::Permission,
+    routes::admin::get_username,
+    session_state::TypedSession,
+    two_factor::{
+        count_unused_recovery_codes, get_totp_secret, is_two_factor_required, TotpSecret,
+    },
+    utils::e500,
+    AppState,
+};
+
+use super::enrollment_html;
+
+#[tracing::instrument(
+    name = "Show two-factor authentication settings",
+    skip(state, session, csrf_token, flash_messages)
+)]
+pub async fn two_factor_settings(
+    State(state): State<AppState>,
+    session: TypedSession,
+    UserId(user_id): UserId,
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+) -> Result<Response, Response> {
+    let required = is_two_factor_required(&state.db_pool).await.map_err(e500)?;
+
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+
+    let status_html = if get_totp_secret(user_id, &state.db_pool)
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        let remaining = count_unused_recovery_codes(user_id, &state.db_pool)
+            .await
+            .map_err(e500)?;
+        let disable_html = if required {
+            "<p>Two-factor authentication is required for every account: it cannot be disabled.</p>"
+                .to_string()
+        } else {
+            code_form(
+                "/admin/two-factor/disable",
+                "Disable two-factor authentication",
+                &csrf_field,
+            )
+        };
+        format!(
+            r#"<p>Two-factor authentication is enabled. You have {remaining} unused recovery codes left.</p>
+    {}
+    {disable_html}"#,
+            code_form(
+                "/admin/two-factor/recovery-codes",
+                "Generate new recovery codes",
+                &csrf_field,
+            ),
+        )
+    } else {
+        // Reuse the pending secret so that reloading the page does not
+        // invalidate a QR code that was already scanned.
+        let secret = match session.get_totp_enrollment_secret().await.map_err(e500)? {
+            Some(secret) => secret,
+            None => {
+                let secret = TotpSecret::generate();
+                session
+                    .insert_totp_enrollment_secret(&secret)
+                    .await
+                    .map_err(e500)?;
+                secret
+            }
+        };
+        let username = get_username(user_id, &state.db_pool).await.map_err(e500)?;
+        format!(
+            r#"<p>Two-factor authentication is disabled.</p>
+    {}
+    {}"#,
+            enrollment_html(&secret, &username).map_err(e500)?,
+            code_form(
+                "/admin/two-factor",
+                "Enable two-factor authentication",
+                &csrf_field,
+            ),
+        )
+    };
+
+    let role = get_user_role(user_id, &state.db_pool).await.map_err(e500)?;
+    let policy_html = if role.can(Permission::ManageUsers) {
+        let (current, require, label) = if required {
+            ("required", false, "Make it optional")
+        } else {
+            ("optional", true, "Require it for every user")
+        };
+        format!(
+            r#"<p>Two-factor authentication is currently {current} for every user.</p>
+    <form action="/admin/two-factor/policy" method="post">
+        {csrf_field}
+        <input type="hidden" name="require" value="{require}">
+        <button type="submit">{label}</button>
+    </form>"#
+        )
+    } else {
+        String::new()
+    };
+
+    Ok((
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+    {msg_html}
+    {status_html}
+    {policy_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )),
+    )
+        .into_response())
+}
+
+/// A form asking for a code from the authenticator app before `action`.
+fn code_form(action: &str, label: &str, csrf_field: &str) -> String {
+    format!(
+        r#"<form action="{action}" method="post">
+        {csrf_field}
+        <label>Authentication code
+            <input
+                type="text"
+                inputmode="numeric"
+                autocomplete="one-time-code"
+                placeholder="Code from your app"
+                name="code"
+            >
+        </label>
+        <button type="submit">{label}</button>
+    </form>"#
+    )
+}
@@ -0,0 +1,47 @@
+mod get;
+pub use get::two_factor_settings;
+mod post;
+pub use post::{
+    enroll_two_factor, regenerate_recovery_codes, set_two_factor_policy, turn_off_two_factor,
+};
+
+use std::fmt::Write;
+
+use axum::response::Html;
+use htmlescape::encode_minimal;
+
+use crate::two_factor::TotpSecret;
+
+/// QR code and key to add the account to an authenticator app.
+pub fn enrollment_html(secret: &TotpSecret, account_name: &str) -> Result<String, anyhow::Error> {
+    let qr_code = secret.qr_code_base64(account_name)?;
+    Ok(format!(
+        r#"<p>Scan this QR code with your authenticator app, or enter the key by hand.</p>
+    <p><img src="data:image/png;base64,{qr_code}" alt="QR code for your authenticator app"></p>
+    <p>Key: <code>{}</code></p>"#,
+        encode_minimal(secret.expose_base32()),
+    ))
+}
+
+/// Recovery codes are only shown once, right after they were generated.
+pub fn recovery_codes_page(recovery_codes: &[String], continue_url: &str) -> Html<String> {
+    let mut codes_html = String::new();
+    for code in recovery_codes {
+        writeln!(codes_html, "        <li><code>{}</code></li>", code).unwrap();
+    }
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Recovery codes</title>
+</head>
+<body>
+    <p>Store these recovery codes somewhere safe. Each of them lets you log in once if you lose access to your authenticator app. They will not be shown again.</p>
+    <ul>
+{codes_html}    </ul>
+    <p><a href="{continue_url}">Continue</a></p>
+</body>
+</html>"#,
+    ))
+}
@@ -0,0 +1,258 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+
+use crate::{
+    audit::{record_audit_entry, AuditAction},
+    authentication::UserId,
+    domain::Permission,
+    routes::admin::{record_user_event, require_permission, UserEvent},
+    session_state::TypedSession,
+    two_factor::{
+        disable_two_factor, enable_two_factor, get_totp_secret, is_two_factor_required,
+        replace_recovery_codes, set_two_factor_required, verify_second_factor,
+    },
+    user_sessions::ClientInfo,
+    utils::e500,
+    AppState,
+};
+
+use super::recovery_codes_page;
+
+#[derive(serde::Deserialize)]
+pub struct CodeFormData {
+    code: String,
+}
+
+#[tracing::instrument(
+    name = "Enable two-factor authentication",
+    skip(state, session, flash, form)
+)]
+pub async fn enroll_two_factor(
+    State(state): State<AppState>,
+    session: TypedSession,
+    UserId(user_id): UserId,
+    flash: Flash,
+    Form(form): Form<CodeFormData>,
+) -> Result<Response, Response> {
+    let Some(secret) = session.get_totp_enrollment_secret().await.map_err(e500)? else {
+        return Ok(Redirect::to("/admin/two-factor").into_response());
+    };
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let Some(recovery_codes) = enable_two_factor(&mut transaction, user_id, &secret, &form.code)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok((
+            flash.error("The authentication code is invalid."),
+            Redirect::to("/admin/two-factor"),
+        )
+            .into_response());
+    };
+    record_user_event(
+        &mut *transaction,
+        UserEvent::TwoFactorEnabled,
+        user_id,
+        Some(user_id),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to enable two-factor authentication.")
+        .map_err(e500)?;
+    session
+        .remove_totp_enrollment_secret()
+        .await
+        .map_err(e500)?;
+
+    Ok(recovery_codes_page(&recovery_codes, "/admin/two-factor").into_response())
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(state, flash, form))]
+pub async fn turn_off_two_factor(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    flash: Flash,
+    Form(form): Form<CodeFormData>,
+) -> Result<Response, Response> {
+    if is_two_factor_required(&state.db_pool).await.map_err(e500)? {
+        return Ok((
+            flash.error("Two-factor authentication is required for every account."),
+            Redirect::to("/admin/two-factor"),
+        )
+            .into_response());
+    }
+    let Some(secret) = get_totp_secret(user_id, &state.db_pool)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(Redirect::to("/admin/two-factor").into_response());
+    };
+    if !verify_second_factor(user_id, &secret, &form.code, &state.db_pool)
+        .await
+        .map_err(e500)?
+    {
+        return Ok((
+            flash.error("The authentication code is invalid."),
+            Redirect::to("/admin/two-factor"),
+        )
+            .into_response());
+    }
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    disable_two_factor(&mut transaction, user_id)
+        .await
+        .map_err(e500)?;
+    record_user_event(
+        &mut *transaction,
+        UserEvent::TwoFactorDisabled,
+        user_id,
+        Some(user_id),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable two-factor authentication.")
+        .map_err(e500)?;
+
+    Ok((
+        flash.info("Two-factor authentication has been disabled."),
+        Redirect::to("/admin/two-factor"),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(name = "Regenerate recovery codes", skip(state, flash, form))]
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    flash: Flash,
+    Form(form): Form<CodeFormData>,
+) -> Result<Response, Response> {
+    let Some(secret) = get_totp_secret(user_id, &state.db_pool)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(Redirect::to("/admin/two-factor").into_response());
+    };
+    if !verify_second_factor(user_id, &secret, &form.code, &state.db_pool)
+        .await
+        .map_err(e500)?
+    {
+        return Ok((
+            flash.error("The authentication code is invalid."),
+            Redirect::to("/admin/two-factor"),
+        )
+            .into_response());
+    }
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let recovery_codes = replace_recovery_codes(&mut transaction, user_id)
+        .await
+        .map_err(e500)?;
+    record_user_event(
+        &mut *transaction,
+        UserEvent::RecoveryCodesRegenerated,
+        user_id,
+        Some(user_id),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to regenerate recovery codes.")
+        .map_err(e500)?;
+
+    Ok(recovery_codes_page(&recovery_codes, "/admin/two-factor").into_response())
+}
+
+#[derive(serde::Deserialize)]
+pub struct PolicyFormData {
+    require: bool,
+}
+
+#[tracing::instrument(
+    name = "Change two-factor authentication policy",
+    skip(state, client, flash, form)
+)]
+pub async fn set_two_factor_policy(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+    Form(form): Form<PolicyFormData>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ManageUsers, &state.db_pool).await?;
+
+    let policy = if form.require { "required" } else { "optional" };
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    set_two_factor_required(&mut *transaction, form.require)
+        .await
+        .map_err(e500)?;
+    record_user_event(
+        &mut *transaction,
+        UserEvent::TwoFactorPolicyChanged,
+        user_id,
+        None,
+        Some(policy),
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::TwoFactorPolicyChanged,
+        Some(user_id),
+        None,
+        Some(client.ip),
+        Some(policy),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change the two-factor policy.")
+        .map_err(e500)?;
+
+    Ok((
+        flash.info(format!(
+            "Two-factor authentication is now {} for every user.",
+            policy
+        )),
+        Redirect::to("/admin/two-factor"),
+    )
+        .into_response())
+}
@@ -0,0 +1,210 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse, Response},
+};
+use axum_flash::IncomingFlashes;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    csrf::CsrfToken,
+    domain::{Permission, UserRole},
+    routes::admin::require_permission,
+    utils::e500,
+    AppState,
+};
+
+struct UserRow {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+    is_active: bool,
+    created_at: DateTime<Utc>,
+}
+
+struct PendingInvitation {
+    email: String,
+    role: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List users", skip(state, csrf_token, flash_messages))]
+pub async fn list_users(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ManageUsers, &state.db_pool).await?;
+    let users = get_users(&state.db_pool).await.map_err(e500)?;
+    let invitations = get_pending_invitations(&state.db_pool)
+        .await
+        .map_err(e500)?;
+
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+
+    let mut users_html = String::new();
+    for user in &users {
+        // You cannot lock yourself out by changing your own role or
+        // deactivating your own account.
+        let actions = if user.is_active && user.user_id != user_id {
+            format!(
+                r#"<form action="/admin/users/{id}/role" method="post">
+                {csrf_field}
+                <select name="role">{roles}</select>
+                <button type="submit">Change role</button>
+            </form>
+            <form action="/admin/users/{id}/deactivate" method="post">
+                {csrf_field}
+                <button type="submit">Deactivate</button>
+            </form>"#,
+                id = user.user_id,
+                roles = role_options(&user.role),
+            )
+        } else {
+            String::new()
+        };
+        writeln!(
+            users_html,
+            r#"        <tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            encode_minimal(&user.username),
+            encode_minimal(user.email.as_deref().unwrap_or("-")),
+            encode_minimal(&user.role),
+            if user.is_active {
+                "active"
+            } else {
+                "deactivated"
+            },
+            user.created_at.format("%Y-%m-%d"),
+            actions,
+        )
+        .unwrap();
+    }
+
+    let mut invitations_html = String::new();
+    for invitation in &invitations {
+        writeln!(
+            invitations_html,
+            "        <li>{} as {} (expires {})</li>",
+            encode_minimal(&invitation.email),
+            encode_minimal(&invitation.role),
+            invitation.expires_at.format("%Y-%m-%d %H:%M UTC"),
+        )
+        .unwrap();
+    }
+
+    let invite_role_options = role_options(UserRole::Editor.as_str());
+
+    Ok((
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Users</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr>
+            <th>Username</th>
+            <th>Email</th>
+            <th>Role</th>
+            <th>Status</th>
+            <th>Created</th>
+            <th></th>
+        </tr>
+{users_html}    </table>
+    <p>Pending invitations:</p>
+    <ul>
+{invitations_html}    </ul>
+    <form action="/admin/users/invite" method="post">
+        {csrf_field}
+        <label>Email
+            <input
+                type="email"
+                placeholder="Email of the new user"
+                name="email"
+            >
+        </label>
+        <label>Role
+            <select name="role">{invite_role_options}</select>
+        </label>
+        <button type="submit">Send invitation</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )),
+    )
+        .into_response())
+}
+
+fn role_options(selected_role: &str) -> String {
+    let mut options = String::new();
+    for role in UserRole::ALL {
+        let selected = if role.as_str() == selected_role {
+            " selected"
+        } else {
+            ""
+        };
+        write!(
+            options,
+            r#"<option value="{role}"{selected}>{role}</option>"#
+        )
+        .unwrap();
+    }
+    options
+}
+
+#[tracing::instrument(name = "Get users", skip(pool))]
+async fn get_users(pool: &PgPool) -> Result<Vec<UserRow>, anyhow::Error> {
+    let users = sqlx::query_as!(
+        UserRow,
+        r#"
+        SELECT user_id, username, email, role, is_active, created_at
+        FROM users
+        ORDER BY created_at, username
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve users.")?;
+    Ok(users)
+}
+
+#[tracing::instrument(name = "Get pending invitations", skip(pool))]
+async fn get_pending_invitations(pool: &PgPool) -> Result<Vec<PendingInvitation>, anyhow::Error> {
+    let invitations = sqlx::query_as!(
+        PendingInvitation,
+        r#"
+        SELECT email, role, expires_at
+        FROM user_invitations
+        WHERE accepted_at IS NULL AND expires_at > now()
+        ORDER BY created_at
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve pending invitations.")?;
+    Ok(invitations)
+}
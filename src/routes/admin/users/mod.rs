@@ -0,0 +1,4 @@
+mod get;
+pub use get::list_users;
+mod post;
+pub use post::{change_user_role, deactivate_user, invite_user, record_user_event, UserEvent};
@@ -0,0 +1,394 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use chrono::{TimeDelta, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_entry, AuditAction},
+    authentication::UserId,
+    domain::{Permission, SubscriberEmail, UserRole},
+    email_client::EmailClient,
+    routes::admin::require_permission,
+    startup::HmacSecret,
+    user_sessions::{end_user_sessions, ClientInfo},
+    utils::e500,
+    AppState,
+};
+
+/// How long an invitation link stays valid.
+const INVITATION_VALIDITY: TimeDelta = TimeDelta::hours(72);
+
+#[derive(serde::Deserialize)]
+pub struct InviteFormData {
+    email: String,
+    role: String,
+}
+
+#[tracing::instrument(name = "Invite a new user", skip(state, client, flash, form))]
+pub async fn invite_user(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+    Form(form): Form<InviteFormData>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ManageUsers, &state.db_pool).await?;
+    let email = match SubscriberEmail::parse(form.email) {
+        Ok(email) => email,
+        Err(e) => return Ok((flash.error(e), Redirect::to("/admin/users")).into_response()),
+    };
+    let role = match UserRole::parse(form.role) {
+        Ok(role) => role,
+        Err(e) => return Ok((flash.error(e), Redirect::to("/admin/users")).into_response()),
+    };
+    if user_exists(email.as_ref(), &state.db_pool)
+        .await
+        .map_err(e500)?
+    {
+        return Ok((
+            flash.error(format!("There already is a user for {}.", email)),
+            Redirect::to("/admin/users"),
+        )
+            .into_response());
+    }
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let invitation_id = insert_invitation(&mut *transaction, email.as_ref(), role, user_id)
+        .await
+        .map_err(e500)?;
+    record_user_event(
+        &mut *transaction,
+        UserEvent::Invited,
+        user_id,
+        None,
+        Some(&format!("{} as {}", email, role)),
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::UserInvited,
+        Some(user_id),
+        Some(&format!("email:{}", email)),
+        Some(client.ip),
+        Some(role.as_str()),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new invitation.")
+        .map_err(e500)?;
+
+    send_invitation_email(
+        &state.email_client,
+        &email,
+        &state.base_url,
+        &state.hmac_secret,
+        invitation_id,
+    )
+    .await
+    .context("Failed to send an invitation email.")
+    .map_err(e500)?;
+
+    Ok((
+        flash.info(format!("An invitation has been sent to {}.", email)),
+        Redirect::to("/admin/users"),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(name = "Deactivate a user", skip(state, client, flash))]
+pub async fn deactivate_user(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+    Path(target_user_id): Path<Uuid>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ManageUsers, &state.db_pool).await?;
+    if user_id == target_user_id {
+        return Ok((
+            flash.error("You cannot deactivate your own account."),
+            Redirect::to("/admin/users"),
+        )
+            .into_response());
+    }
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let result = sqlx::query!(
+        r#"UPDATE users SET is_active = FALSE WHERE user_id = $1 AND is_active"#,
+        target_user_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to deactivate the user in the database.")
+    .map_err(e500)?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+    record_user_event(
+        &mut *transaction,
+        UserEvent::Deactivated,
+        user_id,
+        Some(target_user_id),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::UserDeactivated,
+        Some(user_id),
+        Some(&format!("user:{}", target_user_id)),
+        Some(client.ip),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to deactivate a user.")
+        .map_err(e500)?;
+    end_user_sessions(target_user_id, None, &state.session_store, &state.db_pool)
+        .await
+        .map_err(e500)?;
+
+    Ok((
+        flash.info("The user has been deactivated."),
+        Redirect::to("/admin/users"),
+    )
+        .into_response())
+}
+
+#[derive(serde::Deserialize)]
+pub struct RoleFormData {
+    role: String,
+}
+
+#[tracing::instrument(name = "Change a user's role", skip(state, client, flash, form))]
+pub async fn change_user_role(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+    Path(target_user_id): Path<Uuid>,
+    Form(form): Form<RoleFormData>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ManageUsers, &state.db_pool).await?;
+    if user_id == target_user_id {
+        return Ok((
+            flash.error("You cannot change your own role."),
+            Redirect::to("/admin/users"),
+        )
+            .into_response());
+    }
+    let role = match UserRole::parse(form.role) {
+        Ok(role) => role,
+        Err(e) => return Ok((flash.error(e), Redirect::to("/admin/users")).into_response()),
+    };
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let result = sqlx::query!(
+        r#"UPDATE users SET role = $1 WHERE user_id = $2"#,
+        role.as_str(),
+        target_user_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to change the user's role in the database.")
+    .map_err(e500)?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+    record_user_event(
+        &mut *transaction,
+        UserEvent::RoleChanged,
+        user_id,
+        Some(target_user_id),
+        Some(role.as_str()),
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::RoleChanged,
+        Some(user_id),
+        Some(&format!("user:{}", target_user_id)),
+        Some(client.ip),
+        Some(role.as_str()),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change a user's role.")
+        .map_err(e500)?;
+
+    Ok((
+        flash.info(format!("The user's role is now {}.", role)),
+        Redirect::to("/admin/users"),
+    )
+        .into_response())
+}
+
+/// Security-relevant changes to user accounts, recorded in `user_events`.
+#[derive(Debug, Clone, Copy)]
+pub enum UserEvent {
+    Invited,
+    InvitationAccepted,
+    Deactivated,
+    RoleChanged,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    RecoveryCodesRegenerated,
+    TwoFactorPolicyChanged,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+    PasswordReset,
+    SessionsRevoked,
+}
+
+impl UserEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserEvent::Invited => "invited",
+            UserEvent::InvitationAccepted => "invitation_accepted",
+            UserEvent::Deactivated => "deactivated",
+            UserEvent::RoleChanged => "role_changed",
+            UserEvent::TwoFactorEnabled => "two_factor_enabled",
+            UserEvent::TwoFactorDisabled => "two_factor_disabled",
+            UserEvent::RecoveryCodesRegenerated => "recovery_codes_regenerated",
+            UserEvent::TwoFactorPolicyChanged => "two_factor_policy_changed",
+            UserEvent::ApiTokenCreated => "api_token_created",
+            UserEvent::ApiTokenRevoked => "api_token_revoked",
+            UserEvent::PasswordReset => "password_reset",
+            UserEvent::SessionsRevoked => "sessions_revoked",
+        }
+    }
+}
+
+#[tracing::instrument(name = "Record user event", skip(executor))]
+pub async fn record_user_event(
+    executor: impl PgExecutor<'_>,
+    event: UserEvent,
+    actor_id: Uuid,
+    user_id: Option<Uuid>,
+    details: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO user_events (id, event_type, actor_id, user_id, details, occurred_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        event.as_str(),
+        actor_id,
+        user_id,
+        details,
+        Utc::now(),
+    )
+    .execute(executor)
+    .await
+    .context("Failed to record a user event.")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Check if a user exists", skip(pool))]
+async fn user_exists(email: &str, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE email = $1 OR username = $1"#,
+        email,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up existing users.")?;
+    Ok(row.is_some())
+}
+
+#[tracing::instrument(name = "Store invitation in the database", skip(executor))]
+async fn insert_invitation(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    role: UserRole,
+    invited_by: Uuid,
+) -> Result<Uuid, anyhow::Error> {
+    let invitation_id = Uuid::new_v4();
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations
+            (invitation_id, email, role, invited_by, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        invitation_id,
+        email,
+        role.as_str(),
+        invited_by,
+        now,
+        now + INVITATION_VALIDITY,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store the invitation.")?;
+    Ok(invitation_id)
+}
+
+#[tracing::instrument(
+    name = "Send an invitation email to a new user",
+    skip(email_client, base_url, hmac_secret)
+)]
+async fn send_invitation_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    hmac_secret: &HmacSecret,
+    invitation_id: Uuid,
+) -> Result<(), reqwest::Error> {
+    let invitation_id = invitation_id.to_string();
+    let invitation_link = format!(
+        "{}/invitations/accept?invitation_id={}&tag={}",
+        base_url,
+        invitation_id,
+        hmac_secret.sign(&invitation_id),
+    );
+    let plain_body = &format!(
+        "You have been invited to manage our newsletter!\n\
+        Visit {} to set your password. The link is valid for 72 hours.",
+        invitation_link
+    );
+    let html_body = &format!(
+        "You have been invited to manage our newsletter!<br />\
+        Click <a href=\"{}\">here</a> to set your password. \
+        The link is valid for 72 hours.",
+        invitation_link
+    );
+    email_client
+        .send_email(email, "You have been invited", html_body, plain_body)
+        .await
+}
@@ -0,0 +1,324 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use axum_flash::IncomingFlashes;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    csrf::CsrfToken,
+    domain::Permission,
+    routes::admin::require_permission,
+    utils::e500,
+    webhooks::{self, WebhookEvent},
+    AppState,
+};
+
+/// How many of the latest deliveries the page of an endpoint lists.
+const DELIVERIES_SHOWN: i64 = 50;
+
+#[tracing::instrument(name = "List webhooks", skip(state, csrf_token, flash_messages))]
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ManageWebhooks, &state.db_pool).await?;
+    let endpoints = webhooks::list_endpoints(&state.db_pool)
+        .await
+        .map_err(e500)?;
+
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+
+    let mut endpoints_html = String::new();
+    for endpoint in &endpoints {
+        let (status, disable_html) = if endpoint.disabled_at.is_some() {
+            ("disabled", String::new())
+        } else {
+            (
+                "enabled",
+                format!(
+                    r#"<form action="/admin/webhooks/{}/disable" method="post">
+                {csrf_field}
+                <button type="submit">Disable</button>
+            </form>"#,
+                    endpoint.endpoint_id
+                ),
+            )
+        };
+        writeln!(
+            endpoints_html,
+            r#"        <tr>
+            <td><a href="/admin/webhooks/{}">{}</a></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            endpoint.endpoint_id,
+            encode_minimal(&endpoint.url),
+            encode_minimal(&endpoint.description),
+            encode_minimal(&endpoint.events.join(", ")),
+            endpoint.created_at.format("%Y-%m-%d"),
+            status,
+            disable_html,
+        )
+        .unwrap();
+    }
+
+    let mut events_html = String::new();
+    for event in WebhookEvent::ALL {
+        writeln!(
+            events_html,
+            r#"        <label><input type="checkbox" name="event" value="{event}"> {event}</label>"#
+        )
+        .unwrap();
+    }
+
+    Ok((
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Webhooks</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr>
+            <th>URL</th>
+            <th>Description</th>
+            <th>Events</th>
+            <th>Created</th>
+            <th>Status</th>
+            <th></th>
+        </tr>
+{endpoints_html}    </table>
+    <form action="/admin/webhooks" method="post">
+        {csrf_field}
+        <label>URL
+            <input
+                type="url"
+                placeholder="https://example.com/webhooks"
+                name="url"
+            >
+        </label>
+        <label>Description
+            <input
+                type="text"
+                placeholder="What receives the events"
+                name="description"
+            >
+        </label>
+{events_html}        <button type="submit">Add endpoint</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(name = "Webhook details", skip(state, csrf_token, flash_messages))]
+pub async fn webhook_details(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+    Path(endpoint_id): Path<Uuid>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ManageWebhooks, &state.db_pool).await?;
+    let Some(endpoint) = webhooks::get_endpoint(endpoint_id, &state.db_pool)
+        .await
+        .map_err(e500)?
+    else {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    };
+    let deliveries = webhooks::list_deliveries(endpoint_id, DELIVERIES_SHOWN, &state.db_pool)
+        .await
+        .map_err(e500)?;
+
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+
+    let mut deliveries_html = String::new();
+    for delivery in &deliveries {
+        let replay_html = if endpoint.disabled_at.is_none() {
+            replay_form(delivery.delivery_id, &csrf_field)
+        } else {
+            String::new()
+        };
+        writeln!(
+            deliveries_html,
+            r#"        <tr>
+            <td><a href="/admin/webhooks/deliveries/{}">{}</a></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            delivery.delivery_id,
+            delivery.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            encode_minimal(&delivery.event),
+            delivery.status,
+            delivery.attempts,
+            format_optional_date(delivery.next_attempt_at, "-"),
+            replay_html,
+        )
+        .unwrap();
+    }
+
+    let url = encode_minimal(&endpoint.url);
+    let description = encode_minimal(&endpoint.description);
+    let events = encode_minimal(&endpoint.events.join(", "));
+    let status = match endpoint.disabled_at {
+        Some(disabled_at) => format!("disabled on {}", disabled_at.format("%Y-%m-%d")),
+        None => "enabled".to_string(),
+    };
+
+    Ok((
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Webhook</title>
+</head>
+<body>
+    {msg_html}
+    <p>URL: {url}</p>
+    <p>Description: {description}</p>
+    <p>Events: {events}</p>
+    <p>Status: {status}</p>
+    <table>
+        <tr>
+            <th>Queued</th>
+            <th>Event</th>
+            <th>Status</th>
+            <th>Attempts</th>
+            <th>Next attempt</th>
+            <th></th>
+        </tr>
+{deliveries_html}    </table>
+    <p><a href="/admin/webhooks">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(name = "Webhook delivery details", skip(state, csrf_token))]
+pub async fn webhook_delivery_details(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    csrf_token: CsrfToken,
+    Path(delivery_id): Path<Uuid>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ManageWebhooks, &state.db_pool).await?;
+    let Some(delivery) = webhooks::get_delivery(delivery_id, &state.db_pool)
+        .await
+        .map_err(e500)?
+    else {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    };
+    let attempts = webhooks::list_attempts(delivery_id, &state.db_pool)
+        .await
+        .map_err(e500)?;
+
+    let mut attempts_html = String::new();
+    for attempt in &attempts {
+        writeln!(
+            attempts_html,
+            r#"        <tr>
+            <td>{}</td>
+            <td>{} ms</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            attempt.attempted_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            attempt.duration_ms,
+            attempt
+                .response_status
+                .map(|status| status.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            encode_minimal(attempt.error.as_deref().unwrap_or("")),
+        )
+        .unwrap();
+    }
+
+    let endpoint_id = delivery.endpoint_id;
+    let event = encode_minimal(&delivery.event);
+    let status = delivery.status;
+    let queued_at = delivery.created_at.format("%Y-%m-%d %H:%M:%S UTC");
+    let replay_of_html = match delivery.replay_of {
+        Some(original) => format!(
+            r#"<p>Replay of <a href="/admin/webhooks/deliveries/{original}">{original}</a></p>"#
+        ),
+        None => String::new(),
+    };
+    let payload = encode_minimal(&delivery.payload);
+    let replay_html = replay_form(delivery_id, &csrf_token.form_field());
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Webhook delivery</title>
+</head>
+<body>
+    <p>Event: {event}</p>
+    <p>Queued at: {queued_at}</p>
+    <p>Status: {status}</p>
+    {replay_of_html}
+    <pre>{payload}</pre>
+    <table>
+        <tr>
+            <th>Attempted</th>
+            <th>Duration</th>
+            <th>Response</th>
+            <th>Error</th>
+        </tr>
+{attempts_html}    </table>
+    {replay_html}
+    <p><a href="/admin/webhooks/{endpoint_id}">&lt;- Back</a></p>
+</body>
+</html>"#,
+    ))
+    .into_response())
+}
+
+fn replay_form(delivery_id: Uuid, csrf_field: &str) -> String {
+    format!(
+        r#"<form action="/admin/webhooks/deliveries/{delivery_id}/replay" method="post">
+                {csrf_field}
+                <button type="submit">Replay</button>
+            </form>"#
+    )
+}
+
+fn format_optional_date(date: Option<DateTime<Utc>>, default: &str) -> String {
+    date.map(|d| d.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| default.to_string())
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::{list_webhooks, webhook_delivery_details, webhook_details};
+mod post;
+pub use post::{create_webhook, disable_webhook, replay_webhook_delivery};
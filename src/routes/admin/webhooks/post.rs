@@ -0,0 +1,216 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use htmlescape::encode_minimal;
+use secrecy::ExposeSecret;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_entry, AuditAction},
+    authentication::UserId,
+    domain::Permission,
+    routes::admin::require_permission,
+    user_sessions::ClientInfo,
+    utils::e500,
+    webhooks::{self, WebhookEvent},
+    AppState,
+};
+
+const MAX_URL_LENGTH: usize = 2000;
+const MAX_DESCRIPTION_LENGTH: usize = 100;
+
+struct NewWebhook {
+    url: String,
+    description: String,
+    events: Vec<WebhookEvent>,
+}
+
+/// The form repeats the `event` field once per checked box, so it is read
+/// as a list of pairs rather than into a struct.
+impl TryFrom<Vec<(String, String)>> for NewWebhook {
+    type Error = String;
+
+    fn try_from(fields: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let mut url = String::new();
+        let mut description = String::new();
+        let mut events = Vec::new();
+        for (key, value) in fields {
+            match key.as_str() {
+                "url" => url = value.trim().to_string(),
+                "description" => description = value.trim().to_string(),
+                "event" => {
+                    let event = WebhookEvent::parse(value)?;
+                    if !events.contains(&event) {
+                        events.push(event);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let is_http_url = reqwest::Url::parse(&url)
+            .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.has_host());
+        if !is_http_url || url.len() > MAX_URL_LENGTH {
+            return Err("Enter the HTTP(S) URL to send the events to.".into());
+        }
+        if description.chars().count() > MAX_DESCRIPTION_LENGTH {
+            return Err(format!(
+                "The description cannot be longer than {} characters.",
+                MAX_DESCRIPTION_LENGTH
+            ));
+        }
+        if events.is_empty() {
+            return Err("Select at least one event.".into());
+        }
+        Ok(Self {
+            url,
+            description,
+            events,
+        })
+    }
+}
+
+#[tracing::instrument(name = "Create a webhook", skip(state, client, flash, form))]
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+    Form(form): Form<Vec<(String, String)>>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ManageWebhooks, &state.db_pool).await?;
+    let new_webhook: NewWebhook = match form.try_into() {
+        Ok(new_webhook) => new_webhook,
+        Err(e) => return Ok((flash.error(e), Redirect::to("/admin/webhooks")).into_response()),
+    };
+    if !state.webhooks.allow_private_addresses {
+        if let Err(e) = webhooks::check_endpoint_host(&new_webhook.url).await {
+            return Ok((flash.error(e), Redirect::to("/admin/webhooks")).into_response());
+        }
+    }
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let (endpoint_id, secret) = webhooks::create_endpoint(
+        &mut *transaction,
+        &new_webhook.url,
+        &new_webhook.description,
+        &new_webhook.events,
+        user_id,
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::WebhookCreated,
+        Some(user_id),
+        Some(&format!("webhook:{}", endpoint_id)),
+        Some(client.ip),
+        Some(&new_webhook.url),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create a webhook.")
+        .map_err(e500)?;
+
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>New webhook</title>
+</head>
+<body>
+    <p>Events will be sent to {}.</p>
+    <p>Copy the signing secret of the endpoint now. It will not be shown again.</p>
+    <p><code>{}</code></p>
+    <p>Every delivery carries an <code>X-Webhook-Signature: t=&lt;timestamp&gt;,v1=&lt;signature&gt;</code>
+    header, where the signature is the hex-encoded HMAC-SHA256, keyed with this secret,
+    of the timestamp, a dot and the request body.</p>
+    <p><a href="/admin/webhooks/{}">Continue</a></p>
+</body>
+</html>"#,
+        encode_minimal(&new_webhook.url),
+        secret.expose_secret(),
+        endpoint_id,
+    ))
+    .into_response())
+}
+
+#[tracing::instrument(name = "Disable a webhook", skip(state, client, flash))]
+pub async fn disable_webhook(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+    Path(endpoint_id): Path<Uuid>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ManageWebhooks, &state.db_pool).await?;
+    let disabled = webhooks::disable_endpoint(&state.db_pool, endpoint_id)
+        .await
+        .map_err(e500)?;
+    if !disabled {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+    record_audit_entry(
+        &state.db_pool,
+        AuditAction::WebhookDisabled,
+        Some(user_id),
+        Some(&format!("webhook:{}", endpoint_id)),
+        Some(client.ip),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    Ok((
+        flash.info("The endpoint has been disabled."),
+        Redirect::to("/admin/webhooks"),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(name = "Replay a webhook delivery", skip(state, client, flash))]
+pub async fn replay_webhook_delivery(
+    State(state): State<AppState>,
+    UserId(user_id): UserId,
+    client: ClientInfo,
+    flash: Flash,
+    Path(delivery_id): Path<Uuid>,
+) -> Result<Response, Response> {
+    require_permission(user_id, Permission::ManageWebhooks, &state.db_pool).await?;
+    let Some((replay_id, endpoint_id)) = webhooks::replay_delivery(&state.db_pool, delivery_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    };
+    record_audit_entry(
+        &state.db_pool,
+        AuditAction::WebhookReplayed,
+        Some(user_id),
+        Some(&format!("webhook:{}", endpoint_id)),
+        Some(client.ip),
+        Some(&format!(
+            "delivery {} replayed as {}",
+            delivery_id, replay_id
+        )),
+    )
+    .await
+    .map_err(e500)?;
+    Ok((
+        flash.info("The delivery has been queued again."),
+        Redirect::to(&format!("/admin/webhooks/{}", endpoint_id)),
+    )
+        .into_response())
+}
@@ -0,0 +1,54 @@
+use axum::http::HeaderMap;
+use sqlx::PgPool;
+
+use crate::{
+    api_tokens::{authenticate_api_token, bearer_token, AuthenticatedToken},
+    authentication::get_user_role,
+    domain::ApiScope,
+};
+
+use super::Problem;
+
+/// Look up the bearer token of the request.
+#[tracing::instrument(
+    name = "Authenticate an API request",
+    skip(headers, pool),
+    fields(token_id=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn authenticate(
+    headers: &HeaderMap,
+    pool: &PgPool,
+) -> Result<AuthenticatedToken, Problem> {
+    let token = bearer_token(headers).map_err(|e| Problem::unauthorized(e.to_string()))?;
+    let token = authenticate_api_token(&token, pool)
+        .await?
+        .ok_or_else(|| Problem::unauthorized("Invalid API token."))?;
+    tracing::Span::current()
+        .record("token_id", tracing::field::display(&token.token_id))
+        .record("user_id", tracing::field::display(&token.user_id));
+    Ok(token)
+}
+
+/// Look up the bearer token of the request and check that it grants `scope`.
+pub async fn authorize(
+    headers: &HeaderMap,
+    pool: &PgPool,
+    scope: ApiScope,
+) -> Result<AuthenticatedToken, Problem> {
+    let token = authenticate(headers, pool).await?;
+    if !token.has_scope(scope) {
+        return Err(Problem::forbidden(format!(
+            "The token does not have the {} scope.",
+            scope
+        )));
+    }
+    // The owner's role may have changed since the token was created.
+    let role = get_user_role(token.user_id, pool).await?;
+    if !role.can(scope.permission()) {
+        return Err(Problem::forbidden(format!(
+            "The owner of the token is no longer allowed to use the {} scope.",
+            scope
+        )));
+    }
+    Ok(token)
+}
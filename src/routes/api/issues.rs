@@ -0,0 +1,300 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::ApiScope,
+    issue_deliveries::RecipientStatus,
+    routes::{publish_issue, NewIssue},
+    user_sessions::ClientInfo,
+    AppState,
+};
+
+use super::{
+    auth::{authenticate, authorize},
+    pagination::{Cursor, Page, PageRequest},
+    ApiJson, ApiPath, ApiQuery, Problem,
+};
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct Issue {
+    id: Uuid,
+    #[schema(example = "Newsletter title")]
+    title: String,
+    content: IssueContent,
+    published_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct IssueContent {
+    #[schema(example = "<p>Newsletter body as HTML</p>")]
+    html: String,
+    #[schema(example = "Newsletter body as plain text")]
+    text: String,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct NewIssueBody {
+    #[schema(example = "Newsletter title")]
+    title: String,
+    content: IssueContent,
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParameters {
+    /// How many issues to return, from 1 to 100. Defaults to 50.
+    limit: Option<i64>,
+    /// The `next_cursor` of the previous page.
+    cursor: Option<String>,
+}
+
+/// The delivery of an issue to one of its recipients.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct Delivery {
+    id: Uuid,
+    #[schema(example = "ursula_le_guin@gmail.com")]
+    recipient: String,
+    /// `pending`, `sending`, `sent`, `failed` or `suppressed`.
+    #[schema(example = "sent")]
+    status: String,
+    attempts: i32,
+    /// The id the email provider gave to the email, once sent.
+    provider_message_id: Option<String>,
+    /// Why the last attempt failed.
+    error: Option<String>,
+    created_at: DateTime<Utc>,
+    last_attempt_at: Option<DateTime<Utc>>,
+    sent_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryListParameters {
+    /// Only list deliveries with this status.
+    status: Option<String>,
+    /// How many deliveries to return, from 1 to 100. Defaults to 50.
+    limit: Option<i64>,
+    /// The `next_cursor` of the previous page.
+    cursor: Option<String>,
+}
+
+struct IssueRow {
+    issue_id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+impl From<IssueRow> for Issue {
+    fn from(row: IssueRow) -> Self {
+        Self {
+            id: row.issue_id,
+            title: row.title,
+            content: IssueContent {
+                html: row.html_content,
+                text: row.text_content,
+            },
+            published_at: row.published_at,
+        }
+    }
+}
+
+/// Publish an issue. It is sent to every confirmed subscriber in the
+/// background; its deliveries tell how far along that is.
+#[utoipa::path(
+    post,
+    path = "/issues",
+    tag = "issues",
+    request_body = NewIssueBody,
+    security(("api_token" = ["publish"])),
+    responses(
+        (status = 201, body = Issue, headers(("Location" = String))),
+        (status = 400, body = Problem, content_type = "application/problem+json"),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+        (status = 422, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "API: publish an issue", skip(headers, state, client, body))]
+pub async fn api_publish_issue(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    client: ClientInfo,
+    ApiJson(body): ApiJson<NewIssueBody>,
+) -> Result<Response, Problem> {
+    let token = authorize(&headers, &state.db_pool, ApiScope::Publish).await?;
+    if body.title.trim().is_empty() {
+        return Err(Problem::new(
+            StatusCode::BAD_REQUEST,
+            "The title cannot be empty.",
+        ));
+    }
+    let issue = NewIssue {
+        title: body.title,
+        html_content: body.content.html,
+        text_content: body.content.text,
+    };
+    let issue_id = publish_issue(&state, &issue, &token, client.ip).await?;
+    let issue = get_issue(issue_id, &state.db_pool)
+        .await?
+        .context("The new issue is missing.")?;
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, format!("/api/v1/issues/{}", issue_id))],
+        axum::Json(issue),
+    )
+        .into_response())
+}
+
+/// List the published issues, latest first. Any valid token can read them.
+#[utoipa::path(
+    get,
+    path = "/issues",
+    tag = "issues",
+    params(ListParameters),
+    responses(
+        (status = 200, body = Page<Issue>),
+        (status = 400, body = Problem, content_type = "application/problem+json"),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "API: list issues", skip(headers, state, parameters))]
+pub async fn api_list_issues(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    ApiQuery(parameters): ApiQuery<ListParameters>,
+) -> Result<Response, Problem> {
+    authenticate(&headers, &state.db_pool).await?;
+    let page_request = PageRequest::parse(parameters.limit, parameters.cursor.as_deref())?;
+    let rows = sqlx::query_as!(
+        IssueRow,
+        r#"
+        SELECT issue_id, title, text_content, html_content, published_at
+        FROM newsletter_issues
+        WHERE $1::timestamptz IS NULL OR (published_at, issue_id) < ($1, $2)
+        ORDER BY published_at DESC, issue_id DESC
+        LIMIT $3
+        "#,
+        page_request.after_timestamp(),
+        page_request.after_id(),
+        page_request.fetch_limit(),
+    )
+    .fetch_all(&state.db_pool)
+    .await
+    .context("Failed to list newsletter issues.")?;
+    let issues: Vec<Issue> = rows.into_iter().map(Issue::from).collect();
+    let page = page_request.page(issues, |issue| Cursor {
+        timestamp: issue.published_at,
+        id: issue.id,
+    });
+    Ok(axum::Json(page).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/issues/{issue_id}",
+    tag = "issues",
+    params(("issue_id" = Uuid, Path)),
+    responses(
+        (status = 200, body = Issue),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "API: get an issue", skip(headers, state))]
+pub async fn api_get_issue(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    ApiPath(issue_id): ApiPath<Uuid>,
+) -> Result<Response, Problem> {
+    authenticate(&headers, &state.db_pool).await?;
+    let issue = get_issue(issue_id, &state.db_pool)
+        .await?
+        .ok_or_else(|| Problem::not_found("There is no issue with this id."))?;
+    Ok(axum::Json(issue).into_response())
+}
+
+/// List the recipients of an issue, with how the delivery to each went.
+#[utoipa::path(
+    get,
+    path = "/issues/{issue_id}/deliveries",
+    tag = "issues",
+    params(("issue_id" = Uuid, Path), DeliveryListParameters),
+    security(("api_token" = ["subscribers:read"])),
+    responses(
+        (status = 200, body = Page<Delivery>),
+        (status = 400, body = Problem, content_type = "application/problem+json"),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "API: list deliveries", skip(headers, state, parameters))]
+pub async fn api_list_deliveries(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    ApiPath(issue_id): ApiPath<Uuid>,
+    ApiQuery(parameters): ApiQuery<DeliveryListParameters>,
+) -> Result<Response, Problem> {
+    authorize(&headers, &state.db_pool, ApiScope::SubscribersRead).await?;
+    let status = parameters
+        .status
+        .map(RecipientStatus::parse)
+        .transpose()
+        .map_err(|e| Problem::new(StatusCode::BAD_REQUEST, e))?;
+    let page_request = PageRequest::parse(parameters.limit, parameters.cursor.as_deref())?;
+    if get_issue(issue_id, &state.db_pool).await?.is_none() {
+        return Err(Problem::not_found("There is no issue with this id."));
+    }
+    let deliveries = sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT delivery_id AS id, recipient, status, attempts, provider_message_id, error,
+            created_at, last_attempt_at, sent_at
+        FROM issue_deliveries
+        WHERE issue_id = $1
+            AND ($2::text IS NULL OR status = $2)
+            AND ($3::timestamptz IS NULL OR (created_at, delivery_id) > ($3, $4))
+        ORDER BY created_at, delivery_id
+        LIMIT $5
+        "#,
+        issue_id,
+        status.map(|status| status.as_str()),
+        page_request.after_timestamp(),
+        page_request.after_id(),
+        page_request.fetch_limit(),
+    )
+    .fetch_all(&state.db_pool)
+    .await
+    .context("Failed to list the deliveries of the issue.")?;
+    let page = page_request.page(deliveries, |delivery| Cursor {
+        timestamp: delivery.created_at,
+        id: delivery.id,
+    });
+    Ok(axum::Json(page).into_response())
+}
+
+#[tracing::instrument(name = "Get a newsletter issue", skip(pool))]
+async fn get_issue(issue_id: Uuid, pool: &PgPool) -> Result<Option<Issue>, anyhow::Error> {
+    let row = sqlx::query_as!(
+        IssueRow,
+        r#"
+        SELECT issue_id, title, text_content, html_content, published_at
+        FROM newsletter_issues
+        WHERE issue_id = $1
+        "#,
+        issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the newsletter issue.")?;
+    Ok(row.map(Issue::from))
+}
@@ -0,0 +1,41 @@
+//! The JSON API under `/api/v1`. Callers authenticate with API tokens, and
+//! errors are reported as `application/problem+json`.
+
+mod auth;
+mod issues;
+mod openapi;
+mod pagination;
+mod problem;
+mod subscribers;
+
+use axum::{routing::get, Router};
+
+use crate::AppState;
+
+use issues::{api_get_issue, api_list_deliveries, api_list_issues, api_publish_issue};
+use openapi::openapi_document;
+use problem::{not_found, ApiJson, ApiPath, ApiQuery, Problem};
+use subscribers::{
+    api_create_subscriber, api_delete_subscriber, api_get_subscriber, api_list_subscribers,
+    api_update_subscriber,
+};
+
+/// The routes of the API, to be nested under `/api/v1`.
+pub fn api_router() -> Router<AppState> {
+    Router::new()
+        .route("/issues", get(api_list_issues).post(api_publish_issue))
+        .route("/issues/{issue_id}", get(api_get_issue))
+        .route("/issues/{issue_id}/deliveries", get(api_list_deliveries))
+        .route("/openapi.json", get(openapi_document))
+        .route(
+            "/subscribers",
+            get(api_list_subscribers).post(api_create_subscriber),
+        )
+        .route(
+            "/subscribers/{subscriber_id}",
+            get(api_get_subscriber)
+                .patch(api_update_subscriber)
+                .delete(api_delete_subscriber),
+        )
+        .fallback(not_found)
+}
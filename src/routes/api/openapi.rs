@@ -0,0 +1,60 @@
+use axum::Json;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use super::{issues, subscribers};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Newsletter API",
+        description = "Errors are reported as `application/problem+json` (RFC 7807).",
+    ),
+    servers((url = "/api/v1")),
+    paths(
+        subscribers::api_list_subscribers,
+        subscribers::api_create_subscriber,
+        subscribers::api_get_subscriber,
+        subscribers::api_update_subscriber,
+        subscribers::api_delete_subscriber,
+        issues::api_list_issues,
+        issues::api_publish_issue,
+        issues::api_get_issue,
+        issues::api_list_deliveries,
+    ),
+    modifiers(&ApiTokenAuth),
+    security(("api_token" = [])),
+    tags(
+        (name = "subscribers", description = "The people the newsletter is sent to."),
+        (name = "issues", description = "Published newsletter issues."),
+    )
+)]
+struct ApiDoc;
+
+struct ApiTokenAuth;
+
+impl Modify for ApiTokenAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "An API token, created in the admin area. Each endpoint \
+                        lists the scopes the token needs.",
+                    ))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// The OpenAPI 3 document of the API. Unlike the API itself, it is public.
+#[tracing::instrument(name = "API: OpenAPI document")]
+pub async fn openapi_document() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
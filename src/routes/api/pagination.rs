@@ -0,0 +1,95 @@
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::Problem;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 100;
+
+/// A page of results, and the cursor to pass to get the next one.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    /// Absent on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// Where a page starts: right after the item with this sort key. Items are
+/// sorted by their timestamp, then by their id to break ties.
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+    pub timestamp: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    /// Cursors are opaque to callers, who should only pass them back.
+    fn encode(&self) -> String {
+        hex::encode(format!("{}|{}", self.timestamp.to_rfc3339(), self.id))
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let decoded = String::from_utf8(hex::decode(cursor).ok()?).ok()?;
+        let (timestamp, id) = decoded.split_once('|')?;
+        Some(Self {
+            timestamp: DateTime::parse_from_rfc3339(timestamp).ok()?.to_utc(),
+            id: Uuid::parse_str(id).ok()?,
+        })
+    }
+}
+
+/// The validated `limit` and `cursor` query parameters.
+#[derive(Debug)]
+pub struct PageRequest {
+    pub limit: i64,
+    pub after: Option<Cursor>,
+}
+
+impl PageRequest {
+    pub fn parse(limit: Option<i64>, cursor: Option<&str>) -> Result<Self, Problem> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT);
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(Problem::new(
+                StatusCode::BAD_REQUEST,
+                format!("The limit must be between 1 and {}.", MAX_LIMIT),
+            ));
+        }
+        let after = cursor
+            .map(|cursor| {
+                Cursor::decode(cursor).ok_or_else(|| {
+                    Problem::new(StatusCode::BAD_REQUEST, "The cursor is not valid.")
+                })
+            })
+            .transpose()?;
+        Ok(Self { limit, after })
+    }
+
+    /// How many rows to fetch: one more than the limit, to tell whether
+    /// there is a next page.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    pub fn after_timestamp(&self) -> Option<DateTime<Utc>> {
+        self.after.map(|cursor| cursor.timestamp)
+    }
+
+    pub fn after_id(&self) -> Option<Uuid> {
+        self.after.map(|cursor| cursor.id)
+    }
+
+    /// Turn the fetched rows into a page.
+    pub fn page<T>(&self, mut rows: Vec<T>, sort_key: impl Fn(&T) -> Cursor) -> Page<T> {
+        let next_cursor = if rows.len() as i64 > self.limit {
+            rows.truncate(self.limit as usize);
+            rows.last().map(|row| sort_key(row).encode())
+        } else {
+            None
+        };
+        Page {
+            data: rows,
+            next_cursor,
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use axum::{
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequest, FromRequestParts,
+    },
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    request_id::current_request_id,
+    routes::{ConfirmationError, PublishError, SubscribeError},
+};
+
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// An error, reported as `application/problem+json` (RFC 7807).
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct Problem {
+    /// Always `about:blank`: the status code tells what went wrong.
+    #[serde(rename = "type")]
+    #[schema(example = "about:blank")]
+    kind: String,
+    #[schema(example = "Not Found")]
+    title: String,
+    #[schema(example = 404)]
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    /// The `X-Request-Id` of the request, to look it up in the logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    /// Send a `WWW-Authenticate` challenge with the response.
+    #[serde(skip)]
+    challenge: bool,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, detail: impl Into<String>) -> Self {
+        Self {
+            detail: Some(detail.into()),
+            ..Self::status(status)
+        }
+    }
+
+    fn status(status: StatusCode) -> Self {
+        Self {
+            kind: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: None,
+            request_id: current_request_id().map(|id| id.as_str().to_string()),
+            challenge: false,
+        }
+    }
+
+    pub fn unauthorized(detail: impl Into<String>) -> Self {
+        Self {
+            challenge: true,
+            ..Self::new(StatusCode::UNAUTHORIZED, detail)
+        }
+    }
+
+    pub fn forbidden(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, detail)
+    }
+
+    pub fn not_found(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, detail)
+    }
+
+    /// Log `error` and hide it from the caller.
+    pub fn internal(error: impl std::fmt::Debug) -> Self {
+        tracing::error!(error.cause_chain = ?error, "Internal server error");
+        Self::status(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+impl std::fmt::Debug for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.status, self.title)?;
+        if let Some(detail) = &self.detail {
+            write!(f, ": {}", detail)?;
+        }
+        Ok(())
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let challenge = self.challenge;
+        let mut response = (status, axum::Json(self)).into_response();
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        if challenge {
+            headers.insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(r#"Bearer realm="api""#),
+            );
+        }
+        response
+    }
+}
+
+impl From<anyhow::Error> for Problem {
+    fn from(e: anyhow::Error) -> Self {
+        Self::internal(e)
+    }
+}
+
+impl From<SubscribeError> for Problem {
+    fn from(e: SubscribeError) -> Self {
+        match e {
+            SubscribeError::ValidationError(detail) => Self::new(StatusCode::BAD_REQUEST, detail),
+            SubscribeError::AlreadySubscribed => Self::new(StatusCode::CONFLICT, e.to_string()),
+            SubscribeError::UnexpectedError(_) => Self::internal(e),
+        }
+    }
+}
+
+impl From<PublishError> for Problem {
+    fn from(e: PublishError) -> Self {
+        match e {
+            PublishError::AuthError(_) => Self::unauthorized(e.to_string()),
+            PublishError::Forbidden => Self::forbidden(e.to_string()),
+            PublishError::UnexpectedError(_) => Self::internal(e),
+        }
+    }
+}
+
+impl From<ConfirmationError> for Problem {
+    fn from(e: ConfirmationError) -> Self {
+        match e {
+            ConfirmationError::UnknownToken => Self::new(StatusCode::UNAUTHORIZED, e.to_string()),
+            ConfirmationError::UnexpectedError(_) => Self::internal(e),
+        }
+    }
+}
+
+impl From<JsonRejection> for Problem {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for Problem {
+    fn from(rejection: PathRejection) -> Self {
+        Self::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for Problem {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::new(rejection.status(), rejection.body_text())
+    }
+}
+
+/// Like [`axum::Json`], rejecting bodies with a [`Problem`].
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(Problem))]
+pub struct ApiJson<T>(pub T);
+
+/// Like [`axum::extract::Path`], rejecting paths with a [`Problem`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(Problem))]
+pub struct ApiPath<T>(pub T);
+
+/// Like [`axum::extract::Query`], rejecting query strings with a [`Problem`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(Problem))]
+pub struct ApiQuery<T>(pub T);
+
+/// Answers requests to unknown API paths.
+pub async fn not_found() -> Problem {
+    Problem::not_found("There is no such API endpoint.")
+}
@@ -0,0 +1,329 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_entry, AuditAction},
+    domain::{ApiScope, NewSubscriber, SubscriberEmail, SubscriberName, SubscriberStatus},
+    routes::{register_subscriber, remove_subscriber, update_subscriber, SubscriberUpdate},
+    user_sessions::ClientInfo,
+    AppState,
+};
+
+use super::{
+    auth::authorize,
+    pagination::{Cursor, Page, PageRequest},
+    ApiJson, ApiPath, ApiQuery, Problem,
+};
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct Subscriber {
+    id: Uuid,
+    #[schema(example = "ursula_le_guin@gmail.com")]
+    email: String,
+    #[schema(example = "Ursula Le Guin")]
+    name: String,
+    /// `pending_confirmation`, `confirmed` or `unsubscribed`.
+    #[schema(example = "confirmed")]
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParameters {
+    /// Only list subscribers with this status.
+    status: Option<String>,
+    /// How many subscribers to return, from 1 to 100. Defaults to 50.
+    limit: Option<i64>,
+    /// The `next_cursor` of the previous page.
+    cursor: Option<String>,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct NewSubscriberBody {
+    #[schema(example = "ursula_le_guin@gmail.com")]
+    email: String,
+    #[schema(example = "Ursula Le Guin")]
+    name: String,
+}
+
+/// Fields left out are kept as they are.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct SubscriberPatch {
+    name: Option<String>,
+    /// `pending_confirmation`, `confirmed` or `unsubscribed`.
+    status: Option<String>,
+}
+
+impl TryFrom<SubscriberPatch> for SubscriberUpdate {
+    type Error = String;
+
+    fn try_from(patch: SubscriberPatch) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: patch.name.map(SubscriberName::parse).transpose()?,
+            status: patch.status.map(SubscriberStatus::parse).transpose()?,
+        })
+    }
+}
+
+/// List subscribers, oldest first.
+#[utoipa::path(
+    get,
+    path = "/subscribers",
+    tag = "subscribers",
+    params(ListParameters),
+    security(("api_token" = ["subscribers:read"])),
+    responses(
+        (status = 200, body = Page<Subscriber>),
+        (status = 400, body = Problem, content_type = "application/problem+json"),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "API: list subscribers", skip(headers, state, parameters))]
+pub async fn api_list_subscribers(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    ApiQuery(parameters): ApiQuery<ListParameters>,
+) -> Result<Response, Problem> {
+    authorize(&headers, &state.db_pool, ApiScope::SubscribersRead).await?;
+    let status = parameters
+        .status
+        .map(SubscriberStatus::parse)
+        .transpose()
+        .map_err(|e| Problem::new(StatusCode::BAD_REQUEST, e))?;
+    let page_request = PageRequest::parse(parameters.limit, parameters.cursor.as_deref())?;
+    let subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR status = $1)
+            AND ($2::timestamptz IS NULL OR (subscribed_at, id) > ($2, $3))
+        ORDER BY subscribed_at, id
+        LIMIT $4
+        "#,
+        status.map(|status| status.as_str()),
+        page_request.after_timestamp(),
+        page_request.after_id(),
+        page_request.fetch_limit(),
+    )
+    .fetch_all(&state.db_pool)
+    .await
+    .context("Failed to list subscribers.")?;
+    let page = page_request.page(subscribers, |subscriber| Cursor {
+        timestamp: subscriber.subscribed_at,
+        id: subscriber.id,
+    });
+    Ok(axum::Json(page).into_response())
+}
+
+/// Add a subscriber and send them the confirmation email.
+#[utoipa::path(
+    post,
+    path = "/subscribers",
+    tag = "subscribers",
+    request_body = NewSubscriberBody,
+    security(("api_token" = ["subscribers:write"])),
+    responses(
+        (status = 201, body = Subscriber, headers(("Location" = String))),
+        (status = 400, body = Problem, content_type = "application/problem+json"),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+        (status = 409, body = Problem, content_type = "application/problem+json"),
+        (status = 422, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "API: add a subscriber", skip(headers, state, client, body))]
+pub async fn api_create_subscriber(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    client: ClientInfo,
+    ApiJson(body): ApiJson<NewSubscriberBody>,
+) -> Result<Response, Problem> {
+    let token = authorize(&headers, &state.db_pool, ApiScope::SubscribersWrite).await?;
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(body.email)
+            .map_err(|e| Problem::new(StatusCode::BAD_REQUEST, e))?,
+        name: SubscriberName::parse(body.name)
+            .map_err(|e| Problem::new(StatusCode::BAD_REQUEST, e))?,
+    };
+    let subscriber_id =
+        register_subscriber(&state, new_subscriber, Some((&token, client.ip))).await?;
+    let subscriber = get_subscriber(subscriber_id, &state.db_pool)
+        .await?
+        .context("The new subscriber is missing.")?;
+    Ok((
+        StatusCode::CREATED,
+        [(
+            header::LOCATION,
+            format!("/api/v1/subscribers/{}", subscriber_id),
+        )],
+        axum::Json(subscriber),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path)),
+    security(("api_token" = ["subscribers:read"])),
+    responses(
+        (status = 200, body = Subscriber),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "API: get a subscriber", skip(headers, state))]
+pub async fn api_get_subscriber(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    ApiPath(subscriber_id): ApiPath<Uuid>,
+) -> Result<Response, Problem> {
+    authorize(&headers, &state.db_pool, ApiScope::SubscribersRead).await?;
+    let subscriber = get_subscriber(subscriber_id, &state.db_pool)
+        .await?
+        .ok_or_else(no_such_subscriber)?;
+    Ok(axum::Json(subscriber).into_response())
+}
+
+/// Change the name or the status of a subscriber.
+#[utoipa::path(
+    patch,
+    path = "/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path)),
+    request_body = SubscriberPatch,
+    security(("api_token" = ["subscribers:write"])),
+    responses(
+        (status = 200, body = Subscriber),
+        (status = 400, body = Problem, content_type = "application/problem+json"),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 422, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "API: edit a subscriber", skip(headers, state, client, patch))]
+pub async fn api_update_subscriber(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    client: ClientInfo,
+    ApiPath(subscriber_id): ApiPath<Uuid>,
+    ApiJson(patch): ApiJson<SubscriberPatch>,
+) -> Result<Response, Problem> {
+    let token = authorize(&headers, &state.db_pool, ApiScope::SubscribersWrite).await?;
+    let update: SubscriberUpdate = patch
+        .try_into()
+        .map_err(|e: String| Problem::new(StatusCode::BAD_REQUEST, e))?;
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    if !update_subscriber(&mut transaction, subscriber_id, &update).await? {
+        return Err(no_such_subscriber());
+    }
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::SubscriberEdited,
+        Some(token.user_id),
+        Some(&format!("subscriber:{}", subscriber_id)),
+        Some(client.ip),
+        Some(&audit_details(update.audit_details(), &token.token_id)),
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a subscriber.")?;
+    let subscriber = get_subscriber(subscriber_id, &state.db_pool)
+        .await?
+        .ok_or_else(no_such_subscriber)?;
+    Ok(axum::Json(subscriber).into_response())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path)),
+    security(("api_token" = ["subscribers:write"])),
+    responses(
+        (status = 204),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "API: delete a subscriber", skip(headers, state, client))]
+pub async fn api_delete_subscriber(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    client: ClientInfo,
+    ApiPath(subscriber_id): ApiPath<Uuid>,
+) -> Result<Response, Problem> {
+    let token = authorize(&headers, &state.db_pool, ApiScope::SubscribersWrite).await?;
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    if !remove_subscriber(&mut transaction, subscriber_id).await? {
+        return Err(no_such_subscriber());
+    }
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::SubscriberDeleted,
+        Some(token.user_id),
+        Some(&format!("subscriber:{}", subscriber_id)),
+        Some(client.ip),
+        Some(&audit_details(None, &token.token_id)),
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber.")?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+fn no_such_subscriber() -> Problem {
+    Problem::not_found("There is no subscriber with this id.")
+}
+
+fn audit_details(details: Option<String>, token_id: &Uuid) -> String {
+    match details {
+        Some(details) => format!("{} via API token {}", details, token_id),
+        None => format!("API token {}", token_id),
+    }
+}
+
+#[tracing::instrument(name = "Get a subscriber", skip(pool))]
+async fn get_subscriber(
+    subscriber_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber.")
+}
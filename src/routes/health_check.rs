@@ -1,6 +1,163 @@
-use axum::http::StatusCode;
+use std::{future::Future, time::Instant};
 
-#[tracing::instrument(name = "Health check")]
-pub async fn health_check() -> StatusCode {
-    StatusCode::OK
+use anyhow::Context;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use sqlx::PgPool;
+
+use crate::AppState;
+
+/// How long readiness waits for Postgres before giving up.
+const DATABASE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Public pages keep working without the session store, but logging in does
+/// not: report the application as unavailable until the store is back.
+#[tracing::instrument(name = "Health check", skip(state))]
+pub async fn health_check(State(state): State<AppState>) -> Response {
+    match state.session_store.check_health().await {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => {
+            tracing::error!(error.cause_chain = ?e, "The session store is unavailable.");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "The session store is unavailable.",
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct BuildInfo {
+    name: &'static str,
+    version: &'static str,
+}
+
+const BUILD: BuildInfo = BuildInfo {
+    name: env!("CARGO_PKG_NAME"),
+    version: env!("CARGO_PKG_VERSION"),
+};
+
+#[derive(serde::Serialize)]
+struct Liveness {
+    status: &'static str,
+    build: BuildInfo,
+}
+
+/// The process is up and serving requests. Its dependencies are not
+/// checked: restarting it would not bring them back.
+#[tracing::instrument(name = "Liveness check")]
+pub async fn liveness() -> Response {
+    Json(Liveness {
+        status: "up",
+        build: BUILD,
+    })
+    .into_response()
+}
+
+#[derive(serde::Serialize)]
+struct Readiness {
+    status: &'static str,
+    build: BuildInfo,
+    checks: Checks,
+}
+
+#[derive(serde::Serialize)]
+struct Checks {
+    database: Check,
+    session_store: Check,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Option<Check>,
+}
+
+impl Checks {
+    fn all_up(&self) -> bool {
+        self.database.is_up()
+            && self.session_store.is_up()
+            && self.email.as_ref().is_none_or(Check::is_up)
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Check {
+    status: &'static str,
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    async fn run(check: impl Future<Output = Result<(), anyhow::Error>>) -> Self {
+        let started = Instant::now();
+        let outcome = check.await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+        match outcome {
+            Ok(()) => Check {
+                status: "up",
+                latency_ms,
+                error: None,
+            },
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, "A dependency is unavailable.");
+                Check {
+                    status: "down",
+                    latency_ms,
+                    error: Some(e.to_string()),
+                }
+            }
+        }
+    }
+
+    fn is_up(&self) -> bool {
+        self.status == "up"
+    }
+}
+
+/// Whether the application can serve traffic: Postgres, the session store
+/// and, unless turned off, the email settings are checked at once.
+#[tracing::instrument(name = "Readiness check", skip(state))]
+pub async fn readiness(State(state): State<AppState>) -> Response {
+    let (database, session_store, email) = tokio::join!(
+        Check::run(check_database(&state.db_pool)),
+        Check::run(state.session_store.check_health()),
+        async {
+            if state.health.check_email {
+                Some(Check::run(async { state.email_client.check_settings() }).await)
+            } else {
+                None
+            }
+        },
+    );
+    let checks = Checks {
+        database,
+        session_store,
+        email,
+    };
+    let (status_code, status) = if checks.all_up() {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+    (
+        status_code,
+        Json(Readiness {
+            status,
+            build: BUILD,
+            checks,
+        }),
+    )
+        .into_response()
+}
+
+async fn check_database(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!("SELECT 1 AS one").fetch_one(pool);
+    tokio::time::timeout(DATABASE_TIMEOUT, query)
+        .await
+        .context("Postgres did not answer in time.")?
+        .context("Failed to reach Postgres.")?;
+    Ok(())
 }
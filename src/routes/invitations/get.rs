@@ -0,0 +1,115 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse},
+};
+use axum_flash::IncomingFlashes;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{csrf::CsrfToken, AppState};
+
+use super::{InvitationError, InvitationParameters};
+
+#[tracing::instrument(
+    name = "Show the invitation form",
+    skip(state, csrf_token, flash_messages, parameters)
+)]
+pub async fn accept_invitation_form(
+    State(state): State<AppState>,
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+    Query(parameters): Query<InvitationParameters>,
+) -> Result<impl IntoResponse, InvitationError> {
+    if !state
+        .hmac_secret
+        .verify(&parameters.invitation_id.to_string(), &parameters.tag)
+    {
+        return Err(InvitationError::InvalidLink);
+    }
+    let email = get_pending_invitation_email(parameters.invitation_id, &state.db_pool)
+        .await?
+        .ok_or(InvitationError::InvalidLink)?;
+
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+    let invitation_id = parameters.invitation_id;
+    let tag = encode_attribute(&parameters.tag);
+    let email_html = encode_minimal(&email);
+    let email_attribute = encode_attribute(&email);
+
+    Ok((
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Accept invitation</title>
+</head>
+<body>
+    {msg_html}
+    <p>You have been invited as {email_html}. Choose a username and a password to finish setting up your account.</p>
+    <form action="/invitations/accept" method="post">
+        {csrf_field}
+        <input type="hidden" name="invitation_id" value="{invitation_id}">
+        <input type="hidden" name="tag" value="{tag}">
+        <label>Username
+            <input
+                type="text"
+                placeholder="Enter Username"
+                name="username"
+                value="{email_attribute}"
+            >
+        </label>
+        <br>
+        <label>Password
+            <input
+                type="password"
+                placeholder="Enter password"
+                name="password"
+            >
+        </label>
+        <br>
+        <label>Confirm password
+            <input
+                type="password"
+                placeholder="Type the password again"
+                name="password_check"
+            >
+        </label>
+        <br>
+        <button type="submit">Create account</button>
+    </form>
+</body>
+</html>"#,
+        )),
+    ))
+}
+
+/// Returns the invited email if the invitation exists, has not been used yet
+/// and has not expired.
+#[tracing::instrument(name = "Get pending invitation", skip(pool))]
+pub async fn get_pending_invitation_email(
+    invitation_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email
+        FROM user_invitations
+        WHERE invitation_id = $1 AND accepted_at IS NULL AND expires_at > now()
+        "#,
+        invitation_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the invitation.")?;
+    Ok(row.map(|r| r.email))
+}
@@ -0,0 +1,39 @@
+use axum::{http::StatusCode, response::IntoResponse};
+
+use super::error_chain_fmt;
+
+mod get;
+pub use get::accept_invitation_form;
+mod post;
+pub use post::accept_invitation;
+
+#[derive(serde::Deserialize)]
+pub struct InvitationParameters {
+    invitation_id: uuid::Uuid,
+    tag: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum InvitationError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("The invitation link is invalid or has expired.")]
+    InvalidLink,
+}
+
+impl std::fmt::Debug for InvitationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for InvitationError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            InvitationError::UnexpectedError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+            InvitationError::InvalidLink => StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use secrecy::{ExposeSecret, SecretString};
+use uuid::Uuid;
+
+use crate::{
+    authentication::create_user,
+    domain::{NewPassword, UserRole, Username},
+    routes::admin::{record_user_event, UserEvent},
+    AppState,
+};
+
+use super::InvitationError;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    invitation_id: Uuid,
+    tag: String,
+    username: String,
+    password: SecretString,
+    password_check: SecretString,
+}
+
+#[tracing::instrument(
+    name = "Accept an invitation",
+    skip(state, flash, form),
+    fields(invitation_id = %form.invitation_id, user_id = tracing::field::Empty)
+)]
+pub async fn accept_invitation(
+    State(state): State<AppState>,
+    flash: Flash,
+    Form(form): Form<FormData>,
+) -> Result<Response, InvitationError> {
+    let invitation_id = form.invitation_id.to_string();
+    if !state.hmac_secret.verify(&invitation_id, &form.tag) {
+        return Err(InvitationError::InvalidLink);
+    }
+    let form_url = format!(
+        "/invitations/accept?invitation_id={}&tag={}",
+        invitation_id,
+        urlencoding::encode(&form.tag)
+    );
+    if form.password.expose_secret() != form.password_check.expose_secret() {
+        return Ok((
+            flash.error("You entered two different passwords - the field values must match."),
+            Redirect::to(&form_url),
+        )
+            .into_response());
+    }
+    let password = match NewPassword::parse(form.password, &state.password_policy) {
+        Ok(password) => password,
+        Err(e) => return Ok((flash.error(e), Redirect::to(&form_url)).into_response()),
+    };
+    let username = match Username::parse(form.username) {
+        Ok(username) => username,
+        Err(e) => return Ok((flash.error(e), Redirect::to(&form_url)).into_response()),
+    };
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let invitation = sqlx::query!(
+        r#"
+        SELECT email, role
+        FROM user_invitations
+        WHERE invitation_id = $1 AND accepted_at IS NULL AND expires_at > now()
+        FOR UPDATE
+        "#,
+        form.invitation_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the invitation.")?
+    .ok_or(InvitationError::InvalidLink)?;
+    let email = invitation.email;
+    let role = UserRole::parse(invitation.role).map_err(|e| anyhow::anyhow!(e))?;
+
+    let taken = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE username = $1 OR email = $2"#,
+        username.as_ref(),
+        email,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look up existing users.")?
+    .is_some();
+    if taken {
+        return Ok((
+            flash.error("This username is already taken."),
+            Redirect::to(&form_url),
+        )
+            .into_response());
+    }
+
+    let user_id = create_user(
+        &mut transaction,
+        &username,
+        &email,
+        role,
+        password,
+        &state.password_hashing,
+    )
+    .await?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    sqlx::query!(
+        r#"UPDATE user_invitations SET accepted_at = now() WHERE invitation_id = $1"#,
+        form.invitation_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark the invitation as accepted.")?;
+    record_user_event(
+        &mut *transaction,
+        UserEvent::InvitationAccepted,
+        user_id,
+        Some(user_id),
+        Some(&email),
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to accept an invitation.")?;
+
+    Ok((
+        flash.info("Your account has been created. You can now log in."),
+        Redirect::to("/login"),
+    )
+        .into_response())
+}
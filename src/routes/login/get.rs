@@ -1,12 +1,34 @@
 use std::fmt::Write;
 
-use axum::response::{Html, IntoResponse};
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse},
+};
 use axum_flash::{IncomingFlashes, Level};
+use htmlescape::encode_minimal;
 
-pub async fn login_form(flash_messages: IncomingFlashes) -> (IncomingFlashes, impl IntoResponse) {
+use crate::{csrf::CsrfToken, AppState};
+
+pub async fn login_form(
+    State(state): State<AppState>,
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+) -> (IncomingFlashes, impl IntoResponse) {
+    let csrf_field = csrf_token.form_field();
+    let remember_me_html = match state.session_lifetime.remember_me {
+        Some(lifetime) => format!(
+            r#"<label>
+            <input type="checkbox" name="remember_me">
+            Remember me for {} days
+        </label>
+        "#,
+            lifetime.num_days()
+        ),
+        None => String::new(),
+    };
     let mut error_html = String::new();
     for (_level, content) in flash_messages.iter() {
-        writeln!(error_html, "<p><i>{}</i></p>", content).unwrap();
+        writeln!(error_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
     }
     (
         flash_messages,
@@ -20,6 +42,7 @@ pub async fn login_form(flash_messages: IncomingFlashes) -> (IncomingFlashes, im
 <body>
     {error_html}
     <form action="/login" method="post">
+        {csrf_field}
         <label>Username
             <input
                 type="text"
@@ -34,8 +57,9 @@ pub async fn login_form(flash_messages: IncomingFlashes) -> (IncomingFlashes, im
                 name="password"
             >
         </label>
-        <button type="submit">Login</button>
+        {remember_me_html}<button type="submit">Login</button>
     </form>
+    <p><a href="/password-reset">Forgot your password?</a></p>
 </body>
 </html>"#,
         ))
@@ -1,11 +1,18 @@
+use std::{net::IpAddr, time::Duration};
+
 use axum::{extract::State, response::Redirect, Form};
 use axum_flash::Flash;
 use secrecy::SecretString;
+use sqlx::PgPool;
 
 use crate::{
+    audit::{record_audit_entry, AuditAction},
     authentication::{validate_credentials, Credentials},
+    login_throttle::Verdict,
     routes::error_chain_fmt,
     session_state::TypedSession,
+    two_factor::needs_second_factor,
+    user_sessions::{log_in, ClientInfo},
     AppState,
 };
 
@@ -13,32 +20,85 @@ use crate::{
 pub struct FormData {
     username: String,
     password: SecretString,
+    /// Checkboxes are only submitted when ticked.
+    remember_me: Option<String>,
 }
 
 #[tracing::instrument(
-    skip(state, form, flash, session),
-    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+    skip(state, form, flash, session, client),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty, ip=%client.ip)
 )]
 #[axum::debug_handler]
 pub async fn login(
     State(state): State<AppState>,
+    client: ClientInfo,
     flash: Flash,
     session: TypedSession,
     Form(form): Form<FormData>,
 ) -> Result<Redirect, (Flash, Redirect)> {
+    let ip = client.ip;
+    let remember_me = form.remember_me.is_some();
+    let username = form.username;
+    tracing::Span::current().record("username", tracing::field::display(&username));
+
+    match state.login_throttle.check(&username, ip).await {
+        Ok(Verdict::Proceed { delay }) => {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+        }
+        Ok(Verdict::LockedOut { retry_after }) => {
+            tracing::warn!("Blocked a login attempt after too many failures.");
+            state.metrics.record_login_failure("locked_out");
+            if let Err(e) = record_failed_login(&username, ip, "locked out", &state.db_pool).await {
+                return Err(login_redirect(flash, LoginError::UnexpectedError(e)));
+            }
+            return Err(login_redirect(
+                flash,
+                LoginError::TooManyAttempts(retry_after),
+            ));
+        }
+        Err(e) => return Err(login_redirect(flash, LoginError::UnexpectedError(e))),
+    }
+
     let credentials = Credentials {
-        username: form.username,
+        username: username.clone(),
         password: form.password,
     };
-    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
-    match validate_credentials(credentials, &state.db_pool).await {
+    match validate_credentials(credentials, &state.password_hashing, &state.db_pool).await {
         Ok(user_id) => {
             tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            if let Err(e) = state.login_throttle.record_success(&username).await {
+                return Err(login_redirect(flash, LoginError::UnexpectedError(e)));
+            }
             if let Err(e) = session.renew().await {
                 return Err(login_redirect(flash, LoginError::UnexpectedError(e)));
             }
 
-            if let Err(e) = session.insert_user_id(user_id).await {
+            match needs_second_factor(user_id, &state.db_pool).await {
+                Ok(true) => {
+                    if let Err(e) = session.insert_pending_user_id(user_id).await {
+                        return Err(login_redirect(flash, LoginError::UnexpectedError(e)));
+                    }
+                    if let Err(e) = session.insert_remember_me(remember_me).await {
+                        return Err(login_redirect(flash, LoginError::UnexpectedError(e)));
+                    }
+                    return Ok(Redirect::to("/login/two-factor"));
+                }
+                Ok(false) => {}
+                Err(e) => return Err(login_redirect(flash, LoginError::UnexpectedError(e))),
+            }
+
+            if let Err(e) = log_in(
+                &session,
+                user_id,
+                remember_me,
+                state.session_lifetime,
+                &client,
+                &state.db_pool,
+            )
+            .await
+            {
                 return Err(login_redirect(flash, LoginError::UnexpectedError(e)));
             }
             Ok(Redirect::to("/admin/dashboard"))
@@ -46,6 +106,25 @@ pub async fn login(
         Err(e) => {
             let e = match e {
                 crate::authentication::AuthError::InvalidCredentials(_) => {
+                    state.metrics.record_login_failure("invalid_credentials");
+                    match state.login_throttle.record_failure(&username, ip).await {
+                        Ok(failures) => tracing::warn!(failures, "Failed login attempt."),
+                        Err(throttle_error) => {
+                            return Err(login_redirect(
+                                flash,
+                                LoginError::UnexpectedError(throttle_error),
+                            ))
+                        }
+                    }
+                    if let Err(audit_error) =
+                        record_failed_login(&username, ip, "invalid credentials", &state.db_pool)
+                            .await
+                    {
+                        return Err(login_redirect(
+                            flash,
+                            LoginError::UnexpectedError(audit_error),
+                        ));
+                    }
                     LoginError::AuthError(e.into())
                 }
                 crate::authentication::AuthError::UnexpectedError(_) => {
@@ -57,6 +136,23 @@ pub async fn login(
     }
 }
 
+async fn record_failed_login(
+    username: &str,
+    ip: IpAddr,
+    reason: &str,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    record_audit_entry(
+        pool,
+        AuditAction::LoginFailed,
+        None,
+        Some(&format!("username:{}", username)),
+        Some(ip),
+        Some(reason),
+    )
+    .await
+}
+
 // Redirect to the login page with an error message.
 fn login_redirect(flash: Flash, e: LoginError) -> (Flash, Redirect) {
     (flash.error(e.to_string()), Redirect::to("/login"))
@@ -66,6 +162,11 @@ fn login_redirect(flash: Flash, e: LoginError) -> (Flash, Redirect) {
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error(
+        "Too many failed login attempts. Try again in {} minutes.",
+        .0.as_secs().div_ceil(60)
+    )]
+    TooManyAttempts(Duration),
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
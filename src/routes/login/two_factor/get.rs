@@ -0,0 +1,104 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_flash::IncomingFlashes;
+use htmlescape::encode_minimal;
+
+use crate::{
+    csrf::CsrfToken,
+    routes::admin::{enrollment_html, get_username},
+    session_state::TypedSession,
+    two_factor::{get_totp_secret, TotpSecret},
+    utils::e500,
+    AppState,
+};
+
+#[tracing::instrument(
+    name = "Show the second login step",
+    skip(state, session, csrf_token, flash_messages)
+)]
+pub async fn login_two_factor_form(
+    State(state): State<AppState>,
+    session: TypedSession,
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+) -> Result<Response, Response> {
+    let user_id = match session.get_pending_user_id().await.map_err(e500)? {
+        Some(user_id) => user_id,
+        None => return Ok(Redirect::to("/login").into_response()),
+    };
+
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+
+    let (instructions_html, placeholder) = if get_totp_secret(user_id, &state.db_pool)
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        (
+            "<p>Enter the code from your authenticator app, or one of your recovery codes.</p>"
+                .to_string(),
+            "Code from your app or a recovery code",
+        )
+    } else {
+        // Two-factor authentication is required, but this user never
+        // enrolled: they have to do it before they can log in.
+        let secret = match session.get_totp_enrollment_secret().await.map_err(e500)? {
+            Some(secret) => secret,
+            None => {
+                let secret = TotpSecret::generate();
+                session
+                    .insert_totp_enrollment_secret(&secret)
+                    .await
+                    .map_err(e500)?;
+                secret
+            }
+        };
+        let username = get_username(user_id, &state.db_pool).await.map_err(e500)?;
+        (
+            format!(
+                r#"<p>Two-factor authentication is required for every account. Set it up to finish logging in.</p>
+    {}"#,
+                enrollment_html(&secret, &username).map_err(e500)?
+            ),
+            "Code from your app",
+        )
+    };
+
+    Ok((
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+<body>
+    {msg_html}
+    {instructions_html}
+    <form action="/login/two-factor" method="post">
+        {csrf_field}
+        <label>Authentication code
+            <input
+                type="text"
+                autocomplete="one-time-code"
+                placeholder="{placeholder}"
+                name="code"
+            >
+        </label>
+        <button type="submit">Login</button>
+    </form>
+</body>
+</html>"#,
+        )),
+    )
+        .into_response())
+}
@@ -0,0 +1,203 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_entry, AuditAction},
+    login_throttle::Verdict,
+    routes::{
+        admin::{record_user_event, recovery_codes_page, UserEvent},
+        login::post::LoginError,
+    },
+    session_state::TypedSession,
+    two_factor::{enable_two_factor, get_totp_secret, verify_second_factor},
+    user_sessions::{log_in, ClientInfo},
+    utils::e500,
+    AppState,
+};
+
+/// Wrong codes allowed for one pending login. Past that, the password has
+/// to be entered again.
+const MAX_ATTEMPTS_PER_LOGIN: u32 = 3;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[tracing::instrument(
+    name = "Complete the second login step",
+    skip(state, session, client, flash, form),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn login_two_factor(
+    State(state): State<AppState>,
+    session: TypedSession,
+    client: ClientInfo,
+    flash: Flash,
+    Form(form): Form<FormData>,
+) -> Result<Response, Response> {
+    let user_id = match session.get_pending_user_id().await.map_err(e500)? {
+        Some(user_id) => user_id,
+        None => return Ok(Redirect::to("/login").into_response()),
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    if let Some(secret) = get_totp_secret(user_id, &state.db_pool)
+        .await
+        .map_err(e500)?
+    {
+        match state
+            .login_throttle
+            .check_second_factor(user_id, client.ip)
+            .await
+            .map_err(e500)?
+        {
+            Verdict::Proceed { delay } => {
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            Verdict::LockedOut { retry_after } => {
+                tracing::warn!("Blocked a second factor after too many failures.");
+                state.metrics.record_login_failure("locked_out");
+                record_failed_code(user_id, &client, "locked out", &state)
+                    .await
+                    .map_err(e500)?;
+                session.remove_pending_user_id().await.map_err(e500)?;
+                return Ok(back_to_login(
+                    flash,
+                    &LoginError::TooManyAttempts(retry_after).to_string(),
+                ));
+            }
+        }
+        if !verify_second_factor(user_id, &secret, &form.code, &state.db_pool)
+            .await
+            .map_err(e500)?
+        {
+            state.metrics.record_login_failure("invalid_code");
+            let failures = state
+                .login_throttle
+                .record_second_factor_failure(user_id, client.ip)
+                .await
+                .map_err(e500)?;
+            tracing::warn!(failures, "Failed second factor attempt.");
+            record_failed_code(user_id, &client, "invalid authentication code", &state)
+                .await
+                .map_err(e500)?;
+            if session.record_second_factor_failure().await.map_err(e500)? >= MAX_ATTEMPTS_PER_LOGIN
+            {
+                session.remove_pending_user_id().await.map_err(e500)?;
+                return Ok(back_to_login(
+                    flash,
+                    "Too many invalid authentication codes. Log in again.",
+                ));
+            }
+            return Ok(invalid_code(flash));
+        }
+        state
+            .login_throttle
+            .record_second_factor_success(user_id)
+            .await
+            .map_err(e500)?;
+        complete_login(&session, user_id, &client, &state)
+            .await
+            .map_err(e500)?;
+        return Ok(Redirect::to("/admin/dashboard").into_response());
+    }
+
+    // The user is enrolling as part of logging in.
+    let Some(secret) = session.get_totp_enrollment_secret().await.map_err(e500)? else {
+        return Ok(Redirect::to("/login/two-factor").into_response());
+    };
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let Some(recovery_codes) = enable_two_factor(&mut transaction, user_id, &secret, &form.code)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(invalid_code(flash));
+    };
+    record_user_event(
+        &mut *transaction,
+        UserEvent::TwoFactorEnabled,
+        user_id,
+        Some(user_id),
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to enable two-factor authentication.")
+        .map_err(e500)?;
+    session
+        .remove_totp_enrollment_secret()
+        .await
+        .map_err(e500)?;
+    complete_login(&session, user_id, &client, &state)
+        .await
+        .map_err(e500)?;
+
+    Ok(recovery_codes_page(&recovery_codes, "/admin/dashboard").into_response())
+}
+
+async fn record_failed_code(
+    user_id: Uuid,
+    client: &ClientInfo,
+    reason: &str,
+    state: &AppState,
+) -> Result<(), anyhow::Error> {
+    record_audit_entry(
+        &state.db_pool,
+        AuditAction::LoginFailed,
+        None,
+        Some(&format!("user:{}", user_id)),
+        Some(client.ip),
+        Some(reason),
+    )
+    .await
+}
+
+fn back_to_login(flash: Flash, message: &str) -> Response {
+    (flash.error(message), Redirect::to("/login")).into_response()
+}
+
+fn invalid_code(flash: Flash) -> Response {
+    (
+        flash.error("The authentication code is invalid."),
+        Redirect::to("/login/two-factor"),
+    )
+        .into_response()
+}
+
+/// Turn the pending login into a logged-in session, under a new session id.
+async fn complete_login(
+    session: &TypedSession,
+    user_id: Uuid,
+    client: &ClientInfo,
+    state: &AppState,
+) -> Result<(), anyhow::Error> {
+    session.remove_pending_user_id().await?;
+    let remember_me = session.remove_remember_me().await?;
+    session.renew().await?;
+    log_in(
+        session,
+        user_id,
+        remember_me,
+        state.session_lifetime,
+        client,
+        &state.db_pool,
+    )
+    .await
+}
@@ -1,15 +1,23 @@
 mod admin;
+mod api;
 mod health_check;
 mod home;
+mod invitations;
 mod login;
 mod newsletters;
+mod password_reset;
+mod setup;
 mod subscriptions;
 mod subscriptions_confirm;
 
 pub use admin::*;
+pub use api::*;
 pub use health_check::*;
 pub use home::*;
+pub use invitations::*;
 pub use login::*;
 pub use newsletters::*;
+pub use password_reset::*;
+pub use setup::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
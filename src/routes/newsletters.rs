@@ -1,3 +1,5 @@
+use std::net::IpAddr;
+
 use anyhow::Context;
 use axum::{
     extract::State,
@@ -5,14 +7,17 @@ use axum::{
     response::IntoResponse,
     Json,
 };
-use base64::Engine;
 use reqwest::StatusCode;
-use secrecy::SecretString;
-use sqlx::PgPool;
+use uuid::Uuid;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials},
-    domain::SubscriberEmail,
+    api_tokens::{authenticate_api_token, bearer_token, AuthenticatedToken},
+    audit::{record_audit_entry, AuditAction},
+    authentication::get_user_role,
+    domain::ApiScope,
+    issue_deliveries::record_recipients,
+    user_sessions::ClientInfo,
+    webhooks::{enqueue_event, WebhookEvent},
     AppState,
 };
 
@@ -22,6 +27,8 @@ use super::error_chain_fmt;
 pub enum PublishError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("The token is not allowed to publish newsletter issues")]
+    Forbidden,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -36,9 +43,10 @@ impl IntoResponse for PublishError {
     fn into_response(self) -> axum::response::Response {
         match self {
             PublishError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            PublishError::Forbidden => StatusCode::FORBIDDEN.into_response(),
             PublishError::AuthError(_) => {
                 let mut response = StatusCode::UNAUTHORIZED.into_response();
-                let header_value = HeaderValue::from_str(r#"Basic realm="publish""#).unwrap();
+                let header_value = HeaderValue::from_str(r#"Bearer realm="publish""#).unwrap();
                 response
                     .headers_mut()
                     .insert(header::WWW_AUTHENTICATE, header_value);
@@ -60,105 +68,95 @@ pub struct Content {
     text: String,
 }
 
-struct ConfirmedSubscriber {
-    email: SubscriberEmail,
-}
-
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
-async fn get_confirmed_subscribers(
-    pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let rows = sqlx::query!(
-        r#"
-        SELECT email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-    )
-    .fetch_all(pool)
-    .await?;
-    let confirmed_subscribers = rows
-        .into_iter()
-        .map(|r| match SubscriberEmail::parse(r.email) {
-            Ok(email) => Ok(ConfirmedSubscriber { email }),
-            Err(error) => Err(anyhow::anyhow!(error)),
-        })
-        .collect();
-    Ok(confirmed_subscribers)
-}
-
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip(headers, state, body),
-    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+    skip(headers, state, client, body),
+    fields(token_id=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn publish_newsletter(
     headers: HeaderMap,
     State(state): State<AppState>,
+    client: ClientInfo,
     Json(body): Json<BodyData>,
 ) -> Result<StatusCode, PublishError> {
-    let credentials = basic_authentication(&headers).map_err(PublishError::AuthError)?;
-    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
-    let user_id = validate_credentials(credentials, &state.db_pool)
-        .await
-        .map_err(|e| match e {
-            AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
-            AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
-        })?;
-    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
-    let subscribers = get_confirmed_subscribers(&state.db_pool).await?;
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                state
-                    .email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.title,
-                        &body.content.html,
-                        &body.content.text,
-                    )
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email)
-                    })?;
-            }
-            Err(error) => {
-                tracing::warn!(error.cause_chain = ?error, "Skipping a confirmed subscriber. Their stored contact details are invalid.")
-            }
-        }
+    let token = bearer_token(&headers).map_err(PublishError::AuthError)?;
+    let token = authenticate_api_token(&token, &state.db_pool)
+        .await?
+        .ok_or_else(|| PublishError::AuthError(anyhow::anyhow!("Invalid API token.")))?;
+    tracing::Span::current()
+        .record("token_id", tracing::field::display(&token.token_id))
+        .record("user_id", tracing::field::display(&token.user_id));
+    // The owner's role may have changed since the token was created.
+    let role = get_user_role(token.user_id, &state.db_pool).await?;
+    if !token.has_scope(ApiScope::Publish) || !role.can(ApiScope::Publish.permission()) {
+        return Err(PublishError::Forbidden);
     }
+    let issue = NewIssue {
+        title: body.title,
+        html_content: body.content.html,
+        text_content: body.content.text,
+    };
+    publish_issue(&state, &issue, &token, client.ip).await?;
     Ok(StatusCode::OK)
 }
 
-fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
-    let header_value = headers
-        .get("Authorization")
-        .context("The 'Authorization' header was missing")?
-        .to_str()
-        .context("The 'Authorization' header was not a valid UTF8 string.")?;
-    let base64encoded_segment = header_value
-        .strip_prefix("Basic ")
-        .context("The authorization scheme was not 'Basic'.")?;
-    let decoded_bytes = base64::engine::general_purpose::STANDARD
-        .decode(base64encoded_segment)
-        .context("Failed to base64-decode 'Basic' credentials.")?;
-    let decoded_credentials = String::from_utf8(decoded_bytes)
-        .context("The decoded credential string is not valid UTF8.")?;
-
-    // Split into two segments, using ':' as delimiter
-    let mut credentials = decoded_credentials.splitn(2, ':');
-    let username = credentials
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
-        .to_string();
-    let password = credentials
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
-        .to_string();
+pub struct NewIssue {
+    pub title: String,
+    pub html_content: String,
+    pub text_content: String,
+}
 
-    Ok(Credentials {
-        username,
-        password: SecretString::new(password.into()),
-    })
+/// Keep `issue` with every confirmed subscriber as a pending recipient, queue
+/// the `issue.published` event and audit the publication. The issue is sent
+/// to the recipients by the background workers. Returns the id of the stored
+/// issue.
+#[tracing::instrument(name = "Publish a newsletter issue", skip(state, issue, token))]
+pub async fn publish_issue(
+    state: &AppState,
+    issue: &NewIssue,
+    token: &AuthenticatedToken,
+    ip: IpAddr,
+) -> Result<Uuid, anyhow::Error> {
+    let issue_id = Uuid::new_v4();
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues
+            (issue_id, title, text_content, html_content, published_at, published_by)
+        VALUES ($1, $2, $3, $4, now(), $5)
+        "#,
+        issue_id,
+        issue.title,
+        issue.text_content,
+        issue.html_content,
+        token.user_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store the newsletter issue.")?;
+    record_recipients(&mut *transaction, issue_id).await?;
+    enqueue_event(
+        &mut *transaction,
+        WebhookEvent::IssuePublished,
+        serde_json::json!({ "id": issue_id, "title": issue.title }),
+    )
+    .await?;
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::IssuePublished,
+        Some(token.user_id),
+        Some(&format!("issue:{}", issue_id)),
+        Some(ip),
+        Some(&format!("API token {}", token.token_id)),
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a newsletter issue.")?;
+    Ok(issue_id)
 }
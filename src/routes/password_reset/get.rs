@@ -0,0 +1,134 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse},
+};
+use axum_flash::IncomingFlashes;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+
+use crate::{csrf::CsrfToken, AppState};
+
+use super::{hash_reset_token, PasswordResetError, ResetParameters};
+
+pub async fn password_reset_form(
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+) -> (IncomingFlashes, impl IntoResponse) {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+    (
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Reset your password</title>
+</head>
+<body>
+    {msg_html}
+    <p>Enter your username and we will email you a link to choose a new password.</p>
+    <form action="/password-reset" method="post">
+        {csrf_field}
+        <label>Username
+            <input
+                type="text"
+                placeholder="Enter Username"
+                name="username"
+            >
+        </label>
+        <button type="submit">Send reset link</button>
+    </form>
+    <p><a href="/login">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )),
+    )
+}
+
+#[tracing::instrument(
+    name = "Show the password reset form",
+    skip(state, csrf_token, flash_messages, parameters)
+)]
+pub async fn confirm_password_reset_form(
+    State(state): State<AppState>,
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+    Query(parameters): Query<ResetParameters>,
+) -> Result<impl IntoResponse, PasswordResetError> {
+    if !is_valid_reset_token(&parameters.token, &state.db_pool).await? {
+        return Err(PasswordResetError::InvalidLink);
+    }
+
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+    let token = encode_attribute(&parameters.token);
+
+    Ok((
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Choose a new password</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/password-reset/confirm" method="post">
+        {csrf_field}
+        <input type="hidden" name="token" value="{token}">
+        <label>New password
+            <input
+                type="password"
+                placeholder="Enter new password"
+                name="new_password"
+            >
+        </label>
+        <br>
+        <label>Confirm new password
+            <input
+                type="password"
+                placeholder="Type the new password again"
+                name="new_password_check"
+            >
+        </label>
+        <br>
+        <button type="submit">Set new password</button>
+    </form>
+</body>
+</html>"#,
+        )),
+    ))
+}
+
+/// A token is valid if it has not been used, has not expired and belongs to
+/// an active user.
+#[tracing::instrument(name = "Check password reset token", skip(token, pool))]
+async fn is_valid_reset_token(token: &str, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT password_reset_tokens.user_id
+        FROM password_reset_tokens
+        JOIN users ON users.user_id = password_reset_tokens.user_id
+        WHERE password_reset_tokens.token_hash = $1
+            AND password_reset_tokens.used_at IS NULL
+            AND password_reset_tokens.expires_at > now()
+            AND users.is_active
+        "#,
+        hash_reset_token(token),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the password reset token.")?;
+    Ok(row.is_some())
+}
@@ -0,0 +1,56 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use super::error_chain_fmt;
+
+mod get;
+pub use get::{confirm_password_reset_form, password_reset_form};
+mod post;
+pub use post::{confirm_password_reset, request_password_reset};
+
+const TOKEN_LENGTH: usize = 40;
+
+#[derive(serde::Deserialize)]
+pub struct ResetParameters {
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum PasswordResetError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("The password reset link is invalid or has expired.")]
+    InvalidLink,
+}
+
+impl std::fmt::Debug for PasswordResetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for PasswordResetError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            PasswordResetError::UnexpectedError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+            PasswordResetError::InvalidLink => StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
+}
+
+fn generate_reset_token() -> String {
+    rand::rng()
+        .sample_iter(rand::distr::Alphanumeric)
+        .map(char::from)
+        .take(TOKEN_LENGTH)
+        .collect()
+}
+
+/// Only the hash is stored, so a leaked database does not hand out working
+/// reset links.
+fn hash_reset_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,266 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use chrono::{TimeDelta, Utc};
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::{PgExecutor, PgPool};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_entry, AuditAction},
+    authentication,
+    domain::{NewPassword, SubscriberEmail},
+    email_client::EmailClient,
+    routes::admin::{record_user_event, UserEvent},
+    user_sessions::{end_user_sessions, ClientInfo},
+    AppState,
+};
+
+use super::{generate_reset_token, hash_reset_token, PasswordResetError};
+
+/// How long a password reset link stays valid.
+const RESET_TOKEN_VALIDITY: TimeDelta = TimeDelta::hours(1);
+
+#[derive(serde::Deserialize)]
+pub struct RequestFormData {
+    username: String,
+}
+
+#[tracing::instrument(name = "Request a password reset", skip(state, flash, form))]
+pub async fn request_password_reset(
+    State(state): State<AppState>,
+    flash: Flash,
+    Form(form): Form<RequestFormData>,
+) -> Response {
+    // The answer is the same whether the username exists or not, so that the
+    // form cannot be used to find out who has an account. The link is sent
+    // after answering, so that the response time does not tell either.
+    let username = form.username.trim().to_owned();
+    tokio::spawn(
+        async move {
+            if let Err(e) = send_reset_link(&username, &state).await {
+                tracing::error!(error.cause_chain = ?e, "Failed to send a password reset link.");
+            }
+        }
+        .instrument(tracing::Span::current()),
+    );
+
+    (
+        flash.info(
+            "If the username belongs to an account with an email address, \
+            a link to reset the password has been sent to it.",
+        ),
+        Redirect::to("/login"),
+    )
+        .into_response()
+}
+
+/// Store a reset token for the user with this username and email them the
+/// link, if they have an email address.
+async fn send_reset_link(username: &str, state: &AppState) -> Result<(), anyhow::Error> {
+    let Some((user_id, email)) = get_reset_recipient(username, &state.db_pool).await? else {
+        return Ok(());
+    };
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(e) => {
+            tracing::warn!(error.cause_chain = ?e, "The stored email of a user is invalid.");
+            return Ok(());
+        }
+    };
+    let token = generate_reset_token();
+    insert_reset_token(&state.db_pool, user_id, &token).await?;
+    send_password_reset_email(&state.email_client, &email, &state.base_url, &token)
+        .await
+        .context("Failed to send a password reset email.")?;
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmFormData {
+    token: String,
+    new_password: SecretString,
+    new_password_check: SecretString,
+}
+
+#[tracing::instrument(
+    name = "Reset a password",
+    skip(state, client, flash, form),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn confirm_password_reset(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    flash: Flash,
+    Form(form): Form<ConfirmFormData>,
+) -> Result<Response, PasswordResetError> {
+    let form_url = format!(
+        "/password-reset/confirm?token={}",
+        urlencoding::encode(&form.token)
+    );
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        return Ok((
+            flash.error("You entered two different new passwords - the field values must match."),
+            Redirect::to(&form_url),
+        )
+            .into_response());
+    }
+    let new_password = match NewPassword::parse(form.new_password, &state.password_policy) {
+        Ok(new_password) => new_password,
+        Err(e) => return Ok((flash.error(e), Redirect::to(&form_url)).into_response()),
+    };
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let user_id = sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        FROM users
+        WHERE password_reset_tokens.user_id = users.user_id
+            AND password_reset_tokens.token_hash = $1
+            AND password_reset_tokens.used_at IS NULL
+            AND password_reset_tokens.expires_at > now()
+            AND users.is_active
+        RETURNING password_reset_tokens.user_id
+        "#,
+        hash_reset_token(&form.token),
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the password reset token.")?
+    .ok_or(PasswordResetError::InvalidLink)?
+    .user_id;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    authentication::change_password(
+        user_id,
+        new_password,
+        &state.password_hashing,
+        &mut *transaction,
+    )
+    .await?;
+    // Any other link that is still around must not work anymore either.
+    sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to invalidate the remaining password reset tokens.")?;
+    record_user_event(
+        &mut *transaction,
+        UserEvent::PasswordReset,
+        user_id,
+        Some(user_id),
+        None,
+    )
+    .await?;
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::PasswordReset,
+        None,
+        Some(&format!("user:{}", user_id)),
+        Some(client.ip),
+        None,
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to reset a password.")?;
+
+    // Whoever knew the old password must not stay logged in.
+    end_user_sessions(user_id, None, &state.session_store, &state.db_pool).await?;
+
+    Ok((
+        flash.info("Your password has been reset. You can now log in."),
+        Redirect::to("/login"),
+    )
+        .into_response())
+}
+
+/// Returns the id and email of the active user with this username, if they
+/// have an email address to send a reset link to.
+#[tracing::instrument(name = "Get password reset recipient", skip(pool))]
+async fn get_reset_recipient(
+    username: &str,
+    pool: &PgPool,
+) -> Result<Option<(Uuid, String)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, email AS "email!"
+        FROM users
+        WHERE username = $1 AND is_active AND email IS NOT NULL
+        "#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the user requesting a password reset.")?;
+    Ok(row.map(|r| (r.user_id, r.email)))
+}
+
+#[tracing::instrument(name = "Store password reset token", skip(executor, token))]
+async fn insert_reset_token(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        hash_reset_token(token),
+        user_id,
+        now,
+        now + RESET_TOKEN_VALIDITY,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store the password reset token.")?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send a password reset email",
+    skip(email_client, base_url, token)
+)]
+async fn send_password_reset_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let reset_link = format!("{}/password-reset/confirm?token={}", base_url, token);
+    let plain_body = &format!(
+        "Someone asked to reset the password of your newsletter account.\n\
+        Visit {} to choose a new password. The link is valid for one hour.\n\
+        If you did not ask for it, you can ignore this email.",
+        reset_link
+    );
+    let html_body = &format!(
+        "Someone asked to reset the password of your newsletter account.<br />\
+        Click <a href=\"{}\">here</a> to choose a new password. \
+        The link is valid for one hour.<br />\
+        If you did not ask for it, you can ignore this email.",
+        reset_link
+    );
+    email_client
+        .send_email(email, "Reset your password", html_body, plain_body)
+        .await
+}
@@ -0,0 +1,101 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse},
+};
+use axum_flash::IncomingFlashes;
+use htmlescape::{encode_attribute, encode_minimal};
+
+use crate::{csrf::CsrfToken, AppState};
+
+use super::SetupError;
+
+#[derive(serde::Deserialize)]
+pub struct SetupParameters {
+    token: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Show the setup form",
+    skip(state, csrf_token, flash_messages, parameters)
+)]
+pub async fn setup_form(
+    State(state): State<AppState>,
+    csrf_token: CsrfToken,
+    flash_messages: IncomingFlashes,
+    Query(parameters): Query<SetupParameters>,
+) -> Result<impl IntoResponse, SetupError> {
+    if !state.setup.is_pending() {
+        return Err(SetupError::AlreadyCompleted);
+    }
+
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for (_level, content) in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(content)).unwrap();
+    }
+    let token = encode_attribute(parameters.token.as_deref().unwrap_or_default());
+
+    Ok((
+        flash_messages,
+        Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Setup</title>
+</head>
+<body>
+    {msg_html}
+    <p>Create the first account. It will own this newsletter. The setup token is printed in the application logs.</p>
+    <form action="/setup" method="post">
+        {csrf_field}
+        <label>Setup token
+            <input
+                type="text"
+                placeholder="Enter the setup token"
+                name="token"
+                value="{token}"
+            >
+        </label>
+        <br>
+        <label>Username
+            <input
+                type="text"
+                placeholder="Enter Username"
+                name="username"
+            >
+        </label>
+        <br>
+        <label>Email
+            <input
+                type="email"
+                placeholder="Enter email"
+                name="email"
+            >
+        </label>
+        <br>
+        <label>Password
+            <input
+                type="password"
+                placeholder="Enter password"
+                name="password"
+            >
+        </label>
+        <br>
+        <label>Confirm password
+            <input
+                type="password"
+                placeholder="Type the password again"
+                name="password_check"
+            >
+        </label>
+        <br>
+        <button type="submit">Create account</button>
+    </form>
+</body>
+</html>"#,
+        )),
+    ))
+}
@@ -0,0 +1,31 @@
+use axum::{http::StatusCode, response::IntoResponse};
+
+use super::error_chain_fmt;
+
+mod get;
+pub use get::setup_form;
+mod post;
+pub use post::complete_setup;
+
+#[derive(thiserror::Error)]
+pub enum SetupError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("The setup has already been completed.")]
+    AlreadyCompleted,
+}
+
+impl std::fmt::Debug for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for SetupError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            SetupError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            SetupError::AlreadyCompleted => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_flash::Flash;
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::{
+    domain::{NewPassword, SubscriberEmail, Username},
+    setup::create_first_owner,
+    AppState,
+};
+
+use super::SetupError;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    token: String,
+    username: String,
+    email: String,
+    password: SecretString,
+    password_check: SecretString,
+}
+
+#[tracing::instrument(
+    name = "Complete the setup",
+    skip(state, flash, form),
+    fields(username = %form.username, user_id = tracing::field::Empty)
+)]
+pub async fn complete_setup(
+    State(state): State<AppState>,
+    flash: Flash,
+    Form(form): Form<FormData>,
+) -> Result<Response, SetupError> {
+    if !state.setup.is_pending() {
+        return Err(SetupError::AlreadyCompleted);
+    }
+    if !state.setup.verify(&form.token) {
+        return Ok((
+            flash.error("The setup token is not valid."),
+            Redirect::to("/setup"),
+        )
+            .into_response());
+    }
+    let form_url = format!("/setup?token={}", urlencoding::encode(&form.token));
+    if form.password.expose_secret() != form.password_check.expose_secret() {
+        return Ok((
+            flash.error("You entered two different passwords - the field values must match."),
+            Redirect::to(&form_url),
+        )
+            .into_response());
+    }
+    let password = match NewPassword::parse(form.password, &state.password_policy) {
+        Ok(password) => password,
+        Err(e) => return Ok((flash.error(e), Redirect::to(&form_url)).into_response()),
+    };
+    let email = match SubscriberEmail::parse(form.email) {
+        Ok(email) => email,
+        Err(e) => return Ok((flash.error(e), Redirect::to(&form_url)).into_response()),
+    };
+    let username = match Username::parse(form.username) {
+        Ok(username) => username,
+        Err(e) => return Ok((flash.error(e), Redirect::to(&form_url)).into_response()),
+    };
+
+    let created = create_first_owner(
+        &username,
+        &email,
+        password,
+        &state.password_hashing,
+        "setup",
+        &state.db_pool,
+    )
+    .await?;
+    state.setup.complete();
+    let Some(user_id) = created else {
+        return Err(SetupError::AlreadyCompleted);
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    Ok((
+        flash.info("Setup is complete. You can now log in."),
+        Redirect::to("/login"),
+    )
+        .into_response())
+}
@@ -1,3 +1,5 @@
+use std::net::IpAddr;
+
 use anyhow::Context;
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Form};
 use chrono::Utc;
@@ -7,8 +9,11 @@ use unicode_segmentation::UnicodeSegmentation;
 use uuid::Uuid;
 
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    api_tokens::AuthenticatedToken,
+    audit::{record_audit_entry, AuditAction},
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriberStatus},
     email_client::EmailClient,
+    webhooks::{enqueue_event, WebhookEvent},
     AppState,
 };
 
@@ -32,6 +37,8 @@ impl TryFrom<FormData> for NewSubscriber {
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("The email address is already subscribed.")]
+    AlreadySubscribed,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -46,6 +53,7 @@ impl IntoResponse for SubscribeError {
     fn into_response(self) -> axum::response::Response {
         match self {
             SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST.into_response(),
+            SubscribeError::AlreadySubscribed => StatusCode::CONFLICT.into_response(),
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
         }
     }
@@ -55,7 +63,6 @@ impl IntoResponse for SubscribeError {
     name = "Adding a new subscriber",
     skip(state, form),
     fields(
-        request_id = %Uuid::new_v4(),
         subscriber_email = %form.email,
         subscriber_name = %form.name
     )
@@ -65,18 +72,63 @@ pub async fn subscribe(
     Form(form): Form<FormData>,
 ) -> Result<StatusCode, SubscribeError> {
     let new_subscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
+    match register_subscriber(&state, new_subscriber, None).await {
+        // The form is public: it must not tell who is subscribed already.
+        Ok(_) | Err(SubscribeError::AlreadySubscribed) => Ok(StatusCode::OK),
+        Err(e) => Err(e),
+    }
+}
+
+/// Store a pending subscriber and send them the confirmation email.
+/// Returns the id of the new subscriber. Subscribers added through the API
+/// are audited, with the token in `added_by` and the address it was used
+/// from.
+#[tracing::instrument(name = "Register a new subscriber", skip(state, new_subscriber))]
+pub async fn register_subscriber(
+    state: &AppState,
+    new_subscriber: NewSubscriber,
+    added_by: Option<(&AuthenticatedToken, IpAddr)>,
+) -> Result<Uuid, SubscribeError> {
     let mut transaction = state
         .db_pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool.")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
-        .await
-        .context("Failed to insert new subscriber in the database.")?;
+    let subscriber_id = match insert_subscriber(&mut transaction, &new_subscriber).await {
+        Ok(subscriber_id) => subscriber_id,
+        Err(e) if is_unique_violation(&e) => return Err(SubscribeError::AlreadySubscribed),
+        Err(e) => {
+            return Err(anyhow::Error::new(e)
+                .context("Failed to insert new subscriber in the database.")
+                .into())
+        }
+    };
     let subscription_token = generate_subscription_token();
     store_token(&mut transaction, subscriber_id, &subscription_token)
         .await
         .context("Failed to store the confirmation token for a new subscriber.")?;
+    enqueue_event(
+        &mut *transaction,
+        WebhookEvent::SubscriberCreated,
+        serde_json::json!({
+            "id": subscriber_id,
+            "email": new_subscriber.email.as_ref(),
+            "name": new_subscriber.name.as_ref(),
+            "status": SubscriberStatus::PendingConfirmation.as_str(),
+        }),
+    )
+    .await?;
+    if let Some((token, ip)) = added_by {
+        record_audit_entry(
+            &mut *transaction,
+            AuditAction::SubscriberCreated,
+            Some(token.user_id),
+            Some(&format!("subscriber:{}", subscriber_id)),
+            Some(ip),
+            Some(&format!("API token {}", token.token_id)),
+        )
+        .await?;
+    }
     transaction
         .commit()
         .await
@@ -89,7 +141,13 @@ pub async fn subscribe(
     )
     .await
     .context("Failed to send a confirmation email.")?;
-    Ok(StatusCode::OK)
+    state.metrics.record_signup();
+    Ok(subscriber_id)
+}
+
+fn is_unique_violation(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .is_some_and(|e| e.is_unique_violation())
 }
 
 #[tracing::instrument(
@@ -4,10 +4,14 @@ use axum::{
     http::status::StatusCode,
     response::IntoResponse,
 };
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 use uuid::Uuid;
 
-use crate::AppState;
+use crate::{
+    domain::SubscriberStatus,
+    webhooks::{enqueue_event, WebhookEvent},
+    AppState,
+};
 
 use super::error_chain_fmt;
 
@@ -51,25 +55,65 @@ pub async fn confirm(
             .await
             .context("Failed to retrieve the subscriber id associated with the provided token.")?
             .ok_or(ConfirmationError::UnknownToken)?;
-    confirm_subscriber(&state.db_pool, subscriber_id)
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let confirmed = confirm_subscriber(&mut *transaction, subscriber_id)
         .await
         .context("Failed to updated the subscriber status to `confirmed`.")?;
+    // Following the link again confirms nothing new.
+    let newly_confirmed = confirmed.is_some();
+    if let Some(subscriber) = confirmed {
+        enqueue_event(
+            &mut *transaction,
+            WebhookEvent::SubscriberConfirmed,
+            serde_json::json!({
+                "id": subscriber_id,
+                "email": subscriber.email,
+                "name": subscriber.name,
+                "status": SubscriberStatus::Confirmed.as_str(),
+            }),
+        )
+        .await?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+    if newly_confirmed {
+        state.metrics.record_confirmation();
+    }
     Ok(StatusCode::OK)
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+pub struct ConfirmedSubscriber {
+    pub email: String,
+    pub name: String,
+}
+
+/// Returns `None` if the subscriber was confirmed already.
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, executor))]
+pub async fn confirm_subscriber(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Option<ConfirmedSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        ConfirmedSubscriber,
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status <> 'confirmed'
+        RETURNING email, name
+        "#,
         subscriber_id,
     )
-    .execute(pool)
+    .fetch_optional(executor)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
-    })?;
-    Ok(())
+    })
 }
 
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
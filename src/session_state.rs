@@ -2,20 +2,47 @@ use axum::{
     extract::FromRequestParts,
     http::{self, request::Parts},
 };
+use chrono::{DateTime, Utc};
 use reqwest::StatusCode;
-use tower_sessions::Session;
+use tower_sessions::{session::Id, Expiry, Session};
 use uuid::Uuid;
 
+use crate::two_factor::TotpSecret;
+
 #[derive(Clone)]
 pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const PENDING_USER_ID_KEY: &'static str = "pending_user_id";
+    const TOTP_ENROLLMENT_KEY: &'static str = "totp_enrollment_secret";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
+    const EXPIRES_AT_KEY: &'static str = "expires_at";
+    const REMEMBER_ME_KEY: &'static str = "remember_me";
+    const SECOND_FACTOR_FAILURES_KEY: &'static str = "second_factor_failures";
 
     pub async fn renew(&self) -> Result<(), anyhow::Error> {
         self.0.cycle_id().await.map_err(|e| anyhow::anyhow!(e))
     }
 
+    /// Write the session to the store now rather than at the end of the
+    /// request, so that it gets an id, and return that id.
+    pub async fn save(&self) -> Result<Id, anyhow::Error> {
+        self.0.save().await.map_err(|e| anyhow::anyhow!(e))?;
+        self.0
+            .id()
+            .ok_or_else(|| anyhow::anyhow!("The session has no id after being saved."))
+    }
+
+    pub fn id(&self) -> Option<Id> {
+        self.0.id()
+    }
+
+    /// Override the expiry configured on the session layer for this session.
+    pub fn set_expiry(&self, expiry: Expiry) {
+        self.0.set_expiry(Some(expiry))
+    }
+
     pub async fn insert_user_id(&self, user_id: Uuid) -> Result<(), anyhow::Error> {
         self.0
             .insert(Self::USER_ID_KEY, user_id)
@@ -30,6 +57,132 @@ impl TypedSession {
             .map_err(|e| anyhow::anyhow!(e))
     }
 
+    /// When the logged-in session has to end, however active its user is.
+    pub async fn insert_expires_at(&self, expires_at: DateTime<Utc>) -> Result<(), anyhow::Error> {
+        self.0
+            .insert(Self::EXPIRES_AT_KEY, expires_at.timestamp())
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    pub async fn get_expires_at(&self) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+        self.0
+            .get::<i64>(Self::EXPIRES_AT_KEY)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?
+            .map(|timestamp| {
+                DateTime::from_timestamp(timestamp, 0)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid session expiry: {}", timestamp))
+            })
+            .transpose()
+    }
+
+    /// Carry the "Remember me" choice of a pending login over to the second
+    /// login step.
+    pub async fn insert_remember_me(&self, remember_me: bool) -> Result<(), anyhow::Error> {
+        self.0
+            .insert(Self::REMEMBER_ME_KEY, remember_me)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    pub async fn remove_remember_me(&self) -> Result<bool, anyhow::Error> {
+        self.0
+            .remove::<bool>(Self::REMEMBER_ME_KEY)
+            .await
+            .map(Option::unwrap_or_default)
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    /// Remember a user who entered a valid password but still has to
+    /// provide a second factor before being logged in.
+    pub async fn insert_pending_user_id(&self, user_id: Uuid) -> Result<(), anyhow::Error> {
+        self.0
+            .insert(Self::PENDING_USER_ID_KEY, user_id)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    pub async fn get_pending_user_id(&self) -> Result<Option<Uuid>, anyhow::Error> {
+        self.0
+            .get(Self::PENDING_USER_ID_KEY)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    /// End the pending login, along with the wrong codes entered for it.
+    pub async fn remove_pending_user_id(&self) -> Result<(), anyhow::Error> {
+        self.0
+            .remove::<u32>(Self::SECOND_FACTOR_FAILURES_KEY)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+        self.0
+            .remove::<Uuid>(Self::PENDING_USER_ID_KEY)
+            .await
+            .map(|_| ())
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    /// Count a wrong code entered for the pending login, and return how
+    /// many were entered so far.
+    pub async fn record_second_factor_failure(&self) -> Result<u32, anyhow::Error> {
+        let failures = self
+            .0
+            .get::<u32>(Self::SECOND_FACTOR_FAILURES_KEY)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?
+            .unwrap_or(0)
+            + 1;
+        self.0
+            .insert(Self::SECOND_FACTOR_FAILURES_KEY, failures)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+        Ok(failures)
+    }
+
+    /// Keep a freshly generated TOTP secret around until the user confirmed
+    /// it with a valid code.
+    pub async fn insert_totp_enrollment_secret(
+        &self,
+        secret: &TotpSecret,
+    ) -> Result<(), anyhow::Error> {
+        self.0
+            .insert(Self::TOTP_ENROLLMENT_KEY, secret.expose_base32())
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    pub async fn get_totp_enrollment_secret(&self) -> Result<Option<TotpSecret>, anyhow::Error> {
+        self.0
+            .get::<String>(Self::TOTP_ENROLLMENT_KEY)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?
+            .map(TotpSecret::parse)
+            .transpose()
+    }
+
+    pub async fn remove_totp_enrollment_secret(&self) -> Result<(), anyhow::Error> {
+        self.0
+            .remove::<String>(Self::TOTP_ENROLLMENT_KEY)
+            .await
+            .map(|_| ())
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    pub async fn insert_csrf_token(&self, token: &str) -> Result<(), anyhow::Error> {
+        self.0
+            .insert(Self::CSRF_TOKEN_KEY, token)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    pub async fn get_csrf_token(&self) -> Result<Option<String>, anyhow::Error> {
+        self.0
+            .get(Self::CSRF_TOKEN_KEY)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
     pub async fn log_out(self) -> Result<(), anyhow::Error> {
         self.0.delete().await.map_err(|e| anyhow::anyhow!(e))
     }
@@ -42,11 +195,11 @@ where
     type Rejection = (http::StatusCode, &'static str);
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let parts = parts.extensions.get::<TypedSession>().cloned().ok_or((
+        let session = parts.extensions.get::<Session>().cloned().ok_or((
             StatusCode::INTERNAL_SERVER_ERROR,
             "Can't extract session. Is `SessionManagerLayer` enabled?",
         ))?;
 
-        Ok(parts)
+        Ok(TypedSession(session))
     }
 }
@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tower_sessions::{
+    session::{Id, Record},
+    session_store, MemoryStore, SessionStore,
+};
+use tower_sessions_redis_store::{fred::prelude::*, RedisStore};
+
+use crate::configuration::SessionStoreKind;
+
+/// How long a health check waits for the session store before giving up.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Where sessions are kept, as chosen in the configuration.
+#[derive(Clone, Debug)]
+pub enum SessionBackend {
+    Redis {
+        store: RedisStore<Pool>,
+        pool: Pool,
+    },
+    Postgres(PostgresStore),
+    /// Sessions are lost on restart and not shared between instances.
+    Memory(MemoryStore),
+}
+
+impl SessionBackend {
+    /// Connect to the store for a one-off task, e.g. from the command line,
+    /// waiting for Redis rather than connecting in the background as the
+    /// server does. An in-memory store belongs to the server process: the
+    /// one returned here is empty.
+    pub async fn connect(
+        kind: SessionStoreKind,
+        redis_uri: &SecretString,
+        db_pool: &PgPool,
+    ) -> Result<Self, anyhow::Error> {
+        match kind {
+            SessionStoreKind::Redis => {
+                let pool = Pool::new(
+                    Config::from_url(redis_uri.expose_secret())?,
+                    None,
+                    None,
+                    None,
+                    1,
+                )
+                .context("Failed to create the Redis connection pool.")?;
+                pool.init().await.context("Failed to connect to Redis.")?;
+                Ok(SessionBackend::Redis {
+                    store: RedisStore::new(pool.clone()),
+                    pool,
+                })
+            }
+            SessionStoreKind::Postgres => Ok(SessionBackend::Postgres(PostgresStore::new(
+                db_pool.clone(),
+            ))),
+            SessionStoreKind::Memory => Ok(SessionBackend::Memory(MemoryStore::default())),
+        }
+    }
+
+    /// Check that the store can be reached, without waiting for too long.
+    #[tracing::instrument(name = "Check session store health", skip(self))]
+    pub async fn check_health(&self) -> Result<(), anyhow::Error> {
+        let check = async {
+            match self {
+                SessionBackend::Redis { pool, .. } => pool
+                    .get::<Option<String>, _>("health_check")
+                    .await
+                    .map(|_| ())
+                    .context("Failed to reach Redis."),
+                SessionBackend::Postgres(store) => sqlx::query!("SELECT 1 AS one")
+                    .fetch_one(&store.pool)
+                    .await
+                    .map(|_| ())
+                    .context("Failed to reach Postgres."),
+                SessionBackend::Memory(_) => Ok(()),
+            }
+        };
+        tokio::time::timeout(HEALTH_CHECK_TIMEOUT, check)
+            .await
+            .context("The session store did not answer in time.")?
+    }
+}
+
+#[async_trait]
+impl SessionStore for SessionBackend {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        match self {
+            SessionBackend::Redis { store, .. } => store.create(record).await,
+            SessionBackend::Postgres(store) => store.create(record).await,
+            SessionBackend::Memory(store) => store.create(record).await,
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        match self {
+            SessionBackend::Redis { store, .. } => store.save(record).await,
+            SessionBackend::Postgres(store) => store.save(record).await,
+            SessionBackend::Memory(store) => store.save(record).await,
+        }
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        match self {
+            SessionBackend::Redis { store, .. } => store.load(session_id).await,
+            SessionBackend::Postgres(store) => store.load(session_id).await,
+            SessionBackend::Memory(store) => store.load(session_id).await,
+        }
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        match self {
+            SessionBackend::Redis { store, .. } => store.delete(session_id).await,
+            SessionBackend::Postgres(store) => store.delete(session_id).await,
+            SessionBackend::Memory(store) => store.delete(session_id).await,
+        }
+    }
+}
+
+/// Keeps sessions in the `sessions` table of the application database.
+#[derive(Clone, Debug)]
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Remove the sessions that expired. Loading ignores them anyway, this
+    /// only keeps the table from growing.
+    #[tracing::instrument(name = "Delete expired sessions", skip(self))]
+    pub async fn delete_expired(&self) -> Result<u64, anyhow::Error> {
+        let result = sqlx::query!(r#"DELETE FROM sessions WHERE expires_at <= now()"#)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete expired sessions.")?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl SessionStore for PostgresStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        let (data, expires_at) = encode(record)?;
+        loop {
+            let result = sqlx::query!(
+                r#"
+                INSERT INTO sessions (id, data, expires_at)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (id) DO NOTHING
+                "#,
+                record.id.to_string(),
+                data,
+                expires_at,
+            )
+            .execute(&self.pool)
+            .await
+            .map_err(backend_error)?;
+            if result.rows_affected() == 1 {
+                return Ok(());
+            }
+            // Session id collision.
+            record.id = Id::default();
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        let (data, expires_at) = encode(record)?;
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (id, data, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (id) DO UPDATE
+            SET data = EXCLUDED.data, expires_at = EXCLUDED.expires_at
+            "#,
+            record.id.to_string(),
+            data,
+            expires_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(backend_error)?;
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        let row = sqlx::query!(
+            r#"SELECT data, expires_at FROM sessions WHERE id = $1 AND expires_at > now()"#,
+            session_id.to_string(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(backend_error)?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let data = serde_json::from_str(&row.data)
+            .map_err(|e| session_store::Error::Decode(e.to_string()))?;
+        let expiry_date = OffsetDateTime::from_unix_timestamp(row.expires_at.timestamp())
+            .map_err(|e| session_store::Error::Decode(e.to_string()))?;
+        Ok(Some(Record {
+            id: *session_id,
+            data,
+            expiry_date,
+        }))
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE id = $1"#,
+            session_id.to_string(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(backend_error)?;
+        Ok(())
+    }
+}
+
+fn encode(record: &Record) -> session_store::Result<(String, DateTime<Utc>)> {
+    let data = serde_json::to_string(&record.data)
+        .map_err(|e| session_store::Error::Encode(e.to_string()))?;
+    let expires_at = DateTime::from_timestamp(record.expiry_date.unix_timestamp(), 0)
+        .ok_or_else(|| session_store::Error::Encode("Invalid session expiry.".into()))?;
+    Ok((data, expires_at))
+}
+
+fn backend_error(e: sqlx::Error) -> session_store::Error {
+    session_store::Error::Backend(e.to_string())
+}
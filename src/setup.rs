@@ -0,0 +1,175 @@
+//! First-run setup. While no user can log in, the first owner is created
+//! either from the `initial_admin` settings or through the `/setup` page,
+//! which is guarded by a token printed to the log.
+
+use std::sync::{Arc, RwLock};
+
+use anyhow::Context;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use rand::Rng;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_entry, AuditAction},
+    authentication::{create_user, PasswordHashing},
+    configuration::InitialAdminSettings,
+    csrf::tokens_match,
+    domain::{NewPassword, PasswordPolicy, SubscriberEmail, UserRole, Username},
+    utils::e500,
+    AppState,
+};
+
+const TOKEN_LENGTH: usize = 32;
+
+/// Whether the first owner still has to be created. Shared by every request,
+/// it holds the token of the setup page until the setup is done.
+#[derive(Clone, Default)]
+pub struct Setup(Arc<RwLock<Option<SecretString>>>);
+
+impl Setup {
+    pub fn is_pending(&self) -> bool {
+        self.0.read().unwrap().is_some()
+    }
+
+    /// The token to enter on the setup page, while the setup is pending.
+    pub fn token(&self) -> Option<SecretString> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn verify(&self, token: &str) -> bool {
+        self.0
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|expected| tokens_match(token, expected.expose_secret()))
+    }
+
+    pub fn complete(&self) {
+        *self.0.write().unwrap() = None;
+    }
+
+    /// Create the first owner from `initial_admin` if no user can log in,
+    /// or else start waiting for the setup page to be filled in.
+    #[tracing::instrument(name = "Prepare first-run setup", skip_all)]
+    pub async fn prepare(
+        &self,
+        initial_admin: Option<InitialAdminSettings>,
+        policy: &PasswordPolicy,
+        hashing: &PasswordHashing,
+        base_url: &str,
+        pool: &PgPool,
+    ) -> Result<(), anyhow::Error> {
+        if !needs_setup(pool).await? {
+            return Ok(());
+        }
+        if let Some(admin) = initial_admin {
+            let username = Username::parse(admin.username).map_err(anyhow::Error::msg)?;
+            let email = SubscriberEmail::parse(admin.email).map_err(anyhow::Error::msg)?;
+            let password =
+                NewPassword::parse(admin.password, policy).map_err(anyhow::Error::msg)?;
+            create_first_owner(&username, &email, password, hashing, "configuration", pool).await?;
+            tracing::info!(username = %username.as_ref(), "Created the first owner from the configuration.");
+            return Ok(());
+        }
+        let token: String = rand::rng()
+            .sample_iter(rand::distr::Alphanumeric)
+            .map(char::from)
+            .take(TOKEN_LENGTH)
+            .collect();
+        tracing::warn!(
+            "No user can log in yet. Create the first owner at {}/setup?token={}",
+            base_url,
+            token
+        );
+        *self.0.write().unwrap() = Some(SecretString::new(token.into()));
+        Ok(())
+    }
+}
+
+/// Whether there is no active user, e.g. on a fresh database.
+#[tracing::instrument(name = "Check if setup is needed", skip(pool))]
+pub async fn needs_setup(pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let has_users =
+        sqlx::query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM users WHERE is_active) AS "exists!""#)
+            .fetch_one(pool)
+            .await
+            .context("Failed to check for active users.")?;
+    Ok(!has_users)
+}
+
+/// Create the first owner, unless another one was created in the meantime,
+/// in which case `None` is returned.
+#[tracing::instrument(name = "Create first owner", skip(password, hashing, pool))]
+pub async fn create_first_owner(
+    username: &Username,
+    email: &SubscriberEmail,
+    password: NewPassword,
+    hashing: &PasswordHashing,
+    source: &str,
+    pool: &PgPool,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    // Two setups running at once must not both create an owner.
+    sqlx::query!("LOCK TABLE users IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to lock the users table.")?;
+    let has_users =
+        sqlx::query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM users WHERE is_active) AS "exists!""#)
+            .fetch_one(&mut *transaction)
+            .await
+            .context("Failed to check for active users.")?;
+    if has_users {
+        return Ok(None);
+    }
+    let user_id = create_user(
+        &mut transaction,
+        username,
+        email.as_ref(),
+        UserRole::Owner,
+        password,
+        hashing,
+    )
+    .await?;
+    record_audit_entry(
+        &mut *transaction,
+        AuditAction::UserCreated,
+        None,
+        Some(&format!("user:{}", user_id)),
+        None,
+        Some(source),
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create the first owner.")?;
+    Ok(Some(user_id))
+}
+
+/// Send every visitor of the pages behind a login to the setup page until
+/// the first owner exists.
+pub async fn redirect_until_set_up(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.setup.is_pending() {
+        // Another instance, or the command line, may have finished the setup.
+        match needs_setup(&state.db_pool).await {
+            Ok(true) => return Redirect::to("/setup").into_response(),
+            Ok(false) => state.setup.complete(),
+            Err(e) => return e500(e),
+        }
+    }
+    next.run(request).await
+}
@@ -0,0 +1,57 @@
+//! Shutting down without cutting work off halfway: on SIGTERM or SIGINT the
+//! servers stop accepting connections, and the requests in flight and the
+//! background tasks get a deadline to finish.
+
+use std::future::Future;
+
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+/// Tasks running apart from any request. They are told when the application
+/// shuts down, so that they can finish or set aside what they are doing.
+#[derive(Clone, Default)]
+pub struct BackgroundTasks {
+    shutdown: CancellationToken,
+    tracker: TaskTracker,
+}
+
+impl BackgroundTasks {
+    /// Run `task` in the background. It is given a token to watch for the
+    /// shutdown with.
+    pub fn spawn<F>(&self, task: impl FnOnce(CancellationToken) -> F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tracker.spawn(task(self.shutdown.clone()));
+    }
+
+    /// Tell every task to stop, and wait for them to return.
+    pub async fn stop(&self) {
+        self.shutdown.cancel();
+        self.tracker.close();
+        self.tracker.wait().await;
+    }
+}
+
+/// Resolves on SIGINT (Ctrl+C) or, on Unix, SIGTERM.
+pub async fn shutdown_signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for SIGINT.");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM.")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {},
+        _ = terminate => {},
+    }
+    tracing::info!("Shutting down.");
+}
@@ -1,31 +1,71 @@
-use std::sync::Arc;
+use std::{future::Future, net::SocketAddr, sync::Arc, time::Duration};
 
+use anyhow::Context;
 use axum::{
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo},
+    middleware::AddExtension,
     routing::{get, post},
     serve::Serve,
     Router,
 };
+use hmac::{Hmac, Mac};
 use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use time::Duration;
 use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
 use tower_http::trace::TraceLayer;
-use tower_sessions::{Expiry, SessionManagerLayer};
+use tower_sessions::{Expiry, MemoryStore, SessionManagerLayer};
 use tower_sessions_redis_store::{fred::prelude::*, RedisStore};
 
 use crate::{
-    configuration::{DatabaseSettings, Settings},
+    authentication::{reject_anonymous_users, PasswordHashing},
+    configuration::{ApplicationSettings, DatabaseSettings, SessionStoreKind, Settings},
+    csrf::verify_csrf_token,
     email_client::EmailClient,
+    issue_deliveries::send_issues,
+    login_throttle::{FailureCounters, LoginThrottle},
+    metrics::{metrics_router, track_http_requests, Metrics},
+    request_id::propagate_request_id,
     routes::{
-        admin_dashboard, change_password, change_password_form, confirm, health_check, home,
-        log_out, login, login_form, publish_newsletter, subscribe,
+        accept_invitation, accept_invitation_form, admin_dashboard, api_router, change_password,
+        change_password_form, change_user_role, complete_setup, confirm, confirm_password_reset,
+        confirm_password_reset_form, create_api_token, create_webhook, deactivate_user,
+        delete_subscriber, disable_webhook, edit_subscriber, enroll_two_factor, export_audit_log,
+        export_newsletter_report, health_check, home, invite_user, list_api_tokens, list_audit_log,
+        list_newsletter_issues, list_sessions, list_subscribers, list_users, list_webhooks,
+        liveness, log_out, login, login_form, login_two_factor, login_two_factor_form,
+        newsletter_report, password_reset_form, publish_newsletter, readiness,
+        regenerate_recovery_codes, replay_webhook_delivery, request_password_reset,
+        revoke_api_token, revoke_other_sessions, revoke_session, set_two_factor_policy, setup_form,
+        subscribe, subscriber_details, turn_off_two_factor, two_factor_settings,
+        webhook_delivery_details, webhook_details,
     },
+    session_store::{PostgresStore, SessionBackend},
+    setup::{redirect_until_set_up, Setup},
+    shutdown::{shutdown_signal, BackgroundTasks},
+    telemetry::make_request_span,
+    user_sessions::track_session_activity,
+    webhooks::{deliver_webhooks, WebhookSender},
     AppState,
 };
 
+/// The server hands the peer address of each connection to the handlers, to
+/// throttle failed logins per IP address.
+type Server = Serve<
+    TcpListener,
+    IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+    AddExtension<Router, ConnectInfo<SocketAddr>>,
+>;
+
 pub struct Application {
     port: u16,
-    server: Serve<TcpListener, Router, Router>,
+    server: Server,
+    metrics_port: u16,
+    metrics_server: Serve<TcpListener, Router, Router>,
+    setup: Setup,
+    background_tasks: BackgroundTasks,
+    shutdown_timeout: Duration,
 }
 
 impl Application {
@@ -36,39 +76,108 @@ impl Application {
         let sender_email = configuration
             .email_client
             .sender()
-            .expect("Invalid sender email address");
+            .map_err(|e| anyhow::anyhow!("Invalid sender email address: {}", e))?;
         let timeout = configuration.email_client.timeout();
+        let metrics = Metrics::new().context("Failed to register the metrics.")?;
         let email_client = EmailClient::new(
-            configuration.email_client.base_url,
+            configuration.email_client.base_url.clone(),
             sender_email,
-            configuration.email_client.authorization_token,
+            configuration.email_client.authorization_token.clone(),
             timeout,
-        );
+        )
+        .with_metrics(metrics.clone());
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
         );
         let listener = TcpListener::bind(address).await?;
         let port = listener.local_addr().unwrap().port();
+        let metrics_address = format!(
+            "{}:{}",
+            configuration.metrics.host, configuration.metrics.port
+        );
+        let metrics_listener = TcpListener::bind(metrics_address).await?;
+        let metrics_port = metrics_listener.local_addr().unwrap().port();
+        let metrics_server = axum::serve(
+            metrics_listener,
+            metrics_router(metrics.clone(), connection_pool.clone()),
+        );
+        let setup = Setup::default();
+        let background_tasks = BackgroundTasks::default();
+        let shutdown_timeout = configuration.application.shutdown_timeout();
         let server = run(
             listener,
             connection_pool,
             email_client,
-            configuration.application.base_url.clone(),
-            configuration.application.hmac_secret,
-            configuration.redis_uri,
+            configuration,
+            setup.clone(),
+            metrics,
+            background_tasks.clone(),
         )
         .await?;
 
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            metrics_port,
+            metrics_server,
+            setup,
+            background_tasks,
+            shutdown_timeout,
+        })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    pub fn metrics_port(&self) -> u16 {
+        self.metrics_port
+    }
+
+    /// The token of the setup page, while no user can log in.
+    pub fn setup_token(&self) -> Option<SecretString> {
+        self.setup.token()
+    }
+
+    /// Serve until SIGTERM or SIGINT.
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        self.run_until(shutdown_signal()).await
+    }
+
+    /// Serve until `signal` resolves. New connections are then refused, and
+    /// the requests in flight and the background tasks are given the
+    /// shutdown timeout to finish before they are abandoned.
+    pub async fn run_until(
+        self,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<(), std::io::Error> {
+        let stopping = CancellationToken::new();
+        let server = self
+            .server
+            .with_graceful_shutdown(stopping.clone().cancelled_owned());
+        let metrics_server = self
+            .metrics_server
+            .with_graceful_shutdown(stopping.clone().cancelled_owned());
+        let background_tasks = self.background_tasks;
+        let draining = async {
+            tokio::try_join!(server, metrics_server)?;
+            background_tasks.stop().await;
+            Ok(())
+        };
+        let shutdown_timeout = self.shutdown_timeout;
+        let deadline = async {
+            signal.await;
+            stopping.cancel();
+            tokio::time::sleep(shutdown_timeout).await;
+        };
+        tokio::select! {
+            outcome = draining => outcome,
+            _ = deadline => {
+                tracing::warn!("Work still in progress was abandoned at the shutdown deadline.");
+                Ok(())
+            }
+        }
     }
 }
 
@@ -76,58 +185,313 @@ pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
-    base_url: String,
-    hmac_secret: SecretString,
-    redis_uri: SecretString,
-) -> Result<Serve<TcpListener, Router, Router>, anyhow::Error> {
+    configuration: Settings,
+    setup: Setup,
+    metrics: Metrics,
+    background_tasks: BackgroundTasks,
+) -> Result<Server, anyhow::Error> {
+    let Settings {
+        application,
+        redis_uri,
+        login_throttle,
+        password,
+        session,
+        initial_admin,
+        health,
+        webhooks,
+        issue_deliveries,
+        ..
+    } = configuration;
+    let ApplicationSettings {
+        base_url,
+        hmac_secret,
+        ..
+    } = application;
     let key = axum_flash::Key::from(hmac_secret.expose_secret().as_bytes());
+    let argon2_params = password
+        .argon2
+        .params()
+        .context("Invalid Argon2 parameters.")?;
+    let password_hashing = PasswordHashing::new(argon2_params)?;
+    let password_policy = password.policy();
+    setup
+        .prepare(
+            initial_admin,
+            &password_policy,
+            &password_hashing,
+            &base_url,
+            &db_pool,
+        )
+        .await?;
+
+    let (session_store, failure_counters) =
+        connect_session_store(session.store, &redis_uri, &db_pool, &background_tasks)?;
+    let login_throttle = LoginThrottle::new(failure_counters, login_throttle);
+    let webhook_sender = WebhookSender::new(&webhooks);
+    for _ in 0..webhooks.workers {
+        let (sender, pool) = (webhook_sender.clone(), db_pool.clone());
+        let poll_interval = webhooks.poll_interval();
+        background_tasks
+            .spawn(move |shutdown| deliver_webhooks(sender, pool, poll_interval, shutdown));
+    }
+    let email_client = Arc::new(email_client);
+    for _ in 0..issue_deliveries.workers {
+        let (email_client, pool) = (email_client.clone(), db_pool.clone());
+        let poll_interval = issue_deliveries.poll_interval();
+        background_tasks
+            .spawn(move |shutdown| send_issues(email_client, pool, poll_interval, shutdown));
+    }
     let state = AppState {
         db_pool,
-        email_client: Arc::new(email_client),
+        email_client,
         base_url,
         hmac_secret: HmacSecret(hmac_secret),
         flash_config: axum_flash::Config::new(key),
+        session_store: session_store.clone(),
+        login_throttle,
+        password_policy,
+        password_hashing,
+        session_lifetime: session.lifetime(),
+        setup,
+        metrics: metrics.clone(),
+        health,
+        webhooks,
     };
+    // Saving the session on every request restarts the inactivity timer
+    // whenever the user does anything, not only when the session changes.
+    let mut session_layer = SessionManagerLayer::new(session_store)
+        .with_name(session.cookie_name.clone())
+        .with_secure(session.secure)
+        .with_same_site(session.same_site.into())
+        .with_expiry(Expiry::OnInactivity(session.idle_timeout()))
+        .with_always_save(true);
+    if let Some(domain) = session.domain {
+        session_layer = session_layer.with_domain(domain);
+    }
+
+    // Every page below /admin is for logged-in users only.
+    let admin = Router::new()
+        .route("/api-tokens", get(list_api_tokens).post(create_api_token))
+        .route("/api-tokens/{token_id}/revoke", post(revoke_api_token))
+        .route("/audit", get(list_audit_log))
+        .route("/audit/export", get(export_audit_log))
+        .route("/dashboard", get(admin_dashboard))
+        .route("/logout", post(log_out))
+        .route("/newsletters", get(list_newsletter_issues))
+        .route("/newsletters/{issue_id}", get(newsletter_report))
+        .route(
+            "/newsletters/{issue_id}/export",
+            get(export_newsletter_report),
+        )
+        .route("/password", get(change_password_form))
+        .route("/password", post(change_password))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/revoke-others", post(revoke_other_sessions))
+        .route("/sessions/{session_id}/revoke", post(revoke_session))
+        .route("/subscribers", get(list_subscribers))
+        .route(
+            "/subscribers/{subscriber_id}",
+            get(subscriber_details).post(edit_subscriber),
+        )
+        .route(
+            "/subscribers/{subscriber_id}/delete",
+            post(delete_subscriber),
+        )
+        .route(
+            "/two-factor",
+            get(two_factor_settings).post(enroll_two_factor),
+        )
+        .route("/two-factor/disable", post(turn_off_two_factor))
+        .route("/two-factor/policy", post(set_two_factor_policy))
+        .route(
+            "/two-factor/recovery-codes",
+            post(regenerate_recovery_codes),
+        )
+        .route("/users", get(list_users))
+        .route("/users/invite", post(invite_user))
+        .route("/users/{user_id}/deactivate", post(deactivate_user))
+        .route("/users/{user_id}/role", post(change_user_role))
+        .route("/webhooks", get(list_webhooks).post(create_webhook))
+        .route("/webhooks/{endpoint_id}", get(webhook_details))
+        .route("/webhooks/{endpoint_id}/disable", post(disable_webhook))
+        .route(
+            "/webhooks/deliveries/{delivery_id}",
+            get(webhook_delivery_details),
+        )
+        .route(
+            "/webhooks/deliveries/{delivery_id}/replay",
+            post(replay_webhook_delivery),
+        )
+        .route_layer(axum::middleware::from_fn(reject_anonymous_users));
 
-    let pool = Pool::new(
-        Config::from_url(redis_uri.expose_secret())?,
-        None,
-        None,
-        None,
-        6,
-    )
-    .unwrap();
-    let _redis_conn = pool.connect();
-    pool.wait_for_connect().await?;
-    let session_store = RedisStore::new(pool);
-    let session_layer = SessionManagerLayer::new(session_store)
-        .with_secure(false)
-        .with_expiry(Expiry::OnInactivity(Duration::seconds(10)));
+    // Every form posting back with the session cookie must carry the CSRF
+    // token of the session.
+    let forms = Router::new()
+        .nest("/admin", admin)
+        .route(
+            "/invitations/accept",
+            get(accept_invitation_form).post(accept_invitation),
+        )
+        .route("/login", get(login_form))
+        .route("/login", post(login))
+        .route(
+            "/login/two-factor",
+            get(login_two_factor_form).post(login_two_factor),
+        )
+        .route(
+            "/password-reset",
+            get(password_reset_form).post(request_password_reset),
+        )
+        .route(
+            "/password-reset/confirm",
+            get(confirm_password_reset_form).post(confirm_password_reset),
+        )
+        // Until the first owner exists, there is nobody to log in.
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            redirect_until_set_up,
+        ))
+        .route("/setup", get(setup_form).post(complete_setup))
+        .route_layer(axum::middleware::from_fn(verify_csrf_token));
 
+    // The APIs authenticate with bearer tokens rather than the session
+    // cookie, and subscribing only sends a confirmation email.
     let app = Router::new()
         .route("/", get(home))
-        .route("/admin/dashboard", get(admin_dashboard))
-        .route("/admin/logout", post(log_out))
-        .route("/admin/password", get(change_password_form))
-        .route("/admin/password", post(change_password))
+        .nest("/api/v1", api_router())
+        .route("/health/live", get(liveness))
+        .route("/health/ready", get(readiness))
+        // Kept for the probes set up before liveness and readiness were split.
         .route("/health_check", get(health_check))
-        .route("/login", get(login_form))
-        .route("/login", post(login))
         .route("/newsletters", post(publish_newsletter))
         .route("/subscriptions", post(subscribe))
         .route("/subscriptions/confirm", get(confirm))
+        .merge(forms)
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            track_session_activity,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            metrics,
+            track_http_requests,
+        ))
         .with_state(state)
         .layer(session_layer)
-        .layer(TraceLayer::new_for_http());
+        .layer(TraceLayer::new_for_http().make_span_with(make_request_span))
+        .layer(axum::middleware::from_fn(propagate_request_id));
 
-    let server = axum::serve(listener, app);
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    );
 
     Ok(server)
 }
 
+/// Set up the configured session store. Redis is connected to in the
+/// background, so that the application starts, and reports itself unhealthy,
+/// while Redis is down.
+fn connect_session_store(
+    kind: SessionStoreKind,
+    redis_uri: &SecretString,
+    db_pool: &PgPool,
+    background_tasks: &BackgroundTasks,
+) -> Result<(SessionBackend, FailureCounters), anyhow::Error> {
+    match kind {
+        SessionStoreKind::Redis => {
+            let pool = Pool::new(
+                Config::from_url(redis_uri.expose_secret())?,
+                None,
+                None,
+                Some(ReconnectPolicy::new_exponential(0, 100, 30_000, 2)),
+                6,
+            )
+            .context("Failed to create the Redis connection pool.")?;
+            let _redis_conn = pool.connect();
+            let connecting = pool.clone();
+            tokio::spawn(async move {
+                if let Err(e) = connecting.wait_for_connect().await {
+                    tracing::error!(error.cause_chain = ?e, "Failed to connect to Redis.");
+                }
+            });
+            let store = RedisStore::new(pool.clone());
+            Ok((
+                SessionBackend::Redis {
+                    store,
+                    pool: pool.clone(),
+                },
+                FailureCounters::Redis(pool),
+            ))
+        }
+        SessionStoreKind::Postgres => {
+            let store = PostgresStore::new(db_pool.clone());
+            let counters = FailureCounters::Postgres(db_pool.clone());
+            let (expiring, expiring_counters) = (store.clone(), counters.clone());
+            background_tasks
+                .spawn(|shutdown| delete_expired(Some(expiring), expiring_counters, shutdown));
+            Ok((SessionBackend::Postgres(store), counters))
+        }
+        SessionStoreKind::Memory => {
+            let counters = FailureCounters::memory();
+            let expiring_counters = counters.clone();
+            background_tasks.spawn(|shutdown| delete_expired(None, expiring_counters, shutdown));
+            Ok((SessionBackend::Memory(MemoryStore::default()), counters))
+        }
+    }
+}
+
+/// Every hour, remove the expired sessions of `sessions`, if given, and the
+/// expired counters of failed logins.
+async fn delete_expired(
+    sessions: Option<PostgresStore>,
+    counters: FailureCounters,
+    shutdown: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.cancelled() => return,
+        }
+        if let Some(store) = &sessions {
+            if let Err(e) = store.delete_expired().await {
+                tracing::warn!(error.cause_chain = ?e, "Failed to delete expired sessions.");
+            }
+        }
+        if let Err(e) = counters.delete_expired().await {
+            tracing::warn!(error.cause_chain = ?e, "Failed to delete expired login failures.");
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct HmacSecret(pub SecretString);
 
+impl HmacSecret {
+    /// Compute a hex-encoded HMAC-SHA256 tag for `message`.
+    pub fn sign(&self, message: &str) -> String {
+        let mut mac = self.mac();
+        mac.update(message.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// Check, in constant time, that `tag` was produced by [`HmacSecret::sign`]
+    /// for `message`.
+    pub fn verify(&self, message: &str, tag: &str) -> bool {
+        let Ok(tag) = hex::decode(tag) else {
+            return false;
+        };
+        let mut mac = self.mac();
+        mac.update(message.as_bytes());
+        mac.verify_slice(&tag).is_ok()
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(self.0.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size")
+    }
+}
+
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new().connect_lazy_with(configuration.with_db())
 }
@@ -1,10 +1,19 @@
+use axum::{extract::Request, http::HeaderMap};
+use opentelemetry::{propagation::TextMapPropagator, trace::TracerProvider};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider, Resource};
 use tokio::task::JoinHandle;
-use tracing::{subscriber::set_global_default, Subscriber};
+use tracing::{subscriber::set_global_default, Span, Subscriber};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, EnvFilter, Registry};
 
-/// Compose multiple layers into a `tracing`'s subscriber.
+use crate::{configuration::TelemetrySettings, request_id::RequestId};
+
+/// Compose multiple layers into a `tracing`'s subscriber. Spans are also
+/// handed to `tracer_provider`, when there is one, to be exported.
 ///
 /// # Implementation Notes
 ///
@@ -18,19 +27,79 @@ pub fn get_subscriber<Sink>(
     name: impl Into<String>,
     env_filter: impl AsRef<str>,
     sink: Sink,
+    tracer_provider: Option<&SdkTracerProvider>,
 ) -> impl Subscriber + Send + Sync
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
 {
+    let name = name.into();
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
-    let formatting_layer = BunyanFormattingLayer::new(name.into(), sink);
+    let otlp_layer = tracer_provider
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(name.clone())));
+    let formatting_layer = BunyanFormattingLayer::new(name, sink);
     Registry::default()
         .with(env_filter)
+        .with(otlp_layer)
         .with(JsonStorageLayer)
         .with(formatting_layer)
 }
 
+/// Set up the export of spans to an OpenTelemetry collector over OTLP/HTTP,
+/// if it is enabled. Shut the provider down before exiting, to send the
+/// spans still in its buffer.
+pub fn get_tracer_provider(
+    settings: &TelemetrySettings,
+) -> Result<Option<SdkTracerProvider>, anyhow::Error> {
+    if !settings.otlp_enabled {
+        return Ok(None);
+    }
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(settings.otlp_endpoint.clone())
+        .with_timeout(settings.timeout())
+        .build()?;
+    let resource = Resource::builder()
+        .with_service_name(settings.service_name.clone())
+        .build();
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build();
+    Ok(Some(provider))
+}
+
+/// The span of an incoming request, carrying its id. It continues the
+/// trace of the caller when the request has a `traceparent` header.
+///
+/// Only the path of the URI is recorded: query strings carry secrets, such
+/// as the tokens of confirmation and password reset links.
+pub fn make_request_span(request: &Request) -> Span {
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri().path(),
+        version = ?request.version(),
+        request_id = tracing::field::Empty,
+    );
+    if let Some(request_id) = request.extensions().get::<RequestId>() {
+        span.record("request_id", tracing::field::display(request_id));
+    }
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+    span.set_parent(parent);
+    span
+}
+
+/// Headers passing the current trace on to another service.
+pub fn trace_context_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    TraceContextPropagator::new().inject_context(
+        &Span::current().context(),
+        &mut HeaderInjector(&mut headers),
+    );
+    headers
+}
+
 /// Register a subscriber as global default to process span data.
 ///
 /// It should only be called once!
@@ -47,3 +116,128 @@ where
     let current_span = tracing::Span::current();
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::body::Body;
+    use opentelemetry::trace::TraceContextExt;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    fn traced_subscriber(provider: &SdkTracerProvider) -> impl Subscriber + Send + Sync {
+        get_subscriber("test", "info", std::io::sink, Some(provider))
+    }
+
+    fn settings(otlp_endpoint: String) -> TelemetrySettings {
+        TelemetrySettings {
+            otlp_enabled: true,
+            otlp_endpoint,
+            service_name: "test".into(),
+            timeout_milliseconds: 1000,
+        }
+    }
+
+    #[test]
+    fn requests_continue_the_trace_of_their_caller() {
+        let provider = SdkTracerProvider::builder().build();
+        let _guard = tracing::subscriber::set_default(traced_subscriber(&provider));
+        let request = Request::builder()
+            .uri("/subscriptions")
+            .header(
+                "traceparent",
+                format!("00-{}-00f067aa0ba902b7-01", TRACE_ID),
+            )
+            .body(Body::empty())
+            .unwrap();
+
+        let span = make_request_span(&request);
+
+        let context = span.context();
+        assert_eq!(
+            context.span().span_context().trace_id().to_string(),
+            TRACE_ID
+        );
+        let headers = span.in_scope(trace_context_headers);
+        let traceparent = headers.get("traceparent").unwrap().to_str().unwrap();
+        assert!(traceparent.starts_with(&format!("00-{}-", TRACE_ID)));
+    }
+
+    /// Where log lines are written to, to be inspected.
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn the_query_string_of_a_request_is_not_logged() {
+        let buffer = LogBuffer::default();
+        let sink = buffer.clone();
+        let subscriber = get_subscriber("test", "info", move || sink.clone(), None);
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let request = Request::builder()
+            .uri("/password-reset/confirm?token=a-secret-token")
+            .body(Body::empty())
+            .unwrap();
+
+        make_request_span(&request).in_scope(|| tracing::info!("Handled the request"));
+
+        let logs = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("/password-reset/confirm"));
+        assert!(!logs.contains("a-secret-token"));
+    }
+
+    #[test]
+    fn no_trace_context_is_passed_on_outside_of_a_trace() {
+        assert!(trace_context_headers().is_empty());
+    }
+
+    #[test]
+    fn no_spans_are_exported_unless_enabled() {
+        let mut settings = settings("http://localhost:4318/v1/traces".into());
+        settings.otlp_enabled = false;
+        assert!(get_tracer_provider(&settings).unwrap().is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn spans_are_exported_to_the_collector() {
+        // Arrange
+        let collector = MockServer::start().await;
+        Mock::given(path("/v1/traces"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1..)
+            .mount(&collector)
+            .await;
+        let provider = get_tracer_provider(&settings(format!("{}/v1/traces", collector.uri())))
+            .unwrap()
+            .unwrap();
+
+        // Act
+        tracing::subscriber::with_default(traced_subscriber(&provider), || {
+            tracing::info_span!("Handle a request").in_scope(|| {});
+        });
+        tokio::task::spawn_blocking(move || provider.force_flush())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Assert
+    }
+}
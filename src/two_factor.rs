@@ -0,0 +1,348 @@
+use anyhow::Context;
+use chrono::Utc;
+use rand::Rng;
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+/// Name shown next to the account in authenticator apps.
+const ISSUER: &str = "Newsletter";
+/// Length of a TOTP time step, in seconds (RFC 6238 default).
+const TOTP_STEP: u64 = 30;
+const TOTP_DIGITS: usize = 6;
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Recovery codes avoid characters that are easily confused when copied by hand.
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// A shared TOTP secret, base32-encoded as shown to authenticator apps.
+#[derive(Clone)]
+pub struct TotpSecret(SecretString);
+
+impl TotpSecret {
+    pub fn generate() -> Self {
+        Self(SecretString::new(
+            Secret::generate_secret().to_encoded().to_string().into(),
+        ))
+    }
+
+    pub fn parse(encoded: String) -> Result<Self, anyhow::Error> {
+        Secret::Encoded(encoded.clone())
+            .to_bytes()
+            .context("The TOTP secret is not valid base32.")?;
+        Ok(Self(SecretString::new(encoded.into())))
+    }
+
+    pub fn expose_base32(&self) -> &str {
+        self.0.expose_secret()
+    }
+
+    /// A PNG QR code, base64-encoded, that authenticator apps can scan to
+    /// enroll `account_name`.
+    pub fn qr_code_base64(&self, account_name: &str) -> Result<String, anyhow::Error> {
+        self.totp(account_name)?
+            .get_qr_base64()
+            .map_err(|e| anyhow::anyhow!(e))
+            .context("Failed to render the TOTP QR code.")
+    }
+
+    /// Returns the time step `code` was generated for, allowing one step of
+    /// clock drift in either direction.
+    pub fn matching_step(&self, code: &str, now: u64) -> Option<u64> {
+        let totp = self.totp("").ok()?;
+        let current_step = now / TOTP_STEP;
+        (current_step.saturating_sub(1)..=current_step + 1)
+            .find(|step| totp.check(code, step * TOTP_STEP))
+    }
+
+    fn totp(&self, account_name: &str) -> Result<TOTP, anyhow::Error> {
+        let secret = Secret::Encoded(self.0.expose_secret().to_string())
+            .to_bytes()
+            .context("The TOTP secret is not valid base32.")?;
+        // `:` separates the issuer from the account name in otpauth URLs.
+        TOTP::new(
+            Algorithm::SHA1,
+            TOTP_DIGITS,
+            0,
+            TOTP_STEP,
+            secret,
+            Some(ISSUER.to_string()),
+            account_name.replace(':', ""),
+        )
+        .context("Failed to build a TOTP generator.")
+    }
+}
+
+/// Recovery codes are only ever compared after normalisation, so that
+/// users can type them with or without the dash and in any case.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    hex::encode(Sha256::digest(normalize_recovery_code(code).as_bytes()))
+}
+
+fn generate_recovery_code() -> String {
+    let mut rng = rand::rng();
+    let code: String = (0..10)
+        .map(|_| {
+            let index = rng.random_range(0..RECOVERY_CODE_ALPHABET.len());
+            RECOVERY_CODE_ALPHABET[index] as char
+        })
+        .collect();
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+fn current_timestamp() -> u64 {
+    Utc::now().timestamp() as u64
+}
+
+#[tracing::instrument(name = "Get TOTP secret", skip(pool))]
+pub async fn get_totp_secret(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<TotpSecret>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT totp_secret FROM users WHERE user_id = $1"#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the user's TOTP secret.")?;
+    row.totp_secret.map(TotpSecret::parse).transpose()
+}
+
+#[tracing::instrument(name = "Check if two-factor authentication is required", skip(pool))]
+pub async fn is_two_factor_required(pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT require_two_factor FROM security_settings"#)
+        .fetch_one(pool)
+        .await
+        .context("Failed to retrieve the security settings.")?;
+    Ok(row.require_two_factor)
+}
+
+/// Users must provide a second factor when they enrolled, or when an owner
+/// required it for every account.
+#[tracing::instrument(name = "Check if the user needs a second factor", skip(pool))]
+pub async fn needs_second_factor(user_id: Uuid, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    if get_totp_secret(user_id, pool).await?.is_some() {
+        return Ok(true);
+    }
+    is_two_factor_required(pool).await
+}
+
+#[tracing::instrument(name = "Set two-factor authentication requirement", skip(executor))]
+pub async fn set_two_factor_required(
+    executor: impl PgExecutor<'_>,
+    required: bool,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE security_settings SET require_two_factor = $1"#,
+        required,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to update the security settings.")?;
+    Ok(())
+}
+
+/// Check a code from the authenticator app, or one of the user's unused
+/// recovery codes. Either kind of code is only accepted once.
+#[tracing::instrument(name = "Verify second factor", skip(secret, code, pool))]
+pub async fn verify_second_factor(
+    user_id: Uuid,
+    secret: &TotpSecret,
+    code: &str,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let code = code.trim();
+    if let Some(step) = secret.matching_step(code, current_timestamp()) {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_step = $1
+            WHERE user_id = $2 AND (totp_last_step IS NULL OR totp_last_step < $1)
+            "#,
+            step as i64,
+            user_id,
+        )
+        .execute(pool)
+        .await
+        .context("Failed to record the last used TOTP step.")?;
+        return Ok(result.rows_affected() > 0);
+    }
+
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to use a recovery code.")?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Store `secret` for the user once they proved they can generate codes
+/// from it, and return a fresh set of recovery codes.
+#[tracing::instrument(
+    name = "Enable two-factor authentication",
+    skip(transaction, secret, code)
+)]
+pub async fn enable_two_factor(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    secret: &TotpSecret,
+    code: &str,
+) -> Result<Option<Vec<String>>, anyhow::Error> {
+    let Some(step) = secret.matching_step(code.trim(), current_timestamp()) else {
+        return Ok(None);
+    };
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $1, totp_last_step = $2
+        WHERE user_id = $3
+        "#,
+        secret.expose_base32(),
+        step as i64,
+        user_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to store the user's TOTP secret.")?;
+    let recovery_codes = replace_recovery_codes(transaction, user_id).await?;
+    Ok(Some(recovery_codes))
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(transaction))]
+pub async fn disable_two_factor(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_last_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to remove the user's TOTP secret.")?;
+    sqlx::query!(
+        r#"DELETE FROM user_recovery_codes WHERE user_id = $1"#,
+        user_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete the user's recovery codes.")?;
+    Ok(())
+}
+
+/// Invalidate all existing recovery codes of the user and return new ones.
+/// Only hashes are stored: the codes cannot be shown again.
+#[tracing::instrument(name = "Replace recovery codes", skip(transaction))]
+pub async fn replace_recovery_codes(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<Vec<String>, anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM user_recovery_codes WHERE user_id = $1"#,
+        user_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete the user's recovery codes.")?;
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_recovery_codes (user_id, code_hash)
+        SELECT $1::uuid, code_hash FROM UNNEST($2::TEXT[]) AS code_hash
+        "#,
+        user_id,
+        &code_hashes,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to store the user's recovery codes.")?;
+    Ok(recovery_codes)
+}
+
+#[tracing::instrument(name = "Count unused recovery codes", skip(pool))]
+pub async fn count_unused_recovery_codes(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM user_recovery_codes
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the user's recovery codes.")?;
+    Ok(row.count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_recovery_code, hash_recovery_code, TotpSecret, TOTP_STEP};
+
+    #[test]
+    fn a_code_for_the_current_step_is_accepted() {
+        let secret = TotpSecret::generate();
+        let now = 1_700_000_000;
+        let code = secret.totp("").unwrap().generate(now);
+        assert_eq!(secret.matching_step(&code, now), Some(now / TOTP_STEP));
+    }
+
+    #[test]
+    fn a_code_from_the_previous_step_is_accepted() {
+        let secret = TotpSecret::generate();
+        let now = 1_700_000_000;
+        let code = secret.totp("").unwrap().generate(now - TOTP_STEP);
+        assert_eq!(secret.matching_step(&code, now), Some(now / TOTP_STEP - 1));
+    }
+
+    #[test]
+    fn a_stale_code_is_rejected() {
+        let secret = TotpSecret::generate();
+        let now = 1_700_000_000;
+        let code = secret.totp("").unwrap().generate(now - 3 * TOTP_STEP);
+        assert_eq!(secret.matching_step(&code, now), None);
+    }
+
+    #[test]
+    fn recovery_codes_are_compared_ignoring_case_and_dashes() {
+        let code = generate_recovery_code();
+        assert_eq!(
+            hash_recovery_code(&code),
+            hash_recovery_code(&code.replace('-', "").to_uppercase())
+        );
+    }
+
+    #[test]
+    fn invalid_base32_secrets_are_rejected() {
+        assert!(TotpSecret::parse("not base32!".to_string()).is_err());
+    }
+}
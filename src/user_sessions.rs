@@ -0,0 +1,362 @@
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::Context;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::{header::USER_AGENT, request::Parts, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use tower_sessions::{session::Id, Expiry, SessionStore};
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_entry, AuditAction},
+    session_state::TypedSession,
+    AppState,
+};
+
+/// Longer user agents are cut, they only help users recognise a session.
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
+/// Where a request comes from.
+#[derive(Debug)]
+pub struct ClientInfo {
+    pub ip: IpAddr,
+    pub user_agent: Option<String>,
+}
+
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Sync + Send,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ConnectInfo(address) = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .cloned()
+            .ok_or((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Can't extract the client address. Is the server run with `ConnectInfo`?",
+            ))?;
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect());
+        Ok(ClientInfo {
+            ip: address.ip(),
+            user_agent,
+        })
+    }
+}
+
+/// A logged-in session of a user that is still in the session store.
+pub struct ActiveSession {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// Whether this is the session of the request listing the sessions.
+    pub is_current: bool,
+}
+
+/// How long a logged-in session may last, however active its user is.
+#[derive(Clone, Copy, Debug)]
+pub struct SessionLifetime {
+    pub absolute: TimeDelta,
+    /// `None` if users cannot ask to be remembered.
+    pub remember_me: Option<TimeDelta>,
+}
+
+impl SessionLifetime {
+    /// When a session logged in at `now` has to end. Remembered sessions
+    /// outlive idle periods, so they get their own, usually longer, limit.
+    pub fn expires_at(&self, now: DateTime<Utc>, remember_me: bool) -> DateTime<Utc> {
+        match self.remember_me {
+            Some(lifetime) if remember_me => now + lifetime,
+            _ => now + self.absolute,
+        }
+    }
+}
+
+/// Mark the session as logged in as `user_id` and keep track of it, so that
+/// it can be listed and ended later on without the user's cookie.
+#[tracing::instrument(name = "Log in", skip(session, pool))]
+pub async fn log_in(
+    session: &TypedSession,
+    user_id: Uuid,
+    remember_me: bool,
+    lifetime: SessionLifetime,
+    client: &ClientInfo,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let remember_me = remember_me && lifetime.remember_me.is_some();
+    let expires_at = lifetime.expires_at(Utc::now(), remember_me);
+    session.insert_user_id(user_id).await?;
+    session.insert_expires_at(expires_at).await?;
+    if remember_me {
+        // The cookie survives browser restarts and idle periods, up to the
+        // end of the session.
+        session.set_expiry(Expiry::AtDateTime(
+            OffsetDateTime::from_unix_timestamp(expires_at.timestamp())
+                .context("Invalid session expiry.")?,
+        ));
+    }
+    let session_id = session.save().await?;
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions
+            (session_id, id, user_id, created_at, last_seen_at, ip, user_agent)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (session_id) DO NOTHING
+        "#,
+        session_id.to_string(),
+        Uuid::new_v4(),
+        user_id,
+        now,
+        now,
+        client.ip.to_string(),
+        client.user_agent,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the session of a logged in user.")?;
+    record_audit_entry(
+        pool,
+        AuditAction::LoginSucceeded,
+        Some(user_id),
+        None,
+        Some(client.ip),
+        client.user_agent.as_deref(),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Stop tracking a session that has been deleted, e.g. on log out.
+#[tracing::instrument(name = "Forget session", skip(pool))]
+pub async fn forget_session(session_id: Id, pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM user_sessions WHERE session_id = $1"#,
+        session_id.to_string(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to delete a tracked session.")?;
+    Ok(())
+}
+
+/// List the sessions of `user_id`, most recently used first. Sessions that
+/// expired in the session store are forgotten along the way.
+#[tracing::instrument(name = "List user sessions", skip(store, pool))]
+pub async fn list_user_sessions(
+    user_id: Uuid,
+    current: Option<Id>,
+    store: &dyn SessionStore,
+    pool: &PgPool,
+) -> Result<Vec<ActiveSession>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT session_id, id, created_at, last_seen_at, ip, user_agent
+        FROM user_sessions
+        WHERE user_id = $1
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the sessions of a user.")?;
+
+    let mut sessions = Vec::with_capacity(rows.len());
+    for row in rows {
+        let session_id = parse_session_id(&row.session_id)?;
+        let is_current = current == Some(session_id);
+        if !is_current
+            && store
+                .load(&session_id)
+                .await
+                .map_err(|e| anyhow::anyhow!(e))?
+                .is_none()
+        {
+            forget_session(session_id, pool).await?;
+            continue;
+        }
+        sessions.push(ActiveSession {
+            id: row.id,
+            created_at: row.created_at,
+            last_seen_at: row.last_seen_at,
+            ip: row.ip,
+            user_agent: row.user_agent,
+            is_current,
+        });
+    }
+    Ok(sessions)
+}
+
+/// The id under which a session is listed, if it is tracked.
+#[tracing::instrument(name = "Get public session id", skip(pool))]
+pub async fn get_public_session_id(
+    session_id: Id,
+    pool: &PgPool,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT id FROM user_sessions WHERE session_id = $1"#,
+        session_id.to_string(),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a tracked session.")?;
+    Ok(row.map(|r| r.id))
+}
+
+/// End one session of `user_id`. Returns `false` if the user has no session
+/// with this id.
+#[tracing::instrument(name = "Revoke user session", skip(store, pool))]
+pub async fn revoke_user_session(
+    user_id: Uuid,
+    id: Uuid,
+    store: &dyn SessionStore,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"DELETE FROM user_sessions WHERE id = $1 AND user_id = $2 RETURNING session_id"#,
+        id,
+        user_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to delete a tracked session.")?;
+    let Some(row) = row else {
+        return Ok(false);
+    };
+    delete_from_store(&row.session_id, store).await?;
+    Ok(true)
+}
+
+/// Log `user_id` out everywhere, except in the session `except` if given, by
+/// deleting their sessions from the session store.
+#[tracing::instrument(name = "End user sessions", skip(store, pool))]
+pub async fn end_user_sessions(
+    user_id: Uuid,
+    except: Option<Id>,
+    store: &dyn SessionStore,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE user_id = $1 AND session_id IS DISTINCT FROM $2
+        RETURNING session_id
+        "#,
+        user_id,
+        except.map(|id| id.to_string()),
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to delete the tracked sessions of a user.")?;
+    for row in rows {
+        delete_from_store(&row.session_id, store).await?;
+    }
+    Ok(())
+}
+
+/// Record when a logged-in session was last used. Failures are logged rather
+/// than failing the request.
+pub async fn track_session_activity(
+    State(state): State<AppState>,
+    session: TypedSession,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(session_id) = session.id() {
+        if let Err(e) = touch_session(&session, session_id, &state.db_pool).await {
+            tracing::warn!(error.cause_chain = ?e, "Failed to record the activity of a session.");
+        }
+    }
+    next.run(request).await
+}
+
+async fn touch_session(
+    session: &TypedSession,
+    session_id: Id,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    if session.get_user_id().await?.is_none() {
+        return Ok(());
+    }
+    // Writing on every request would be wasteful: a minute is precise enough.
+    sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET last_seen_at = now()
+        WHERE session_id = $1 AND last_seen_at < now() - interval '1 minute'
+        "#,
+        session_id.to_string(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the last activity of a session.")?;
+    Ok(())
+}
+
+async fn delete_from_store(
+    session_id: &str,
+    store: &dyn SessionStore,
+) -> Result<(), anyhow::Error> {
+    store
+        .delete(&parse_session_id(session_id)?)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))
+}
+
+fn parse_session_id(session_id: &str) -> Result<Id, anyhow::Error> {
+    session_id
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid session id: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+
+    use super::SessionLifetime;
+
+    #[test]
+    fn sessions_end_after_the_absolute_lifetime() {
+        let lifetime = SessionLifetime {
+            absolute: TimeDelta::hours(12),
+            remember_me: Some(TimeDelta::days(30)),
+        };
+        let now = Utc::now();
+        assert_eq!(lifetime.expires_at(now, false), now + TimeDelta::hours(12));
+    }
+
+    #[test]
+    fn remembered_sessions_last_longer() {
+        let lifetime = SessionLifetime {
+            absolute: TimeDelta::hours(12),
+            remember_me: Some(TimeDelta::days(30)),
+        };
+        let now = Utc::now();
+        assert_eq!(lifetime.expires_at(now, true), now + TimeDelta::days(30));
+    }
+
+    #[test]
+    fn remember_me_is_ignored_when_disabled() {
+        let lifetime = SessionLifetime {
+            absolute: TimeDelta::hours(12),
+            remember_me: None,
+        };
+        let now = Utc::now();
+        assert_eq!(lifetime.expires_at(now, true), now + TimeDelta::hours(12));
+    }
+}
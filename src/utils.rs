@@ -1,6 +1,6 @@
 use axum::{
     http::StatusCode,
-    response::{IntoResponse, Response},
+    response::{Html, IntoResponse, Response},
 };
 
 pub fn e500<E>(e: E) -> Response
@@ -10,3 +10,39 @@ where
     tracing::error!("Internal server error: {:?}", e);
     StatusCode::INTERNAL_SERVER_ERROR.into_response()
 }
+
+pub fn e403() -> Response {
+    (
+        StatusCode::FORBIDDEN,
+        Html(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Forbidden</title>
+</head>
+<body>
+    <p>You are not allowed to perform this action.</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        ),
+    )
+        .into_response()
+}
+
+/// Quote a CSV field if it contains a separator, a quote or a line break.
+/// A field that a spreadsheet would run as a formula is prefixed with `'`,
+/// so that a subscriber cannot make the admin who opens an export run one.
+pub fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
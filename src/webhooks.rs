@@ -0,0 +1,785 @@
+//! Outbound webhooks. Admins register endpoints for some events; each event
+//! is queued, in the transaction that causes it, for every endpoint that
+//! listens for it, and background workers send the queued deliveries,
+//! retrying failures with an exponential backoff.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, TimeDelta, Utc};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    Url,
+};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+use sqlx::{PgExecutor, PgPool};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::configuration::WebhookSettings;
+
+/// Makes secrets easy to recognise, e.g. for secret scanners.
+const SECRET_PREFIX: &str = "whsec_";
+const SECRET_LENGTH: usize = 32;
+/// However many attempts failed, the next one is at most this far away.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(6 * 60 * 60);
+/// How long a claimed delivery is left to its worker. Past that, the worker
+/// is assumed to have stopped halfway, and another one takes the delivery
+/// over.
+const CLAIM_TIMEOUT: TimeDelta = TimeDelta::minutes(10);
+
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+/// `t=<unix timestamp>,v1=<hex HMAC-SHA256 of "<timestamp>.<body>">`. The
+/// timestamp lets receivers reject old deliveries replayed by a third party.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookEvent {
+    SubscriberCreated,
+    SubscriberConfirmed,
+    SubscriberUnsubscribed,
+    IssuePublished,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 4] = [
+        WebhookEvent::SubscriberCreated,
+        WebhookEvent::SubscriberConfirmed,
+        WebhookEvent::SubscriberUnsubscribed,
+        WebhookEvent::IssuePublished,
+    ];
+
+    pub fn parse(s: String) -> Result<WebhookEvent, String> {
+        Self::ALL
+            .into_iter()
+            .find(|event| event.as_str() == s)
+            .ok_or_else(|| format!("{} is not a known event.", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::SubscriberCreated => "subscriber.created",
+            WebhookEvent::SubscriberConfirmed => "subscriber.confirmed",
+            WebhookEvent::SubscriberUnsubscribed => "subscriber.unsubscribed",
+            WebhookEvent::IssuePublished => "issue.published",
+        }
+    }
+}
+
+impl std::fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Pending,
+    /// Claimed by a worker, which is calling the endpoint right now.
+    Sending,
+    Succeeded,
+    /// Every attempt failed.
+    Failed,
+    /// The endpoint was disabled before the delivery went through.
+    Cancelled,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Sending => "sending",
+            DeliveryStatus::Succeeded => "succeeded",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+pub struct WebhookEndpointRecord {
+    pub endpoint_id: Uuid,
+    pub url: String,
+    pub description: String,
+    pub events: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub disabled_at: Option<DateTime<Utc>>,
+}
+
+pub struct DeliveryRecord {
+    pub delivery_id: Uuid,
+    pub endpoint_id: Uuid,
+    pub event: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub created_at: DateTime<Utc>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub replay_of: Option<Uuid>,
+}
+
+pub struct AttemptRecord {
+    pub attempted_at: DateTime<Utc>,
+    pub duration_ms: i32,
+    pub response_status: Option<i16>,
+    pub error: Option<String>,
+}
+
+fn generate_secret() -> SecretString {
+    let secret: String = rand::rng()
+        .sample_iter(rand::distr::Alphanumeric)
+        .map(char::from)
+        .take(SECRET_LENGTH)
+        .collect();
+    SecretString::new(format!("{}{}", SECRET_PREFIX, secret).into())
+}
+
+/// The value of the signature header of `payload`, sent at `timestamp`.
+pub fn signature(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+    format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+/// Register an endpoint and return it with its signing secret.
+#[tracing::instrument(name = "Create webhook endpoint", skip(executor))]
+pub async fn create_endpoint(
+    executor: impl PgExecutor<'_>,
+    url: &str,
+    description: &str,
+    events: &[WebhookEvent],
+    created_by: Uuid,
+) -> Result<(Uuid, SecretString), anyhow::Error> {
+    let endpoint_id = Uuid::new_v4();
+    let secret = generate_secret();
+    let events: Vec<String> = events.iter().map(|e| e.as_str().to_string()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO webhook_endpoints
+            (endpoint_id, url, description, secret, events, created_by, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        endpoint_id,
+        url,
+        description,
+        secret.expose_secret(),
+        &events,
+        created_by,
+        Utc::now(),
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store the webhook endpoint.")?;
+    Ok((endpoint_id, secret))
+}
+
+#[tracing::instrument(name = "List webhook endpoints", skip(pool))]
+pub async fn list_endpoints(pool: &PgPool) -> Result<Vec<WebhookEndpointRecord>, anyhow::Error> {
+    sqlx::query_as!(
+        WebhookEndpointRecord,
+        r#"
+        SELECT endpoint_id, url, description, events, created_at, disabled_at
+        FROM webhook_endpoints
+        ORDER BY disabled_at IS NOT NULL, created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the webhook endpoints.")
+}
+
+#[tracing::instrument(name = "Get webhook endpoint", skip(pool))]
+pub async fn get_endpoint(
+    endpoint_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<WebhookEndpointRecord>, anyhow::Error> {
+    sqlx::query_as!(
+        WebhookEndpointRecord,
+        r#"
+        SELECT endpoint_id, url, description, events, created_at, disabled_at
+        FROM webhook_endpoints
+        WHERE endpoint_id = $1
+        "#,
+        endpoint_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to get the webhook endpoint.")
+}
+
+/// Stop sending events to an endpoint, and cancel its pending deliveries.
+/// Returns `false` if there is no enabled endpoint with the given id.
+#[tracing::instrument(name = "Disable webhook endpoint", skip(executor))]
+pub async fn disable_endpoint(
+    executor: impl PgExecutor<'_>,
+    endpoint_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let disabled = sqlx::query_scalar!(
+        r#"
+        WITH disabled AS (
+            UPDATE webhook_endpoints
+            SET disabled_at = now()
+            WHERE endpoint_id = $1 AND disabled_at IS NULL
+            RETURNING endpoint_id
+        ), cancelled AS (
+            UPDATE webhook_deliveries
+            SET status = 'cancelled', next_attempt_at = NULL, claimed_at = NULL
+            WHERE status IN ('pending', 'sending')
+                AND endpoint_id IN (SELECT endpoint_id FROM disabled)
+        )
+        SELECT count(*) AS "count!" FROM disabled
+        "#,
+        endpoint_id,
+    )
+    .fetch_one(executor)
+    .await
+    .context("Failed to disable the webhook endpoint.")?;
+    Ok(disabled > 0)
+}
+
+/// Queue `event` for every enabled endpoint listening for it. Call it in the
+/// transaction making the change that the event reports, so that the event
+/// is sent if, and only if, the change is committed.
+#[tracing::instrument(name = "Queue webhook event", skip(executor, data))]
+pub async fn enqueue_event(
+    executor: impl PgExecutor<'_>,
+    event: WebhookEvent,
+    data: serde_json::Value,
+) -> Result<(), anyhow::Error> {
+    // Every endpoint gets the same event id, for receivers to deduplicate.
+    let payload = serde_json::json!({
+        "id": Uuid::new_v4(),
+        "type": event.as_str(),
+        "created_at": Utc::now(),
+        "data": data,
+    })
+    .to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO webhook_deliveries
+            (delivery_id, endpoint_id, event, payload, status, created_at, next_attempt_at)
+        SELECT gen_random_uuid(), endpoint_id, $1, $2, 'pending', now(), now()
+        FROM webhook_endpoints
+        WHERE disabled_at IS NULL AND $1 = ANY(events)
+        "#,
+        event.as_str(),
+        payload,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to queue the webhook event.")?;
+    Ok(())
+}
+
+/// The most recent deliveries to an endpoint, newest first.
+#[tracing::instrument(name = "List webhook deliveries", skip(pool))]
+pub async fn list_deliveries(
+    endpoint_id: Uuid,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<DeliveryRecord>, anyhow::Error> {
+    sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT delivery_id, endpoint_id, event, payload, status, attempts,
+            created_at, next_attempt_at, replay_of
+        FROM webhook_deliveries
+        WHERE endpoint_id = $1
+        ORDER BY created_at DESC
+        LIMIT $2
+        "#,
+        endpoint_id,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the webhook deliveries.")
+}
+
+#[tracing::instrument(name = "Get webhook delivery", skip(pool))]
+pub async fn get_delivery(
+    delivery_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<DeliveryRecord>, anyhow::Error> {
+    sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT delivery_id, endpoint_id, event, payload, status, attempts,
+            created_at, next_attempt_at, replay_of
+        FROM webhook_deliveries
+        WHERE delivery_id = $1
+        "#,
+        delivery_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to get the webhook delivery.")
+}
+
+/// The attempts of a delivery, oldest first.
+#[tracing::instrument(name = "List webhook delivery attempts", skip(pool))]
+pub async fn list_attempts(
+    delivery_id: Uuid,
+    pool: &PgPool,
+) -> Result<Vec<AttemptRecord>, anyhow::Error> {
+    sqlx::query_as!(
+        AttemptRecord,
+        r#"
+        SELECT attempted_at, duration_ms, response_status, error
+        FROM webhook_delivery_attempts
+        WHERE delivery_id = $1
+        ORDER BY id
+        "#,
+        delivery_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the webhook delivery attempts.")
+}
+
+/// Queue the payload of a delivery again, whatever became of it, as a new
+/// delivery. Returns the new delivery and its endpoint, or `None` if there
+/// is no such delivery or its endpoint is disabled.
+#[tracing::instrument(name = "Replay webhook delivery", skip(executor))]
+pub async fn replay_delivery(
+    executor: impl PgExecutor<'_>,
+    delivery_id: Uuid,
+) -> Result<Option<(Uuid, Uuid)>, anyhow::Error> {
+    let replay = sqlx::query!(
+        r#"
+        INSERT INTO webhook_deliveries
+            (delivery_id, endpoint_id, event, payload, status, created_at,
+                next_attempt_at, replay_of)
+        SELECT gen_random_uuid(), d.endpoint_id, d.event, d.payload, 'pending', now(),
+            now(), d.delivery_id
+        FROM webhook_deliveries d
+        JOIN webhook_endpoints e ON e.endpoint_id = d.endpoint_id
+        WHERE d.delivery_id = $1 AND e.disabled_at IS NULL
+        RETURNING delivery_id, endpoint_id
+        "#,
+        delivery_id,
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to queue the webhook delivery again.")?;
+    Ok(replay.map(|r| (r.delivery_id, r.endpoint_id)))
+}
+
+const PRIVATE_HOST_ERROR: &str = "Events cannot be sent to this host or to a private network.";
+
+/// Whether an address is reachable from anywhere, rather than being this
+/// host, a private network, or a link-local service such as the
+/// `169.254.169.254` metadata endpoint of cloud providers.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            let is_shared = a == 100 && (b & 0xc0) == 64;
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || is_shared
+                || a == 0)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_address(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                let is_unique_local = (first & 0xfe00) == 0xfc00;
+                let is_link_local = (first & 0xffc0) == 0xfe80;
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || is_unique_local
+                    || is_link_local)
+            }
+        },
+    }
+}
+
+/// The address of a URL whose host is an IP address rather than a name.
+fn literal_address(url: &Url) -> Option<IpAddr> {
+    url.host_str()?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+/// Check that the host of an endpoint being registered is public. Names
+/// that cannot be resolved yet are let through: the addresses are checked
+/// again on every delivery.
+pub async fn check_endpoint_host(url: &str) -> Result<(), String> {
+    let url = Url::parse(url).map_err(|_| PRIVATE_HOST_ERROR.to_string())?;
+    let addresses = match (literal_address(&url), url.host_str()) {
+        (Some(ip), _) => vec![ip],
+        (None, Some(host)) => {
+            let port = url.port_or_known_default().unwrap_or(0);
+            match tokio::net::lookup_host((host, port)).await {
+                Ok(addresses) => addresses.map(|address| address.ip()).collect(),
+                Err(_) => return Ok(()),
+            }
+        }
+        (None, None) => return Err(PRIVATE_HOST_ERROR.into()),
+    };
+    if addresses.into_iter().all(is_public_address) {
+        Ok(())
+    } else {
+        Err(PRIVATE_HOST_ERROR.into())
+    }
+}
+
+/// Resolves the hosts of endpoints to their public addresses only, so that
+/// a name pointed at an internal host after it was registered is not sent
+/// to either.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|address| is_public_address(address.ip()))
+                .collect();
+            if addresses.is_empty() {
+                return Err(format!("{} has no public address.", name.as_str()).into());
+            }
+            Ok::<Addrs, Box<dyn std::error::Error + Send + Sync>>(Box::new(addresses.into_iter()))
+        })
+    }
+}
+
+/// Sends the queued deliveries.
+#[derive(Clone)]
+pub struct WebhookSender {
+    http_client: reqwest::Client,
+    max_attempts: u32,
+    first_retry_delay: Duration,
+    allow_private_addresses: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    /// No delivery is due.
+    EmptyQueue,
+    Succeeded,
+    /// The attempt failed, and another one is scheduled.
+    WillRetry,
+    /// The last attempt failed.
+    Failed,
+}
+
+impl WebhookSender {
+    pub fn new(settings: &WebhookSettings) -> Self {
+        let mut builder = reqwest::Client::builder()
+            .timeout(settings.timeout())
+            .user_agent(concat!("newsletter-webhooks/", env!("CARGO_PKG_VERSION")))
+            // A redirect could send the signed payload anywhere.
+            .redirect(reqwest::redirect::Policy::none());
+        if !settings.allow_private_addresses {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        let http_client = builder
+            .build()
+            .expect("Failed to build the webhook HTTP client");
+        Self {
+            http_client,
+            max_attempts: settings.max_attempts,
+            first_retry_delay: settings.first_retry_delay(),
+            allow_private_addresses: settings.allow_private_addresses,
+        }
+    }
+
+    /// Hosts given as IP addresses are not resolved, so they are checked
+    /// here rather than by `PublicResolver`.
+    fn check_address(&self, url: &str) -> Result<(), String> {
+        if self.allow_private_addresses {
+            return Ok(());
+        }
+        let url = Url::parse(url).map_err(|e| e.to_string())?;
+        match literal_address(&url) {
+            Some(ip) if !is_public_address(ip) => Err(format!("{} is not a public address.", ip)),
+            _ => Ok(()),
+        }
+    }
+
+    /// How long to wait after the `attempts`th failed attempt.
+    fn retry_delay(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.first_retry_delay
+            .saturating_mul(factor)
+            .min(MAX_RETRY_DELAY)
+    }
+
+    /// Make one attempt at the next due delivery, and record it. The
+    /// delivery is claimed, in a transaction of its own, before the endpoint
+    /// is called, so that workers never send it twice at once and no
+    /// connection is held while waiting for the endpoint.
+    #[tracing::instrument(
+        name = "Deliver a webhook",
+        skip(self, pool),
+        fields(delivery_id=tracing::field::Empty, event=tracing::field::Empty)
+    )]
+    pub async fn try_deliver_next(&self, pool: &PgPool) -> Result<DeliveryOutcome, anyhow::Error> {
+        let Some(delivery) = sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries d
+            SET status = 'sending', claimed_at = now()
+            FROM webhook_endpoints e
+            WHERE e.endpoint_id = d.endpoint_id
+                AND d.delivery_id = (
+                    SELECT delivery_id
+                    FROM webhook_deliveries
+                    WHERE (status = 'pending' AND next_attempt_at <= now())
+                        OR (status = 'sending' AND claimed_at < $1)
+                    ORDER BY next_attempt_at
+                    FOR UPDATE SKIP LOCKED
+                    LIMIT 1
+                )
+            RETURNING d.delivery_id, d.event, d.payload, d.attempts, d.claimed_at AS "claimed_at!",
+                e.url, e.secret
+            "#,
+            Utc::now() - CLAIM_TIMEOUT,
+        )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to claim a webhook delivery.")?
+        else {
+            return Ok(DeliveryOutcome::EmptyQueue);
+        };
+        tracing::Span::current()
+            .record(
+                "delivery_id",
+                tracing::field::display(&delivery.delivery_id),
+            )
+            .record("event", tracing::field::display(&delivery.event));
+
+        let attempted_at = Utc::now();
+        let started = Instant::now();
+        let response = match self.check_address(&delivery.url) {
+            Err(e) => Err(e),
+            Ok(()) => self
+                .http_client
+                .post(&delivery.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, &delivery.event)
+                .header(DELIVERY_HEADER, delivery.delivery_id.to_string())
+                .header(
+                    SIGNATURE_HEADER,
+                    signature(
+                        &delivery.secret,
+                        attempted_at.timestamp(),
+                        &delivery.payload,
+                    ),
+                )
+                .body(delivery.payload)
+                .send()
+                .await
+                .map(|response| response.status())
+                .map_err(|e| e.to_string()),
+        };
+        let duration_ms = i32::try_from(started.elapsed().as_millis()).unwrap_or(i32::MAX);
+        let (response_status, error) = match &response {
+            Ok(status) => (Some(status.as_u16() as i16), None),
+            Err(e) => (None, Some(e.clone())),
+        };
+        let succeeded = response.is_ok_and(|status| status.is_success());
+
+        let attempts = delivery.attempts + 1;
+        let (status, next_attempt_at, outcome) = if succeeded {
+            (DeliveryStatus::Succeeded, None, DeliveryOutcome::Succeeded)
+        } else if attempts as u32 >= self.max_attempts {
+            (DeliveryStatus::Failed, None, DeliveryOutcome::Failed)
+        } else {
+            let delay = TimeDelta::from_std(self.retry_delay(attempts as u32))
+                .context("The webhook retry delay is out of range.")?;
+            (
+                DeliveryStatus::Pending,
+                Some(Utc::now() + delay),
+                DeliveryOutcome::WillRetry,
+            )
+        };
+        if !succeeded {
+            tracing::warn!(
+                attempts,
+                ?response_status,
+                ?error,
+                "A webhook delivery attempt failed."
+            );
+        }
+
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool.")?;
+        sqlx::query!(
+            r#"
+            INSERT INTO webhook_delivery_attempts
+                (delivery_id, attempted_at, duration_ms, response_status, error)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            delivery.delivery_id,
+            attempted_at,
+            duration_ms,
+            response_status,
+            error,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to record the webhook delivery attempt.")?;
+        sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $1, attempts = $2, next_attempt_at = $3, claimed_at = NULL
+            WHERE delivery_id = $4 AND status = 'sending' AND claimed_at = $5
+            "#,
+            status.as_str(),
+            attempts,
+            next_attempt_at,
+            delivery.delivery_id,
+            delivery.claimed_at,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the webhook delivery.")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to record a webhook delivery attempt.")?;
+        Ok(outcome)
+    }
+}
+
+/// Send the queued deliveries until the application shuts down, checking
+/// the queue every `poll_interval` while it is empty.
+pub async fn deliver_webhooks(
+    sender: WebhookSender,
+    pool: PgPool,
+    poll_interval: Duration,
+    shutdown: CancellationToken,
+) {
+    while !shutdown.is_cancelled() {
+        let idle = match sender.try_deliver_next(&pool).await {
+            Ok(DeliveryOutcome::EmptyQueue) => true,
+            Ok(_) => false,
+            Err(e) => {
+                tracing::warn!(error.cause_chain = ?e, "Failed to deliver a webhook.");
+                true
+            }
+        };
+        if idle {
+            tokio::select! {
+                _ = tokio::time::sleep(poll_interval) => {}
+                _ = shutdown.cancelled() => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use super::*;
+
+    fn sender(first_retry_delay: Duration) -> WebhookSender {
+        WebhookSender {
+            http_client: reqwest::Client::new(),
+            max_attempts: 10,
+            first_retry_delay,
+            allow_private_addresses: false,
+        }
+    }
+
+    #[test]
+    fn every_event_round_trips_through_its_string_form() {
+        for event in WebhookEvent::ALL {
+            assert_ok_eq!(WebhookEvent::parse(event.as_str().to_string()), event);
+        }
+    }
+
+    #[test]
+    fn unknown_event_is_rejected() {
+        assert_err!(WebhookEvent::parse("subscriber.deleted".to_string()));
+    }
+
+    #[test]
+    fn the_signature_covers_the_timestamp_and_the_payload() {
+        let signature = signature("whsec_secret", 1_700_000_000, r#"{"a":1}"#);
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"whsec_secret").unwrap();
+        mac.update(br#"1700000000.{"a":1}"#);
+        let expected = hex::encode(mac.finalize().into_bytes());
+        assert_eq!(signature, format!("t=1700000000,v1={}", expected));
+    }
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["93.184.216.34", "2606:2800:220:1::"] {
+            assert!(is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn deliveries_to_internal_addresses_are_refused() {
+        let sender = sender(Duration::from_secs(30));
+
+        assert_err!(sender.check_address("http://169.254.169.254/latest/meta-data"));
+        assert_err!(sender.check_address("http://[::1]:9000/metrics"));
+        assert!(sender.check_address("https://93.184.216.34/hooks").is_ok());
+    }
+
+    #[tokio::test]
+    async fn internal_endpoints_cannot_be_registered() {
+        assert_err!(check_endpoint_host("http://127.0.0.1:8000/").await);
+        assert_err!(check_endpoint_host("http://localhost/hooks").await);
+        assert_err!(check_endpoint_host("http://[fd00::1]/hooks").await);
+    }
+
+    #[test]
+    fn retries_back_off_exponentially() {
+        let sender = sender(Duration::from_secs(30));
+
+        assert_eq!(sender.retry_delay(1), Duration::from_secs(30));
+        assert_eq!(sender.retry_delay(2), Duration::from_secs(60));
+        assert_eq!(sender.retry_delay(3), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn retries_are_never_further_apart_than_the_maximum_delay() {
+        let sender = sender(Duration::from_secs(30));
+
+        assert_eq!(sender.retry_delay(40), MAX_RETRY_DELAY);
+    }
+}
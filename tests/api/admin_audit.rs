@@ -0,0 +1,233 @@
+use newsletter::domain::ApiScope;
+use reqwest::Method;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_audit("").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn only_owners_can_see_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_user_with_role("editor").await;
+
+    // Act
+    let page = app.get_admin_audit("").await;
+    let export = app.get_admin_audit_export("").await;
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 403);
+    assert_eq!(export.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn logins_are_recorded_in_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password"
+    }))
+    .await;
+    app.login_test_user().await;
+
+    // Act
+    let html_page = app.get_admin_audit_html("").await;
+
+    // Assert
+    assert!(html_page.contains("<td>login_succeeded</td>"));
+    assert!(html_page.contains("<td>login_failed</td>"));
+    assert!(html_page.contains(&format!("username:{}", app.test_user.username)));
+    assert!(html_page.contains("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn password_changes_are_recorded_in_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let new_password = uuid::Uuid::new_v4().to_string();
+    app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+
+    // Act
+    let html_page = app.get_admin_audit_html("action=password_changed").await;
+
+    // Assert
+    assert!(html_page.contains(&format!("user:{}", app.test_user.user_id)));
+    assert!(!html_page.contains("<td>login_succeeded</td>"));
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_searched() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": "someone-else",
+        "password": "wrong-password"
+    }))
+    .await;
+    app.login_test_user().await;
+
+    // Act
+    let html_page = app.get_admin_audit_html("search=someone-else").await;
+
+    // Assert
+    assert!(html_page.contains("username:someone-else"));
+    assert!(!html_page.contains("<td>login_succeeded</td>"));
+    assert!(html_page.contains("Entries found: 1."));
+}
+
+#[tokio::test]
+async fn unknown_actions_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app.get_admin_audit("action=deleted_everything").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_exported_as_json() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app.get_admin_audit_export("action=login_succeeded").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let entries: serde_json::Value = response.json().await.unwrap();
+    let entries = entries.as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["action"], "login_succeeded");
+    assert_eq!(entries[0]["actor"], app.test_user.username.as_str());
+    assert_eq!(
+        entries[0]["actor_id"],
+        app.test_user.user_id.to_string().as_str()
+    );
+    assert_eq!(entries[0]["ip"], "127.0.0.1");
+}
+
+#[tokio::test]
+async fn audit_entries_cannot_be_changed_or_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let update = sqlx::query!("UPDATE audit_log SET action = 'tampered'")
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM audit_log")
+        .execute(&app.db_pool)
+        .await;
+
+    // Assert
+    assert!(update.is_err());
+    assert!(delete.is_err());
+    let count = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM audit_log"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn api_tokens_are_recorded_in_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    app.post_create_api_token(&[("name", "Audited token"), ("scope", "publish")])
+        .await;
+    let token_id = sqlx::query!("SELECT token_id FROM api_tokens WHERE name = 'Audited token'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .token_id;
+    app.post_revoke_api_token(token_id).await;
+
+    // Act
+    let html_page = app.get_admin_audit_html("search=api_token").await;
+
+    // Assert
+    assert!(html_page.contains("<td>api_token_created</td>"));
+    assert!(html_page.contains("<td>api_token_revoked</td>"));
+    assert!(html_page.contains(&format!("api_token:{}", token_id)));
+}
+
+#[tokio::test]
+async fn revoked_sessions_are_recorded_in_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    app.login_test_user_elsewhere("Other Browser/1.0").await;
+    app.post_revoke_other_sessions().await;
+
+    // Act
+    let html_page = app.get_admin_audit_html("action=session_revoked").await;
+
+    // Assert
+    assert!(html_page.contains(&format!("user:{}", app.test_user.user_id)));
+    assert!(html_page.contains("all other sessions"));
+}
+
+#[tokio::test]
+async fn subscribers_added_through_the_api_are_recorded_in_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::SubscribersWrite], None)
+        .await;
+    let response = app
+        .api_request(
+            Method::POST,
+            "/subscribers",
+            Some(&token),
+            Some(serde_json::json!({
+                "email": "ursula_le_guin@gmail.com",
+                "name": "le guin",
+            })),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let subscriber_id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.login_test_user().await;
+
+    // Act
+    let html_page = app.get_admin_audit_html("action=subscriber_created").await;
+
+    // Assert
+    assert!(html_page.contains(&format!("subscriber:{}", subscriber_id)));
+}
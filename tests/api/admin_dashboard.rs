@@ -12,6 +12,34 @@ async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
     assert_is_redirect_to(&response, "/login");
 }
 
+#[tokio::test]
+async fn every_admin_page_requires_a_logged_in_user() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for page in [
+        "/admin/api-tokens",
+        "/admin/dashboard",
+        "/admin/password",
+        "/admin/sessions",
+        "/admin/subscribers",
+        "/admin/two-factor",
+        "/admin/users",
+    ] {
+        // Act
+        let response = app
+            .api_client
+            .get(&format!("{}{}", &app.address, page))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 303, "{} is not protected", page);
+        assert_eq!(response.headers().get("Location").unwrap(), "/login");
+    }
+}
+
 #[tokio::test]
 async fn logout_clears_session_state() {
     // Arrange
@@ -41,3 +69,25 @@ async fn logout_clears_session_state() {
     let response = app.get_admin_dashboard().await;
     assert_is_redirect_to(&response, "/login");
 }
+
+#[tokio::test]
+async fn the_username_is_escaped_on_the_dashboard() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    // A username stored before usernames were validated.
+    sqlx::query!(
+        "UPDATE users SET username = '<b>le guin</b>' WHERE user_id = $1",
+        app.test_user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // Assert
+    assert!(html_page.contains("Welcome &lt;b&gt;le guin&lt;/b&gt;!"));
+    assert!(!html_page.contains("<b>le guin</b>"));
+}
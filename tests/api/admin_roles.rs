@@ -0,0 +1,188 @@
+use newsletter::domain::ApiScope;
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestUser};
+
+#[tokio::test]
+async fn viewers_cannot_manage_users() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_user_with_role("viewer").await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(&format!("{}/admin/users", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn editors_cannot_invite_users() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_user_with_role("editor").await;
+
+    // Act
+    let response = app
+        .post_invite_user(&serde_json::json!({
+            "email": "new_admin@example.com",
+            "role": "owner"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn viewers_can_see_subscribers_but_cannot_edit_them() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_user_with_role("viewer").await;
+    let subscriber_id = Uuid::new_v4();
+
+    // Act - Part 1 - List subscribers
+    let response = app.get_admin_subscribers("").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - Part 2 - Try to edit a subscriber
+    let response = app
+        .post_edit_subscriber(
+            subscriber_id,
+            &serde_json::json!({ "name": "Ursula", "status": "confirmed" }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    // Act - Part 3 - Try to delete a subscriber
+    let response = app.post_delete_subscriber(subscriber_id).await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_dashboard_only_links_to_pages_the_user_can_access() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_user_with_role("viewer").await;
+
+    // Act
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // Assert
+    assert!(html_page.contains("You are signed in as viewer."));
+    assert!(html_page.contains(r#"href="/admin/subscribers""#));
+    assert!(!html_page.contains(r#"href="/admin/users""#));
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    let token = app
+        .create_api_token(viewer.user_id, &[ApiScope::Publish], None)
+        .await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(&format!("{}/newsletters", &app.address))
+        .bearer_auth(token)
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn owners_can_change_the_role_of_other_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::with_role("editor");
+    editor.store(&app.db_pool).await;
+    app.login_test_user().await;
+
+    // Act - Part 1 - Change the role
+    let response = app
+        .post_change_user_role(editor.user_id, &serde_json::json!({ "role": "viewer" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains("<p><i>The user's role is now viewer.</i></p>"));
+
+    // Assert
+    let role = sqlx::query!("SELECT role FROM users WHERE user_id = $1", editor.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .role;
+    assert_eq!(role, "viewer");
+}
+
+#[tokio::test]
+async fn you_cannot_change_your_own_role() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app
+        .post_change_user_role(
+            app.test_user.user_id,
+            &serde_json::json!({ "role": "viewer" }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains("<p><i>You cannot change your own role.</i></p>"));
+}
+
+#[tokio::test]
+async fn rejected_roles_are_escaped_in_the_error_message() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::with_role("editor");
+    editor.store(&app.db_pool).await;
+    app.login_test_user().await;
+    let role = "<script>alert(1)</script>";
+    let escaped = "<p><i>&lt;script&gt;alert(1)&lt;/script&gt; is not a valid role.</i></p>";
+
+    // Act - Part 1 - Invite a user
+    let response = app
+        .post_invite_user(&serde_json::json!({
+            "email": "new_admin@example.com",
+            "role": role,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains(escaped));
+    assert!(!html_page.contains(role));
+
+    // Act - Part 2 - Change a role
+    let response = app
+        .post_change_user_role(editor.user_id, &serde_json::json!({ "role": role }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains(escaped));
+    assert!(!html_page.contains(role));
+}
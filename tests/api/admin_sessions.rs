@@ -0,0 +1,153 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, csrf_token_for, spawn_app, TestApp};
+
+/// Whether `client` can still reach the admin dashboard.
+async fn is_logged_in(app: &TestApp, client: &reqwest::Client) -> bool {
+    let response = client
+        .get(&format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    response.status().as_u16() == 200
+}
+
+async fn session_id_of(app: &TestApp, user_agent: &str) -> Uuid {
+    sqlx::query!(
+        "SELECT id FROM user_sessions WHERE user_agent = $1",
+        user_agent
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the tracked session.")
+    .id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_sessions().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn sessions_are_listed_with_their_client() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    app.login_test_user_elsewhere("Other Browser/1.0").await;
+
+    // Act
+    let html_page = app.get_admin_sessions_html().await;
+
+    // Assert
+    assert!(html_page.contains("This session"));
+    assert!(html_page.contains("127.0.0.1"));
+    assert!(html_page.contains("Other Browser/1.0"));
+    let other_id = session_id_of(&app, "Other Browser/1.0").await;
+    assert!(html_page.contains(&format!("/admin/sessions/{}/revoke", other_id)));
+}
+
+#[tokio::test]
+async fn revoking_a_session_logs_it_out() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let other_client = app.login_test_user_elsewhere("Other Browser/1.0").await;
+    let other_id = session_id_of(&app, "Other Browser/1.0").await;
+
+    // Act
+    let response = app.post_revoke_session(other_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_admin_sessions_html().await;
+    assert!(html_page.contains("<p><i>The session has been revoked.</i></p>"));
+    assert!(!html_page.contains("Other Browser/1.0"));
+    assert!(!is_logged_in(&app, &other_client).await);
+    assert!(app.get_admin_dashboard().await.status().is_success());
+}
+
+#[tokio::test]
+async fn the_current_session_cannot_be_revoked_from_the_list() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = app.login_test_user_elsewhere("Current Browser/1.0").await;
+    let id = session_id_of(&app, "Current Browser/1.0").await;
+
+    let csrf_token = csrf_token_for(&client, &app.address).await;
+
+    // Act
+    let response = client
+        .post(&format!("{}/admin/sessions/{}/revoke", &app.address, id))
+        .header("X-CSRF-Token", csrf_token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    assert!(is_logged_in(&app, &client).await);
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_cannot_be_revoked() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_client = app.login_test_user_elsewhere("Other Browser/1.0").await;
+    let other_id = session_id_of(&app, "Other Browser/1.0").await;
+    app.login_user_with_role("viewer").await;
+
+    // Act
+    let response = app.post_revoke_session(other_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    assert!(is_logged_in(&app, &other_client).await);
+}
+
+#[tokio::test]
+async fn all_other_sessions_can_be_revoked_at_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let first_client = app.login_test_user_elsewhere("First Browser/1.0").await;
+    let second_client = app.login_test_user_elsewhere("Second Browser/1.0").await;
+
+    // Act
+    let response = app.post_revoke_other_sessions().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    assert!(!is_logged_in(&app, &first_client).await);
+    assert!(!is_logged_in(&app, &second_client).await);
+    assert!(app.get_admin_dashboard().await.status().is_success());
+}
+
+#[tokio::test]
+async fn changing_the_password_revokes_the_other_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let other_client = app.login_test_user_elsewhere("Other Browser/1.0").await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/password");
+    assert!(!is_logged_in(&app, &other_client).await);
+    assert!(app.get_admin_dashboard().await.status().is_success());
+}
@@ -0,0 +1,266 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Use the public API of the application under test to create a subscriber
+/// and return its id.
+async fn create_subscriber(app: &TestApp, name: &str, email: &str) -> Uuid {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create subscriber")
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = format!(
+        "name={}&email={}",
+        urlencoding::encode(name),
+        urlencoding::encode(email)
+    );
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_subscriber_list() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_subscribers("").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_edit_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app, "le guin", "ursula_le_guin@gmail.com").await;
+
+    // Act
+    let response = app
+        .post_edit_subscriber(
+            subscriber_id,
+            &serde_json::json!({
+                "name": "Ursula",
+                "status": "confirmed",
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscriber_list_can_be_searched_by_email_or_name() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    create_subscriber(&app, "Octavia Butler", "octavia@example.com").await;
+    app.login_test_user().await;
+
+    // Act - Part 1 - No filters
+    let html_page = app.get_admin_subscribers_html("").await;
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+    assert!(html_page.contains("octavia@example.com"));
+
+    // Act - Part 2 - Search by email
+    let html_page = app.get_admin_subscribers_html("search=ursula").await;
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+    assert!(!html_page.contains("octavia@example.com"));
+
+    // Act - Part 3 - Search by name, case-insensitive
+    let html_page = app.get_admin_subscribers_html("search=BUTLER").await;
+    assert!(!html_page.contains("ursula_le_guin@gmail.com"));
+    assert!(html_page.contains("octavia@example.com"));
+}
+
+#[tokio::test]
+async fn subscriber_list_can_be_filtered_by_status() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmed_id = create_subscriber(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    create_subscriber(&app, "Octavia Butler", "octavia@example.com").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'confirmed' WHERE id = $1",
+        confirmed_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.login_test_user().await;
+
+    // Act
+    let html_page = app.get_admin_subscribers_html("status=confirmed").await;
+
+    // Assert
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+    assert!(!html_page.contains("octavia@example.com"));
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let test_cases = [
+        ("status=deleted", "unknown status"),
+        ("subscribed_from=yesterday", "invalid date"),
+    ];
+
+    for (query, description) in test_cases {
+        // Act
+        let response = app.get_admin_subscribers(query).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the filter was {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn unknown_subscribers_return_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app.get_subscriber_details(Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn editing_a_subscriber_updates_name_and_status() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    app.login_test_user().await;
+
+    // Act - Part 1 - Submit the edit form
+    let response = app
+        .post_edit_subscriber(
+            subscriber_id,
+            &serde_json::json!({
+                "name": "Ursula K. Le Guin",
+                "status": "confirmed",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("<p><i>The subscriber has been updated.</i></p>"));
+
+    // Assert
+    let saved = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn edits_must_pass_domain_validation() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    app.login_test_user().await;
+    let test_cases = [
+        (
+            serde_json::json!({"name": "", "status": "confirmed"}),
+            "empty name",
+        ),
+        (
+            serde_json::json!({"name": "<script>", "status": "confirmed"}),
+            "forbidden characters",
+        ),
+        (
+            serde_json::json!({"name": "Ursula", "status": "deleted"}),
+            "unknown status",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_edit_subscriber(subscriber_id, &body).await;
+
+        // Assert
+        assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+        let saved = sqlx::query!("SELECT name, status FROM subscriptions")
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("Failed to fetch saved subscription.");
+        assert_eq!(
+            saved.name, "le guin",
+            "The subscriber was updated when the payload had {}.",
+            description
+        );
+        assert_eq!(saved.status, "pending_confirmation");
+    }
+}
+
+#[tokio::test]
+async fn rejected_input_is_escaped_in_the_error_message() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    app.login_test_user().await;
+
+    // Act
+    app.post_edit_subscriber(
+        subscriber_id,
+        &serde_json::json!({"name": "<script>alert(1)</script>", "status": "confirmed"}),
+    )
+    .await;
+
+    // Assert
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    assert!(!html_page.contains("<script>alert(1)</script>"));
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_removes_it_and_its_tokens() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    app.login_test_user().await;
+
+    // Act - Part 1 - Delete
+    let response = app.post_delete_subscriber(subscriber_id).await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_admin_subscribers_html("").await;
+    assert!(html_page.contains("<p><i>The subscriber has been deleted.</i></p>"));
+    assert!(!html_page.contains("ursula_le_guin@gmail.com"));
+
+    // Assert
+    let remaining = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(remaining.is_empty());
+}
@@ -0,0 +1,264 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Invite `email` as the logged-in test user and return the invitation link
+/// sent by email.
+async fn invite_user(app: &TestApp, email: &str) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Send invitation")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_invite_user(&serde_json::json!({ "email": email, "role": "editor" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request).html
+}
+
+/// Submit the invitation form behind `link`.
+async fn accept_invitation(
+    app: &TestApp,
+    link: &reqwest::Url,
+    username: &str,
+    password: &str,
+) -> reqwest::Response {
+    let query: std::collections::HashMap<_, _> = link.query_pairs().into_owned().collect();
+    app.post_accept_invitation(&serde_json::json!({
+        "invitation_id": query["invitation_id"],
+        "tag": query["tag"],
+        "username": username,
+        "password": password,
+        "password_check": password,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_invite_users() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_invite_user(&serde_json::json!({
+            "email": "new_admin@example.com",
+            "role": "editor"
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn invited_users_can_set_their_password_and_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let password = Uuid::new_v4().to_string();
+
+    // Act - Part 1 - Invite
+    let link = invite_user(&app, "new_admin@example.com").await;
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains("An invitation has been sent to new_admin@example.com."));
+
+    // Act - Part 2 - Open the invitation link
+    let response = app.api_client.get(link.clone()).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - Part 3 - Set a password
+    let response = accept_invitation(&app, &link, "new_admin", &password).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 4 - Log in as the new user
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "new_admin",
+            "password": &password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert
+    let events = sqlx::query!("SELECT event_type FROM user_events ORDER BY occurred_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let events: Vec<_> = events.into_iter().map(|e| e.event_type).collect();
+    assert_eq!(events, ["invited", "invitation_accepted"]);
+}
+
+#[tokio::test]
+async fn invitations_are_not_accepted_with_an_invalid_username() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let link = invite_user(&app, "new_admin@example.com").await;
+
+    // Act
+    let response = accept_invitation(
+        &app,
+        &link,
+        "<img src=x onerror=alert(1)>",
+        "a-good-password",
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    let users = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM users WHERE email = 'new_admin@example.com'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(users, 0);
+}
+
+#[tokio::test]
+async fn invitation_links_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let link = invite_user(&app, "new_admin@example.com").await;
+    let response = accept_invitation(&app, &link, "new_admin", "first-password").await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act
+    let response = accept_invitation(&app, &link, "another_admin", "second-password").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn tampered_invitation_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let mut link = invite_user(&app, "new_admin@example.com").await;
+    let invitation_id = link
+        .query_pairs()
+        .find(|(k, _)| k == "invitation_id")
+        .unwrap()
+        .1
+        .into_owned();
+    link.query_pairs_mut()
+        .clear()
+        .append_pair("invitation_id", &invitation_id)
+        .append_pair("tag", "deadbeef");
+
+    // Act
+    let response = app.api_client.get(link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn deactivated_users_cannot_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let link = invite_user(&app, "new_admin@example.com").await;
+    accept_invitation(&app, &link, "new_admin", "a-good-password").await;
+    let user_id = sqlx::query!("SELECT user_id FROM users WHERE username = 'new_admin'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .user_id;
+
+    // Act - Part 1 - Deactivate
+    let response = app.post_deactivate_user(user_id).await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains("The user has been deactivated."));
+
+    // Act - Part 2 - Try to log in
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "new_admin",
+            "password": "a-good-password",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let event =
+        sqlx::query!("SELECT actor_id, user_id FROM user_events WHERE event_type = 'deactivated'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(event.actor_id, app.test_user.user_id);
+    assert_eq!(event.user_id, Some(user_id));
+}
+
+#[tokio::test]
+async fn deactivated_users_are_logged_out() {
+    // Arrange
+    let app = spawn_app().await;
+    let deactivated_client = app.login_test_user_elsewhere("Other Browser/1.0").await;
+    app.login_user_with_role("owner").await;
+
+    // Act
+    let response = app.post_deactivate_user(app.test_user.user_id).await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    // Assert
+    let response = deactivated_client
+        .get(&format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn rejected_emails_are_escaped_in_the_error_message() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    app.post_invite_user(&serde_json::json!({
+        "email": "<img src=x onerror=alert(1)>",
+        "role": "editor",
+    }))
+    .await;
+
+    // Assert
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains("&lt;img src=x onerror=alert(1)&gt;"));
+    assert!(!html_page.contains("<img src=x onerror=alert(1)>"));
+}
+
+#[tokio::test]
+async fn you_cannot_deactivate_yourself() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app.post_deactivate_user(app.test_user.user_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains("You cannot deactivate your own account."));
+}
@@ -0,0 +1,224 @@
+use chrono::{TimeDelta, Utc};
+use newsletter::domain::ApiScope;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+async fn publish_with(app: &TestApp, token: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(&format!("{}/newsletters", &app.address))
+        .bearer_auth(token)
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Extract the token from the page shown right after its creation.
+fn extract_token(html_page: &str) -> String {
+    html_page
+        .split("<p><code>")
+        .nth(1)
+        .and_then(|rest| rest.split("</code>").next())
+        .expect("No token on the page.")
+        .to_string()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_create_api_tokens() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_create_api_token(&[("name", "CI"), ("scope", "publish")])
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_token_created_in_the_admin_area_can_publish() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Create a token
+    let response = app
+        .post_create_api_token(&[("name", "CI pipeline"), ("scope", "publish")])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = extract_token(&response.text().await.unwrap());
+
+    // Act - Part 2 - Use it
+    let response = publish_with(&app, &token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Assert
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("CI pipeline"));
+    assert!(!html_page.contains(&token));
+    let row =
+        sqlx::query!("SELECT token_hash, last_used_at FROM api_tokens WHERE name = 'CI pipeline'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_ne!(row.token_hash, token);
+    assert!(row.last_used_at.is_some());
+}
+
+#[tokio::test]
+async fn a_token_needs_a_name_and_a_scope() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let test_cases = [
+        (vec![("scope", "publish")], "The token needs a name."),
+        (vec![("name", "CI")], "Select at least one scope."),
+        (
+            vec![
+                ("name", "CI"),
+                ("scope", "publish"),
+                ("expires_in_days", "0"),
+            ],
+            "The expiry must be between 1 and 3650 days.",
+        ),
+        (
+            vec![("name", "CI"), ("scope", "everything")],
+            "everything is not a valid scope.",
+        ),
+        (
+            vec![("name", "CI"), ("scope", "<script>alert(1)</script>")],
+            "&lt;script&gt;alert(1)&lt;/script&gt; is not a valid scope.",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        // Act
+        let response = app.post_create_api_token(&body).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/api-tokens");
+        let html_page = app.get_api_tokens_html().await;
+        assert!(
+            html_page.contains(&format!("<p><i>{}</i></p>", error_message)),
+            "Expected the error `{}`.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn viewers_cannot_create_tokens_that_publish() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_user_with_role("viewer").await;
+
+    // Act
+    let response = app
+        .post_create_api_token(&[("name", "CI"), ("scope", "publish")])
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("<p><i>Your role does not allow the publish scope.</i></p>"));
+}
+
+#[tokio::test]
+async fn a_token_without_the_publish_scope_is_forbidden_to_publish() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::SubscribersRead], None)
+        .await;
+
+    // Act
+    let response = publish_with(&app, &token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn an_expired_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app
+        .create_api_token(
+            app.test_user.user_id,
+            &[ApiScope::Publish],
+            Some(Utc::now() - TimeDelta::minutes(1)),
+        )
+        .await;
+
+    // Act
+    let response = publish_with(&app, &token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_revoked_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let token_id: Uuid = sqlx::query!("SELECT token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .token_id;
+
+    // Act - Part 1 - Revoke the token
+    let response = app.post_revoke_api_token(token_id).await;
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("<p><i>The token has been revoked.</i></p>"));
+
+    // Act - Part 2 - Use it
+    let response = publish_with(&app, &app.api_token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn tokens_of_deactivated_users_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        "UPDATE users SET is_active = FALSE WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = publish_with(&app, &app.api_token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,411 @@
+use newsletter::domain::ApiScope;
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+/// A token of the test user allowed to read and edit subscribers.
+async fn subscribers_token(app: &TestApp) -> String {
+    app.create_api_token(
+        app.test_user.user_id,
+        &[ApiScope::SubscribersRead, ApiScope::SubscribersWrite],
+        None,
+    )
+    .await
+}
+
+async fn mount_email_mock(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn store_subscriber(app: &TestApp, status: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'le guin', now(), $3)",
+        id,
+        format!("{}@example.com", id),
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    id
+}
+
+fn assert_is_problem(response: &reqwest::Response, status: u16) {
+    assert_eq!(response.status().as_u16(), status);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+}
+
+#[tokio::test]
+async fn the_openapi_document_is_served_without_a_token() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_request(Method::GET, "/openapi.json", None, None)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let document: serde_json::Value = response.json().await.unwrap();
+    assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+    for path in [
+        "/subscribers",
+        "/subscribers/{subscriber_id}",
+        "/issues",
+        "/issues/{issue_id}",
+        "/issues/{issue_id}/deliveries",
+    ] {
+        assert!(document["paths"][path].is_object(), "{} is missing", path);
+    }
+    assert_eq!(
+        document["components"]["securitySchemes"]["api_token"]["scheme"],
+        "bearer"
+    );
+}
+
+#[tokio::test]
+async fn requests_without_a_token_are_rejected_with_a_problem() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_request(Method::GET, "/subscribers", None, None)
+        .await;
+
+    // Assert
+    assert_is_problem(&response, 401);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Bearer realm="api""#
+    );
+    let request_id = response.headers()["X-Request-Id"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["title"], "Unauthorized");
+    assert_eq!(problem["status"], 401);
+    assert_eq!(problem["request_id"], request_id);
+}
+
+#[tokio::test]
+async fn a_token_without_the_scope_is_forbidden() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - the default token may only publish
+    let response = app
+        .api_request(Method::GET, "/subscribers", Some(&app.api_token), None)
+        .await;
+
+    // Assert
+    assert_is_problem(&response, 403);
+}
+
+#[tokio::test]
+async fn a_token_is_limited_to_what_its_owner_may_do() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    let token = app
+        .create_api_token(viewer.user_id, &[ApiScope::SubscribersWrite], None)
+        .await;
+
+    // Act
+    let response = app
+        .api_request(
+            Method::POST,
+            "/subscribers",
+            Some(&token),
+            Some(serde_json::json!({
+                "email": "ursula_le_guin@gmail.com",
+                "name": "le guin",
+            })),
+        )
+        .await;
+
+    // Assert
+    assert_is_problem(&response, 403);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_added_read_edited_and_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = subscribers_token(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Add
+    let response = app
+        .api_request(
+            Method::POST,
+            "/subscribers",
+            Some(&token),
+            Some(serde_json::json!({
+                "email": "ursula_le_guin@gmail.com",
+                "name": "le guin",
+            })),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let location = response.headers()["Location"].to_str().unwrap().to_owned();
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(created["status"], "pending_confirmation");
+    let subscriber_path = format!("/subscribers/{}", created["id"].as_str().unwrap());
+    assert_eq!(location, format!("/api/v1{}", subscriber_path));
+
+    // Act - Part 2 - Read
+    let response = app
+        .api_request(Method::GET, &subscriber_path, Some(&token), None)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let read: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(read, created);
+
+    // Act - Part 3 - Edit
+    let response = app
+        .api_request(
+            Method::PATCH,
+            &subscriber_path,
+            Some(&token),
+            Some(serde_json::json!({ "status": "unsubscribed" })),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let edited: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(edited["status"], "unsubscribed");
+    assert_eq!(edited["name"], "le guin");
+
+    // Act - Part 4 - Delete
+    let response = app
+        .api_request(Method::DELETE, &subscriber_path, Some(&token), None)
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+    let response = app
+        .api_request(Method::GET, &subscriber_path, Some(&token), None)
+        .await;
+    assert_is_problem(&response, 404);
+}
+
+#[tokio::test]
+async fn adding_a_subscriber_twice_is_a_conflict() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = subscribers_token(&app).await;
+    mount_email_mock(&app).await;
+    let body = serde_json::json!({
+        "email": "ursula_le_guin@gmail.com",
+        "name": "le guin",
+    });
+    app.api_request(
+        Method::POST,
+        "/subscribers",
+        Some(&token),
+        Some(body.clone()),
+    )
+    .await;
+
+    // Act
+    let response = app
+        .api_request(Method::POST, "/subscribers", Some(&token), Some(body))
+        .await;
+
+    // Assert
+    assert_is_problem(&response, 409);
+}
+
+#[tokio::test]
+async fn invalid_subscribers_are_rejected_with_a_problem() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = subscribers_token(&app).await;
+    let subscriber_id = store_subscriber(&app, "confirmed").await;
+    let test_cases = vec![
+        (
+            Method::POST,
+            "/subscribers".to_string(),
+            serde_json::json!({ "email": "not-an-email", "name": "le guin" }),
+            400,
+            "invalid email",
+        ),
+        (
+            Method::POST,
+            "/subscribers".to_string(),
+            serde_json::json!({ "name": "le guin" }),
+            422,
+            "missing email",
+        ),
+        (
+            Method::PATCH,
+            format!("/subscribers/{}", subscriber_id),
+            serde_json::json!({ "status": "gone" }),
+            400,
+            "unknown status",
+        ),
+    ];
+
+    for (method, path, body, status, description) in test_cases {
+        // Act
+        let response = app
+            .api_request(method, &path, Some(&token), Some(body))
+            .await;
+
+        // Assert
+        assert_is_problem(&response, status);
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert!(
+            problem["detail"].is_string(),
+            "The problem did not explain the {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_page_by_page() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = subscribers_token(&app).await;
+    let mut stored = Vec::new();
+    for _ in 0..3 {
+        stored.push(store_subscriber(&app, "confirmed").await.to_string());
+    }
+    store_subscriber(&app, "unsubscribed").await;
+
+    // Act
+    let mut listed = Vec::new();
+    let mut query = "/subscribers?status=confirmed&limit=2".to_string();
+    let mut pages = 0;
+    loop {
+        let response = app
+            .api_request(Method::GET, &query, Some(&token), None)
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+        let page: serde_json::Value = response.json().await.unwrap();
+        pages += 1;
+        for subscriber in page["data"].as_array().unwrap() {
+            listed.push(subscriber["id"].as_str().unwrap().to_owned());
+        }
+        match page["next_cursor"].as_str() {
+            Some(cursor) => {
+                query = format!("/subscribers?status=confirmed&limit=2&cursor={}", cursor)
+            }
+            None => break,
+        }
+    }
+
+    // Assert
+    assert_eq!(pages, 2);
+    listed.sort();
+    stored.sort();
+    assert_eq!(listed, stored);
+}
+
+#[tokio::test]
+async fn an_invalid_cursor_is_a_bad_request() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = subscribers_token(&app).await;
+
+    // Act
+    let response = app
+        .api_request(
+            Method::GET,
+            "/subscribers?cursor=not-a-cursor",
+            Some(&token),
+            None,
+        )
+        .await;
+
+    // Assert
+    assert_is_problem(&response, 400);
+}
+
+#[tokio::test]
+async fn published_issues_can_be_read_back() {
+    // Arrange
+    let app = spawn_app().await;
+    store_subscriber(&app, "confirmed").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish
+    let response = app
+        .api_request(
+            Method::POST,
+            "/issues",
+            Some(&app.api_token),
+            Some(serde_json::json!({
+                "title": "Newsletter title",
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            })),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let published: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(published["title"], "Newsletter title");
+    app.send_issues().await;
+
+    // Act - Part 2 - List
+    let response = app
+        .api_request(Method::GET, "/issues", Some(&app.api_token), None)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let page: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(page["data"], serde_json::json!([published.clone()]));
+    assert!(page["next_cursor"].is_null());
+
+    // Act - Part 3 - Read
+    let issue_path = format!("/issues/{}", published["id"].as_str().unwrap());
+    let response = app
+        .api_request(Method::GET, &issue_path, Some(&app.api_token), None)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let read: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(read, published);
+}
+
+#[tokio::test]
+async fn unknown_api_paths_are_problems() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_request(Method::GET, "/lists", Some(&app.api_token), None)
+        .await;
+
+    // Assert
+    assert_is_problem(&response, 404);
+}
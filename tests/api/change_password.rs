@@ -140,3 +140,37 @@ async fn changing_password_works() {
     let response = app.post_login(&login_body).await;
     assert_is_redirect_to(&response, "/admin/dashboard");
 }
+
+#[tokio::test]
+async fn new_password_must_follow_the_password_policy() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let test_cases = [
+        ("short", "The password must be at least 12 characters long."),
+        (
+            "password1234",
+            "This password is too common. Choose one that is harder to guess.",
+        ),
+    ];
+
+    for (new_password, error_message) in test_cases {
+        // Act
+        let response = app
+            .post_change_password(&serde_json::json!({
+                "current_password": &app.test_user.password,
+                "new_password": new_password,
+                "new_password_check": new_password,
+            }))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/password");
+        let html_page = app.get_change_password_html().await;
+        assert!(
+            html_page.contains(&format!("<p><i>{}</i></p>", error_message)),
+            "Expected the error `{}`.",
+            error_message
+        );
+    }
+}
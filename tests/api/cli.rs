@@ -0,0 +1,28 @@
+use newsletter::cli::{run, Command};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn users_disabled_from_the_command_line_are_logged_out() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = app.login_test_user_elsewhere("Other Browser/1.0").await;
+
+    // Act
+    run(
+        Command::DisableUser {
+            username: app.test_user.username.clone(),
+        },
+        app.configuration.clone(),
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    let response = client
+        .get(&format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
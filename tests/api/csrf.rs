@@ -0,0 +1,110 @@
+use crate::helpers::{assert_is_redirect_to, csrf_token_for, extract_csrf_token, spawn_app};
+
+#[tokio::test]
+async fn forms_embed_the_csrf_token_of_the_session() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let first_token = extract_csrf_token(&app.get_login_html().await);
+    let second_token = extract_csrf_token(&app.get_login_html().await);
+
+    // Assert
+    assert_eq!(first_token.len(), 40);
+    assert_eq!(first_token, second_token);
+}
+
+#[tokio::test]
+async fn a_login_without_csrf_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.get_login_html().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(&format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_csrf_token_is_accepted_as_a_form_field() {
+    // Arrange
+    let app = spawn_app().await;
+    let csrf_token = app.csrf_token().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(&format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "csrf_token": csrf_token,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_token_from_another_session_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let other_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let other_token = csrf_token_for(&other_client, &app.address).await;
+
+    // Act - A forged logout carrying someone else's token
+    let response = app
+        .api_client
+        .post(&format!("{}/admin/logout", &app.address))
+        .header("X-CSRF-Token", other_token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_password_change_without_csrf_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(&format!("{}/admin/password", &app.address))
+        .form(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": "a-new-password-nobody-knows",
+            "new_password_check": "a-new-password-nobody-knows",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
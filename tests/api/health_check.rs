@@ -1,4 +1,7 @@
-use crate::helpers::spawn_app;
+use newsletter::configuration::SessionStoreKind;
+use secrecy::SecretString;
+
+use crate::helpers::{spawn_app, spawn_app_with};
 
 #[tokio::test]
 async fn health_check_works() {
@@ -19,3 +22,96 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+async fn get_json(url: String) -> (u16, serde_json::Value) {
+    let response = reqwest::get(url).await.expect("Failed to execute request.");
+    let status = response.status().as_u16();
+    (status, response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn liveness_reports_the_build() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let (status, body) = get_json(format!("{}/health/live", app.address)).await;
+
+    // Assert
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "up");
+    assert_eq!(body["build"]["name"], "newsletter");
+    assert_eq!(body["build"]["version"], env!("CARGO_PKG_VERSION"));
+}
+
+#[tokio::test]
+async fn readiness_reports_every_dependency() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let (status, body) = get_json(format!("{}/health/ready", app.address)).await;
+
+    // Assert
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "ready");
+    for dependency in ["database", "session_store", "email"] {
+        assert_eq!(body["checks"][dependency]["status"], "up", "{}", dependency);
+        assert!(body["checks"][dependency]["latency_ms"].is_u64());
+    }
+}
+
+#[tokio::test]
+async fn readiness_fails_while_redis_is_down_but_liveness_does_not() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.session.store = SessionStoreKind::Redis;
+        c.redis_uri = SecretString::new("redis://127.0.0.1:1".into());
+    })
+    .await;
+
+    // Act
+    let (ready_status, ready) = get_json(format!("{}/health/ready", app.address)).await;
+    let (live_status, _) = get_json(format!("{}/health/live", app.address)).await;
+
+    // Assert
+    assert_eq!(ready_status, 503);
+    assert_eq!(ready["status"], "unavailable");
+    assert_eq!(ready["checks"]["session_store"]["status"], "down");
+    assert_eq!(ready["checks"]["database"]["status"], "up");
+    assert_eq!(live_status, 200);
+}
+
+#[tokio::test]
+async fn readiness_fails_with_unusable_email_settings() {
+    // Arrange
+    let app = spawn_app_with(|c| c.email_client.base_url = "localhost".into()).await;
+
+    // Act
+    let (status, body) = get_json(format!("{}/health/ready", app.address)).await;
+
+    // Assert
+    assert_eq!(status, 503);
+    assert_eq!(body["checks"]["email"]["status"], "down");
+    assert_eq!(
+        body["checks"]["email"]["error"],
+        "The base URL of the email API is invalid."
+    );
+}
+
+#[tokio::test]
+async fn readiness_can_leave_the_email_settings_out() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.email_client.base_url = "localhost".into();
+        c.health.check_email = false;
+    })
+    .await;
+
+    // Act
+    let (status, body) = get_json(format!("{}/health/ready", app.address)).await;
+
+    // Assert
+    assert_eq!(status, 200);
+    assert!(body["checks"].get("email").is_none());
+}
@@ -2,11 +2,19 @@ use std::sync::LazyLock;
 
 use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
 use newsletter::{
-    configuration::{get_configuration, DatabaseSettings},
+    api_tokens::create_api_token,
+    configuration::{get_configuration, DatabaseSettings, Settings},
+    domain::ApiScope,
+    email_client::EmailClient,
+    issue_deliveries::{resume_pending, send_pending},
     startup::{get_connection_pool, Application},
     telemetry::{get_subscriber, init_subscriber},
+    webhooks::{self, DeliveryOutcome, WebhookEvent, WebhookSender},
 };
+use secrecy::ExposeSecret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 use wiremock::MockServer;
 
@@ -15,10 +23,11 @@ static TRACING: LazyLock<()> = LazyLock::new(|| {
     let subscriber_name = "test".to_string();
 
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let subscriber =
+            get_subscriber(subscriber_name, default_filter_level, std::io::stdout, None);
         init_subscriber(subscriber);
     } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink, None);
         init_subscriber(subscriber);
     }
 });
@@ -26,19 +35,27 @@ static TRACING: LazyLock<()> = LazyLock::new(|| {
 pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
+    pub email: String,
     pub password: String,
+    pub role: String,
 }
 
 impl TestUser {
     pub fn generate() -> Self {
+        Self::with_role("owner")
+    }
+
+    pub fn with_role(role: &str) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
+            email: format!("{}@example.com", Uuid::new_v4()),
             password: "everythinghastostartsomewhere".into(),
+            role: role.into(),
         }
     }
 
-    async fn store(&self, pool: &PgPool) {
+    pub async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand_08::thread_rng());
         // Match parameters of the default password
         let password_hash = Argon2::new(
@@ -51,11 +68,13 @@ impl TestUser {
         .to_string();
         dbg!(&password_hash);
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password_hash)
-            VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, email, password_hash, role)
+            VALUES ($1, $2, $3, $4, $5)",
             self.user_id,
             self.username,
+            self.email,
             password_hash,
+            self.role,
         )
         .execute(pool)
         .await
@@ -66,10 +85,28 @@ impl TestUser {
 pub struct TestApp {
     pub address: String,
     pub port: u16,
+    /// Where `/metrics` is served, apart from the public address.
+    pub metrics_address: String,
     pub db_pool: PgPool,
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    /// A token of the test user allowed to publish newsletter issues.
+    pub api_token: String,
+    /// The token of the setup page, if no user existed at startup.
+    pub setup_token: Option<String>,
+    /// Sends the queued webhook deliveries when told to: the application
+    /// runs no delivery worker of its own in tests.
+    pub webhook_sender: WebhookSender,
+    /// Sends the published issues when told to, to the mock email API: the
+    /// application runs no issue delivery worker of its own in tests.
+    pub email_client: EmailClient,
+    /// The settings the application was built with, e.g. to run commands of
+    /// `newsletter-admin` against it.
+    pub configuration: Settings,
+    /// Cancel to shut the application down, as SIGTERM would.
+    pub stopping: CancellationToken,
+    pub server: JoinHandle<Result<(), std::io::Error>>,
 }
 
 /// Confirmation links embedded in the request to the email API.
@@ -79,29 +116,75 @@ pub struct ConfirmationLinks {
 }
 
 impl TestApp {
-    pub async fn get_login_html(&self) -> String {
+    /// The CSRF token of the client's session, as embedded in the forms.
+    pub async fn csrf_token(&self) -> String {
+        csrf_token_for(&self.api_client, &self.address).await
+    }
+
+    pub async fn get_login(&self) -> reqwest::Response {
         self.api_client
             .get(&format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
-            .text()
-            .await
-            .unwrap()
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.get_login().await.text().await.unwrap()
     }
 
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
+        let csrf_token = self.csrf_token().await;
         self.api_client
             .post(&format!("{}/login", &self.address))
             .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_setup(&self) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/setup", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_setup_html(&self) -> String {
+        self.get_setup().await.text().await.unwrap()
+    }
+
+    /// Fill in the setup form, with the CSRF token embedded in it.
+    pub async fn post_setup<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let csrf_token = extract_csrf_token(&self.get_setup_html().await);
+        self.api_client
+            .post(&format!("{}/setup", &self.address))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_metrics(&self) -> String {
+        self.api_client
+            .get(&format!("{}/metrics", &self.metrics_address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
             .post(format!("{}/subscriptions", &self.address))
@@ -137,22 +220,157 @@ impl TestApp {
     }
 
     pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
-        let (username, password) = self.test_user().await;
         self.api_client
             .post(&format!("{}/newsletters", &self.address))
-            .basic_auth(username, Some(password))
+            .bearer_auth(&self.api_token)
             .json(&body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
-    pub async fn test_user(&self) -> (String, String) {
-        let row = sqlx::query!("SELECT username, password_hash FROM users LIMIT 1")
-            .fetch_one(&self.db_pool)
+    /// Send a request to the JSON API, under `/api/v1`.
+    pub async fn api_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        token: Option<&str>,
+        body: Option<serde_json::Value>,
+    ) -> reqwest::Response {
+        let mut request = self
+            .api_client
+            .request(method, format!("{}/api/v1{}", &self.address, path));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    /// Store an API token for `user_id` without going through the admin area.
+    pub async fn create_api_token(
+        &self,
+        user_id: Uuid,
+        scopes: &[ApiScope],
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> String {
+        let (_, token) = create_api_token(&self.db_pool, user_id, "test", scopes, expires_at)
             .await
-            .expect("Failed to create test users.");
-        (row.username, row.password_hash)
+            .expect("Failed to create an API token.");
+        token.expose_secret().to_string()
+    }
+
+    /// Register a webhook endpoint for the test user and return it with its
+    /// signing secret.
+    pub async fn create_webhook_endpoint(
+        &self,
+        url: &str,
+        events: &[WebhookEvent],
+    ) -> (Uuid, String) {
+        let (endpoint_id, secret) =
+            webhooks::create_endpoint(&self.db_pool, url, "test", events, self.test_user.user_id)
+                .await
+                .expect("Failed to create a webhook endpoint.");
+        (endpoint_id, secret.expose_secret().to_string())
+    }
+
+    /// Send the published issues to their pending recipients, as the issue
+    /// delivery workers would.
+    pub async fn send_issues(&self) {
+        for issue_id in resume_pending(None, &self.db_pool)
+            .await
+            .expect("Failed to resume the pending deliveries.")
+        {
+            send_pending(
+                &self.email_client,
+                &self.db_pool,
+                issue_id,
+                &CancellationToken::new(),
+            )
+            .await
+            .expect("Failed to send an issue.");
+        }
+    }
+
+    /// Make an attempt at every due webhook delivery, and return the
+    /// outcomes.
+    pub async fn deliver_webhooks(&self) -> Vec<DeliveryOutcome> {
+        let mut outcomes = Vec::new();
+        loop {
+            let outcome = self
+                .webhook_sender
+                .try_deliver_next(&self.db_pool)
+                .await
+                .expect("Failed to deliver a webhook.");
+            if outcome == DeliveryOutcome::EmptyQueue {
+                return outcomes;
+            }
+            outcomes.push(outcome);
+        }
+    }
+
+    pub async fn get_webhooks_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/webhooks", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_webhook(&self, body: &[(&str, &str)]) -> reqwest::Response {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/admin/webhooks", &self.address))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_disable_webhook(&self, endpoint_id: Uuid) -> reqwest::Response {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!(
+                "{}/admin/webhooks/{}/disable",
+                &self.address, endpoint_id
+            ))
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_webhook_delivery_html(&self, delivery_id: Uuid) -> String {
+        self.api_client
+            .get(&format!(
+                "{}/admin/webhooks/deliveries/{}",
+                &self.address, delivery_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_replay_webhook_delivery(&self, delivery_id: Uuid) -> reqwest::Response {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!(
+                "{}/admin/webhooks/deliveries/{}/replay",
+                &self.address, delivery_id
+            ))
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
     }
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
@@ -179,9 +397,11 @@ impl TestApp {
     where
         Body: serde::Serialize,
     {
+        let csrf_token = self.csrf_token().await;
         self.api_client
             .post(&format!("{}/admin/password", &self.address))
             .form(body)
+            .header("X-CSRF-Token", csrf_token)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -191,9 +411,432 @@ impl TestApp {
         self.get_change_password().await.text().await.unwrap()
     }
 
+    pub async fn get_admin_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_subscribers_html(&self, query: &str) -> String {
+        self.get_admin_subscribers(query)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_subscriber_details(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(&format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscriber_details_html(&self, subscriber_id: Uuid) -> String {
+        self.get_subscriber_details(subscriber_id)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_edit_subscriber<Body>(
+        &self,
+        subscriber_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_delete_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!(
+                "{}/admin/subscribers/{}/delete",
+                &self.address, subscriber_id
+            ))
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_users_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_invite_user<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/admin/users/invite", &self.address))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_deactivate_user(&self, user_id: Uuid) -> reqwest::Response {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!(
+                "{}/admin/users/{}/deactivate",
+                &self.address, user_id
+            ))
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_user_role<Body>(&self, user_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/admin/users/{}/role", &self.address, user_id))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_accept_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/invitations/accept", &self.address))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn login_test_user(&self) {
+        let response = self
+            .post_login(&serde_json::json!({
+                "username": &self.test_user.username,
+                "password": &self.test_user.password
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+    }
+
+    /// Store a new user with the given role and log in as them.
+    pub async fn login_user_with_role(&self, role: &str) -> TestUser {
+        let user = TestUser::with_role(role);
+        user.store(&self.db_pool).await;
+        let response = self
+            .post_login(&serde_json::json!({
+                "username": &user.username,
+                "password": &user.password
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+        user
+    }
+
+    pub async fn get_two_factor_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/two-factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_enroll_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/admin/two-factor", &self.address))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_disable_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/admin/two-factor/disable", &self.address))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_two_factor_policy<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/admin/two-factor/policy", &self.address))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_login_two_factor_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/login/two-factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_login_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/login/two-factor", &self.address))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_tokens_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/api-tokens", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_api_token(&self, body: &[(&str, &str)]) -> reqwest::Response {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/admin/api-tokens", &self.address))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke_api_token(&self, token_id: Uuid) -> reqwest::Response {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!(
+                "{}/admin/api-tokens/{}/revoke",
+                &self.address, token_id
+            ))
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/password-reset", &self.address))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_confirm_password_reset<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/password-reset/confirm", &self.address))
+            .form(body)
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_sessions_html(&self) -> String {
+        self.get_admin_sessions().await.text().await.unwrap()
+    }
+
+    pub async fn post_revoke_session(&self, id: Uuid) -> reqwest::Response {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/admin/sessions/{}/revoke", &self.address, id))
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke_other_sessions(&self) -> reqwest::Response {
+        let csrf_token = self.csrf_token().await;
+        self.api_client
+            .post(&format!("{}/admin/sessions/revoke-others", &self.address))
+            .header("X-CSRF-Token", csrf_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_audit(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/audit?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_audit_html(&self, query: &str) -> String {
+        self.get_admin_audit(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_admin_audit_export(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/audit/export?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_newsletter_issues_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/newsletters", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_newsletter_report(&self, issue_id: Uuid, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!(
+                "{}/admin/newsletters/{}?{}",
+                &self.address, issue_id, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_newsletter_report_html(&self, issue_id: Uuid, query: &str) -> String {
+        self.get_newsletter_report(issue_id, query)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_newsletter_report_export(
+        &self,
+        issue_id: Uuid,
+        query: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .get(&format!(
+                "{}/admin/newsletters/{}/export?{}",
+                &self.address, issue_id, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Log the test user in from a second browser, identified by its user
+    /// agent, and return its client.
+    pub async fn login_test_user_elsewhere(&self, user_agent: &str) -> reqwest::Client {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .cookie_store(true)
+            .user_agent(user_agent)
+            .build()
+            .unwrap();
+        let csrf_token = csrf_token_for(&client, &self.address).await;
+        let response = client
+            .post(&format!("{}/login", &self.address))
+            .header("X-CSRF-Token", csrf_token)
+            .form(&serde_json::json!({
+                "username": &self.test_user.username,
+                "password": &self.test_user.password
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_is_redirect_to(&response, "/admin/dashboard");
+        client
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
+        let csrf_token = self.csrf_token().await;
         self.api_client
             .post(&format!("{}/admin/logout", &self.address))
+            .header("X-CSRF-Token", csrf_token)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -201,6 +844,22 @@ impl TestApp {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like [`spawn_app`], with a chance to change the configuration first.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
+    let mut test_app = spawn_app_without_users(configure).await;
+    test_app.test_user.store(&test_app.db_pool).await;
+    test_app.api_token = test_app
+        .create_api_token(test_app.test_user.user_id, &[ApiScope::Publish], None)
+        .await;
+    test_app
+}
+
+/// Launch the application on an empty database, as on its first run. The
+/// test user is generated but not stored.
+pub async fn spawn_app_without_users(configure: impl FnOnce(&mut Settings)) -> TestApp {
     let _ = &*TRACING;
 
     // Launch a mock server to stand in for SendGrid's API
@@ -213,8 +872,18 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         // Use a random OS port
         c.application.port = 0;
+        c.metrics.port = 0;
         // Use the mock server as email API
         c.email_client.base_url = email_server.uri();
+        // Keep failed logins of different test cases apart
+        c.login_throttle.key_prefix = format!("login_throttle:{}", Uuid::new_v4());
+        // Deliver webhooks only when a test asks for it
+        c.webhooks.workers = 0;
+        // The receivers of the tests listen on localhost
+        c.webhooks.allow_private_addresses = true;
+        // Send issues only when a test asks for it
+        c.issue_deliveries.workers = 0;
+        configure(&mut c);
         c
     };
 
@@ -226,7 +895,19 @@ pub async fn spawn_app() -> TestApp {
         .await
         .expect("Failed to build application");
     let application_port = application.port();
-    tokio::spawn(application.run_until_stopped());
+    let metrics_port = application.metrics_port();
+    let setup_token = application
+        .setup_token()
+        .map(|token| token.expose_secret().to_string());
+    let webhook_sender = WebhookSender::new(&configuration.webhooks);
+    let email_client = EmailClient::new(
+        configuration.email_client.base_url.clone(),
+        configuration.email_client.sender().unwrap(),
+        configuration.email_client.authorization_token.clone(),
+        configuration.email_client.timeout(),
+    );
+    let stopping = CancellationToken::new();
+    let server = tokio::spawn(application.run_until(stopping.clone().cancelled_owned()));
 
     let api_client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
@@ -234,16 +915,22 @@ pub async fn spawn_app() -> TestApp {
         .build()
         .unwrap();
 
-    let test_app = TestApp {
+    TestApp {
         address: format!("http://127.0.0.1:{}", application_port),
         port: application_port,
+        metrics_address: format!("http://127.0.0.1:{}", metrics_port),
         db_pool: get_connection_pool(&configuration.database),
         email_server,
         test_user: TestUser::generate(),
         api_client,
-    };
-    test_app.test_user.store(&test_app.db_pool).await;
-    test_app
+        api_token: String::new(),
+        setup_token,
+        webhook_sender,
+        email_client,
+        configuration,
+        stopping,
+        server,
+    }
 }
 
 pub async fn configure_database(config: &DatabaseSettings) -> PgPool {
@@ -268,6 +955,27 @@ pub async fn configure_database(config: &DatabaseSettings) -> PgPool {
     connection_pool
 }
 
+/// Load the login form with `client` and read the CSRF token of its session
+/// from it.
+pub async fn csrf_token_for(client: &reqwest::Client, address: &str) -> String {
+    let html_page = client
+        .get(&format!("{}/login", address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap();
+    extract_csrf_token(&html_page)
+}
+
+pub fn extract_csrf_token(html_page: &str) -> String {
+    let prefix = r#"name="csrf_token" value=""#;
+    let start = html_page.find(prefix).expect("No CSRF token in the page.") + prefix.len();
+    let end = start + html_page[start..].find('"').unwrap();
+    html_page[start..end].to_string()
+}
+
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
@@ -1,4 +1,37 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
+use secrecy::ExposeSecret;
+use tower_sessions_redis_store::fred::prelude::*;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Submit a wrong password for the test user `times` times.
+async fn fail_to_log_in(app: &TestApp, times: usize) {
+    for _ in 0..times {
+        let response = app
+            .post_login(&serde_json::json!({
+                "username": &app.test_user.username,
+                "password": "wrong-password",
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+/// The `Max-Age` of the session cookie set by `response`, in seconds.
+fn session_cookie_max_age(response: &reqwest::Response) -> i64 {
+    response
+        .headers()
+        .get_all(reqwest::header::SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|cookie| cookie.starts_with("session_id="))
+        .expect("No session cookie was set.")
+        .split(';')
+        .find_map(|attribute| attribute.trim().strip_prefix("Max-Age="))
+        .expect("The session cookie has no Max-Age.")
+        .parse()
+        .unwrap()
+}
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
@@ -41,3 +74,161 @@ async fn redirect_to_admin_dashboard_after_login_success() {
     let html_page = app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
 }
+
+#[tokio::test]
+async fn a_username_is_locked_out_after_too_many_failed_attempts() {
+    // Arrange
+    let app = spawn_app().await;
+    fail_to_log_in(&app, 5).await;
+
+    // Act - Use the right password
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page
+        .contains("<p><i>Too many failed login attempts. Try again in 15 minutes.</i></p>"));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failed_attempts_of_the_username() {
+    // Arrange
+    let app = spawn_app().await;
+    fail_to_log_in(&app, 4).await;
+    app.login_test_user().await;
+    app.post_logout().await;
+
+    // Act
+    fail_to_log_in(&app, 1).await;
+
+    // Assert
+    app.login_test_user().await;
+}
+
+#[tokio::test]
+async fn an_outdated_password_hash_is_upgraded_on_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let salt = SaltString::generate(&mut rand_08::thread_rng());
+    let outdated_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(4096, 1, 1, None).unwrap(),
+    )
+    .hash_password(app.test_user.password.as_bytes(), &salt)
+    .unwrap()
+    .to_string();
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+        outdated_hash,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.login_test_user().await;
+
+    // Assert
+    let password_hash = sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .password_hash;
+    assert!(password_hash.starts_with("$argon2id$v=19$m=15000,t=2,p=1$"));
+    app.post_logout().await;
+    app.login_test_user().await;
+}
+
+#[tokio::test]
+async fn the_login_form_offers_to_remember_the_user() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let html_page = app.get_login_html().await;
+
+    // Assert
+    assert!(html_page.contains(r#"name="remember_me""#));
+    assert!(html_page.contains("Remember me for 30 days"));
+}
+
+#[tokio::test]
+async fn sessions_expire_after_the_idle_timeout_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(session_cookie_max_age(&response), 30 * 60);
+}
+
+#[tokio::test]
+async fn remembered_sessions_last_for_days() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "remember_me": "on",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let max_age = session_cookie_max_age(&response);
+    assert!(max_age > 29 * 24 * 60 * 60);
+    assert!(max_age <= 30 * 24 * 60 * 60);
+    assert!(app.get_admin_dashboard().await.status().is_success());
+}
+
+#[tokio::test]
+async fn failed_login_counters_always_expire() {
+    // Arrange
+    let app = spawn_app().await;
+    let redis = Pool::new(
+        Config::from_url(app.configuration.redis_uri.expose_secret()).unwrap(),
+        None,
+        None,
+        None,
+        1,
+    )
+    .unwrap();
+    redis.init().await.unwrap();
+
+    // Act
+    fail_to_log_in(&app, 2).await;
+
+    // Assert
+    let key = format!(
+        "{}:username:{}",
+        app.configuration.login_throttle.key_prefix, app.test_user.username
+    );
+    let failures: u32 = redis.get(&key).await.unwrap();
+    let ttl: i64 = redis.ttl(&key).await.unwrap();
+    assert_eq!(failures, 2);
+    assert!(ttl > 0);
+}
@@ -1,8 +1,26 @@
+mod admin_audit;
 mod admin_dashboard;
+mod admin_roles;
+mod admin_sessions;
+mod admin_subscribers;
+mod admin_users;
+mod api_tokens;
+mod api_v1;
 mod change_password;
+mod cli;
+mod csrf;
 mod health_check;
 mod helpers;
 mod login;
+mod metrics;
 mod newsletter;
+mod newsletter_deliveries;
+mod password_reset;
+mod request_id;
+mod session_store;
+mod setup;
+mod shutdown;
 mod subscriptions;
 mod subscriptions_confirm;
+mod two_factor;
+mod webhooks;
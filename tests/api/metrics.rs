@@ -0,0 +1,178 @@
+use newsletter::webhooks::WebhookEvent;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn metrics_count_requests_by_route_and_status() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    app.get_login_html().await;
+    let metrics = app.get_metrics().await;
+
+    // Assert
+    assert!(metrics.contains(r#"http_requests_total{method="GET",route="/login",status="200"} 1"#));
+    assert!(metrics.contains("http_request_duration_seconds_bucket"));
+    assert!(metrics.contains(r#"db_pool_connections{state="idle"}"#));
+}
+
+#[tokio::test]
+async fn metrics_are_not_served_on_the_public_port() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(&format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn metrics_count_signups_and_the_emails_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let metrics = app.get_metrics().await;
+
+    // Assert
+    assert!(metrics.contains("subscriber_signups_total 1"));
+    assert!(metrics.contains(r#"emails_total{outcome="sent",transport="api"} 1"#));
+}
+
+#[tokio::test]
+async fn following_a_confirmation_link_again_is_not_counted() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // Act
+    for _ in 0..2 {
+        let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let metrics = app.get_metrics().await;
+
+    // Assert
+    assert!(metrics.contains("subscriber_confirmations_total 1"));
+}
+
+#[tokio::test]
+async fn metrics_count_failed_emails() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let metrics = app.get_metrics().await;
+
+    // Assert
+    assert!(metrics.contains(r#"emails_total{outcome="failed",transport="api"} 1"#));
+    assert!(!metrics.contains("subscriber_signups_total 1"));
+}
+
+#[tokio::test]
+async fn metrics_count_login_failures() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    app.post_login(&serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    }))
+    .await;
+    let metrics = app.get_metrics().await;
+
+    // Assert
+    assert!(metrics.contains(r#"login_failures_total{reason="invalid_credentials"} 1"#));
+}
+
+#[tokio::test]
+async fn metrics_report_the_pending_deliveries_of_each_queue() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let receiver = MockServer::start().await;
+    Mock::given(path("/hooks"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&receiver)
+        .await;
+    app.create_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &[WebhookEvent::IssuePublished],
+    )
+    .await;
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula_le_guin@gmail.com', 'le guin', now(), 'confirmed')",
+        Uuid::new_v4(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act - Part 1 - Publish an issue
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let metrics = app.get_metrics().await;
+
+    // Assert - Part 1
+    assert!(metrics.contains(r#"pending_deliveries{queue="issue_deliveries"} 1"#));
+    assert!(metrics.contains(r#"pending_deliveries{queue="webhook_deliveries"} 1"#));
+
+    // Act - Part 2 - Send it and notify the receiver
+    app.send_issues().await;
+    app.deliver_webhooks().await;
+    let metrics = app.get_metrics().await;
+
+    // Assert - Part 2
+    assert!(metrics.contains(r#"pending_deliveries{queue="issue_deliveries"} 0"#));
+    assert!(metrics.contains(r#"pending_deliveries{queue="webhook_deliveries"} 0"#));
+}
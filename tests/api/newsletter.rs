@@ -28,6 +28,7 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
         }
     });
     let response = app.post_newsletters(newsletter_request_body).await;
+    app.send_issues().await;
 
     // Assert
     assert_eq!(response.status().as_u16(), 200);
@@ -85,6 +86,7 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
         }
     });
     let response = app.post_newsletters(newsletter_request_body).await;
+    app.send_issues().await;
 
     // Assert
     assert_eq!(response.status().as_u16(), 200);
@@ -142,13 +144,7 @@ async fn requests_missing_authorization_are_rejected() {
 
     let response = reqwest::Client::new()
         .post(&format!("{}/newsletters", &app.address))
-        .json(&serde_json::json!({
-            "title": "Newsletter title",
-            "content": {
-                "text": "Newsletter body as plain text",
-                "html": "<p>Newsletter body as HTML</p>",
-            }
-        }))
+        .json(&newsletter_request_body())
         .send()
         .await
         .expect("Failed to execute request.");
@@ -156,29 +152,22 @@ async fn requests_missing_authorization_are_rejected() {
     // Assert
     assert_eq!(401, response.status().as_u16());
     assert_eq!(
-        r#"Basic realm="publish""#,
+        r#"Bearer realm="publish""#,
         response.headers()["WWW-Authenticate"]
     );
 }
 
 #[tokio::test]
-async fn non_existing_user_is_rejected() {
+async fn unknown_token_is_rejected() {
     // Arrange
     let app = spawn_app().await;
-    // Random credentials
-    let username = Uuid::new_v4().to_string();
-    let password = Uuid::new_v4().to_string();
+    // Random token
+    let token = format!("nlt_{}", Uuid::new_v4().simple());
 
     let response = reqwest::Client::new()
         .post(&format!("{}/newsletters", &app.address))
-        .basic_auth(username, Some(password))
-        .json(&serde_json::json!({
-            "title": "Newsletter title",
-            "content": {
-                "text": "Newsletter body as plain text",
-                "html": "<p>Newsletter body as plain text</p>",
-            }
-        }))
+        .bearer_auth(token)
+        .json(&newsletter_request_body())
         .send()
         .await
         .expect("Failed to execute request.");
@@ -186,36 +175,38 @@ async fn non_existing_user_is_rejected() {
     // Assert
     assert_eq!(401, response.status().as_u16());
     assert_eq!(
-        r#"Basic realm="publish""#,
+        r#"Bearer realm="publish""#,
         response.headers()["WWW-Authenticate"]
     );
 }
 
 #[tokio::test]
-async fn invalid_password_is_rejected() {
+async fn basic_credentials_are_rejected() {
     // Arrange
     let app = spawn_app().await;
-    let username = &app.test_user.username;
-    // Random password
-    let password = Uuid::new_v4().to_string();
-    assert_ne!(app.test_user.password, password);
+
     let response = reqwest::Client::new()
         .post(&format!("{}/newsletters", &app.address))
-        .basic_auth(username, Some(password))
-        .json(&serde_json::json!({
-        "title": "Newsletter title",
-        "content": {
-            "text": "Newsletter body as plain text",
-        "html": "<p>Newsletter body as HTML</p>",
-        }
-        }))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&newsletter_request_body())
         .send()
         .await
         .expect("Failed to execute request.");
+
     // Assert
     assert_eq!(401, response.status().as_u16());
     assert_eq!(
-        r#"Basic realm="publish""#,
+        r#"Bearer realm="publish""#,
         response.headers()["WWW-Authenticate"]
     );
 }
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
@@ -0,0 +1,556 @@
+use std::time::Duration;
+
+use newsletter::{
+    domain::ApiScope,
+    issue_deliveries::{retry_failed, send_pending, SendReport},
+};
+use reqwest::Method;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, body_partial_json, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+async fn store_confirmed_subscriber(app: &TestApp, email: &str) {
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'le guin', now(), 'confirmed')",
+        Uuid::new_v4(),
+        email,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// Publish an issue, send it, and return its id.
+async fn publish_issue(app: &TestApp) -> Uuid {
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.send_issues().await;
+    sqlx::query_scalar!("SELECT issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+/// Let the provider accept emails to `delivered@example.com`, and reject
+/// every other one.
+async fn mount_email_provider(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(
+            serde_json::json!({ "To": "delivered@example.com" }),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "To": "delivered@example.com",
+            "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+            "ErrorCode": 0,
+        })))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+}
+
+/// Retry the deliveries of the issue, as `deliveries retry` does.
+async fn retry(app: &TestApp, issue_id: Uuid) {
+    for issue_id in retry_failed(Some(issue_id), &app.db_pool).await.unwrap() {
+        send_pending(
+            &app.email_client,
+            &app.db_pool,
+            issue_id,
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn every_recipient_of_an_issue_is_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    store_confirmed_subscriber(&app, "delivered@example.com").await;
+    store_confirmed_subscriber(&app, "rejected@example.com").await;
+    store_confirmed_subscriber(&app, "not-an-email").await;
+    mount_email_provider(&app).await;
+
+    // Act - a failed email does not fail the publishing
+    let issue_id = publish_issue(&app).await;
+
+    // Assert
+    let deliveries = sqlx::query!(
+        "SELECT recipient, status, attempts, provider_message_id, error, sent_at
+        FROM issue_deliveries WHERE issue_id = $1 ORDER BY recipient",
+        issue_id,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(deliveries.len(), 3);
+
+    assert_eq!(deliveries[0].recipient, "delivered@example.com");
+    assert_eq!(deliveries[0].status, "sent");
+    assert_eq!(deliveries[0].attempts, 1);
+    assert_eq!(
+        deliveries[0].provider_message_id.as_deref(),
+        Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+    );
+    assert!(deliveries[0].sent_at.is_some());
+
+    assert_eq!(deliveries[1].recipient, "not-an-email");
+    assert_eq!(deliveries[1].status, "suppressed");
+    assert_eq!(deliveries[1].attempts, 0);
+
+    assert_eq!(deliveries[2].recipient, "rejected@example.com");
+    assert_eq!(deliveries[2].status, "failed");
+    assert_eq!(deliveries[2].attempts, 1);
+    assert!(deliveries[2].error.is_some());
+    assert!(deliveries[2].sent_at.is_none());
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_a_delivery_report() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_newsletter_report(Uuid::new_v4(), "").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_report_of_an_unknown_issue_is_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app.get_newsletter_report(Uuid::new_v4(), "").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_delivery_report_counts_and_filters_recipients() {
+    // Arrange
+    let app = spawn_app().await;
+    store_confirmed_subscriber(&app, "delivered@example.com").await;
+    store_confirmed_subscriber(&app, "rejected@example.com").await;
+    store_confirmed_subscriber(&app, "not-an-email").await;
+    mount_email_provider(&app).await;
+    let issue_id = publish_issue(&app).await;
+    app.login_user_with_role("viewer").await;
+
+    // Act - Part 1 - The list of issues
+    let html_page = app.get_newsletter_issues_html().await;
+    assert!(html_page.contains(&format!("/admin/newsletters/{}", issue_id)));
+    assert!(html_page.contains("Newsletter title"));
+
+    // Act - Part 2 - The report
+    let html_page = app.get_newsletter_report_html(issue_id, "").await;
+    assert!(html_page.contains("<li>Sent: 1</li>"));
+    assert!(html_page.contains("<li>Failed: 1</li>"));
+    assert!(html_page.contains("<li>Pending: 0</li>"));
+    assert!(html_page.contains("<li>Suppressed: 1</li>"));
+    assert!(html_page.contains("b7bc2f4a-e38e-4336-af7d-e6c392c2f817"));
+
+    // Act - Part 3 - Filtered
+    let html_page = app
+        .get_newsletter_report_html(issue_id, "status=failed")
+        .await;
+    assert!(html_page.contains("rejected@example.com"));
+    assert!(!html_page.contains("delivered@example.com"));
+    let html_page = app
+        .get_newsletter_report_html(issue_id, "search=DELIVERED")
+        .await;
+    assert!(html_page.contains("delivered@example.com"));
+    assert!(!html_page.contains("rejected@example.com"));
+}
+
+#[tokio::test]
+async fn an_unknown_status_filter_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let issue_id = publish_issue(&app).await;
+
+    // Act
+    let response = app
+        .get_newsletter_report(issue_id, "status=delivered")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_delivery_report_can_be_exported_as_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    store_confirmed_subscriber(&app, "delivered@example.com").await;
+    store_confirmed_subscriber(&app, "rejected@example.com").await;
+    mount_email_provider(&app).await;
+    let issue_id = publish_issue(&app).await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app
+        .get_newsletter_report_export(issue_id, "status=sent")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/csv"));
+    assert!(response.headers()["Content-Disposition"]
+        .to_str()
+        .unwrap()
+        .starts_with("attachment"));
+    let csv = response.text().await.unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("recipient,status,attempts,provider_message_id,error,created_at,last_attempt_at,sent_at")
+    );
+    let row = lines.next().unwrap();
+    assert!(row.starts_with("delivered@example.com,sent,1,b7bc2f4a-e38e-4336-af7d-e6c392c2f817,,"));
+    assert_eq!(lines.next(), None);
+}
+
+#[tokio::test]
+async fn deliveries_can_be_listed_through_the_api() {
+    // Arrange
+    let app = spawn_app().await;
+    store_confirmed_subscriber(&app, "delivered@example.com").await;
+    store_confirmed_subscriber(&app, "rejected@example.com").await;
+    mount_email_provider(&app).await;
+    let issue_id = publish_issue(&app).await;
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::SubscribersRead], None)
+        .await;
+
+    // Act
+    let response = app
+        .api_request(
+            Method::GET,
+            &format!("/issues/{}/deliveries?status=failed", issue_id),
+            Some(&token),
+            None,
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let page: serde_json::Value = response.json().await.unwrap();
+    let deliveries = page["data"].as_array().unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0]["recipient"], "rejected@example.com");
+    assert_eq!(deliveries[0]["status"], "failed");
+    assert!(page["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn concurrent_retries_send_to_each_recipient_once() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..5 {
+        store_confirmed_subscriber(&app, &format!("subscriber-{}@example.com", i)).await;
+    }
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_issue(&app).await;
+    app.email_server.reset().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        // Slow enough for the retries to overlap
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(50)))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    tokio::join!(retry(&app, issue_id), retry(&app, issue_id));
+
+    // Assert
+    let requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 5);
+    let deliveries = sqlx::query!(
+        "SELECT status, attempts FROM issue_deliveries WHERE issue_id = $1",
+        issue_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(deliveries.len(), 5);
+    for delivery in deliveries {
+        assert_eq!(delivery.status, "sent");
+        assert_eq!(delivery.attempts, 2);
+    }
+}
+
+#[tokio::test]
+async fn interrupted_deliveries_are_resumed_by_a_retry() {
+    // Arrange
+    let app = spawn_app().await;
+    store_confirmed_subscriber(&app, "delivered@example.com").await;
+    mount_email_provider(&app).await;
+    let issue_id = publish_issue(&app).await;
+    // A sender claimed the recipient and was killed before recording how it
+    // went, while another recipient was never got to.
+    sqlx::query!(
+        "UPDATE issue_deliveries
+        SET status = 'sending', claimed_at = now() - interval '1 hour'
+        WHERE issue_id = $1",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_deliveries (delivery_id, issue_id, recipient, status, created_at)
+        VALUES ($1, $2, 'interrupted@example.com', 'pending', now())",
+        Uuid::new_v4(),
+        issue_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    retry(&app, issue_id).await;
+
+    // Assert
+    let deliveries = sqlx::query!(
+        "SELECT recipient, status FROM issue_deliveries WHERE issue_id = $1 ORDER BY recipient",
+        issue_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(deliveries[0].recipient, "delivered@example.com");
+    assert_eq!(deliveries[0].status, "sent");
+    assert_eq!(deliveries[1].recipient, "interrupted@example.com");
+    assert_eq!(deliveries[1].status, "failed");
+}
+
+#[tokio::test]
+async fn recipients_being_sent_to_are_left_to_their_sender() {
+    // Arrange
+    let app = spawn_app().await;
+    store_confirmed_subscriber(&app, "delivered@example.com").await;
+    mount_email_provider(&app).await;
+    let issue_id = publish_issue(&app).await;
+    sqlx::query!(
+        "UPDATE issue_deliveries SET status = 'sending', claimed_at = now() WHERE issue_id = $1",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let sent_before = app.email_server.received_requests().await.unwrap().len();
+
+    // Act
+    retry(&app, issue_id).await;
+
+    // Assert
+    let sent_after = app.email_server.received_requests().await.unwrap().len();
+    assert_eq!(sent_before, sent_after);
+    let status = sqlx::query_scalar!(
+        "SELECT status FROM issue_deliveries WHERE issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(status, "sending");
+}
+
+/// Wait for the delivery of the issue to `recipient` to leave the queue,
+/// and return its status.
+async fn wait_for_delivery(app: &TestApp, issue_id: Uuid, recipient: &str) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let status = sqlx::query_scalar!(
+                "SELECT status FROM issue_deliveries WHERE issue_id = $1 AND recipient = $2",
+                issue_id,
+                recipient,
+            )
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+            if status != "pending" && status != "sending" {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The issue was not sent.")
+}
+
+#[tokio::test]
+async fn published_issues_are_sent_in_the_background() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.issue_deliveries.workers = 1;
+        c.issue_deliveries.poll_interval_milliseconds = 10;
+    })
+    .await;
+    store_confirmed_subscriber(&app, "delivered@example.com").await;
+    mount_email_provider(&app).await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let issue_id = sqlx::query_scalar!("SELECT issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let status = wait_for_delivery(&app, issue_id, "delivered@example.com").await;
+    assert_eq!(status, "sent");
+}
+
+#[tokio::test]
+async fn recipients_left_pending_by_a_previous_run_are_sent() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.issue_deliveries.workers = 1;
+        c.issue_deliveries.poll_interval_milliseconds = 10;
+    })
+    .await;
+    mount_email_provider(&app).await;
+    // An issue published right before the application was stopped.
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO newsletter_issues
+            (issue_id, title, text_content, html_content, published_at, published_by)
+        VALUES ($1, 'Newsletter title', 'Body', '<p>Body</p>', now(), $2)",
+        issue_id,
+        app.test_user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_deliveries (delivery_id, issue_id, recipient, status, created_at)
+        VALUES ($1, $2, 'delivered@example.com', 'pending', now())",
+        Uuid::new_v4(),
+        issue_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let status = wait_for_delivery(&app, issue_id, "delivered@example.com").await;
+
+    // Assert
+    assert_eq!(status, "sent");
+}
+
+#[tokio::test]
+async fn sending_stops_between_recipients_once_shutting_down() {
+    // Arrange
+    let app = spawn_app().await;
+    store_confirmed_subscriber(&app, "delivered@example.com").await;
+    mount_email_provider(&app).await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let issue_id = sqlx::query_scalar!("SELECT issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let shutdown = CancellationToken::new();
+    shutdown.cancel();
+
+    // Act
+    send_pending(&app.email_client, &app.db_pool, issue_id, &shutdown)
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+    let status = sqlx::query_scalar!("SELECT status FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "pending");
+}
+
+#[tokio::test]
+async fn a_retry_reports_only_the_recipients_it_tried() {
+    // Arrange
+    let app = spawn_app().await;
+    store_confirmed_subscriber(&app, "delivered@example.com").await;
+    store_confirmed_subscriber(&app, "rejected@example.com").await;
+    mount_email_provider(&app).await;
+    let issue_id = publish_issue(&app).await;
+
+    // Act
+    let mut reports = Vec::new();
+    for issue_id in retry_failed(Some(issue_id), &app.db_pool).await.unwrap() {
+        let report = send_pending(
+            &app.email_client,
+            &app.db_pool,
+            issue_id,
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        reports.push(report);
+    }
+
+    // Assert
+    assert_eq!(
+        reports,
+        vec![SendReport {
+            sent: 0,
+            failed: 1,
+            suppressed: 0,
+        }]
+    );
+}
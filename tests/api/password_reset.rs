@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+const GENERIC_MESSAGE: &str = "If the username belongs to an account with an email address, \
+    a link to reset the password has been sent to it.";
+
+/// Ask for a reset link for `username` and return the link sent by email.
+async fn request_reset_link(app: &TestApp, username: &str) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Send password reset link")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_password_reset(&serde_json::json!({ "username": username }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // The link is sent after the answer
+    let email_request = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(request) = app.email_server.received_requests().await.unwrap().pop() {
+                break request;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("No password reset email was sent.");
+    app.get_confirmation_links(&email_request).html
+}
+
+fn token_of(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+        .expect("No token in the reset link.")
+}
+
+async fn set_new_password(app: &TestApp, token: &str, password: &str) -> reqwest::Response {
+    app.post_confirm_password_reset(&serde_json::json!({
+        "token": token,
+        "new_password": password,
+        "new_password_check": password,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn the_login_form_links_to_the_password_reset() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let html_page = app.get_login_html().await;
+
+    // Assert
+    assert!(html_page.contains(r#"<a href="/password-reset">Forgot your password?</a>"#));
+}
+
+#[tokio::test]
+async fn unknown_usernames_get_the_same_answer_and_no_email() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_password_reset(&serde_json::json!({ "username": "nobody" }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(&format!("<p><i>{}</i></p>", GENERIC_MESSAGE)));
+}
+
+#[tokio::test]
+async fn a_reset_link_lets_the_user_choose_a_new_password() {
+    // Arrange
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act - Part 1 - Ask for a link
+    let link = request_reset_link(&app, &app.test_user.username).await;
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(&format!("<p><i>{}</i></p>", GENERIC_MESSAGE)));
+
+    // Act - Part 2 - Follow it
+    let response = app.api_client.get(link.clone()).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - Part 3 - Set a new password
+    let response = set_new_password(&app, &token_of(&link), &new_password).await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Your password has been reset. You can now log in.</i></p>"));
+
+    // Act - Part 4 - The old password does not work anymore
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 5 - The new one does
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_reset_link_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = request_reset_link(&app, &app.test_user.username).await;
+    let token = token_of(&link);
+    set_new_password(&app, &token, &Uuid::new_v4().to_string()).await;
+
+    // Act
+    let response = set_new_password(&app, &token, &Uuid::new_v4().to_string()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.api_client.get(link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_expired_reset_link_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = request_reset_link(&app, &app.test_user.username).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = set_new_password(&app, &token_of(&link), &Uuid::new_v4().to_string()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn new_passwords_must_match() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = request_reset_link(&app, &app.test_user.username).await;
+    let token = token_of(&link);
+
+    // Act
+    let response = app
+        .post_confirm_password_reset(&serde_json::json!({
+            "token": &token,
+            "new_password": Uuid::new_v4().to_string(),
+            "new_password_check": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/password-reset/confirm?token={}", token),
+    );
+    let html_page = app
+        .api_client
+        .get(link)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(
+        "<p><i>You entered two different new passwords - the field values must match.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn resetting_the_password_ends_existing_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let link = request_reset_link(&app, &app.test_user.username).await;
+
+    // Act
+    let response = set_new_password(&app, &token_of(&link), &Uuid::new_v4().to_string()).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Assert
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,87 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{header, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn every_response_carries_a_request_id() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/health/live", &app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    let request_id = response.headers().get("X-Request-Id").unwrap();
+    assert!(Uuid::parse_str(request_id.to_str().unwrap()).is_ok());
+}
+
+#[tokio::test]
+async fn the_request_id_of_the_caller_is_kept() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/health/live", &app.address))
+        .header("X-Request-Id", "support-ticket-42")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(
+        response.headers().get("X-Request-Id").unwrap(),
+        "support-ticket-42"
+    );
+}
+
+#[tokio::test]
+async fn an_unusable_request_id_is_replaced() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/health/live", &app.address))
+        .header("X-Request-Id", "<script>")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    let request_id = response.headers().get("X-Request-Id").unwrap();
+    assert!(Uuid::parse_str(request_id.to_str().unwrap()).is_ok());
+}
+
+#[tokio::test]
+async fn the_request_id_is_passed_on_to_the_email_api() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(header("X-Request-Id", "signup-1"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Request-Id", "signup-1")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("X-Request-Id").unwrap(), "signup-1");
+}
@@ -0,0 +1,96 @@
+use newsletter::configuration::SessionStoreKind;
+use secrecy::SecretString;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app_with};
+
+#[tokio::test]
+async fn sessions_can_be_kept_in_postgres() {
+    // Arrange
+    let app = spawn_app_with(|c| c.session.store = SessionStoreKind::Postgres).await;
+
+    // Act - Part 1 - Login
+    app.login_test_user().await;
+
+    // Assert
+    let stored = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM sessions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(stored > 0);
+
+    // Act - Part 2 - Logout
+    app.post_logout().await;
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn sessions_can_be_kept_in_memory() {
+    // Arrange
+    let app = spawn_app_with(|c| c.session.store = SessionStoreKind::Memory).await;
+
+    // Act - Part 1 - Login
+    app.login_test_user().await;
+
+    // Assert
+    assert!(app.get_admin_dashboard().await.status().is_success());
+
+    // Act - Part 2 - Logout
+    app.post_logout().await;
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn failed_logins_are_throttled_without_redis() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.session.store = SessionStoreKind::Postgres;
+        c.login_throttle.max_failures_per_username = 2;
+        c.login_throttle.delay_step_milliseconds = 0;
+    })
+    .await;
+    for _ in 0..2 {
+        app.post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "wrong-password",
+        }))
+        .await;
+    }
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts."));
+}
+
+#[tokio::test]
+async fn the_app_starts_and_reports_unhealthy_without_redis() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.redis_uri = SecretString::new("redis://127.0.0.1:1".into());
+    })
+    .await;
+
+    // Act
+    let health = reqwest::get(format!("{}/health_check", app.address))
+        .await
+        .expect("Failed to execute request.");
+    let home = reqwest::get(format!("{}/", app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(health.status().as_u16(), 503);
+    assert_eq!(
+        health.text().await.unwrap(),
+        "The session store is unavailable."
+    );
+    assert!(home.status().is_success());
+}
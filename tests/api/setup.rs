@@ -0,0 +1,150 @@
+use secrecy::SecretString;
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_without_users};
+
+fn setup_form(token: &str) -> serde_json::Value {
+    serde_json::json!({
+        "token": token,
+        "username": "first-owner",
+        "email": "owner@example.com",
+        "password": "everythinghastostartsomewhere",
+        "password_check": "everythinghastostartsomewhere",
+    })
+}
+
+#[tokio::test]
+async fn pages_behind_a_login_redirect_to_setup_until_the_first_owner_exists() {
+    // Arrange
+    let app = spawn_app_without_users(|_| {}).await;
+
+    // Act
+    let login = app.get_login().await;
+    let dashboard = app.get_admin_dashboard().await;
+
+    // Assert
+    assert!(app.setup_token.is_some());
+    assert_is_redirect_to(&login, "/setup");
+    assert_is_redirect_to(&dashboard, "/setup");
+    assert!(app.get_setup().await.status().is_success());
+}
+
+#[tokio::test]
+async fn setup_is_rejected_with_a_wrong_token() {
+    // Arrange
+    let app = spawn_app_without_users(|_| {}).await;
+
+    // Act
+    let response = app.post_setup(&setup_form("not-the-token")).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/setup");
+    let html_page = app.get_setup_html().await;
+    assert!(html_page.contains("<p><i>The setup token is not valid.</i></p>"));
+    let users = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM users"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(users, 0);
+}
+
+#[tokio::test]
+async fn setup_is_rejected_with_an_invalid_username() {
+    // Arrange
+    let app = spawn_app_without_users(|_| {}).await;
+    let token = app.setup_token.clone().unwrap();
+    let mut form = setup_form(&token);
+    form["username"] = "<script>alert(1)</script>".into();
+
+    // Act
+    let response = app.post_setup(&form).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    let users = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM users"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(users, 0);
+}
+
+#[tokio::test]
+async fn setup_creates_an_owner_who_can_log_in() {
+    // Arrange
+    let app = spawn_app_without_users(|_| {}).await;
+    let token = app.setup_token.clone().unwrap();
+
+    // Act - Part 1 - Complete the setup
+    let response = app.post_setup(&setup_form(&token)).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Setup is complete. You can now log in.</i></p>"));
+
+    // Act - Part 3 - Log in
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "first-owner",
+            "password": "everythinghastostartsomewhere",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert
+    let role = sqlx::query_scalar!("SELECT role FROM users WHERE username = 'first-owner'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(role, "owner");
+    assert_eq!(app.get_setup().await.status().as_u16(), 404);
+    let response = app
+        .api_client
+        .post(&format!("{}/setup", &app.address))
+        .form(&setup_form(&token))
+        .header("X-CSRF-Token", app.csrf_token().await)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn setup_is_not_available_once_a_user_exists() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_setup().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    assert!(app.get_login().await.status().is_success());
+}
+
+#[tokio::test]
+async fn the_first_owner_can_be_created_from_the_configuration() {
+    // Arrange
+    let username = Uuid::new_v4().to_string();
+    let app = spawn_app_without_users(|c| {
+        c.initial_admin = Some(newsletter::configuration::InitialAdminSettings {
+            username: username.clone(),
+            email: "owner@example.com".into(),
+            password: SecretString::new("everythinghastostartsomewhere".into()),
+        })
+    })
+    .await;
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &username,
+            "password": "everythinghastostartsomewhere",
+        }))
+        .await;
+
+    // Assert
+    assert!(app.setup_token.is_none());
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(app.get_setup().await.status().as_u16(), 404);
+}
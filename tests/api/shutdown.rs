@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use newsletter::configuration::SessionStoreKind;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+const BODY: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+/// Start a subscription whose confirmation email takes `delay` to send.
+async fn subscribe_slowly(
+    app: &TestApp,
+    delay: Duration,
+) -> tokio::task::JoinHandle<Result<reqwest::Response, reqwest::Error>> {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(delay))
+        .mount(&app.email_server)
+        .await;
+    let request = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(BODY)
+        .send();
+    let in_flight = tokio::spawn(request);
+    // Let the request reach the application before shutting it down.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    in_flight
+}
+
+#[tokio::test]
+async fn requests_in_flight_are_finished_on_shutdown() {
+    // Arrange
+    let app = spawn_app().await;
+    let in_flight = subscribe_slowly(&app, Duration::from_secs(1)).await;
+
+    // Act
+    app.stopping.cancel();
+    let response = in_flight.await.unwrap().expect("The request was cut off.");
+    let stopped = tokio::time::timeout(Duration::from_secs(5), app.server).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(stopped
+        .expect("The application did not stop.")
+        .unwrap()
+        .is_ok());
+    assert!(reqwest::get(format!("{}/health/live", &app.address))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn work_still_running_at_the_deadline_is_abandoned() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.shutdown_timeout_seconds = 1;
+        c.email_client.timeout_milliseconds = 60_000;
+    })
+    .await;
+    let _in_flight = subscribe_slowly(&app, Duration::from_secs(30)).await;
+
+    // Act
+    app.stopping.cancel();
+    let stopped = tokio::time::timeout(Duration::from_secs(5), app.server).await;
+
+    // Assert
+    assert!(stopped
+        .expect("The application did not stop.")
+        .unwrap()
+        .is_ok());
+}
+
+#[tokio::test]
+async fn background_tasks_stop_on_shutdown() {
+    // Arrange
+    let app = spawn_app_with(|c| c.session.store = SessionStoreKind::Postgres).await;
+
+    // Act
+    app.stopping.cancel();
+    let stopped = tokio::time::timeout(Duration::from_secs(5), app.server).await;
+
+    // Assert
+    assert!(stopped
+        .expect("The application did not stop.")
+        .unwrap()
+        .is_ok());
+}
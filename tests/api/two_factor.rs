@@ -0,0 +1,324 @@
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+
+/// Extract the TOTP key shown next to the QR code.
+fn extract_key(html_page: &str) -> String {
+    html_page
+        .split("Key: <code>")
+        .nth(1)
+        .and_then(|rest| rest.split("</code>").next())
+        .expect("No TOTP key on the page.")
+        .to_string()
+}
+
+fn extract_recovery_codes(html_page: &str) -> Vec<String> {
+    html_page
+        .split("<li><code>")
+        .skip(1)
+        .map(|rest| rest.split("</code>").next().unwrap().to_string())
+        .collect()
+}
+
+/// Generate the code an authenticator app would show `step_offset` time
+/// steps from now.
+fn totp_code(key: &str, step_offset: u64) -> String {
+    let secret = Secret::Encoded(key.to_string()).to_bytes().unwrap();
+    let totp = TOTP::new(Algorithm::SHA1, 6, 1, 30, secret, None, "".into()).unwrap();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    totp.generate(now + step_offset * 30)
+}
+
+/// Enroll the logged-in user and return their TOTP key and recovery codes.
+async fn enroll(app: &TestApp) -> (String, Vec<String>) {
+    let key = extract_key(&app.get_two_factor_html().await);
+    let response = app
+        .post_enroll_two_factor(&serde_json::json!({ "code": totp_code(&key, 0) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let recovery_codes = extract_recovery_codes(&response.text().await.unwrap());
+    assert_eq!(recovery_codes.len(), 10);
+    (key, recovery_codes)
+}
+
+async fn log_in_with_password(app: &TestApp, user: &TestUser) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &user.username,
+        "password": &user.password
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_two_factor_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_enroll_two_factor(&serde_json::json!({ "code": "123456" }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn enrollment_requires_a_valid_code() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    app.get_two_factor_html().await;
+
+    // Act - Part 1 - Submit a wrong code
+    let response = app
+        .post_enroll_two_factor(&serde_json::json!({ "code": "abcdef" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("<p><i>The authentication code is invalid.</i></p>"));
+    assert!(html_page.contains("Two-factor authentication is disabled."));
+}
+
+#[tokio::test]
+async fn enrolled_users_must_enter_a_code_to_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let (key, _) = enroll(&app).await;
+    app.post_logout().await;
+
+    // Act - Part 1 - Password only
+    let response = log_in_with_password(&app, &app.test_user).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+
+    // Act - Part 2 - The password alone does not give access
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 3 - Enter a code
+    // The enrollment code used up the current time step.
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": totp_code(&key, 1) }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Act - Part 4 - Access the dashboard
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn an_invalid_code_is_rejected_at_login() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    enroll(&app).await;
+    app.post_logout().await;
+    log_in_with_password(&app, &app.test_user).await;
+
+    // Act
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": "abcdef" }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let html_page = app.get_login_two_factor_html().await;
+    assert!(html_page.contains("<p><i>The authentication code is invalid.</i></p>"));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+/// Submit `times` wrong codes for the pending login.
+async fn enter_invalid_codes(app: &TestApp, times: usize) -> reqwest::Response {
+    let mut response = None;
+    for _ in 0..times {
+        response = Some(
+            app.post_login_two_factor(&serde_json::json!({ "code": "abcdef" }))
+                .await,
+        );
+    }
+    response.unwrap()
+}
+
+#[tokio::test]
+async fn a_pending_login_ends_after_too_many_invalid_codes() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let (key, _) = enroll(&app).await;
+    app.post_logout().await;
+    log_in_with_password(&app, &app.test_user).await;
+
+    // Act - Part 1 - Enter three wrong codes
+    let response = enter_invalid_codes(&app, 3).await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(
+        html_page.contains("<p><i>Too many invalid authentication codes. Log in again.</i></p>")
+    );
+
+    // Act - Part 2 - A right code no longer completes the login
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": totp_code(&key, 1) }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_user_is_locked_out_after_too_many_invalid_codes() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let (key, _) = enroll(&app).await;
+    app.post_logout().await;
+    log_in_with_password(&app, &app.test_user).await;
+    enter_invalid_codes(&app, 3).await;
+    log_in_with_password(&app, &app.test_user).await;
+    enter_invalid_codes(&app, 2).await;
+
+    // Act - Enter the right code
+    log_in_with_password(&app, &app.test_user).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": totp_code(&key, 1) }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page
+        .contains("<p><i>Too many failed login attempts. Try again in 15 minutes.</i></p>"));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_code_cannot_be_used_twice() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let (key, _) = enroll(&app).await;
+    app.post_logout().await;
+    let code = totp_code(&key, 1);
+    log_in_with_password(&app, &app.test_user).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": &code }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    // Act
+    log_in_with_password(&app, &app.test_user).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": &code }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn a_recovery_code_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let (_, recovery_codes) = enroll(&app).await;
+    app.post_logout().await;
+
+    // Act - Part 1 - Use a recovery code
+    log_in_with_password(&app, &app.test_user).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": &recovery_codes[0] }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("You have 9 unused recovery codes left."));
+    app.post_logout().await;
+
+    // Act - Part 2 - Use it again
+    log_in_with_password(&app, &app.test_user).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": &recovery_codes[0] }))
+        .await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn users_can_disable_two_factor_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let (key, _) = enroll(&app).await;
+
+    // Act
+    let response = app
+        .post_disable_two_factor(&serde_json::json!({ "code": totp_code(&key, 1) }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    app.post_logout().await;
+
+    // Assert
+    let response = log_in_with_password(&app, &app.test_user).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn owners_can_require_two_factor_authentication_for_every_user() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::with_role("editor");
+    editor.store(&app.db_pool).await;
+    app.login_test_user().await;
+
+    // Act - Part 1 - Require two-factor authentication
+    let response = app
+        .post_two_factor_policy(&serde_json::json!({ "require": true }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page
+        .contains("<p><i>Two-factor authentication is now required for every user.</i></p>"));
+    app.post_logout().await;
+
+    // Act - Part 2 - Users without a second factor have to enroll to log in
+    let response = log_in_with_password(&app, &editor).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let html_page = app.get_login_two_factor_html().await;
+    assert!(html_page.contains("Two-factor authentication is required for every account."));
+    let key = extract_key(&html_page);
+
+    // Act - Part 3 - Confirm the enrollment
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": totp_code(&key, 0) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert_eq!(extract_recovery_codes(&html_page).len(), 10);
+
+    // Assert
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn editors_cannot_change_the_two_factor_policy() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_user_with_role("editor").await;
+
+    // Act
+    let response = app
+        .post_two_factor_policy(&serde_json::json!({ "require": true }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
@@ -0,0 +1,650 @@
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use newsletter::{
+    domain::ApiScope,
+    webhooks::{DeliveryOutcome, WebhookEvent},
+};
+use reqwest::Method;
+use sha2::Sha256;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app, spawn_app_with, ConfirmationLinks, TestApp,
+};
+
+/// Use the public API of the application under test to create an
+/// unconfirmed subscriber.
+async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+async fn receiver() -> MockServer {
+    let receiver = MockServer::start().await;
+    Mock::given(path("/hooks"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&receiver)
+        .await;
+    receiver
+}
+
+async fn received_events(receiver: &MockServer) -> Vec<serde_json::Value> {
+    receiver
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice(&request.body).unwrap())
+        .collect()
+}
+
+async fn delivery_ids(app: &TestApp, endpoint_id: Uuid) -> Vec<Uuid> {
+    sqlx::query_scalar!(
+        "SELECT delivery_id FROM webhook_deliveries WHERE endpoint_id = $1 ORDER BY created_at",
+        endpoint_id,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_webhooks() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_user_with_role("editor").await;
+
+    // Act
+    let response = app
+        .post_create_webhook(&[
+            ("url", "https://crm.example.com/hooks"),
+            ("event", "subscriber.created"),
+        ])
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let endpoints = sqlx::query_scalar!("SELECT count(*) FROM webhook_endpoints")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(endpoints, Some(0));
+}
+
+#[tokio::test]
+async fn a_new_endpoint_shows_its_signing_secret_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app
+        .post_create_webhook(&[
+            ("url", "https://crm.example.com/hooks"),
+            ("description", "CRM"),
+            ("event", "subscriber.confirmed"),
+            ("event", "subscriber.unsubscribed"),
+        ])
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let secret = sqlx::query_scalar!("SELECT secret FROM webhook_endpoints")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(secret.starts_with("whsec_"));
+    assert!(html_page.contains(&secret));
+    let html_page = app.get_webhooks_html().await;
+    assert!(html_page.contains("https://crm.example.com/hooks"));
+    assert!(html_page.contains("subscriber.confirmed, subscriber.unsubscribed"));
+    assert!(!html_page.contains(&secret));
+}
+
+#[tokio::test]
+async fn endpoints_need_an_http_url_and_known_events() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let test_cases = [
+        (
+            vec![("url", "crm.example.com"), ("event", "subscriber.created")],
+            "Enter the HTTP(S) URL to send the events to.",
+        ),
+        (
+            vec![("url", "https://crm.example.com/hooks")],
+            "Select at least one event.",
+        ),
+        (
+            vec![
+                ("url", "https://crm.example.com/hooks"),
+                ("event", "subscriber.deleted"),
+            ],
+            "subscriber.deleted is not a known event.",
+        ),
+    ];
+
+    for (body, message) in test_cases {
+        // Act
+        let response = app.post_create_webhook(&body).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/webhooks");
+        let html_page = app.get_webhooks_html().await;
+        assert!(html_page.contains(message), "{}", message);
+    }
+}
+
+#[tokio::test]
+async fn endpoints_cannot_be_on_internal_hosts() {
+    // Arrange
+    let app = spawn_app_with(|c| c.webhooks.allow_private_addresses = false).await;
+    app.login_test_user().await;
+    let metrics_url = format!("{}/metrics", app.metrics_address);
+
+    for url in [
+        "http://169.254.169.254/latest/meta-data/",
+        "http://10.0.0.1/hooks",
+        "http://[::1]/hooks",
+        metrics_url.as_str(),
+    ] {
+        // Act
+        let response = app
+            .post_create_webhook(&[("url", url), ("event", "subscriber.created")])
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/webhooks");
+        let html_page = app.get_webhooks_html().await;
+        assert!(
+            html_page.contains("Events cannot be sent to this host or to a private network."),
+            "{} was accepted.",
+            url
+        );
+    }
+    let endpoints = sqlx::query_scalar!("SELECT COUNT(*) FROM webhook_endpoints")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(endpoints, Some(0));
+}
+
+#[tokio::test]
+async fn rejected_events_are_escaped_in_the_error_message() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    app.post_create_webhook(&[
+        ("url", "https://crm.example.com/hooks"),
+        ("event", "<script>alert(1)</script>"),
+    ])
+    .await;
+
+    // Assert
+    let html_page = app.get_webhooks_html().await;
+    assert!(html_page.contains("&lt;script&gt;alert(1)&lt;/script&gt; is not a known event."));
+    assert!(!html_page.contains("<script>alert(1)</script>"));
+}
+
+#[tokio::test]
+async fn confirming_a_subscriber_sends_a_signed_event() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = receiver().await;
+    let (_, secret) = app
+        .create_webhook_endpoint(
+            &format!("{}/hooks", receiver.uri()),
+            &[WebhookEvent::SubscriberConfirmed],
+        )
+        .await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.deliver_webhooks().await;
+
+    // Assert
+    let requests = receiver.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    let request = &requests[0];
+    assert_eq!(
+        request.headers.get("X-Webhook-Event").unwrap(),
+        "subscriber.confirmed"
+    );
+    let event: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(event["type"], "subscriber.confirmed");
+    assert_eq!(event["data"]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(event["data"]["status"], "confirmed");
+
+    let signature = request
+        .headers
+        .get("X-Webhook-Signature")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    let (timestamp, tag) = signature
+        .strip_prefix("t=")
+        .and_then(|s| s.split_once(",v1="))
+        .unwrap();
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(&request.body);
+    mac.verify_slice(&hex::decode(tag).unwrap())
+        .expect("The signature does not match the body.");
+}
+
+#[tokio::test]
+async fn events_are_only_sent_to_the_enabled_endpoints_listening_for_them() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let listening = receiver().await;
+    let elsewhere = receiver().await;
+    let disabled = receiver().await;
+    app.create_webhook_endpoint(
+        &format!("{}/hooks", listening.uri()),
+        &[WebhookEvent::SubscriberCreated],
+    )
+    .await;
+    app.create_webhook_endpoint(
+        &format!("{}/hooks", elsewhere.uri()),
+        &[WebhookEvent::IssuePublished],
+    )
+    .await;
+    let (disabled_id, _) = app
+        .create_webhook_endpoint(
+            &format!("{}/hooks", disabled.uri()),
+            &[WebhookEvent::SubscriberCreated],
+        )
+        .await;
+    let response = app.post_disable_webhook(disabled_id).await;
+    assert_is_redirect_to(&response, "/admin/webhooks");
+
+    // Act
+    create_unconfirmed_subscriber(&app).await;
+    app.deliver_webhooks().await;
+
+    // Assert
+    let events = received_events(&listening).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["type"], "subscriber.created");
+    assert_eq!(events[0]["data"]["status"], "pending_confirmation");
+    assert!(received_events(&elsewhere).await.is_empty());
+    assert!(received_events(&disabled).await.is_empty());
+}
+
+#[tokio::test]
+async fn unsubscribing_a_subscriber_sends_an_event() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let receiver = receiver().await;
+    app.create_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &[WebhookEvent::SubscriberUnsubscribed],
+    )
+    .await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let body = serde_json::json!({ "name": "le guin", "status": "unsubscribed" });
+
+    // Act
+    app.post_edit_subscriber(subscriber_id, &body).await;
+    // Saving again changes nothing
+    app.post_edit_subscriber(subscriber_id, &body).await;
+    app.deliver_webhooks().await;
+
+    // Assert
+    let events = received_events(&receiver).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["type"], "subscriber.unsubscribed");
+    assert_eq!(events[0]["data"]["id"], subscriber_id.to_string());
+    assert_eq!(events[0]["data"]["email"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_sends_an_event() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let receiver = receiver().await;
+    app.create_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &[WebhookEvent::SubscriberUnsubscribed],
+    )
+    .await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    app.post_delete_subscriber(subscriber_id).await;
+    app.deliver_webhooks().await;
+
+    // Assert
+    let events = received_events(&receiver).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["type"], "subscriber.unsubscribed");
+    assert_eq!(events[0]["data"]["id"], subscriber_id.to_string());
+    assert_eq!(events[0]["data"]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(events[0]["data"]["deleted"], true);
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_through_the_api_sends_an_event() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = receiver().await;
+    app.create_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &[WebhookEvent::SubscriberUnsubscribed],
+    )
+    .await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let token = app
+        .create_api_token(app.test_user.user_id, &[ApiScope::SubscribersWrite], None)
+        .await;
+
+    // Act
+    let response = app
+        .api_request(
+            Method::DELETE,
+            &format!("/subscribers/{}", subscriber_id),
+            Some(&token),
+            None,
+        )
+        .await;
+    app.deliver_webhooks().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let events = received_events(&receiver).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["type"], "subscriber.unsubscribed");
+    assert_eq!(events[0]["data"]["id"], subscriber_id.to_string());
+}
+
+#[tokio::test]
+async fn publishing_an_issue_sends_an_event() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = receiver().await;
+    app.create_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &[WebhookEvent::IssuePublished],
+    )
+    .await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    app.deliver_webhooks().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let events = received_events(&receiver).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["type"], "issue.published");
+    assert_eq!(events[0]["data"]["title"], "Newsletter title");
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_and_every_attempt_is_logged() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let receiver = MockServer::start().await;
+    Mock::given(path("/hooks"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&receiver)
+        .await;
+    Mock::given(path("/hooks"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&receiver)
+        .await;
+    let (endpoint_id, _) = app
+        .create_webhook_endpoint(
+            &format!("{}/hooks", receiver.uri()),
+            &[WebhookEvent::SubscriberCreated],
+        )
+        .await;
+    create_unconfirmed_subscriber(&app).await;
+
+    // Act - Part 1 - The receiver fails
+    let outcomes = app.deliver_webhooks().await;
+    assert_eq!(outcomes, [DeliveryOutcome::WillRetry]);
+    // A retry is not due straight away
+    assert!(app.deliver_webhooks().await.is_empty());
+
+    // Act - Part 2 - The retry is due, and succeeds
+    sqlx::query!("UPDATE webhook_deliveries SET next_attempt_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let outcomes = app.deliver_webhooks().await;
+
+    // Assert
+    assert_eq!(outcomes, [DeliveryOutcome::Succeeded]);
+    let delivery_id = delivery_ids(&app, endpoint_id).await[0];
+    let statuses = sqlx::query_scalar!(
+        "SELECT response_status FROM webhook_delivery_attempts WHERE delivery_id = $1 ORDER BY id",
+        delivery_id,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(statuses, [Some(500), Some(200)]);
+    let html_page = app.get_webhook_delivery_html(delivery_id).await;
+    assert!(html_page.contains("Status: succeeded"));
+    assert!(html_page.contains("<td>500</td>"));
+}
+
+#[tokio::test]
+async fn deliveries_are_given_up_on_after_the_last_attempt() {
+    // Arrange
+    let app = spawn_app_with(|c| c.webhooks.max_attempts = 2).await;
+    let receiver = MockServer::start().await;
+    Mock::given(path("/hooks"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(2)
+        .mount(&receiver)
+        .await;
+    app.create_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &[WebhookEvent::SubscriberCreated],
+    )
+    .await;
+    create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    let first = app.deliver_webhooks().await;
+    sqlx::query!("UPDATE webhook_deliveries SET next_attempt_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let second = app.deliver_webhooks().await;
+
+    // Assert
+    assert_eq!(first, [DeliveryOutcome::WillRetry]);
+    assert_eq!(second, [DeliveryOutcome::Failed]);
+    let status = sqlx::query_scalar!("SELECT status FROM webhook_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "failed");
+}
+
+#[tokio::test]
+async fn admins_can_replay_a_delivery() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let receiver = MockServer::start().await;
+    Mock::given(path("/hooks"))
+        .and(header("X-Webhook-Event", "subscriber.created"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&receiver)
+        .await;
+    let (endpoint_id, _) = app
+        .create_webhook_endpoint(
+            &format!("{}/hooks", receiver.uri()),
+            &[WebhookEvent::SubscriberCreated],
+        )
+        .await;
+    create_unconfirmed_subscriber(&app).await;
+    app.deliver_webhooks().await;
+    let original_id = delivery_ids(&app, endpoint_id).await[0];
+
+    // Act
+    let response = app.post_replay_webhook_delivery(original_id).await;
+    app.deliver_webhooks().await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/webhooks/{}", endpoint_id));
+    let requests = receiver.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    // The same event, under a new delivery id
+    assert_eq!(requests[0].body, requests[1].body);
+    assert_ne!(
+        requests[0].headers.get("X-Webhook-Delivery"),
+        requests[1].headers.get("X-Webhook-Delivery")
+    );
+    let replay_id = delivery_ids(&app, endpoint_id).await[1];
+    let html_page = app.get_webhook_delivery_html(replay_id).await;
+    assert!(html_page.contains(&format!(
+        "Replay of <a href=\"/admin/webhooks/deliveries/{}\"",
+        original_id
+    )));
+}
+
+#[tokio::test]
+async fn replaying_an_unknown_delivery_returns_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app.post_replay_webhook_delivery(Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn deliveries_being_sent_are_left_to_their_worker() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = MockServer::start().await;
+    Mock::given(path("/hooks"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&receiver)
+        .await;
+    app.create_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &[WebhookEvent::SubscriberCreated],
+    )
+    .await;
+    create_unconfirmed_subscriber(&app).await;
+
+    // Act - Part 1 - A worker is waiting for the receiver
+    let sender = app.webhook_sender.clone();
+    let pool = app.db_pool.clone();
+    let sending = tokio::spawn(async move { sender.try_deliver_next(&pool).await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Assert - Part 1 - The claim is committed, and no other worker takes it
+    let status = sqlx::query_scalar!("SELECT status FROM webhook_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "sending");
+    assert!(app.deliver_webhooks().await.is_empty());
+
+    // Act - Part 2 - The receiver responds
+    let outcome = sending.await.unwrap();
+
+    // Assert - Part 2
+    assert_eq!(outcome, DeliveryOutcome::Succeeded);
+    let status = sqlx::query_scalar!("SELECT status FROM webhook_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "succeeded");
+}
+
+#[tokio::test]
+async fn deliveries_left_behind_by_a_stopped_worker_are_taken_over() {
+    // Arrange
+    let app = spawn_app().await;
+    let receiver = receiver().await;
+    app.create_webhook_endpoint(
+        &format!("{}/hooks", receiver.uri()),
+        &[WebhookEvent::SubscriberCreated],
+    )
+    .await;
+    create_unconfirmed_subscriber(&app).await;
+    sqlx::query!(
+        "UPDATE webhook_deliveries SET status = 'sending', claimed_at = now() - interval '1 hour'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let outcomes = app.deliver_webhooks().await;
+
+    // Assert
+    assert_eq!(outcomes, [DeliveryOutcome::Succeeded]);
+    assert_eq!(received_events(&receiver).await.len(), 1);
+}